@@ -11,7 +11,7 @@ use crate::{
     components::library::cover_library::DO_PAINT_SHADOWS,
     models::library::SELECTED_BOOK_SELECTOR,
     traits::gui::GUIBook,
-    utils::{colors, fonts},
+    utils::{colors, ctx_menu, fonts},
 };
 
 pub const BOOK_WIDGET_SIZE: Size = Size::new(150.0, 250.0);
@@ -22,6 +22,7 @@ pub struct BookCover<B: GUIBook> {
     is_hot: bool,
     star: WidgetPod<B, Label<B>>,
     label: WidgetPod<B, Label<B>>,
+    stack_label: WidgetPod<B, Label<B>>,
 }
 
 impl<B: GUIBook> BookCover<B> {
@@ -40,10 +41,33 @@ impl<B: GUIBook> BookCover<B> {
             .with_font(fonts::medium)
             .with_text_color(colors::ON_PRIMARY);
 
+        let stack_label = Label::dynamic(|data: &B, _| match data.get_stack_size() {
+            n if n > 1 => format!("{} volumi", n),
+            _ => "".into(),
+        })
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_SECONDARY);
+
         Self {
             is_hot: false,
             star: WidgetPod::new(star),
             label: WidgetPod::new(label),
+            stack_label: WidgetPod::new(stack_label),
+        }
+    }
+
+    /// Paints the covers of the other volumes of a series behind this one
+    fn paint_stack(&self, ctx: &mut PaintCtx, data: &B, env: &Env) {
+        let volumes = data.get_stack_size().min(4);
+        for i in (1..volumes).rev() {
+            let offset = 6.0 * i as f64;
+            let rect = ctx
+                .size()
+                .to_rect()
+                .with_origin((offset, -offset))
+                .to_rounded_rect(10.0);
+            ctx.fill(rect, &env.get(colors::PRIMARY_VARIANT));
+            ctx.stroke(rect, &env.get(colors::BACKGROUND), 1.0);
         }
     }
 
@@ -113,6 +137,7 @@ impl<B: GUIBook + Data> Widget<B> for BookCover<B> {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut B, env: &Env) {
         self.star.event(ctx, event, data, env);
         self.label.event(ctx, event, data, env);
+        self.stack_label.event(ctx, event, data, env);
 
         if ctx.is_hot() {
             ctx.set_cursor(&Pointer);
@@ -121,6 +146,18 @@ impl<B: GUIBook + Data> Widget<B> for BookCover<B> {
         }
 
         match event {
+            // ctrl-click adds the book to the multiple selection, or removes it
+            Event::MouseDown(e) if e.mods.ctrl() || e.mods.meta() => {
+                data.set_marked(!data.is_marked());
+                ctx.set_handled();
+                ctx.request_paint();
+            }
+            // the actions on the book are in its context menu
+            Event::MouseDown(e) if e.button.is_right() => {
+                let menu = ctx_menu::book_menu(data.get_index(), data.is_archived());
+                ctx.show_context_menu(menu, e.window_pos);
+                ctx.set_handled();
+            }
             Event::MouseDown(_) => {
                 data.select();
                 ctx.set_handled();
@@ -138,12 +175,14 @@ impl<B: GUIBook + Data> Widget<B> for BookCover<B> {
     fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &B, env: &Env) {
         self.star.lifecycle(ctx, event, data, env);
         self.label.lifecycle(ctx, event, data, env);
+        self.stack_label.lifecycle(ctx, event, data, env);
     }
 
     fn update(&mut self, ctx: &mut UpdateCtx, old_data: &B, data: &B, env: &Env) {
         if !data.same(old_data) || ctx.env_changed() {
             self.star.update(ctx, data, env);
             self.label.update(ctx, data, env);
+            self.stack_label.update(ctx, data, env);
         }
     }
 
@@ -162,6 +201,9 @@ impl<B: GUIBook + Data> Widget<B> for BookCover<B> {
         let origin_y = (BOOK_WIDGET_SIZE.height - ls.height) / 2.0;
         let origin = (origin_x, origin_y).into();
         self.label.set_origin(ctx, data, env, origin);
+        let ss = self.stack_label.layout(ctx, &lbc, data, env);
+        let origin = (BOOK_WIDGET_SIZE.width - ss.width - 10.0, 10.0).into();
+        self.stack_label.set_origin(ctx, data, env, origin);
         BOOK_WIDGET_SIZE
     }
 
@@ -169,7 +211,21 @@ impl<B: GUIBook + Data> Widget<B> for BookCover<B> {
         if env.get(DO_PAINT_SHADOWS) {
             self.paint_shadow(ctx);
         }
+        self.paint_stack(ctx, data, env);
         self.paint_cover(ctx, data, env);
         self.star.paint(ctx, data, env);
+        if data.get_stack_size() > 1 {
+            let badge = self
+                .stack_label
+                .layout_rect()
+                .inflate(5.0, 3.0)
+                .to_rounded_rect(8.0);
+            ctx.fill(badge, &env.get(colors::SECONDARY));
+            self.stack_label.paint(ctx, data, env);
+        }
+        if data.is_marked() {
+            let rrect = ctx.size().to_rect().to_rounded_rect(10.0);
+            ctx.stroke(rrect, &env.get(colors::SECONDARY), 6.0);
+        }
     }
 }
@@ -1,15 +1,20 @@
 use druid::{
-    widget::{Flex, Label, LineBreaking},
+    widget::{Either, Flex, Label, LineBreaking, TextBox},
     BoxConstraints, Command, Data, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx,
     PaintCtx, Size, Target, UpdateCtx, Widget, WidgetExt, WidgetPod,
 };
 
 use crate::{
     components::buttons::rbtn::RoundedButton,
-    models::book::Book,
+    models::{
+        book::Book,
+        goals::finish_plan,
+        metadata::OPEN_METADATA_EDITOR,
+        reading::{self, format_date, ReadingStatus},
+    },
     traits::{
         gui::{GUIBook, GUILibrary},
-        reader::BookManagement,
+        reader::{BookManagement, BookReading},
     },
     utils::{colors, fonts, saveload::delete_book},
     Library, ENTERING_READING_MODE,
@@ -42,7 +47,12 @@ impl BookDetails {
         let author_label = Label::dynamic(|data: &Library<Book>, _| {
             data.get_selected_book()
                 .map_or("Nessun libro selezionato".into(), |book| {
-                    format!("Autore: {}", book.get_author().to_string())
+                    match book.get_contributors() {
+                        contributors if contributors.is_empty() => {
+                            format!("Autore: {}", book.get_author())
+                        }
+                        contributors => format!("Autori: {}", contributors),
+                    }
                 })
         })
         .with_font(fonts::medium)
@@ -61,6 +71,274 @@ impl BookDetails {
         .align_left()
         .padding(5.0);
 
+        let shelves_label = Label::dynamic(|data: &Library<Book>, _| {
+            data.get_selected_book().map_or("".into(), |book: &Book| {
+                let join = |items: &druid::im::Vector<String>| {
+                    if items.is_empty() {
+                        "nessuno".to_string()
+                    } else {
+                        items.iter().cloned().collect::<Vec<_>>().join(", ")
+                    }
+                };
+                format!(
+                    "Raccolte: {}\nTag: {}",
+                    join(book.get_collections()),
+                    join(book.get_tags())
+                )
+            })
+        })
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_BACKGROUND)
+        .with_line_break_mode(LineBreaking::WordWrap)
+        .align_left()
+        .padding(5.0);
+
+        let dates_label = Label::dynamic(|data: &Library<Book>, _| {
+            data.get_selected_book().map_or("".into(), |book: &Book| {
+                let info = book.get_reading_info();
+                let mut dates = format!("Aggiunto il {}", format_date(info.added));
+                if let Some(opened) = info.last_opened {
+                    dates.push_str(&format!("\nUltima apertura: {}", format_date(opened)));
+                }
+                if let Some(finished) = info.finished {
+                    dates.push_str(&format!("\nFinito il {}", format_date(finished)));
+                }
+                dates
+            })
+        })
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_BACKGROUND)
+        .with_line_break_mode(LineBreaking::WordWrap)
+        .align_left()
+        .padding(5.0);
+
+        // every click moves the book to the next status
+        let status_btn = RoundedButton::dynamic(|data: &Library<Book>, _| {
+            data.get_selected_book()
+                .map_or("".into(), |book| {
+                    format!("Stato: {}", book.get_reading_status().label())
+                })
+        })
+        .with_on_click(|_: &mut EventCtx, library: &mut Library<Book>, _: &Env| {
+            if let Some(book) = library.get_selected_book_mut() {
+                let next = book.get_reading_status().next();
+                book.set_reading_status(next);
+            }
+        })
+        .with_font(fonts::medium);
+
+        let mut rating_row = Flex::row();
+        for stars in 1..=5u8 {
+            let star = RoundedButton::dynamic(move |data: &Library<Book>, _| {
+                let rating = data
+                    .get_selected_book()
+                    .and_then(|book| book.get_reading_info().rating)
+                    .unwrap_or(0);
+                let star = if stars <= rating { "★" } else { "☆" };
+                star.to_string()
+            })
+            .with_on_click(move |_: &mut EventCtx, library: &mut Library<Book>, _: &Env| {
+                if let Some(book) = library.get_selected_book_mut() {
+                    // a click on the current rating removes it
+                    let rating = book.get_reading_info().rating;
+                    book.set_rating((rating != Some(stars)).then_some(stars));
+                }
+            })
+            .secondary()
+            .with_font(fonts::medium);
+            rating_row.add_child(star);
+            rating_row.add_spacer(3.0);
+        }
+
+        let reading_ctls = Flex::row()
+            .with_flex_child(status_btn, 1.0)
+            .with_spacer(5.0)
+            .with_child(rating_row)
+            .expand_width()
+            .padding(5.0);
+
+        // pages to read each day to finish the book by the day chosen by the user
+        let finish_by = TextBox::new()
+            .with_placeholder("Finire entro il (gg/mm/aaaa)")
+            .with_font(fonts::small)
+            .with_text_color(colors::ON_BACKGROUND)
+            .lens(Library::<Book>::finish_by)
+            .expand_width();
+
+        let save_finish_by_btn = RoundedButton::from_text("Pianifica")
+            .with_on_click(|ctx, library: &mut Library<Book>, _: &Env| {
+                if let Err(e) = library.save_finish_by_edit() {
+                    println!("ERROR: failed to save the day to finish the book by: {}", e);
+                }
+                ctx.request_layout();
+            })
+            .with_font(fonts::small);
+
+        let finish_plan_label = Label::dynamic(|data: &Library<Book>, _| {
+            data.get_selected_book().map_or("".into(), |book: &Book| {
+                match book.get_reading_info().finish_by {
+                    Some(finish_by) => finish_plan(
+                        book.get_cumulative_current_page_number(),
+                        book.get_number_of_pages(),
+                        reading::now(),
+                        finish_by,
+                    ),
+                    None => "Nessuna data per finire il libro".into(),
+                }
+            })
+        })
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_BACKGROUND)
+        .with_line_break_mode(LineBreaking::WordWrap)
+        .align_left()
+        .padding(5.0);
+
+        let finish_planner = Flex::row()
+            .with_flex_child(finish_by, 1.0)
+            .with_spacer(5.0)
+            .with_child(save_finish_by_btn)
+            .expand_width()
+            .padding(5.0);
+
+        let series_label = Label::dynamic(|data: &Library<Book>, _| {
+            let Some(idx) = data.get_selected_book_idx() else {
+                return "".into();
+            };
+            let Some(series) = data.get_book(idx).and_then(|book| book.get_series()) else {
+                return "Serie: nessuna".into();
+            };
+            let volumes = data.series_volumes(idx);
+            let pos = volumes
+                .iter()
+                .position(|volume| *volume == idx)
+                .unwrap_or(0);
+            format!(
+                "Serie: {} ({} di {} nella libreria)",
+                series.label(),
+                pos + 1,
+                volumes.len()
+            )
+        })
+        .with_font(fonts::medium)
+        .with_text_color(colors::ON_BACKGROUND)
+        .with_line_break_mode(LineBreaking::WordWrap)
+        .align_left()
+        .padding(5.0);
+
+        let series_name = TextBox::new()
+            .with_placeholder("Serie")
+            .with_font(fonts::small)
+            .with_text_color(colors::ON_BACKGROUND)
+            .lens(Library::<Book>::series_name)
+            .expand_width();
+
+        let series_index = TextBox::new()
+            .with_placeholder("N°")
+            .with_font(fonts::small)
+            .with_text_color(colors::ON_BACKGROUND)
+            .lens(Library::<Book>::series_index)
+            .fix_width(50.0);
+
+        let save_series_btn = RoundedButton::from_text("Salva serie")
+            .with_on_click(|ctx, library: &mut Library<Book>, _: &Env| {
+                if let Err(e) = library.save_series_edit() {
+                    println!("ERROR: failed to save the series: {}", e);
+                }
+                ctx.request_layout();
+            })
+            .with_font(fonts::small);
+
+        let series_edit = Flex::row()
+            .with_flex_child(series_name, 1.0)
+            .with_spacer(5.0)
+            .with_child(series_index)
+            .with_spacer(5.0)
+            .with_child(save_series_btn)
+            .expand_width()
+            .padding(5.0);
+
+        let prev_volume_btn = RoundedButton::from_text("◀ Volume precedente")
+            .with_on_click(|_, library: &mut Library<Book>, _: &Env| {
+                let prev = library
+                    .get_selected_book_idx()
+                    .and_then(|idx| library.prev_in_series(idx));
+                if let Some(prev) = prev {
+                    library.set_selected_book_idx(prev);
+                }
+            })
+            .disabled_if(|library: &Library<Book>, _| {
+                library
+                    .get_selected_book_idx()
+                    .and_then(|idx| library.prev_in_series(idx))
+                    .is_none()
+            })
+            .secondary()
+            .with_font(fonts::small);
+
+        let next_volume_btn = RoundedButton::from_text("Volume successivo ▶")
+            .with_on_click(|_, library: &mut Library<Book>, _: &Env| {
+                let next = library
+                    .get_selected_book_idx()
+                    .and_then(|idx| library.next_in_series(idx));
+                if let Some(next) = next {
+                    library.set_selected_book_idx(next);
+                }
+            })
+            .disabled_if(|library: &Library<Book>, _| {
+                library
+                    .get_selected_book_idx()
+                    .and_then(|idx| library.next_in_series(idx))
+                    .is_none()
+            })
+            .secondary()
+            .with_font(fonts::small);
+
+        let series_nav = Flex::row()
+            .with_flex_child(prev_volume_btn, 1.0)
+            .with_spacer(5.0)
+            .with_flex_child(next_volume_btn, 1.0)
+            .expand_width()
+            .padding(5.0);
+
+        // when a volume has been finished the next one is one click away
+        let open_next_btn = RoundedButton::dynamic(|library: &Library<Book>, _| {
+            library
+                .get_selected_book_idx()
+                .and_then(|idx| library.next_in_series(idx))
+                .and_then(|idx| library.get_book(idx))
+                .map_or("".into(), |book| {
+                    format!("Apri il prossimo della serie: {}", book.get_title())
+                })
+        })
+        .with_on_click(|ctx, library: &mut Library<Book>, _: &Env| {
+            let next = library
+                .get_selected_book_idx()
+                .and_then(|idx| library.next_in_series(idx));
+            if let Some(next) = next {
+                library.set_selected_book_idx(next);
+                let book = library.get_selected_book_mut().unwrap();
+                book.load_chapter();
+                book.load_notes();
+                ctx.submit_command(Command::new(ENTERING_READING_MODE, (), Target::Auto));
+            }
+        })
+        .with_font(fonts::medium)
+        .expand_width()
+        .padding(5.0);
+
+        let open_next = Either::new(
+            |library: &Library<Book>, _| {
+                library.get_selected_book_idx().is_some_and(|idx| {
+                    library
+                        .get_book(idx)
+                        .is_some_and(|book| book.get_reading_status() == ReadingStatus::Finished)
+                        && library.next_in_series(idx).is_some()
+                })
+            },
+            open_next_btn,
+            Flex::column(),
+        );
+
         let completion_label = Label::dynamic(|data: &Library<Book>, _| {
             data.get_selected_book()
                 .map_or("Nessun libro selezionato".into(), |book: &Book| {
@@ -114,6 +392,14 @@ impl BookDetails {
 
         let btn_ctls = btn_ctls.expand_width().padding(5.0);
 
+        let edit_metadata_btn = RoundedButton::from_text("Modifica metadati")
+            .with_on_click(|ctx, _: &mut Library<Book>, _: &Env| {
+                ctx.submit_command(Command::new(OPEN_METADATA_EDITOR, (), Target::Auto));
+            })
+            .secondary()
+            .with_font(fonts::medium)
+            .padding(5.0);
+
         let del_btn = RoundedButton::from_text("Elimina")
             .with_on_click(|ctx, library: &mut Library<Book>, _: &Env| {
                 if let Some(book) = library.get_selected_book() {
@@ -121,6 +407,7 @@ impl BookDetails {
                     if let Ok(_) = delete_book(&book.get_path()) {
                         // remove book from library
                         library.remove_book(book.get_index());
+                        library.reload_shelves();
                         println!("Eliminato libro");
                         ctx.children_changed();
                         ctx.request_layout();
@@ -142,8 +429,18 @@ impl BookDetails {
             .with_child(title_label)
             .with_child(author_label)
             .with_child(lang_label)
+            .with_child(shelves_label)
+            .with_child(series_label)
+            .with_child(series_edit)
+            .with_child(series_nav)
             .with_child(completion_label)
+            .with_child(dates_label)
+            .with_child(reading_ctls)
+            .with_child(finish_planner)
+            .with_child(finish_plan_label)
+            .with_child(open_next)
             .with_child(btn_ctls)
+            .with_child(edit_metadata_btn)
             .with_child(del_btn)
             .padding(10.0)
             .expand()
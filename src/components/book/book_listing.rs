@@ -5,25 +5,41 @@ use druid::{
 };
 
 use crate::{
-    models::library::SELECTED_BOOK_SELECTOR,
+    models::{library::SELECTED_BOOK_SELECTOR, reading::format_date},
     traits::gui::GUIBook,
-    utils::{colors, fonts},
+    utils::{colors, ctx_menu, fonts},
 };
 
+/// Share of the width of every column: title, contributors, favorite, status,
+/// rating, last opened and pages read
+pub const COLUMN_WIDTHS: [f64; 7] = [0.25, 0.2, 0.05, 0.12, 0.11, 0.12, 0.15];
+
 pub struct BookListing<T> {
     is_hot: bool,
     title_label: WidgetPod<T, Label<T>>,
+    contributors_label: WidgetPod<T, Label<T>>,
     page_cnt_label: WidgetPod<T, Label<T>>,
     star: WidgetPod<T, Label<T>>,
+    status_label: WidgetPod<T, Label<T>>,
+    rating_label: WidgetPod<T, Label<T>>,
+    opened_label: WidgetPod<T, Label<T>>,
 }
 
 impl<T: GUIBook> BookListing<T> {
     pub fn new() -> Self {
-        let title_label = Label::dynamic(|data: &T, _| format!("{}", data.get_title().to_string()))
+        let title_label = Label::dynamic(|data: &T, _| match data.get_stack_size() {
+            n if n > 1 => format!("{} (+{} volumi)", data.get_title(), n - 1),
+            _ => data.get_title(),
+        })
             .with_font(fonts::medium)
             .with_line_break_mode(LineBreaking::WordWrap)
             .with_text_color(colors::ON_PRIMARY);
 
+        let contributors_label = Label::dynamic(|data: &T, _| data.get_contributors())
+            .with_font(fonts::small)
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .with_text_color(colors::ON_PRIMARY);
+
         let page_cnt_label = Label::dynamic(|data: &T, _| {
             format!(
                 "{}/{} pagine lette",
@@ -45,11 +61,35 @@ impl<T: GUIBook> BookListing<T> {
         })
         .with_font(fonts::large);
 
+        let status_label =
+            Label::dynamic(|data: &T, _| data.get_reading_info().status.label().to_string())
+                .with_font(fonts::small)
+                .with_text_color(colors::ON_PRIMARY);
+
+        let rating_label = Label::dynamic(|data: &T, _| {
+            let stars = data.get_reading_info().rating.unwrap_or(0) as usize;
+            format!("{}{}", "★".repeat(stars), "☆".repeat(5 - stars))
+        })
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_PRIMARY);
+
+        let opened_label = Label::dynamic(|data: &T, _| {
+            data.get_reading_info()
+                .last_opened
+                .map_or("Mai aperto".into(), format_date)
+        })
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_PRIMARY);
+
         Self {
             is_hot: false,
             title_label: WidgetPod::new(title_label),
+            contributors_label: WidgetPod::new(contributors_label),
             page_cnt_label: WidgetPod::new(page_cnt_label),
             star: WidgetPod::new(star_label),
+            status_label: WidgetPod::new(status_label),
+            rating_label: WidgetPod::new(rating_label),
+            opened_label: WidgetPod::new(opened_label),
         }
     }
 }
@@ -57,10 +97,26 @@ impl<T: GUIBook> BookListing<T> {
 impl<B: GUIBook + Data> Widget<B> for BookListing<B> {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut B, env: &Env) {
         self.title_label.event(ctx, event, data, env);
+        self.contributors_label.event(ctx, event, data, env);
         self.page_cnt_label.event(ctx, event, data, env);
         self.star.event(ctx, event, data, env);
+        self.status_label.event(ctx, event, data, env);
+        self.rating_label.event(ctx, event, data, env);
+        self.opened_label.event(ctx, event, data, env);
 
         match event {
+            // ctrl-click adds the book to the multiple selection, or removes it
+            Event::MouseDown(e) if e.mods.ctrl() || e.mods.meta() => {
+                data.set_marked(!data.is_marked());
+                ctx.set_handled();
+                ctx.request_paint();
+            }
+            // the actions on the book are in its context menu
+            Event::MouseDown(e) if e.button.is_right() => {
+                let menu = ctx_menu::book_menu(data.get_index(), data.is_archived());
+                ctx.show_context_menu(menu, e.window_pos);
+                ctx.set_handled();
+            }
             Event::MouseDown(_) => {
                 data.select();
                 ctx.set_handled();
@@ -82,15 +138,23 @@ impl<B: GUIBook + Data> Widget<B> for BookListing<B> {
 
     fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &B, env: &Env) {
         self.title_label.lifecycle(ctx, event, data, env);
+        self.contributors_label.lifecycle(ctx, event, data, env);
         self.page_cnt_label.lifecycle(ctx, event, data, env);
         self.star.lifecycle(ctx, event, data, env);
+        self.status_label.lifecycle(ctx, event, data, env);
+        self.rating_label.lifecycle(ctx, event, data, env);
+        self.opened_label.lifecycle(ctx, event, data, env);
     }
 
     fn update(&mut self, ctx: &mut UpdateCtx, old_data: &B, data: &B, env: &Env) {
         if !old_data.same(data) || ctx.env_changed() {
             self.title_label.update(ctx, data, env);
+            self.contributors_label.update(ctx, data, env);
             self.page_cnt_label.update(ctx, data, env);
             self.star.update(ctx, data, env);
+            self.status_label.update(ctx, data, env);
+            self.rating_label.update(ctx, data, env);
+            self.opened_label.update(ctx, data, env);
         }
     }
 
@@ -104,37 +168,32 @@ impl<B: GUIBook + Data> Widget<B> for BookListing<B> {
             bc.min().width.max(10.)
         };
 
-        let title_w = w * 0.65;
-        let star_w = w * 0.1;
-        let page_cnt_w = w * 0.25;
-
-        let title_bc = BoxConstraints::new((title_w, minh).into(), (title_w, maxh).into());
-        let page_cnt_bc = BoxConstraints::new((minh, minh).into(), (page_cnt_w, maxh).into());
-        let star_bc = BoxConstraints::new((star_w, minh).into(), (star_w, maxh).into());
-
-        let title_label_size = self.title_label.layout(ctx, &title_bc, data, env);
-        let page_cnt_label_size = self.page_cnt_label.layout(ctx, &page_cnt_bc, data, env);
-        let star_size = self.star.layout(ctx, &star_bc, data, env);
-
-        let h = title_label_size.height.max(page_cnt_label_size.height) + 20.0;
-
-        let title_origin = (10.0, h / 2. - title_label_size.height / 2.).into();
-        self.title_label.set_origin(ctx, data, env, title_origin);
+        let mut labels = [
+            &mut self.title_label,
+            &mut self.contributors_label,
+            &mut self.star,
+            &mut self.status_label,
+            &mut self.rating_label,
+            &mut self.opened_label,
+            &mut self.page_cnt_label,
+        ];
+
+        let mut sizes = vec![];
+        for (label, share) in labels.iter_mut().zip(COLUMN_WIDTHS) {
+            let column_w = (w * share - 10.0).max(minh);
+            let bc = BoxConstraints::new((minh, minh).into(), (column_w, maxh).into());
+            sizes.push(label.layout(ctx, &bc, data, env));
+        }
 
-        let star_origin = (
-            title_origin.x + title_label_size.width + 10.0,
-            h / 2. - star_size.height / 2.,
-        )
-            .into();
-        self.star.set_origin(ctx, data, env, star_origin);
+        let h = sizes.iter().map(|size| size.height).fold(0.0, f64::max) + 20.0;
 
-        let page_cnt_origin = (
-            w - page_cnt_label_size.width - 10.,
-            h / 2. - page_cnt_label_size.height / 2.,
-        )
-            .into();
-        self.page_cnt_label
-            .set_origin(ctx, data, env, page_cnt_origin);
+        // every label is at the beginning of its column
+        let mut x = 10.0;
+        for ((label, size), share) in labels.iter_mut().zip(sizes).zip(COLUMN_WIDTHS) {
+            let origin = (x, h / 2. - size.height / 2.).into();
+            label.set_origin(ctx, data, env, origin);
+            x += w * share;
+        }
 
         (w, h).into()
     }
@@ -150,9 +209,16 @@ impl<B: GUIBook + Data> Widget<B> for BookListing<B> {
 
         let rect = ctx.size().to_rect().to_rounded_rect(10.0);
         ctx.fill(rect, &color);
+        if data.is_marked() {
+            ctx.stroke(rect, &env.get(colors::SECONDARY), 4.0);
+        }
 
         self.title_label.paint(ctx, data, env);
+        self.contributors_label.paint(ctx, data, env);
         self.page_cnt_label.paint(ctx, data, env);
         self.star.paint(ctx, data, env);
+        self.status_label.paint(ctx, data, env);
+        self.rating_label.paint(ctx, data, env);
+        self.opened_label.paint(ctx, data, env);
     }
 }
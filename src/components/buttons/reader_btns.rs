@@ -1,16 +1,22 @@
 use crate::{
-    models::{book::Book, command::Trigger},
+    models::{
+        book::Book,
+        command::Trigger,
+        navigation::{Navigation, Position},
+        stats::{format_duration, time_left, CharProgress},
+    },
     traits::{
         gui::{GUIBook, GUILibrary},
         reader::BookReading,
     },
     utils::{
         button_functions::{
-            edit_btn_fn, go_next, go_prev, page_number_switch_button, save_btn_fn, undo_btn_fn,
+            edit_btn_fn, go_next, go_prev, navigate, page_number_switch_button, save_btn_fn,
+            toggle_continuous, undo_btn_fn,
         },
         fonts,
     },
-    CrabReaderState,
+    CrabReaderState, ReadingState, LEAVING_READING_MODE,
 };
 use druid::{
     commands::SHOW_OPEN_PANEL,
@@ -33,18 +39,47 @@ pub enum ReaderBtn {
     ChaptersList,
     Ocr,
     OcrInverse,
+    ComicFit,
+    ComicDirection,
+    HistoryBack,
+    HistoryForward,
+    Furthest,
+    ContinuousSwitch,
 }
 
 enum PageCounterStyle {
     CUMULATIVE,
     ENDOFCHAPTER,
     ENDOFBOOK,
+    TIMETOENDOFCHAPTER,
+    TIMETOENDOFBOOK,
+    PERCENT,
 }
 impl PageCounterStyle {
-    fn to_string(&self, book: &Book, single_view: bool) -> String {
+    fn to_string(&self, book: &Book, reading_state: &ReadingState) -> String {
         let page_number = book.get_cumulative_current_page_number();
+        let single_view = reading_state.single_view;
+        let progress = CharProgress::new(
+            &reading_state.chapter_chars,
+            book.get_chapter_number(),
+            book.get_chapter_fraction(),
+        );
+        let time_to = |chars: f64| match reading_state.reading_speed {
+            Some(speed) => format_duration(time_left(chars, speed)),
+            // nothing has been read yet to measure the speed
+            None => "?".to_string(),
+        };
 
         match self {
+            PageCounterStyle::TIMETOENDOFCHAPTER => {
+                format!("Time to end of ch: {}", time_to(progress.chapter_left))
+            }
+            PageCounterStyle::TIMETOENDOFBOOK => {
+                format!("Time to end of bk: {}", time_to(progress.book_left))
+            }
+            PageCounterStyle::PERCENT => {
+                format!("Read: {:.0}%", progress.percent())
+            }
             PageCounterStyle::ENDOFCHAPTER => {
                 let chapter_page_number = book.get_current_page_number();
                 let pages_to_end = book.get_last_page_number() - chapter_page_number;
@@ -83,6 +118,9 @@ impl From<u8> for PageCounterStyle {
             0 => PageCounterStyle::CUMULATIVE,
             1 => PageCounterStyle::ENDOFCHAPTER,
             2 => PageCounterStyle::ENDOFBOOK,
+            3 => PageCounterStyle::TIMETOENDOFCHAPTER,
+            4 => PageCounterStyle::TIMETOENDOFBOOK,
+            5 => PageCounterStyle::PERCENT,
             _ => PageCounterStyle::CUMULATIVE,
         }
     }
@@ -103,6 +141,12 @@ impl ReaderBtn {
             ReaderBtn::ChaptersList => chapters_list_btn(),
             ReaderBtn::Ocr => ocr_btn(),
             ReaderBtn::OcrInverse => ocr_inverse_btn(),
+            ReaderBtn::ComicFit => comic_fit_btn(),
+            ReaderBtn::ComicDirection => comic_direction_btn(),
+            ReaderBtn::HistoryBack => history_btn("◀ Indietro", Navigation::Back),
+            ReaderBtn::HistoryForward => history_btn("Avanti ▶", Navigation::Forward),
+            ReaderBtn::Furthest => history_btn("Torna al punto più avanzato", Navigation::Furthest),
+            ReaderBtn::ContinuousSwitch => continuous_btn(),
         }
     }
 }
@@ -110,8 +154,8 @@ impl ReaderBtn {
 // button that let to go in library view
 fn leave_btn() -> RoundedButton<CrabReaderState> {
     RoundedButton::from_text("Vai indietro")
-        .with_on_click(|_, data: &mut CrabReaderState, _| {
-            data.reading = false;
+        .with_on_click(|ctx, _: &mut CrabReaderState, _| {
+            ctx.submit_command(LEAVING_READING_MODE);
         })
         .with_font(fonts::xlarge)
 }
@@ -136,6 +180,10 @@ fn edit_btn() -> RoundedButton<CrabReaderState> {
             );
         }
     })
+    .disabled_if(|data: &CrabReaderState, _env: &_| {
+        // pages of a comic are images, there is no text to edit
+        data.library.get_selected_book().unwrap().is_comic()
+    })
     .with_font(fonts::large)
 }
 
@@ -203,6 +251,24 @@ fn views_btn() -> RoundedButton<CrabReaderState> {
     .with_on_click(|_, data: &mut CrabReaderState, _| {
         data.reading_state.single_view = !data.reading_state.single_view;
     })
+    .disabled_if(|data: &CrabReaderState, _env: &_| {
+        data.reading_state.is_editing || data.reading_state.continuous
+    })
+    .with_font(fonts::large)
+}
+
+// button that let to switch between the pages and the continuous scroll of the text
+fn continuous_btn() -> RoundedButton<CrabReaderState> {
+    RoundedButton::dynamic(|data: &CrabReaderState, _env: &_| {
+        if data.reading_state.continuous {
+            "Attiva pagine".into()
+        } else {
+            "Attiva scorrimento continuo".into()
+        }
+    })
+    .with_on_click(|_, data: &mut CrabReaderState, _| {
+        toggle_continuous(data);
+    })
     .disabled_if(|data: &CrabReaderState, _env: &_| data.reading_state.is_editing)
     .with_font(fonts::large)
 }
@@ -212,7 +278,7 @@ fn pages_number_btn() -> RoundedButton<CrabReaderState> {
     RoundedButton::dynamic(|data: &CrabReaderState, _env: &_| {
         PageCounterStyle::from(data.reading_state.pages_btn_style).to_string(
             data.library.get_selected_book().unwrap(),
-            data.reading_state.single_view,
+            &data.reading_state,
         )
     })
     .with_on_click(|_, data: &mut CrabReaderState, _| {
@@ -221,6 +287,26 @@ fn pages_number_btn() -> RoundedButton<CrabReaderState> {
     .with_font(fonts::small)
 }
 
+// buttons that move in the history of the jumps made while reading
+fn history_btn(text: &str, to: Navigation) -> RoundedButton<CrabReaderState> {
+    RoundedButton::from_text(text)
+        .with_on_click(move |_, data: &mut CrabReaderState, _| {
+            navigate(data, to);
+        })
+        .disabled_if(move |data: &CrabReaderState, _env: &_| {
+            let book = data.library.get_selected_book().unwrap();
+            let history = book.get_history();
+            data.reading_state.is_editing
+                || match to {
+                    Navigation::Back => !history.can_go_back(),
+                    Navigation::Forward => !history.can_go_forward(),
+                    Navigation::Furthest => history.furthest_after(book.get_position()).is_none(),
+                }
+        })
+        .secondary()
+        .with_font(fonts::small)
+}
+
 fn chapters_list_btn() -> RoundedButton<CrabReaderState> {
     RoundedButton::from_text("Chapters")
         .with_on_click(|_, data: &mut CrabReaderState, _| {
@@ -233,7 +319,7 @@ pub fn chapter_label(number: usize) -> Align<CrabReaderState> {
     Label::new(format!("Chapter {}", number))
         .on_click(move |_, data: &mut CrabReaderState, _| {
             let book = data.library.get_selected_book_mut().unwrap();
-            book.set_chapter_number(number, true);
+            book.jump_to(Position::new(number, 0));
         })
         .padding(5.0)
         .center()
@@ -253,6 +339,9 @@ pub fn ocr_btn() -> RoundedButton<CrabReaderState> {
 
             ctx.submit_command(cmd);
         })
+        .disabled_if(|data: &CrabReaderState, _env: &_| {
+            data.library.get_selected_book().unwrap().is_comic()
+        })
         .with_font(fonts::large)
 }
 
@@ -270,5 +359,39 @@ pub fn ocr_inverse_btn() -> RoundedButton<CrabReaderState> {
 
             ctx.submit_command(cmd);
         })
+        .disabled_if(|data: &CrabReaderState, _env: &_| {
+            data.library.get_selected_book().unwrap().is_comic()
+        })
         .with_font(fonts::large)
 }
+
+// button that let to scale the pages of a comic to the page or to the width
+fn comic_fit_btn() -> RoundedButton<CrabReaderState> {
+    RoundedButton::dynamic(|data: &CrabReaderState, _env: &_| {
+        if data.reading_state.fit_page {
+            "Adatta alla larghezza".into()
+        } else {
+            "Adatta alla pagina".into()
+        }
+    })
+    .with_on_click(|_, data: &mut CrabReaderState, _| {
+        data.reading_state.fit_page = !data.reading_state.fit_page;
+    })
+    .with_font(fonts::large)
+}
+
+// button that let to switch between left to right and manga (right to left) order
+fn comic_direction_btn() -> RoundedButton<CrabReaderState> {
+    RoundedButton::dynamic(|data: &CrabReaderState, _env: &_| {
+        if data.reading_state.right_to_left {
+            "Lettura da sinistra a destra".into()
+        } else {
+            "Lettura manga (da destra)".into()
+        }
+    })
+    .with_on_click(|_, data: &mut CrabReaderState, _| {
+        data.reading_state.right_to_left = !data.reading_state.right_to_left;
+    })
+    .with_toggle(|data: &CrabReaderState, _env: &_| data.reading_state.right_to_left)
+    .with_font(fonts::large)
+}
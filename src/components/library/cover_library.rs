@@ -115,7 +115,7 @@ impl Widget<Library<Book>> for CoverLibrary {
         data: &Library<Book>,
         env: &Env,
     ) {
-        if data.only_fav() != old_data.only_fav() {
+        if data.filters_changed(old_data) {
             ctx.request_layout();
         }
 
@@ -108,7 +108,7 @@ where
     }
 
     fn update(&mut self, ctx: &mut UpdateCtx, old_data: &L, data: &L, env: &Env) {
-        if data.only_fav() != old_data.only_fav() {
+        if data.filters_changed(old_data) {
             ctx.request_layout();
         }
 
@@ -1,2 +1,3 @@
 pub mod cover_library;
-pub mod listing_library;
\ No newline at end of file
+pub mod listing_library;
+pub mod shelves;
\ No newline at end of file
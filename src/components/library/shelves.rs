@@ -0,0 +1,245 @@
+use druid::{
+    widget::{Controller, CrossAxisAlignment, Either, Flex, Label, List, TextBox},
+    Env, Event, EventCtx, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::{
+        book::Book,
+        library::{
+            ShelfEntry, DELETE_COLLECTION_SELECTOR, FILTER_COLLECTION_SELECTOR, TOGGLE_TAG_SELECTOR,
+        },
+    },
+    traits::gui::GUILibrary,
+    utils::{colors, fonts},
+    Library,
+};
+
+/// Applies to the library the filters chosen in the sidebar
+pub struct ShelvesController;
+
+impl<W: Widget<Library<Book>>> Controller<Library<Book>, W> for ShelvesController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut Library<Book>,
+        env: &Env,
+    ) {
+        if let Event::Notification(cmd) = event {
+            if let Some(name) = cmd.get(FILTER_COLLECTION_SELECTOR) {
+                data.set_collection_filter(name.clone());
+                ctx.set_handled();
+                ctx.request_layout();
+                return;
+            }
+            if let Some(tag) = cmd.get(TOGGLE_TAG_SELECTOR) {
+                data.toggle_tag_filter(tag);
+                ctx.set_handled();
+                ctx.request_layout();
+                return;
+            }
+            if let Some(name) = cmd.get(DELETE_COLLECTION_SELECTOR) {
+                if let Err(e) = data.delete_collection(name) {
+                    println!("ERROR: failed to delete the collection {}: {}", name, e);
+                }
+                ctx.set_handled();
+                ctx.request_layout();
+                return;
+            }
+        }
+        child.event(ctx, event, data, env);
+    }
+}
+
+fn collection_entry() -> impl Widget<ShelfEntry> {
+    let name = RoundedButton::dynamic(|data: &ShelfEntry, _| data.name.to_string())
+        .with_on_click(|ctx, data: &mut ShelfEntry, _| {
+            // a second click on the active collection shows all the books again
+            let name = (!data.active).then(|| data.name.to_string());
+            ctx.submit_notification(FILTER_COLLECTION_SELECTOR.with(name));
+        })
+        .with_toggle(|data: &ShelfEntry, _| data.active)
+        .with_font(fonts::small);
+
+    let delete = RoundedButton::from_text("✕")
+        .with_on_click(|ctx, data: &mut ShelfEntry, _| {
+            ctx.submit_notification(DELETE_COLLECTION_SELECTOR.with(data.name.to_string()));
+        })
+        .secondary()
+        .with_font(fonts::xsmall);
+
+    Flex::row()
+        .with_flex_child(name.expand_width(), 1.0)
+        .with_spacer(5.0)
+        .with_child(delete)
+        .padding((0.0, 2.0))
+}
+
+fn tag_entry() -> impl Widget<ShelfEntry> {
+    RoundedButton::dynamic(|data: &ShelfEntry, _| format!("#{}", data.name))
+        .with_on_click(|ctx, data: &mut ShelfEntry, _| {
+            ctx.submit_notification(TOGGLE_TAG_SELECTOR.with(data.name.to_string()));
+        })
+        .with_toggle(|data: &ShelfEntry, _| data.active)
+        .secondary()
+        .with_font(fonts::small)
+        .padding((0.0, 2.0))
+}
+
+fn section_title(title: &str) -> impl Widget<Library<Book>> {
+    Label::new(title)
+        .with_font(fonts::bold::medium)
+        .with_text_color(colors::ON_BACKGROUND)
+        .padding((0.0, 5.0))
+}
+
+fn empty_label(text: &str) -> impl Widget<Library<Book>> {
+    Label::new(text)
+        .with_font(fonts::xsmall)
+        .with_text_color(colors::ON_BACKGROUND)
+}
+
+/// Collections and tags of the library, a click on them filters the books.
+/// The tags are combined with each other and with the text filter
+pub fn shelves_sidebar() -> impl Widget<Library<Book>> {
+    let all_books = RoundedButton::from_text("Tutti i libri")
+        .with_on_click(|ctx, data: &mut Library<Book>, _| {
+            data.set_collection_filter(None);
+            ctx.request_layout();
+        })
+        .with_toggle(|data: &Library<Book>, _| data.get_collection_filter().is_none())
+        .with_font(fonts::small)
+        .expand_width()
+        .padding((0.0, 2.0));
+
+    let collections = Either::new(
+        |data: &Library<Book>, _| data.collections.is_empty(),
+        empty_label("Nessuna raccolta"),
+        List::new(collection_entry).lens(Library::<Book>::collections),
+    );
+
+    let tags = Either::new(
+        |data: &Library<Book>, _| data.tags.is_empty(),
+        empty_label("Nessun tag"),
+        List::new(tag_entry).lens(Library::<Book>::tags),
+    );
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(section_title("Raccolte"))
+        .with_child(all_books)
+        .with_child(collections)
+        .with_default_spacer()
+        .with_child(section_title("Tag"))
+        .with_child(tags)
+        .padding(10.0)
+        .background(colors::BACKGROUND_VARIANT)
+        .rounded(10.0)
+        .controller(ShelvesController)
+}
+
+/// Adds or removes the marked books, or the selected one,
+/// from a collection or a tag
+fn bulk_button(
+    text: &str,
+    edit: impl Fn(&mut Library<Book>, &str) -> Result<(), String> + 'static,
+) -> impl Widget<Library<Book>> {
+    RoundedButton::from_text(text)
+        .with_on_click(move |ctx, data: &mut Library<Book>, _| {
+            let name = data.shelf_name.clone();
+            match edit(data, &name) {
+                Ok(()) => data.shelf_name.clear(),
+                Err(e) => println!("ERROR: failed to update the collections: {}", e),
+            }
+            ctx.request_layout();
+        })
+        .disabled_if(|data: &Library<Book>, _| data.shelf_name.trim().is_empty())
+        .with_font(fonts::small)
+        .padding(3.0)
+}
+
+/// Bar shown while some books are selected, to organize all of them at once.
+/// More books are selected with ctrl-click, both in the covers and in the list
+pub fn bulk_assign_bar() -> impl Widget<Library<Book>> {
+    let count = Label::dynamic(
+        |data: &Library<Book>, _| match data.number_of_marked_books() {
+            0 => "Libro selezionato".to_string(),
+            1 => "1 libro selezionato".to_string(),
+            n => format!("{} libri selezionati", n),
+        },
+    )
+    .with_font(fonts::medium)
+    .with_text_color(colors::ON_BACKGROUND);
+
+    let name = TextBox::new()
+        .with_placeholder("Raccolta o tag")
+        .with_font(fonts::medium)
+        .with_text_color(colors::ON_BACKGROUND)
+        .lens(Library::<Book>::shelf_name)
+        .expand_width();
+
+    let clear = RoundedButton::from_text("Annulla selezione")
+        .with_on_click(|_, data: &mut Library<Book>, _| data.clear_marks())
+        .disabled_if(|data: &Library<Book>, _| data.number_of_marked_books() == 0)
+        .secondary()
+        .with_font(fonts::small)
+        .padding(3.0);
+
+    let buttons = Flex::row()
+        .with_flex_child(
+            bulk_button("Aggiungi alla raccolta", |data, name| {
+                data.edit_shelves(|shelves, books| shelves.add_to_collection(name, books))
+            }),
+            1.0,
+        )
+        .with_flex_child(
+            bulk_button("Togli dalla raccolta", |data, name| {
+                data.edit_shelves(|shelves, books| {
+                    shelves.remove_from_collection(name, books);
+                    Ok(())
+                })
+            }),
+            1.0,
+        )
+        .with_flex_child(
+            bulk_button("Aggiungi tag", |data, name| {
+                data.edit_shelves(|shelves, books| shelves.add_tag(name, books))
+            }),
+            1.0,
+        )
+        .with_flex_child(
+            bulk_button("Rimuovi tag", |data, name| {
+                data.edit_shelves(|shelves, books| {
+                    shelves.remove_tag(name, books);
+                    Ok(())
+                })
+            }),
+            1.0,
+        );
+
+    let bar = Flex::column()
+        .with_child(
+            Flex::row()
+                .with_child(count)
+                .with_default_spacer()
+                .with_flex_child(name, 1.0)
+                .with_child(clear),
+        )
+        .with_spacer(5.0)
+        .with_child(buttons)
+        .padding(druid::Insets::uniform_xy(15.0, 10.0))
+        .background(colors::BACKGROUND_VARIANT)
+        .rounded(10.0)
+        .expand_width();
+
+    Either::new(
+        |data: &Library<Book>, _| {
+            data.number_of_marked_books() > 0 || data.get_selected_book().is_some()
+        },
+        bar,
+        Flex::column(),
+    )
+}
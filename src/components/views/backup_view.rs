@@ -0,0 +1,122 @@
+use druid::{
+    widget::{Flex, Label, LineBreaking, List, Scroll, TextBox},
+    Command, Env, Target, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::backup::{
+        BackupState, RestoreScope, SnapshotItem, BACKUP_LIST, BACKUP_RESTORE, BACKUP_RUN,
+        BACKUP_SAVE_SETTINGS,
+    },
+    utils::{colors, fonts},
+    CrabReaderState,
+};
+
+fn field(label: &str, text_box: impl Widget<BackupState> + 'static) -> impl Widget<BackupState> {
+    Flex::row()
+        .with_child(
+            Label::new(label)
+                .with_font(fonts::small)
+                .with_text_color(colors::ON_BACKGROUND)
+                .fix_width(120.0),
+        )
+        .with_flex_child(text_box.expand_width(), 1.0)
+        .padding(5.0)
+}
+
+fn restore_button(text: &str, scope: RestoreScope) -> impl Widget<SnapshotItem> {
+    RoundedButton::from_text(text)
+        .with_on_click(move |ctx, data: &mut SnapshotItem, _| {
+            let payload = (data.name.clone(), scope);
+            ctx.submit_command(Command::new(BACKUP_RESTORE, payload, Target::Auto));
+        })
+        .secondary()
+        .with_font(fonts::xsmall)
+}
+
+fn snapshot_widget() -> impl Widget<SnapshotItem> {
+    Flex::row()
+        .with_flex_child(
+            Label::dynamic(|data: &SnapshotItem, _| data.label.clone())
+                .with_font(fonts::small)
+                .with_text_color(colors::ON_BACKGROUND)
+                .expand_width(),
+            1.0,
+        )
+        .with_child(restore_button("Tutto", RestoreScope::All))
+        .with_spacer(5.0)
+        .with_child(restore_button("Libro selezionato", RestoreScope::Book))
+        .with_spacer(5.0)
+        .with_child(restore_button("Note del libro", RestoreScope::Notes))
+        .padding(5.0)
+}
+
+/// Window to back up the library on a WebDAV server and to restore its snapshots
+pub fn backup_window_widget() -> impl Widget<CrabReaderState> {
+    let url = TextBox::new()
+        .with_placeholder("https://cloud.example.com/remote.php/dav/files/utente")
+        .lens(BackupState::url);
+    let username = TextBox::new().lens(BackupState::username);
+    let password = TextBox::protected().lens(BackupState::password);
+    let interval = TextBox::new()
+        .with_placeholder("0 = solo manuale")
+        .lens(BackupState::interval);
+
+    let is_configured = |data: &BackupState, _: &Env| !data.loading && !data.url.is_empty();
+    let save = RoundedButton::from_text("Salva")
+        .with_on_click(|ctx, _: &mut BackupState, _| {
+            ctx.submit_command(Command::new(BACKUP_SAVE_SETTINGS, (), Target::Auto));
+        })
+        .disabled_if(move |data, env| !is_configured(data, env))
+        .with_font(fonts::small);
+    let run = RoundedButton::from_text("Esegui backup ora")
+        .with_on_click(|ctx, _: &mut BackupState, _| {
+            ctx.submit_command(Command::new(BACKUP_RUN, (), Target::Auto));
+        })
+        .disabled_if(move |data, env| !is_configured(data, env))
+        .with_font(fonts::small);
+    let list = RoundedButton::from_text("Aggiorna elenco")
+        .with_on_click(|ctx, _: &mut BackupState, _| {
+            ctx.submit_command(Command::new(BACKUP_LIST, (), Target::Auto));
+        })
+        .disabled_if(move |data, env| !is_configured(data, env))
+        .secondary()
+        .with_font(fonts::small);
+
+    let status = Label::dynamic(|data: &BackupState, _| {
+        if data.loading {
+            "Connessione in corso...".into()
+        } else {
+            data.status.clone()
+        }
+    })
+    .with_font(fonts::small)
+    .with_text_color(colors::ON_BACKGROUND)
+    .with_line_break_mode(LineBreaking::WordWrap)
+    .padding(5.0);
+
+    let snapshots = Scroll::new(List::new(snapshot_widget).lens(BackupState::snapshots))
+        .vertical()
+        .expand();
+
+    Flex::column()
+        .with_child(field("Indirizzo WebDAV", url))
+        .with_child(field("Utente", username))
+        .with_child(field("Password", password))
+        .with_child(field("Backup ogni (ore)", interval))
+        .with_spacer(10.0)
+        .with_child(
+            Flex::row()
+                .with_child(save)
+                .with_spacer(5.0)
+                .with_child(run)
+                .with_spacer(5.0)
+                .with_child(list),
+        )
+        .with_child(status.expand_width())
+        .with_flex_child(snapshots, 1.0)
+        .padding(10.0)
+        .background(colors::BACKGROUND)
+        .lens(CrabReaderState::backup)
+}
@@ -0,0 +1,74 @@
+use druid::{
+    commands::CLOSE_WINDOW,
+    widget::{Either, Flex, Label, LineBreaking},
+    Command, Target, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::book_actions::{BookAction, UndoState, RUN_BOOK_ACTION, UNDO_BOOK_ACTION},
+    utils::{colors, fonts},
+    CrabReaderState, ROUND_FACTR,
+};
+
+/// Window that asks the confirmation of an action on a book
+pub fn confirm_action_widget(
+    path: String,
+    title: String,
+    action: BookAction,
+) -> impl Widget<CrabReaderState> {
+    let confirm = RoundedButton::from_text(action.label())
+        .with_on_click(move |ctx, _: &mut CrabReaderState, _| {
+            let payload = (path.clone(), action);
+            ctx.submit_command(Command::new(RUN_BOOK_ACTION, payload, Target::Auto));
+            ctx.submit_command(CLOSE_WINDOW.to(ctx.window_id()));
+        })
+        .with_font(fonts::small);
+    let cancel = RoundedButton::from_text("Annulla")
+        .with_on_click(|ctx, _: &mut CrabReaderState, _| {
+            ctx.submit_command(CLOSE_WINDOW.to(ctx.window_id()));
+        })
+        .secondary()
+        .with_font(fonts::small);
+
+    Flex::column()
+        .with_child(
+            Label::new(action.question(&title))
+                .with_text_color(colors::ON_BACKGROUND)
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(10.0)
+        .with_child(
+            Flex::row()
+                .with_child(confirm)
+                .with_spacer(5.0)
+                .with_child(cancel),
+        )
+        .padding(10.0)
+        .background(colors::BACKGROUND)
+}
+
+/// Bar shown while the last action on a book can be undone
+pub fn undo_bar() -> impl Widget<UndoState> {
+    let label = Label::dynamic(|data: &UndoState, _| data.label.clone())
+        .with_font(fonts::medium)
+        .with_text_color(colors::ON_BACKGROUND)
+        .with_line_break_mode(LineBreaking::WordWrap);
+    let undo = RoundedButton::from_text("Annulla")
+        .with_on_click(|ctx, _: &mut UndoState, _| {
+            ctx.submit_command(Command::new(UNDO_BOOK_ACTION, (), Target::Auto));
+        })
+        .with_font(fonts::small);
+
+    Either::new(
+        |data: &UndoState, _| data.can_undo(),
+        Flex::row()
+            .with_flex_child(label.expand_width(), 1.0)
+            .with_spacer(10.0)
+            .with_child(undo)
+            .padding(druid::Insets::uniform_xy(15.0, 5.0))
+            .background(colors::BACKGROUND_VARIANT)
+            .rounded(ROUND_FACTR),
+        Flex::row(),
+    )
+}
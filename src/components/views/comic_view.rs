@@ -0,0 +1,230 @@
+use druid::{
+    piet::{ImageFormat, InterpolationMode, PietImage},
+    widget::{Container, Either, Flex, Scroll},
+    BoxConstraints, Env, Event, EventCtx, LayoutCtx, LifeCycle, LifeCycleCtx, PaintCtx, Rect,
+    RenderContext, Size, UpdateCtx, Widget,
+};
+
+use crate::{
+    traits::{
+        gui::GUILibrary,
+        reader::{BookManagement, BookReading},
+    },
+    utils::cbz_utils,
+    CrabReaderState,
+};
+
+/// Position of a page inside the reader
+#[derive(Clone, Copy, PartialEq)]
+pub enum ComicSlot {
+    Single,
+    Left,
+    Right,
+}
+
+struct DecodedPage {
+    name: String,
+    buffer: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+/// Widget that renders a page of a comic book archive,
+/// scaling the image to the width of the widget or to the whole page
+pub struct ComicPage {
+    slot: ComicSlot,
+    fit_page: bool,
+    page: Option<DecodedPage>,
+    image: Option<PietImage>,
+}
+
+impl ComicPage {
+    pub fn new(slot: ComicSlot, fit_page: bool) -> Self {
+        Self {
+            slot,
+            fit_page,
+            page: None,
+            image: None,
+        }
+    }
+
+    /// Returns the path of the comic and the name of the page to show in this slot
+    fn get_page_name(&self, data: &CrabReaderState) -> Option<(String, String)> {
+        let book = data.library.get_selected_book()?;
+        if !book.is_comic() {
+            return None;
+        }
+
+        // in right to left order the first page of the spread is on the right
+        let rtl = data.reading_state.right_to_left;
+        let name = match self.slot {
+            ComicSlot::Single => book.get_page_of_chapter(),
+            ComicSlot::Left if rtl => book.get_dual_pages().1,
+            ComicSlot::Left => book.get_dual_pages().0,
+            ComicSlot::Right if rtl => book.get_dual_pages().0,
+            ComicSlot::Right => book.get_dual_pages().1,
+        };
+
+        if name.is_empty() {
+            None
+        } else {
+            Some((book.get_path(), name))
+        }
+    }
+
+    /// Decodes the page to show if it is changed,
+    /// returns true if the widget has to be laid out again
+    fn load_page(&mut self, data: &CrabReaderState) -> bool {
+        let Some((path, name)) = self.get_page_name(data) else {
+            let changed = self.page.is_some();
+            self.page = None;
+            self.image = None;
+            return changed;
+        };
+
+        if self.page.as_ref().map_or(false, |page| page.name == name) {
+            return false;
+        }
+
+        self.image = None;
+        self.page = match cbz_utils::decode_page(&path, &name) {
+            Ok((buffer, width, height)) => Some(DecodedPage {
+                name,
+                buffer,
+                width,
+                height,
+            }),
+            Err(e) => {
+                println!("ERROR: failed to decode page {}: {}", name, e);
+                None
+            }
+        };
+        true
+    }
+
+    /// Returns the rect where the image has to be drawn
+    fn get_image_rect(&self, size: Size, page: &DecodedPage) -> Rect {
+        let (iw, ih) = (page.width as f64, page.height as f64);
+        let scale = if self.fit_page {
+            (size.width / iw).min(size.height / ih)
+        } else {
+            size.width / iw
+        };
+        let (w, h) = (iw * scale, ih * scale);
+
+        // pages of a spread are drawn next to the spine
+        let x = match self.slot {
+            ComicSlot::Single => (size.width - w) / 2.0,
+            ComicSlot::Left => size.width - w,
+            ComicSlot::Right => 0.0,
+        };
+        let y = if self.fit_page {
+            (size.height - h) / 2.0
+        } else {
+            0.0
+        };
+
+        Rect::from_origin_size((x, y), (w, h))
+    }
+}
+
+impl Widget<CrabReaderState> for ComicPage {
+    fn event(&mut self, _: &mut EventCtx, _: &Event, _: &mut CrabReaderState, _: &Env) {}
+
+    fn lifecycle(
+        &mut self,
+        _: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &CrabReaderState,
+        _: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.load_page(data);
+        }
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        _: &CrabReaderState,
+        data: &CrabReaderState,
+        _: &Env,
+    ) {
+        if self.load_page(data) {
+            ctx.request_layout();
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        _: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _: &CrabReaderState,
+        _: &Env,
+    ) -> Size {
+        let Some(page) = self.page.as_ref() else {
+            return bc.min();
+        };
+
+        let width = bc.max().width;
+        if self.fit_page && bc.is_height_bounded() {
+            return bc.max();
+        }
+
+        let height = width * page.height as f64 / page.width as f64;
+        bc.constrain((width, height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _: &CrabReaderState, _: &Env) {
+        let Some(page) = self.page.as_ref() else {
+            return;
+        };
+
+        if self.image.is_none() {
+            match ctx.make_image(
+                page.width as usize,
+                page.height as usize,
+                &page.buffer,
+                ImageFormat::RgbaSeparate,
+            ) {
+                Ok(image) => self.image = Some(image),
+                Err(e) => {
+                    println!("ERROR: failed to build page {}: {}", page.name, e);
+                    return;
+                }
+            }
+        }
+
+        let clip = ctx.size().to_rect();
+        let rect = self.get_image_rect(ctx.size(), page);
+        if let Some(image) = self.image.as_ref() {
+            ctx.with_save(|ctx| {
+                ctx.clip(clip);
+                ctx.draw_image(image, rect, InterpolationMode::Bilinear);
+            });
+        }
+    }
+}
+
+fn comic_pages_widget(fit_page: bool) -> impl Widget<CrabReaderState> {
+    Either::new(
+        |data: &CrabReaderState, _env| data.reading_state.single_view,
+        ComicPage::new(ComicSlot::Single, fit_page),
+        Flex::row()
+            .with_flex_child(ComicPage::new(ComicSlot::Left, fit_page), 1.0)
+            .with_flex_child(ComicPage::new(ComicSlot::Right, fit_page), 1.0),
+    )
+}
+
+/// View for comic book archives: single page or spread,
+/// scaled to the whole page or to the width of the reader
+pub fn comic_view_widget() -> Container<CrabReaderState> {
+    let inner = Either::new(
+        |data: &CrabReaderState, _env| data.reading_state.fit_page,
+        comic_pages_widget(true),
+        Scroll::new(comic_pages_widget(false)).vertical(),
+    );
+
+    Container::new(inner)
+}
@@ -0,0 +1,132 @@
+use druid::{
+    widget::{CrossAxisAlignment, Flex, Label, LineBreaking, List, Scroll},
+    Command, Env, Target, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::duplicates::{
+        DuplicateBook, DuplicateGroup, DuplicatesState, DUPLICATES_IGNORE, DUPLICATES_KEEP,
+        DUPLICATES_MERGE, DUPLICATES_SEARCH,
+    },
+    utils::{colors, fonts},
+    CrabReaderState, ROUND_FACTR,
+};
+
+fn book_widget() -> impl Widget<DuplicateBook> {
+    let text = |text: fn(&DuplicateBook) -> String, font| {
+        Label::dynamic(move |data: &DuplicateBook, _| text(data))
+            .with_font(font)
+            .with_text_color(colors::ON_BACKGROUND)
+            .with_line_break_mode(LineBreaking::WordWrap)
+    };
+
+    let keep = RoundedButton::from_text("Tieni questo")
+        .with_on_click(|ctx, data: &mut DuplicateBook, _| {
+            let payload = (data.group, data.path.clone());
+            ctx.submit_command(Command::new(DUPLICATES_KEEP, payload, Target::Auto));
+        })
+        .with_toggle(|data: &DuplicateBook, _: &Env| data.keep)
+        .with_font(fonts::xsmall);
+
+    Flex::column()
+        .with_child(text(|data| data.title.clone(), fonts::small))
+        .with_child(text(|data| data.author.clone(), fonts::xsmall))
+        .with_spacer(5.0)
+        .with_child(text(|data| data.details.clone(), fonts::xsmall))
+        .with_child(text(|data| data.path.clone(), fonts::xsmall))
+        .with_spacer(5.0)
+        .with_child(keep)
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .fix_width(220.0)
+        .padding(5.0)
+}
+
+fn group_widget() -> impl Widget<DuplicateGroup> {
+    let reasons = Label::dynamic(|data: &DuplicateGroup, _| data.reasons.clone())
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_BACKGROUND);
+
+    // the books are shown side by side
+    let books = Scroll::new(
+        List::new(book_widget)
+            .horizontal()
+            .lens(DuplicateGroup::books),
+    )
+    .horizontal();
+
+    let merge = RoundedButton::from_text("Unisci nel libro scelto")
+        .with_on_click(|ctx, data: &mut DuplicateGroup, _| {
+            ctx.submit_command(Command::new(DUPLICATES_MERGE, data.id, Target::Auto));
+        })
+        .disabled_if(|data: &DuplicateGroup, _: &Env| data.kept().is_none())
+        .with_font(fonts::small);
+    let ignore = RoundedButton::from_text("Non sono duplicati")
+        .with_on_click(|ctx, data: &mut DuplicateGroup, _| {
+            ctx.submit_command(Command::new(DUPLICATES_IGNORE, data.id, Target::Auto));
+        })
+        .secondary()
+        .with_font(fonts::small);
+
+    Flex::column()
+        .with_child(reasons)
+        .with_child(books)
+        .with_child(
+            Flex::row()
+                .with_child(merge)
+                .with_spacer(5.0)
+                .with_child(ignore),
+        )
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .padding(10.0)
+        .background(colors::BACKGROUND_VARIANT)
+        .rounded(ROUND_FACTR)
+        .padding((0.0, 5.0))
+}
+
+/// Window to review the duplicated books and to merge them in the one to keep
+pub fn duplicates_window_widget() -> impl Widget<CrabReaderState> {
+    let status = Label::dynamic(|data: &DuplicatesState, _| {
+        if data.searching {
+            "Ricerca dei duplicati in corso...".into()
+        } else {
+            data.status.clone()
+        }
+    })
+    .with_font(fonts::medium)
+    .with_text_color(colors::ON_BACKGROUND)
+    .padding(5.0);
+
+    let search = RoundedButton::from_text("Cerca di nuovo")
+        .with_on_click(|ctx, _: &mut DuplicatesState, _| {
+            ctx.submit_command(Command::new(DUPLICATES_SEARCH, (), Target::Auto));
+        })
+        .disabled_if(|data: &DuplicatesState, _: &Env| data.searching)
+        .secondary()
+        .with_font(fonts::small);
+
+    let note = Label::new(
+        "Il libro scelto riceve le note, le collezioni, i tag e la posizione più avanzata degli altri, \
+        che vengono tolti dalla libreria.",
+    )
+    .with_font(fonts::xsmall)
+    .with_text_color(colors::ON_BACKGROUND)
+    .with_line_break_mode(LineBreaking::WordWrap)
+    .padding(5.0);
+
+    let groups = Scroll::new(List::new(group_widget).lens(DuplicatesState::groups))
+        .vertical()
+        .expand();
+
+    Flex::column()
+        .with_child(
+            Flex::row()
+                .with_flex_child(status.expand_width(), 1.0)
+                .with_child(search),
+        )
+        .with_child(note.expand_width())
+        .with_flex_child(groups, 1.0)
+        .padding(10.0)
+        .background(colors::BACKGROUND)
+        .lens(CrabReaderState::duplicates)
+}
@@ -0,0 +1,110 @@
+use druid::{
+    widget::{CrossAxisAlignment, Flex, Label, LineBreaking, ProgressBar, TextBox},
+    Command, Lens, Target, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::goals::{GoalsState, GOALS_SAVE, OPEN_GOALS_WINDOW},
+    utils::{colors, fonts},
+    CrabReaderState, ROUND_FACTR,
+};
+
+fn field(label: &str, text_box: impl Widget<GoalsState> + 'static) -> impl Widget<GoalsState> {
+    Flex::row()
+        .with_child(
+            Label::new(label)
+                .with_font(fonts::small)
+                .with_text_color(colors::ON_BACKGROUND)
+                .fix_width(180.0),
+        )
+        .with_flex_child(text_box.expand_width(), 1.0)
+        .padding(5.0)
+}
+
+fn goal_progress(
+    text: fn(&GoalsState) -> String,
+    progress: impl Lens<GoalsState, f64> + 'static,
+) -> impl Widget<GoalsState> {
+    Flex::column()
+        .with_child(
+            Label::dynamic(move |data: &GoalsState, _| text(data))
+                .with_font(fonts::small)
+                .with_text_color(colors::ON_BACKGROUND),
+        )
+        .with_child(ProgressBar::new().lens(progress).expand_width())
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+}
+
+/// Progress towards the reading goals, shown in the library
+pub fn goals_bar() -> impl Widget<GoalsState> {
+    let edit = RoundedButton::from_text("Obiettivi")
+        .with_on_click(|ctx, _: &mut GoalsState, _| {
+            ctx.submit_command(Command::new(OPEN_GOALS_WINDOW, (), Target::Auto));
+        })
+        .secondary()
+        .with_font(fonts::small);
+
+    Flex::row()
+        .with_flex_child(
+            goal_progress(|data| data.today.clone(), GoalsState::today_progress),
+            1.0,
+        )
+        .with_spacer(15.0)
+        .with_flex_child(
+            goal_progress(|data| data.year.clone(), GoalsState::year_progress),
+            1.0,
+        )
+        .with_spacer(15.0)
+        .with_child(edit)
+        .padding(druid::Insets::uniform_xy(15.0, 5.0))
+        .background(colors::BACKGROUND_VARIANT)
+        .rounded(ROUND_FACTR)
+}
+
+/// Window to set the daily minutes, the books of the year and the reminder
+pub fn goals_window_widget() -> impl Widget<CrabReaderState> {
+    let daily_minutes = TextBox::new()
+        .with_placeholder("vuoto = nessun obiettivo")
+        .lens(GoalsState::daily_minutes);
+    let yearly_books = TextBox::new()
+        .with_placeholder("vuoto = nessun obiettivo")
+        .lens(GoalsState::yearly_books);
+    let reminder_hour = TextBox::new()
+        .with_placeholder("0-23, vuoto = nessun promemoria")
+        .lens(GoalsState::reminder_hour);
+
+    let save = RoundedButton::from_text("Salva")
+        .with_on_click(|ctx, _: &mut GoalsState, _| {
+            ctx.submit_command(Command::new(GOALS_SAVE, (), Target::Auto));
+        })
+        .with_font(fonts::small);
+
+    let note = Label::new(
+        "I minuti sono contati dalle sessioni di lettura, i libri da quelli segnati come letti. \
+        Il promemoria appare se a quell'ora non hai ancora raggiunto i minuti di oggi.",
+    )
+    .with_font(fonts::xsmall)
+    .with_text_color(colors::ON_BACKGROUND)
+    .with_line_break_mode(LineBreaking::WordWrap)
+    .padding(5.0);
+
+    let status = Label::dynamic(|data: &GoalsState, _| data.status.clone())
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_BACKGROUND)
+        .with_line_break_mode(LineBreaking::WordWrap)
+        .padding(5.0);
+
+    Flex::column()
+        .with_child(field("Minuti di lettura al giorno", daily_minutes))
+        .with_child(field("Libri da leggere all'anno", yearly_books))
+        .with_child(field("Ora del promemoria", reminder_hour))
+        .with_child(note.expand_width())
+        .with_spacer(10.0)
+        .with_child(Flex::row().with_child(save))
+        .with_child(status.expand_width())
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .padding(10.0)
+        .background(colors::BACKGROUND)
+        .lens(CrabReaderState::goals)
+}
@@ -0,0 +1,133 @@
+use druid::{
+    commands::SHOW_OPEN_PANEL,
+    widget::{CrossAxisAlignment, Either, Flex, Label, LineBreaking, List, Scroll},
+    Command, Env, LensExt, Target, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::{
+        book::Book,
+        command::Trigger,
+        import::{ImportItem, ImportState, IMPORT_CLEAR},
+        library::Library,
+    },
+    utils::{book_import, colors, fonts},
+    CrabReaderState,
+};
+
+fn item_widget() -> impl Widget<ImportItem> {
+    let name = Label::dynamic(|data: &ImportItem, _| data.name.clone())
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_BACKGROUND)
+        .with_line_break_mode(LineBreaking::WordWrap);
+
+    let status = Label::dynamic(|data: &ImportItem, _| data.status.label())
+        .with_font(fonts::xsmall)
+        .with_text_color(colors::ON_BACKGROUND)
+        .with_line_break_mode(LineBreaking::WordWrap);
+
+    Flex::row()
+        .with_flex_child(name.expand_width(), 1.0)
+        .with_spacer(10.0)
+        .with_flex_child(status.expand_width(), 1.0)
+        .padding((0.0, 3.0))
+}
+
+fn failures_widget() -> impl Widget<ImportState> {
+    let failures = Label::dynamic(|data: &ImportState, _| {
+        data.failures()
+            .map(|item| format!("{}: {}", item.name, item.status.label()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    })
+    .with_font(fonts::xsmall)
+    .with_text_color(colors::ON_BACKGROUND)
+    .with_line_break_mode(LineBreaking::WordWrap);
+
+    // the failures are listed together when the import is over
+    Either::new(
+        |data: &ImportState, _| !data.is_running() && data.failures().next().is_some(),
+        Flex::column()
+            .with_child(
+                Label::new("Libri non importati")
+                    .with_font(fonts::small)
+                    .with_text_color(colors::ON_BACKGROUND),
+            )
+            .with_child(failures)
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .padding(5.0),
+        Flex::column(),
+    )
+}
+
+/// Window with the progress of the books being added to the library
+pub fn import_window_widget() -> impl Widget<CrabReaderState> {
+    let summary = Label::dynamic(|data: &ImportState, _| data.summary())
+        .with_font(fonts::medium)
+        .with_text_color(colors::ON_BACKGROUND)
+        .padding(5.0);
+
+    let items = Scroll::new(List::new(item_widget).lens(ImportState::items)).vertical();
+
+    let add_files = RoundedButton::from_text("Aggiungi file")
+        .with_on_click(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::ADDBOOK;
+            let cmd = Command::new(
+                SHOW_OPEN_PANEL,
+                book_import::open_books_options(),
+                Target::Auto,
+            );
+            ctx.submit_command(cmd);
+        })
+        .with_font(fonts::small);
+
+    let add_folder = RoundedButton::from_text("Aggiungi cartella")
+        .with_on_click(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::IMPORTDIR;
+            let cmd = Command::new(
+                SHOW_OPEN_PANEL,
+                book_import::open_folder_options(),
+                Target::Auto,
+            );
+            ctx.submit_command(cmd);
+        })
+        .secondary()
+        .with_font(fonts::small);
+
+    let clear = RoundedButton::from_text("Pulisci elenco")
+        .with_on_click(|ctx, _: &mut CrabReaderState, _| {
+            ctx.submit_command(Command::new(IMPORT_CLEAR, (), Target::Auto));
+        })
+        .disabled_if(|data: &CrabReaderState, _: &Env| {
+            !data
+                .library
+                .import
+                .items
+                .iter()
+                .any(|item| item.status.is_done())
+        })
+        .secondary()
+        .with_font(fonts::small);
+
+    let progress = Flex::column()
+        .with_child(summary)
+        .with_flex_child(items, 1.0)
+        .with_child(failures_widget())
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .lens(CrabReaderState::library.then(Library::<Book>::import));
+
+    let buttons = Flex::row()
+        .with_child(add_files)
+        .with_spacer(5.0)
+        .with_child(add_folder)
+        .with_spacer(5.0)
+        .with_child(clear);
+
+    Flex::column()
+        .with_flex_child(progress, 1.0)
+        .with_spacer(10.0)
+        .with_child(buttons)
+        .padding(10.0)
+        .background(colors::BACKGROUND)
+}
@@ -0,0 +1,134 @@
+use druid::{
+    commands::CLOSE_WINDOW,
+    widget::{Flex, Label, LineBreaking, TextBox},
+    Command, Env, Target, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::kosync::{KoSyncState, KOSYNC_APPLY_PROGRESS, KOSYNC_LOGIN, KOSYNC_LOGOUT},
+    utils::{colors, fonts, kosync_client::Progress},
+    CrabReaderState, MYENV,
+};
+
+fn field(label: &str, text_box: impl Widget<KoSyncState> + 'static) -> impl Widget<KoSyncState> {
+    Flex::row()
+        .with_child(
+            Label::new(label)
+                .with_font(fonts::small)
+                .with_text_color(colors::ON_BACKGROUND)
+                .fix_width(100.0),
+        )
+        .with_flex_child(text_box.expand_width(), 1.0)
+        .padding(5.0)
+}
+
+/// Window to log in a KOReader sync server,
+/// the positions of the books are synced with the account
+pub fn kosync_window_widget() -> impl Widget<CrabReaderState> {
+    let server = TextBox::new()
+        .with_placeholder("https://sync.koreader.rocks")
+        .lens(KoSyncState::server);
+    let username = TextBox::new().lens(KoSyncState::username);
+    let password = TextBox::protected().lens(KoSyncState::password);
+
+    let can_login = |data: &KoSyncState, _: &Env| {
+        !data.loading
+            && !data.server.is_empty()
+            && !data.username.is_empty()
+            && !data.password.is_empty()
+    };
+    let login = RoundedButton::from_text("Accedi")
+        .with_on_click(|ctx, _: &mut KoSyncState, _| {
+            ctx.submit_command(Command::new(KOSYNC_LOGIN, false, Target::Auto));
+        })
+        .disabled_if(move |data, env| !can_login(data, env))
+        .with_font(fonts::small);
+    let register = RoundedButton::from_text("Registrati")
+        .with_on_click(|ctx, _: &mut KoSyncState, _| {
+            ctx.submit_command(Command::new(KOSYNC_LOGIN, true, Target::Auto));
+        })
+        .disabled_if(move |data, env| !can_login(data, env))
+        .secondary()
+        .with_font(fonts::small);
+    let logout = RoundedButton::from_text("Esci")
+        .with_on_click(|ctx, _: &mut KoSyncState, _| {
+            ctx.submit_command(Command::new(KOSYNC_LOGOUT, (), Target::Auto));
+        })
+        .disabled_if(|data: &KoSyncState, _| data.loading || MYENV.lock().unwrap().kosync.is_none())
+        .secondary()
+        .with_font(fonts::small);
+
+    let status = Label::dynamic(|data: &KoSyncState, _| {
+        if data.loading {
+            "Connessione in corso...".into()
+        } else {
+            data.status.clone()
+        }
+    })
+    .with_font(fonts::small)
+    .with_text_color(colors::ON_BACKGROUND)
+    .with_line_break_mode(LineBreaking::WordWrap)
+    .padding(5.0);
+
+    Flex::column()
+        .with_child(field("Server", server))
+        .with_child(field("Utente", username))
+        .with_child(field("Password", password))
+        .with_spacer(10.0)
+        .with_child(
+            Flex::row()
+                .with_child(login)
+                .with_spacer(5.0)
+                .with_child(register)
+                .with_spacer(5.0)
+                .with_child(logout),
+        )
+        .with_child(status.expand_width())
+        .padding(10.0)
+        .background(colors::BACKGROUND)
+        .lens(CrabReaderState::kosync)
+}
+
+/// Dialog that offers to move a book to the position read on another device
+pub fn progress_offer_widget(path: String, progress: Progress) -> impl Widget<CrabReaderState> {
+    let text = format!(
+        "Su {} sei arrivato al {:.0}% di questo libro, vuoi continuare da lì?",
+        if progress.device.is_empty() {
+            "un altro dispositivo"
+        } else {
+            progress.device.as_str()
+        },
+        progress.percentage * 100.0
+    );
+
+    let go = RoundedButton::from_text("Vai")
+        .with_on_click(move |ctx, _: &mut CrabReaderState, _| {
+            let payload = (path.clone(), progress.clone());
+            ctx.submit_command(Command::new(KOSYNC_APPLY_PROGRESS, payload, Target::Auto));
+            ctx.submit_command(CLOSE_WINDOW.to(ctx.window_id()));
+        })
+        .with_font(fonts::small);
+    let ignore = RoundedButton::from_text("Ignora")
+        .with_on_click(|ctx, _: &mut CrabReaderState, _| {
+            ctx.submit_command(CLOSE_WINDOW.to(ctx.window_id()));
+        })
+        .secondary()
+        .with_font(fonts::small);
+
+    Flex::column()
+        .with_child(
+            Label::new(text)
+                .with_text_color(colors::ON_BACKGROUND)
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(10.0)
+        .with_child(
+            Flex::row()
+                .with_child(go)
+                .with_spacer(5.0)
+                .with_child(ignore),
+        )
+        .padding(10.0)
+        .background(colors::BACKGROUND)
+}
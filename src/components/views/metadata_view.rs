@@ -0,0 +1,205 @@
+use druid::{
+    commands::SHOW_OPEN_PANEL,
+    widget::{CrossAxisAlignment, Either, Flex, Label, LineBreaking, List, Scroll, TextBox},
+    Command, Env, FileDialogOptions, FileSpec, Target, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::{
+        command::Trigger,
+        metadata::{
+            AuthorItem, MetadataEditorState, METADATA_ADD_AUTHOR, METADATA_REMOVE_AUTHOR,
+            METADATA_SAVE,
+        },
+    },
+    utils::{colors, fonts},
+    CrabReaderState,
+};
+
+fn label(text: &str) -> impl Widget<MetadataEditorState> {
+    Label::new(text)
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_BACKGROUND)
+        .fix_width(120.0)
+}
+
+fn field(
+    text: &str,
+    text_box: impl Widget<MetadataEditorState> + 'static,
+) -> impl Widget<MetadataEditorState> {
+    Flex::row()
+        .with_child(label(text))
+        .with_flex_child(text_box.expand_width(), 1.0)
+        .padding(5.0)
+}
+
+fn author_widget() -> impl Widget<AuthorItem> {
+    let remove = RoundedButton::from_text("✕")
+        .with_on_click(|ctx, data: &mut AuthorItem, _| {
+            ctx.submit_command(Command::new(METADATA_REMOVE_AUTHOR, data.id, Target::Auto));
+        })
+        .secondary()
+        .with_font(fonts::xsmall);
+
+    Flex::row()
+        .with_flex_child(
+            TextBox::new()
+                .with_placeholder("Nome")
+                .lens(AuthorItem::name)
+                .expand_width(),
+            1.0,
+        )
+        .with_spacer(5.0)
+        .with_flex_child(
+            TextBox::new()
+                .with_placeholder("Ordina come (Cognome, Nome)")
+                .lens(AuthorItem::file_as)
+                .expand_width(),
+            1.0,
+        )
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_placeholder("aut")
+                .lens(AuthorItem::role)
+                .fix_width(50.0),
+        )
+        .with_spacer(5.0)
+        .with_child(remove)
+        .padding((0.0, 2.0))
+}
+
+/// Window to edit the metadata and the cover of the selected book
+pub fn metadata_window_widget() -> impl Widget<CrabReaderState> {
+    let authors = Flex::column()
+        .with_child(List::new(author_widget).lens(MetadataEditorState::authors))
+        .with_child(
+            RoundedButton::from_text("Aggiungi autore")
+                .with_on_click(|ctx, _: &mut MetadataEditorState, _| {
+                    ctx.submit_command(Command::new(METADATA_ADD_AUTHOR, (), Target::Auto));
+                })
+                .secondary()
+                .with_font(fonts::xsmall),
+        )
+        .cross_axis_alignment(CrossAxisAlignment::Start);
+
+    let description = TextBox::multiline()
+        .with_line_wrapping(true)
+        .lens(MetadataEditorState::description)
+        .fix_height(120.0);
+
+    let series = Flex::row()
+        .with_flex_child(
+            TextBox::new()
+                .with_placeholder("Nome della serie")
+                .lens(MetadataEditorState::series)
+                .expand_width(),
+            1.0,
+        )
+        .with_spacer(5.0)
+        .with_child(
+            TextBox::new()
+                .with_placeholder("N.")
+                .lens(MetadataEditorState::series_index)
+                .fix_width(60.0),
+        );
+
+    let cover_path = Label::dynamic(|data: &MetadataEditorState, _| {
+        if data.cover_path.is_empty() {
+            "Copertina attuale".into()
+        } else {
+            data.cover_path.clone()
+        }
+    })
+    .with_font(fonts::small)
+    .with_text_color(colors::ON_BACKGROUND)
+    .with_line_break_mode(LineBreaking::WordWrap);
+
+    let write_to_epub = Either::new(
+        |data: &MetadataEditorState, _| data.is_epub,
+        RoundedButton::from_text("Scrivi anche nel file EPUB")
+            .with_on_click(|_, data: &mut MetadataEditorState, _| {
+                data.write_to_epub = !data.write_to_epub;
+            })
+            .with_toggle(|data: &MetadataEditorState, _| data.write_to_epub)
+            .with_font(fonts::small),
+        Label::new("I metadati verranno salvati solo nella libreria")
+            .with_font(fonts::small)
+            .with_text_color(colors::ON_BACKGROUND),
+    );
+
+    let save = RoundedButton::from_text("Salva")
+        .with_on_click(|ctx, _: &mut MetadataEditorState, _| {
+            ctx.submit_command(Command::new(METADATA_SAVE, (), Target::Auto));
+        })
+        .disabled_if(|data: &MetadataEditorState, _: &Env| data.saving)
+        .with_font(fonts::small);
+
+    let status = Label::dynamic(|data: &MetadataEditorState, _| data.status.clone())
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_BACKGROUND)
+        .with_line_break_mode(LineBreaking::WordWrap)
+        .padding(5.0);
+
+    let form = Flex::column()
+        .with_child(field(
+            "Titolo",
+            TextBox::new().lens(MetadataEditorState::title),
+        ))
+        .with_child(field("Autori", authors))
+        .with_child(field(
+            "Lingua",
+            TextBox::new().lens(MetadataEditorState::lang),
+        ))
+        .with_child(field(
+            "Editore",
+            TextBox::new().lens(MetadataEditorState::publisher),
+        ))
+        .with_child(field(
+            "Data",
+            TextBox::new()
+                .with_placeholder("AAAA-MM-GG")
+                .lens(MetadataEditorState::date),
+        ))
+        .with_child(field("Descrizione", description))
+        .with_child(field(
+            "Argomenti",
+            TextBox::new()
+                .with_placeholder("separati da virgole")
+                .lens(MetadataEditorState::subjects),
+        ))
+        .with_child(field("Serie", series))
+        .with_child(field("Copertina", cover_path))
+        .lens(CrabReaderState::metadata_editor);
+
+    // the file picker is handled by the delegate with the trigger of the whole state
+    let choose_cover = RoundedButton::from_text("Scegli copertina")
+        .with_on_click(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::COVER;
+            let cmd = Command::new(
+                SHOW_OPEN_PANEL,
+                FileDialogOptions::new()
+                    .allowed_types(vec![FileSpec::new("Immagine", &["jpg", "jpeg", "png"])]),
+                Target::Auto,
+            );
+            ctx.submit_command(cmd);
+        })
+        .secondary()
+        .with_font(fonts::small);
+
+    let buttons = Flex::row()
+        .with_child(save)
+        .with_spacer(5.0)
+        .with_child(write_to_epub)
+        .lens(CrabReaderState::metadata_editor);
+
+    Flex::column()
+        .with_flex_child(Scroll::new(form).vertical(), 1.0)
+        .with_child(choose_cover.padding(5.0))
+        .with_spacer(10.0)
+        .with_child(buttons)
+        .with_child(status.expand_width().lens(CrabReaderState::metadata_editor))
+        .padding(10.0)
+        .background(colors::BACKGROUND)
+}
@@ -1,2 +1,15 @@
+pub mod backup_view;
+pub mod book_actions_view;
+pub mod comic_view;
+pub mod duplicates_view;
+pub mod goals_view;
+pub mod import_view;
+pub mod kosync_view;
+pub mod metadata_view;
+pub mod navigation_view;
+pub mod opds_view;
 pub mod reader_view;
-pub mod sidebar;
\ No newline at end of file
+pub mod scroll_view;
+pub mod sidebar;
+pub mod stats_view;
+pub mod sync_view;
\ No newline at end of file
@@ -0,0 +1,195 @@
+use druid::{
+    commands::CLOSE_WINDOW,
+    widget::{Controller, CrossAxisAlignment, Flex, Label, LineBreaking, Slider, TextBox},
+    Data, Env, Event, EventCtx, Lens, LensExt, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::navigation::{Position, OPEN_GO_TO_WINDOW},
+    traits::{gui::GUILibrary, reader::BookReading},
+    utils::{
+        button_functions::{go_to_page, go_to_typed_page},
+        colors, fonts,
+    },
+    CrabReaderState, ReadingState,
+};
+
+fn last_page(data: &CrabReaderState) -> usize {
+    data.reading_state
+        .pages_per_chapter
+        .last()
+        .map_or(0, |(_, last)| *last)
+}
+
+/// Part of the book before the current page, or the part under the progress bar while it is dragged
+fn scrub_fraction(data: &CrabReaderState) -> f64 {
+    if let Some(scrub) = data.reading_state.scrub {
+        return scrub;
+    }
+    let last = last_page(data);
+    if last == 0 {
+        return 0.0;
+    }
+    let page = data
+        .library
+        .get_selected_book()
+        .map_or(0, |book| book.get_cumulative_current_page_number());
+    (page as f64 / last as f64).min(1.0)
+}
+
+fn scrub_page(data: &CrabReaderState) -> usize {
+    (scrub_fraction(data) * last_page(data) as f64).round() as usize
+}
+
+/// Chapter and page the progress bar points to
+fn preview(data: &CrabReaderState) -> String {
+    let page = scrub_page(data);
+    let Some(position) = Position::from_page(page, &data.reading_state.pages_per_chapter) else {
+        return String::new();
+    };
+    let page = format!("Page {} of {}", page, last_page(data));
+    if data.reading_state.chapter_chars.is_empty() {
+        // a comic has a single chapter
+        return page;
+    }
+    match data.reading_state.chapter_titles.get(position.chapter) {
+        Some(title) if !title.is_empty() => {
+            format!("Chapter {}: {} · {}", position.chapter + 1, title, page)
+        }
+        _ => format!("Chapter {} · {}", position.chapter + 1, page),
+    }
+}
+
+/// Position in the book shown by the progress bar,
+/// moving the bar only changes the preview until it is released
+struct ScrubLens;
+
+impl Lens<CrabReaderState, f64> for ScrubLens {
+    fn with<V, F: FnOnce(&f64) -> V>(&self, data: &CrabReaderState, f: F) -> V {
+        f(&scrub_fraction(data))
+    }
+
+    fn with_mut<V, F: FnOnce(&mut f64) -> V>(&self, data: &mut CrabReaderState, f: F) -> V {
+        let fraction = scrub_fraction(data);
+        let mut value = fraction;
+        let result = f(&mut value);
+        if !value.same(&fraction) {
+            data.reading_state.scrub = Some(value);
+        }
+        result
+    }
+}
+
+/// Goes to the page under the progress bar when it is released
+struct ScrubController;
+
+impl<W: Widget<CrabReaderState>> Controller<CrabReaderState, W> for ScrubController {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut CrabReaderState,
+        env: &Env,
+    ) {
+        child.event(ctx, event, data, env);
+        if let Event::MouseUp(_) = event {
+            if data.reading_state.scrub.is_some() {
+                let page = scrub_page(data);
+                data.reading_state.scrub = None;
+                go_to_page(data, page);
+            }
+        }
+    }
+}
+
+/// Bar at the bottom of the reader to move through the whole book,
+/// while it is dragged it shows the chapter and the page it goes to
+pub fn progress_bar() -> impl Widget<CrabReaderState> {
+    let slider = Slider::new()
+        .with_range(0.0, 1.0)
+        .lens(ScrubLens)
+        .controller(ScrubController)
+        .disabled_if(|data: &CrabReaderState, _| data.reading_state.is_editing);
+
+    let preview = Label::dynamic(|data: &CrabReaderState, _| preview(data))
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_BACKGROUND)
+        .fix_width(320.0);
+
+    let go_to = RoundedButton::from_text("Vai a...")
+        .with_on_click(|ctx, _: &mut CrabReaderState, _| {
+            ctx.submit_command(OPEN_GO_TO_WINDOW);
+        })
+        .disabled_if(|data: &CrabReaderState, _env: &_| data.reading_state.is_editing)
+        .secondary()
+        .with_font(fonts::small);
+
+    Flex::row()
+        .with_flex_child(slider.expand_width(), 1.0)
+        .with_spacer(10.0)
+        .with_child(preview)
+        .with_child(go_to)
+}
+
+fn field(
+    label: &str,
+    text_box: impl Widget<CrabReaderState> + 'static,
+) -> impl Widget<CrabReaderState> {
+    Flex::row()
+        .with_child(
+            Label::new(label)
+                .with_font(fonts::small)
+                .with_text_color(colors::ON_BACKGROUND)
+                .fix_width(180.0),
+        )
+        .with_flex_child(text_box.expand_width(), 1.0)
+        .padding(5.0)
+}
+
+/// Window to go to a page, a percentage of the book or a page of its printed edition
+pub fn go_to_window_widget() -> impl Widget<CrabReaderState> {
+    let go_to = TextBox::new()
+        .with_placeholder("120, 45% o p. 120")
+        .lens(CrabReaderState::reading_state.then(ReadingState::go_to));
+    let printed_pages = TextBox::new()
+        .with_placeholder("vuoto = non indicate")
+        .lens(CrabReaderState::reading_state.then(ReadingState::printed_pages));
+
+    let go = RoundedButton::from_text("Vai")
+        .with_on_click(
+            |ctx, data: &mut CrabReaderState, _| match go_to_typed_page(data) {
+                Ok(()) => ctx.submit_command(CLOSE_WINDOW.to(ctx.window_id())),
+                Err(e) => data.reading_state.go_to_status = format!("Impossibile andare: {}", e),
+            },
+        )
+        .with_font(fonts::small);
+
+    let note = Label::new(
+        "Una pagina come nel contatore, una percentuale del libro oppure, con \"p.\", \
+        una pagina dell'edizione stampata, che richiede il numero delle sue pagine.",
+    )
+    .with_font(fonts::xsmall)
+    .with_text_color(colors::ON_BACKGROUND)
+    .with_line_break_mode(LineBreaking::WordWrap)
+    .padding(5.0);
+
+    let status =
+        Label::dynamic(|data: &CrabReaderState, _| data.reading_state.go_to_status.clone())
+            .with_font(fonts::small)
+            .with_text_color(colors::ON_BACKGROUND)
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .padding(5.0);
+
+    Flex::column()
+        .with_child(field("Vai a", go_to))
+        .with_child(field("Pagine dell'edizione stampata", printed_pages))
+        .with_child(note.expand_width())
+        .with_spacer(10.0)
+        .with_child(Flex::row().with_child(go))
+        .with_child(status.expand_width())
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .padding(10.0)
+        .background(colors::BACKGROUND)
+}
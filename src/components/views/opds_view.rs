@@ -0,0 +1,191 @@
+use druid::{
+    piet::{ImageFormat, InterpolationMode},
+    widget::{Either, Flex, Label, LineBreaking, List, Painter, Scroll, SizedBox, TextBox},
+    Command, RenderContext, Target, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::opds::{
+        OpdsEntryItem, OpdsState, OPDS_ACTIVATE_ENTRY, OPDS_BACK, OPDS_COVER_HEIGHT,
+        OPDS_COVER_WIDTH, OPDS_OPEN_FEED, OPDS_SEARCH,
+    },
+    utils::{colors, fonts},
+    CrabReaderState,
+};
+
+fn cover_widget() -> impl Widget<OpdsEntryItem> {
+    Painter::new(|ctx, data: &OpdsEntryItem, env| {
+        let rect = ctx.size().to_rect();
+        if data.cover.is_empty() {
+            ctx.fill(rect, &env.get(colors::BACKGROUND_VARIANT));
+            return;
+        }
+        if let Ok(image) = ctx.make_image(
+            OPDS_COVER_WIDTH as usize,
+            OPDS_COVER_HEIGHT as usize,
+            &data.cover,
+            ImageFormat::Rgb,
+        ) {
+            ctx.draw_image(&image, rect, InterpolationMode::Bilinear);
+        }
+    })
+    .fix_size(OPDS_COVER_WIDTH as f64, OPDS_COVER_HEIGHT as f64)
+}
+
+fn entry_widget() -> impl Widget<OpdsEntryItem> {
+    let title = Label::dynamic(|data: &OpdsEntryItem, _| data.title.clone())
+        .with_font(fonts::bold::medium)
+        .with_text_color(colors::ON_BACKGROUND)
+        .with_line_break_mode(LineBreaking::WordWrap);
+    let author = Label::dynamic(|data: &OpdsEntryItem, _| data.author.clone())
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_BACKGROUND);
+    let summary = Label::dynamic(|data: &OpdsEntryItem, _| {
+        // long descriptions are cut, the list has to stay readable
+        let mut summary = data.summary.chars().take(200).collect::<String>();
+        if summary.len() < data.summary.len() {
+            summary.push('…');
+        }
+        summary
+    })
+    .with_font(fonts::xsmall)
+    .with_text_color(colors::ON_BACKGROUND)
+    .with_line_break_mode(LineBreaking::WordWrap);
+
+    let text = Flex::column()
+        .cross_axis_alignment(druid::widget::CrossAxisAlignment::Start)
+        .with_child(title)
+        .with_child(author)
+        .with_spacer(5.0)
+        .with_child(summary);
+
+    let button = RoundedButton::dynamic(|data: &OpdsEntryItem, _| {
+        if data.is_book {
+            "Scarica".into()
+        } else {
+            "Apri".into()
+        }
+    })
+    .with_on_click(|ctx, data: &mut OpdsEntryItem, _| {
+        ctx.submit_command(Command::new(
+            OPDS_ACTIVATE_ENTRY,
+            data.entry.clone(),
+            Target::Auto,
+        ));
+    })
+    .with_font(fonts::small);
+
+    Flex::row()
+        .with_child(cover_widget())
+        .with_spacer(10.0)
+        .with_flex_child(text.expand_width(), 1.0)
+        .with_spacer(10.0)
+        .with_child(SizedBox::new(button).width(100.0))
+        .padding(5.0)
+}
+
+fn toolbar_widget() -> impl Widget<OpdsState> {
+    let url = TextBox::new()
+        .with_placeholder("http://192.168.1.10:8080/opds")
+        .lens(OpdsState::url)
+        .expand_width();
+    let open = RoundedButton::from_text("Apri")
+        .with_on_click(|ctx, data: &mut OpdsState, _| {
+            ctx.submit_command(Command::new(OPDS_OPEN_FEED, data.url.clone(), Target::Auto));
+        })
+        .disabled_if(|data: &OpdsState, _| data.url.is_empty() || data.loading)
+        .with_font(fonts::small);
+    let back = RoundedButton::from_text("Indietro")
+        .with_on_click(|ctx, _: &mut OpdsState, _| {
+            ctx.submit_command(Command::new(OPDS_BACK, (), Target::Auto));
+        })
+        .disabled_if(|data: &OpdsState, _| !data.can_go_back() || data.loading)
+        .secondary()
+        .with_font(fonts::small);
+
+    let search = TextBox::new()
+        .with_placeholder("Cerca nel catalogo")
+        .lens(OpdsState::search)
+        .expand_width();
+    let search_btn = RoundedButton::from_text("Cerca")
+        .with_on_click(|ctx, data: &mut OpdsState, _| {
+            ctx.submit_command(Command::new(OPDS_SEARCH, data.search.clone(), Target::Auto));
+        })
+        .disabled_if(|data: &OpdsState, _| {
+            !data.can_search || data.search.is_empty() || data.loading
+        })
+        .with_font(fonts::small);
+
+    Flex::column()
+        .with_child(
+            Flex::row()
+                .with_child(back)
+                .with_spacer(5.0)
+                .with_flex_child(url, 1.0)
+                .with_spacer(5.0)
+                .with_child(open),
+        )
+        .with_spacer(5.0)
+        .with_child(
+            Flex::row()
+                .with_flex_child(search, 1.0)
+                .with_spacer(5.0)
+                .with_child(search_btn),
+        )
+        .padding(5.0)
+}
+
+/// Window to browse the OPDS catalogs of the local network
+/// and download their books
+pub fn opds_window_widget() -> impl Widget<CrabReaderState> {
+    let title = Label::dynamic(|data: &OpdsState, _| {
+        if data.title.is_empty() {
+            "Inserisci l'indirizzo di un catalogo OPDS".into()
+        } else {
+            data.title.clone()
+        }
+    })
+    .with_font(fonts::bold::large)
+    .with_text_color(colors::ON_BACKGROUND)
+    .with_line_break_mode(LineBreaking::WordWrap)
+    .padding(5.0);
+
+    let status = Label::dynamic(|data: &OpdsState, _| {
+        if data.loading {
+            "Caricamento...".into()
+        } else {
+            data.status.clone()
+        }
+    })
+    .with_font(fonts::small)
+    .with_text_color(colors::ON_BACKGROUND)
+    .padding(5.0);
+
+    let entries = Scroll::new(List::new(entry_widget).lens(OpdsState::entries))
+        .vertical()
+        .expand();
+
+    let next = Either::new(
+        |data: &OpdsState, _| data.next.is_some(),
+        RoundedButton::from_text("Pagina successiva")
+            .with_on_click(|ctx, data: &mut OpdsState, _| {
+                if let Some(next) = data.next.clone() {
+                    ctx.submit_command(Command::new(OPDS_OPEN_FEED, next, Target::Auto));
+                }
+            })
+            .disabled_if(|data: &OpdsState, _| data.loading)
+            .with_font(fonts::small)
+            .padding(5.0),
+        SizedBox::empty(),
+    );
+
+    Flex::column()
+        .with_child(toolbar_widget())
+        .with_child(title.align_left())
+        .with_child(status.align_left())
+        .with_flex_child(entries, 1.0)
+        .with_child(next)
+        .background(colors::BACKGROUND)
+        .lens(CrabReaderState::opds)
+}
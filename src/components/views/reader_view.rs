@@ -3,6 +3,7 @@ use druid::{
     Data, Env, FontDescriptor, LensExt, TextAlignment, Widget, WidgetExt, Key, KeyOrValue,
 };
 
+use super::{comic_view::comic_view_widget, scroll_view::continuous_view_widget};
 use crate::{
     models::library::LibrarySelectedBookLens,
     models::rich::custom_lens::{DualPage0Lens, DualPage1Lens, SelectedPageLens},
@@ -17,6 +18,9 @@ pub enum ReaderView {
     SingleEdit,
     Dual,
     DualEdit,
+    Comic,
+    /// the chapters as a single flow of text, read scrolling it
+    Continuous,
 }
 
 impl ReaderView {
@@ -27,6 +31,8 @@ impl ReaderView {
             ReaderView::SingleEdit => single_view_edit_widget(font),
             ReaderView::Dual => dual_view_widget(font),
             ReaderView::DualEdit => dual_view_edit_widget(font),
+            ReaderView::Comic => comic_view_widget(),
+            ReaderView::Continuous => continuous_view_widget(),
         }        
         .boxed()
     }
@@ -37,6 +43,14 @@ impl ReaderView {
             data.reading_state.single_view,
             data.reading_state.is_editing,
         ) {
+            _ if data
+                .library
+                .get_selected_book()
+                .map_or(false, |book| book.is_comic()) =>
+            {
+                ReaderView::Comic
+            }
+            (_, false) if data.reading_state.continuous => ReaderView::Continuous,
             (true, true) => ReaderView::SingleEdit,
             (true, false) => ReaderView::Single,
             (false, true) => ReaderView::DualEdit,
@@ -0,0 +1,400 @@
+use std::collections::{HashMap, HashSet};
+
+use druid::{
+    im::Vector,
+    kurbo::Line,
+    text::TextLayout,
+    widget::{Container, Controller, Scroll},
+    Axis, BoxConstraints, Env, Event, EventCtx, ExtEventSink, LayoutCtx, LifeCycle, LifeCycleCtx,
+    PaintCtx, Rect, RenderContext, Selector, Size, Target, TextAlignment, UpdateCtx, Widget,
+    WidgetExt, WidgetId,
+};
+
+use crate::{
+    models::{book::Book, navigation::Position, rich::rich_text::RichText},
+    traits::{
+        gui::GUILibrary,
+        reader::{BookManagement, BookReading},
+    },
+    utils::{
+        button_functions::follow_scroll, colors, fonts::FONT, rich_text_fn::rebuild_rendered_text,
+    },
+    CrabReaderState,
+};
+
+/// Space between the end of a chapter and the start of the next one
+const CHAPTER_GAP: f64 = 80.0;
+/// Space on the left of the text for the marks of the notes
+const NOTES_MARGIN: f64 = 16.0;
+
+/// Sent to the flow by the thread that splits a chapter next to the current one:
+/// the path of the book, the chapter and its pages
+const CHAPTER_SPLIT: Selector<(String, usize, Vector<String>)> =
+    Selector::new("continuous-view.chapter-split");
+
+/// A page of the flow, the pages follow each other without breaks
+struct FlowPage {
+    position: Position,
+    /// offset in the text of the chapter where the page starts
+    start: usize,
+    len: usize,
+    layout: TextLayout<RichText>,
+    top: f64,
+    has_notes: bool,
+}
+
+impl FlowPage {
+    fn height(&self) -> f64 {
+        self.layout.size().height
+    }
+
+    fn bottom(&self) -> f64 {
+        self.top + self.height()
+    }
+}
+
+/// The current chapter and the ones before and after it as a single column of text,
+/// read scrolling it. The chapters next to the current one are split in background
+/// and added to the flow when they are ready
+struct ChapterFlow {
+    /// book and chapter the flow is built around, with the pages of the chapter to notice edits
+    built: Option<(String, usize, Vector<String>)>,
+    /// pages of the chapters around the current one
+    cache: HashMap<usize, Vector<String>>,
+    /// chapters being split in background
+    splitting: HashSet<usize>,
+    pages: Vec<FlowPage>,
+    /// page at the top of the view
+    top: Option<Position>,
+    /// chapter and offset to bring to the top of the view once the flow is laid out
+    anchor: Option<(usize, usize)>,
+    /// vertical position of the anchor, to scroll to
+    scroll_to: Option<f64>,
+}
+
+impl ChapterFlow {
+    fn new() -> Self {
+        Self {
+            built: None,
+            cache: HashMap::new(),
+            splitting: HashSet::new(),
+            pages: vec![],
+            top: None,
+            anchor: None,
+            scroll_to: None,
+        }
+    }
+
+    fn needs_build(&self, book: &Book) -> bool {
+        self.built.as_ref().map_or(true, |(path, chapter, pages)| {
+            *path != book.get_path()
+                || *chapter != book.get_chapter_number()
+                || !pages.ptr_eq(book.get_chapter_pages())
+        })
+    }
+
+    /// Chapters shown around the current one
+    fn chapters(book: &Book) -> (usize, usize) {
+        let chapter = book.get_chapter_number();
+        let last = (chapter + 1).min(book.get_number_of_chapters().saturating_sub(1));
+        (chapter.saturating_sub(1), last)
+    }
+
+    fn build(&mut self, book: &Book, sink: ExtEventSink, id: WidgetId) {
+        let path = book.get_path();
+        let chapter = book.get_chapter_number();
+        if self
+            .built
+            .as_ref()
+            .map_or(true, |(built, _, _)| *built != path)
+        {
+            self.cache.clear();
+            self.splitting.clear();
+        }
+        let (first, last) = Self::chapters(book);
+        self.cache
+            .retain(|cached, _| (first..=last).contains(cached));
+        // the current chapter may have been edited, its pages are the ones of the book
+        self.cache.insert(chapter, book.get_chapter_pages().clone());
+
+        for chapter in first..=last {
+            if self.cache.contains_key(&chapter) || !self.splitting.insert(chapter) {
+                continue;
+            }
+            let sink = sink.clone();
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let split = (
+                    path.clone(),
+                    chapter,
+                    Book::get_pages_of_chapter(&path, chapter),
+                );
+                let _ = sink.submit_command(CHAPTER_SPLIT, split, Target::Widget(id));
+            });
+        }
+        self.add_pages(book);
+        self.built = Some((path, chapter, book.get_chapter_pages().clone()));
+    }
+
+    /// Lays out again the pages of the chapters that have been split
+    fn add_pages(&mut self, book: &Book) {
+        let (first, last) = Self::chapters(book);
+        self.pages = vec![];
+        for chapter in first..=last {
+            let Some(pages) = self.cache.get(&chapter) else {
+                continue;
+            };
+            let mut start = 0;
+            for (page, text) in pages.iter().enumerate() {
+                let mut layout = TextLayout::new();
+                layout.set_text(rebuild_rendered_text(text));
+                layout.set_font(FONT);
+                layout.set_text_color(colors::ON_BACKGROUND);
+                layout.set_text_alignment(TextAlignment::Justified);
+                self.pages.push(FlowPage {
+                    position: Position::new(chapter, page),
+                    start,
+                    len: text.len(),
+                    layout,
+                    top: 0.0,
+                    has_notes: false,
+                });
+                start += text.len();
+            }
+        }
+        self.update_notes(book);
+    }
+
+    /// Marks the pages with notes, returns true if a mark has changed
+    fn update_notes(&mut self, book: &Book) -> bool {
+        let notes = book.get_notes();
+        let mut changed = false;
+        for page in self.pages.iter_mut() {
+            let has_notes = notes.has_notes(page.position.chapter, page.position.page);
+            changed |= page.has_notes != has_notes;
+            page.has_notes = has_notes;
+        }
+        changed
+    }
+
+    /// Shows again the current position of the book, after it has been moved without scrolling
+    fn anchor_to(&mut self, book: &Book, saved_offset: usize) {
+        let offset = book.get_text_offset(saved_offset);
+        self.anchor = Some((book.get_chapter_number(), offset));
+        self.top = Some(book.get_position());
+    }
+
+    /// Vertical position of an offset in the text of a chapter
+    fn y_of(&self, chapter: usize, offset: usize) -> Option<f64> {
+        let mut pages = self
+            .pages
+            .iter()
+            .filter(|page| page.position.chapter == chapter);
+        let page = pages
+            .clone()
+            .find(|page| offset < page.start + page.len)
+            .or_else(|| pages.next_back())?;
+        let fraction = offset.saturating_sub(page.start) as f64 / page.len.max(1) as f64;
+        Some(page.top + page.height() * fraction.min(1.0))
+    }
+
+    /// Page and offset in the text of its chapter at a vertical position
+    fn position_at(&self, y: f64) -> Option<(Position, usize)> {
+        let page = self
+            .pages
+            .iter()
+            .find(|page| y < page.bottom())
+            .or_else(|| self.pages.last())?;
+        let fraction = if page.height() > 0.0 {
+            ((y - page.top) / page.height()).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        Some((
+            page.position,
+            page.start + (page.len as f64 * fraction) as usize,
+        ))
+    }
+
+    /// The flow is being laid out or scrolled around the text to show
+    fn is_moving(&self) -> bool {
+        self.anchor.is_some() || self.scroll_to.is_some()
+    }
+}
+
+impl Widget<CrabReaderState> for ChapterFlow {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut CrabReaderState, _: &Env) {
+        let Event::Command(cmd) = event else {
+            return;
+        };
+        let Some((path, chapter, pages)) = cmd.get(CHAPTER_SPLIT) else {
+            return;
+        };
+        ctx.set_handled();
+        let Some(book) = data.library.get_selected_book() else {
+            return;
+        };
+        // the book may have been closed meanwhile
+        if self
+            .built
+            .as_ref()
+            .map_or(true, |(built, _, _)| built != path)
+        {
+            return;
+        }
+        self.splitting.remove(chapter);
+        let (first, last) = Self::chapters(book);
+        if !(first..=last).contains(chapter) {
+            return;
+        }
+        self.cache.insert(*chapter, pages.clone());
+        // the text read stays at the top of the view
+        self.add_pages(book);
+        self.anchor_to(book, data.reading_state.scroll_offset);
+        ctx.request_layout();
+        ctx.request_anim_frame();
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &CrabReaderState,
+        _: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            let Some(book) = data.library.get_selected_book() else {
+                return;
+            };
+            self.build(book, ctx.get_external_handle(), ctx.widget_id());
+            self.anchor_to(book, data.reading_state.scroll_offset);
+            ctx.request_anim_frame();
+        }
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        _: &CrabReaderState,
+        data: &CrabReaderState,
+        _: &Env,
+    ) {
+        let Some(book) = data.library.get_selected_book() else {
+            return;
+        };
+        let built = self.needs_build(book);
+        if built {
+            self.build(book, ctx.get_external_handle(), ctx.widget_id());
+            ctx.request_layout();
+        } else if self.update_notes(book) {
+            ctx.request_paint();
+        }
+        // the position has changed without scrolling, e.g. from the chapter list,
+        // or the chapters around the text read are new
+        if built || self.top != Some(book.get_position()) {
+            self.anchor_to(book, data.reading_state.scroll_offset);
+            ctx.request_layout();
+            ctx.request_anim_frame();
+        }
+        if self
+            .pages
+            .iter_mut()
+            .any(|page| page.layout.needs_rebuild_after_update(ctx))
+        {
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _: &CrabReaderState,
+        env: &Env,
+    ) -> Size {
+        let width = bc.max().width;
+        let mut top = 0.0;
+        let mut chapter = None;
+        for page in self.pages.iter_mut() {
+            if chapter.map_or(false, |chapter| chapter != page.position.chapter) {
+                top += CHAPTER_GAP;
+            }
+            chapter = Some(page.position.chapter);
+            page.layout.set_wrap_width(width - NOTES_MARGIN);
+            page.layout.rebuild_if_needed(ctx.text(), env);
+            page.top = top;
+            top += page.height();
+        }
+        if let Some((chapter, offset)) = self.anchor.take() {
+            self.scroll_to = self.y_of(chapter, offset);
+        }
+        bc.constrain((width, top))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _: &CrabReaderState, env: &Env) {
+        let visible = ctx.region().bounding_box();
+        let width = ctx.size().width;
+        let mark = env.get(colors::PRIMARY);
+        let separator = env.get(colors::ON_BACKGROUND);
+        for page in self.pages.iter() {
+            if page.bottom() < visible.y0 || page.top > visible.y1 {
+                continue;
+            }
+            page.layout.draw(ctx, (NOTES_MARGIN, page.top));
+            // the notes are about the whole page, their mark is where it starts
+            if page.has_notes {
+                let rect = Rect::new(2.0, page.top, 6.0, page.top + 20.0);
+                ctx.fill(rect.to_rounded_rect(2.0), &mark);
+            }
+        }
+        for pages in self.pages.windows(2) {
+            if pages[0].position.chapter != pages[1].position.chapter {
+                let y = (pages[0].bottom() + pages[1].top) / 2.0;
+                let line = Line::new((width * 0.3, y), (width * 0.7, y));
+                ctx.stroke(line, &separator, 1.0);
+            }
+        }
+    }
+}
+
+/// Scrolls to the text to show once the flow is laid out
+/// and follows the text at the top of the view while scrolling
+struct FlowController;
+
+impl Controller<CrabReaderState, Scroll<CrabReaderState, ChapterFlow>> for FlowController {
+    fn event(
+        &mut self,
+        child: &mut Scroll<CrabReaderState, ChapterFlow>,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut CrabReaderState,
+        env: &Env,
+    ) {
+        child.event(ctx, event, data, env);
+        if let Event::AnimFrame(_) = event {
+            if child.child().anchor.is_some() {
+                // not laid out yet
+                ctx.request_anim_frame();
+            } else if let Some(y) = child.child_mut().scroll_to.take() {
+                child.scroll_to_on_axis(ctx, Axis::Vertical, y);
+            }
+        }
+        if child.child().is_moving() {
+            return;
+        }
+        let Some((position, offset)) = child.child().position_at(child.offset().y) else {
+            return;
+        };
+        child.child_mut().top = Some(position);
+        follow_scroll(data, position, offset);
+    }
+}
+
+/// Continuous view of the text: the chapters are read scrolling them, without pages
+pub fn continuous_view_widget() -> Container<CrabReaderState> {
+    let inner = Scroll::new(ChapterFlow::new())
+        .vertical()
+        .controller(FlowController);
+
+    Container::new(inner)
+}
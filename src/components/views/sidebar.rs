@@ -35,6 +35,24 @@ impl Sidebar {
 fn left_sidebar_widget() -> Flex<CrabReaderState> {
     let views_btn = ReaderBtn::ViewsSwitch.button();
 
+    // scaling and reading order make sense only for comics,
+    // the continuous scroll only for text
+    let comic_btns = Either::new(
+        |data: &CrabReaderState, _env| {
+            data.library
+                .get_selected_book()
+                .map_or(false, |book| book.is_comic())
+        },
+        Flex::column()
+            .with_default_spacer()
+            .with_child(ReaderBtn::ComicFit.button())
+            .with_default_spacer()
+            .with_child(ReaderBtn::ComicDirection.button()),
+        Flex::column()
+            .with_default_spacer()
+            .with_child(ReaderBtn::ContinuousSwitch.button()),
+    );
+
     let btn = RoundedButton::dynamic(|data: &ReadingState, _env: &_| {
         if !data.sidebar_open {
             "Apri selezione capitoli".into()
@@ -62,6 +80,7 @@ fn left_sidebar_widget() -> Flex<CrabReaderState> {
 
     Flex::column()
         .with_child(views_btn)
+        .with_child(comic_btns)
         .with_default_spacer()
         .with_child(btn)
         .with_default_spacer()
@@ -0,0 +1,117 @@
+use druid::{
+    commands::SHOW_SAVE_PANEL,
+    widget::{CrossAxisAlignment, Flex, Label, LineBreaking, List, Scroll},
+    Command, FileDialogOptions, FileSpec, Target, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::{
+        command::Trigger,
+        stats::{BookStats, StatsRow, StatsState},
+    },
+    utils::{colors, fonts},
+    CrabReaderState, ROUND_FACTR,
+};
+
+fn title(text: &str) -> impl Widget<StatsState> {
+    Label::new(text)
+        .with_font(fonts::medium)
+        .with_text_color(colors::ON_BACKGROUND)
+        .padding((0.0, 10.0, 0.0, 5.0))
+}
+
+fn row_widget() -> impl Widget<StatsRow> {
+    Flex::row()
+        .with_flex_child(
+            Label::dynamic(|data: &StatsRow, _| data.label.clone())
+                .with_font(fonts::small)
+                .with_text_color(colors::ON_BACKGROUND)
+                .expand_width(),
+            1.0,
+        )
+        .with_child(
+            Label::dynamic(|data: &StatsRow, _| data.value.clone())
+                .with_font(fonts::small)
+                .with_text_color(colors::ON_BACKGROUND),
+        )
+        .padding((0.0, 2.0))
+}
+
+fn book_widget() -> impl Widget<BookStats> {
+    Flex::column()
+        .with_child(
+            Label::dynamic(|data: &BookStats, _| data.title.clone())
+                .with_font(fonts::small)
+                .with_text_color(colors::ON_BACKGROUND)
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_child(
+            Label::dynamic(|data: &BookStats, _| data.summary.clone())
+                .with_font(fonts::xsmall)
+                .with_text_color(colors::ON_BACKGROUND),
+        )
+        .with_spacer(5.0)
+        .with_child(List::new(row_widget).lens(BookStats::timeline))
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .padding(10.0)
+        .background(colors::BACKGROUND_VARIANT)
+        .rounded(ROUND_FACTR)
+        .padding((0.0, 5.0))
+}
+
+/// Window with the reading time, the pages and the speed, by day, by week and by book
+pub fn stats_window_widget() -> impl Widget<CrabReaderState> {
+    let export = RoundedButton::from_text("Esporta CSV")
+        .with_on_click(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::EXPORTSTATS;
+            let options = FileDialogOptions::new()
+                .allowed_types(vec![FileSpec::new("CSV", &["csv"])])
+                .default_name("statistiche.csv")
+                .title("Esporta le sessioni di lettura");
+            ctx.submit_command(Command::new(SHOW_SAVE_PANEL, options, Target::Auto));
+        })
+        .with_font(fonts::small);
+
+    let summary = Label::dynamic(|data: &StatsState, _| data.summary.clone())
+        .with_font(fonts::small)
+        .with_text_color(colors::ON_BACKGROUND);
+    let status = Label::dynamic(|data: &StatsState, _| data.status.clone())
+        .with_font(fonts::xsmall)
+        .with_text_color(colors::ON_BACKGROUND)
+        .with_line_break_mode(LineBreaking::WordWrap);
+
+    let periods = Flex::row()
+        .with_flex_child(
+            Flex::column()
+                .with_child(title("Ultimi 14 giorni"))
+                .with_child(List::new(row_widget).lens(StatsState::days))
+                .cross_axis_alignment(CrossAxisAlignment::Start),
+            1.0,
+        )
+        .with_spacer(20.0)
+        .with_flex_child(
+            Flex::column()
+                .with_child(title("Ultime 8 settimane"))
+                .with_child(List::new(row_widget).lens(StatsState::weeks))
+                .cross_axis_alignment(CrossAxisAlignment::Start),
+            1.0,
+        )
+        .cross_axis_alignment(CrossAxisAlignment::Start);
+
+    let content = Flex::column()
+        .with_child(summary)
+        .with_child(status.expand_width())
+        .with_child(periods)
+        .with_child(title("Libri"))
+        .with_child(List::new(book_widget).lens(StatsState::books))
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .padding(5.0)
+        .lens(CrabReaderState::stats);
+
+    Flex::column()
+        .with_child(Flex::row().with_flex_spacer(1.0).with_child(export))
+        .with_flex_child(Scroll::new(content).vertical().expand(), 1.0)
+        .padding(10.0)
+        .background(colors::BACKGROUND)
+}
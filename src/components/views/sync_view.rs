@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use druid::{
+    widget::{CrossAxisAlignment, Either, Flex, Label, LineBreaking, List, Scroll},
+    Command, Target, Widget, WidgetExt,
+};
+
+use crate::{
+    components::buttons::rbtn::RoundedButton,
+    models::sync::SYNC_RESOLVE_CONFLICT,
+    utils::{colors, fonts, sync_log::Conflict},
+    CrabReaderState,
+};
+
+/// Beginning of a version of the chapter, enough to tell the two apart
+fn preview(text: &Option<String>) -> String {
+    let Some(text) = text else {
+        return "(testo originale)".into();
+    };
+    let mut preview = text.chars().take(300).collect::<String>();
+    if preview.len() < text.len() {
+        preview.push('…');
+    }
+    preview
+}
+
+fn version_widget(
+    title: &str,
+    text: impl Fn(&Conflict) -> String + 'static,
+    keep_remote: bool,
+) -> impl Widget<Conflict> {
+    let keep = RoundedButton::from_text("Mantieni questa versione")
+        .with_on_click(move |ctx, data: &mut Conflict, _| {
+            ctx.submit_command(Command::new(
+                SYNC_RESOLVE_CONFLICT,
+                (data.clone(), keep_remote),
+                Target::Auto,
+            ));
+        })
+        .with_font(fonts::small);
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            Label::new(title)
+                .with_font(fonts::bold::small)
+                .with_text_color(colors::ON_BACKGROUND),
+        )
+        .with_child(
+            Label::dynamic(move |data: &Conflict, _| text(data))
+                .with_font(fonts::xsmall)
+                .with_text_color(colors::ON_BACKGROUND)
+                .with_line_break_mode(LineBreaking::WordWrap),
+        )
+        .with_spacer(5.0)
+        .with_child(keep)
+}
+
+fn conflict_widget() -> impl Widget<Conflict> {
+    let title = Label::dynamic(|data: &Conflict, _| {
+        let book = Path::new(&data.book_path)
+            .file_name()
+            .map_or(data.book_path.clone(), |name| {
+                name.to_string_lossy().to_string()
+            });
+        format!(
+            "{}, capitolo {} (modificato su {})",
+            book, data.chapter, data.device
+        )
+    })
+    .with_font(fonts::bold::medium)
+    .with_text_color(colors::ON_BACKGROUND)
+    .with_line_break_mode(LineBreaking::WordWrap);
+
+    let versions = Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_flex_child(
+            version_widget(
+                "Questo dispositivo",
+                |data| preview(&data.local_text),
+                false,
+            ),
+            1.0,
+        )
+        .with_spacer(10.0)
+        .with_flex_child(
+            version_widget("Altro dispositivo", |data| preview(&data.remote_text), true),
+            1.0,
+        );
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(title)
+        .with_spacer(5.0)
+        .with_child(versions)
+        .padding(10.0)
+}
+
+/// Window with the chapters edited both here and on another device,
+/// the user chooses which text to keep
+pub fn sync_conflicts_widget() -> impl Widget<CrabReaderState> {
+    let conflicts = Scroll::new(List::new(conflict_widget).lens(CrabReaderState::sync_conflicts))
+        .vertical()
+        .expand();
+
+    Either::new(
+        |data: &CrabReaderState, _| data.sync_conflicts.is_empty(),
+        Label::new("Non ci sono conflitti da risolvere")
+            .with_text_color(colors::ON_BACKGROUND)
+            .center(),
+        conflicts,
+    )
+    .background(colors::BACKGROUND)
+}
@@ -1,28 +1,52 @@
 use crate::models::book::Book;
 use crate::utils::colors;
 use components::book::book_details::BookDetails;
+use components::book::book_listing::COLUMN_WIDTHS;
 use components::buttons::{rbtn::RoundedButton, reader_btns::ReaderBtn};
 use components::library::cover_library::CoverLibrary;
 use components::library::listing_library::ListLibrary;
+use components::library::shelves::{bulk_assign_bar, shelves_sidebar};
 use druid::commands::SHOW_OPEN_PANEL;
+use components::views::book_actions_view::undo_bar;
+use components::views::goals_view::goals_bar;
+use components::views::navigation_view::progress_bar;
+use models::backup::BackupState;
+use models::book_actions::UndoState;
+use models::metadata::MetadataEditorState;
 use models::command::Trigger;
+use models::duplicates::DuplicatesState;
+use models::goals::{GoalsState, GOALS_CHECK, GOALS_CHECK_INTERVAL};
 use models::library::{Library, LibraryFilterLens, SortBy};
+use models::reading::{PeriodFilter, ReadingStatus};
+use models::stats::{ReadingSession, StatsState};
+use models::kosync::KoSyncState;
+use models::opds::OpdsState;
+use models::sync::{SYNC_INTERVAL, SYNC_MERGE};
 
 use components::views::reader_view::{current_chapter_widget, ReaderView};
 use components::views::sidebar::Sidebar;
-use druid::widget::{Either, Flex, Label, Scroll, SizedBox, ViewSwitcher};
+use druid::im::Vector;
+use druid::widget::{CrossAxisAlignment, Either, Flex, Label, Scroll, SizedBox, ViewSwitcher};
 use druid::{
-    AppLauncher, Command, Data, Env, FileDialogOptions, FileSpec, FontDescriptor, Lens,
+    AppLauncher, Command, Data, Env, FontDescriptor, Lens,
     PlatformError, Selector, Target, UnitPoint, Widget, WidgetExt, WindowDesc,
 };
 
 use once_cell::sync::Lazy;
+use std::path::PathBuf;
 use std::rc::Rc;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use traits::gui::{GUIBook, GUILibrary};
 use utils::colors::{update_theme, CrabTheme};
 use utils::envmanager::MyEnv;
 use utils::fonts::{update_font_family, FONT};
+use utils::kosync_server::KoSyncServer;
+use utils::opds_server::{OpdsCatalogController, OpdsServer};
+use utils::sync_log::{self, Conflict};
+use utils::button_functions::ChaptersInfo;
+use utils::{trash, webdav_backup};
+use utils::{book_import, dir_watcher};
 use utils::{ctx_menu, delegates, fonts};
 
 mod components;
@@ -32,9 +56,11 @@ mod utils;
 
 pub const ENTERING_READING_MODE: Selector<()> = Selector::new("reading-mode.on");
 pub const LEAVING_READING_MODE: Selector<()> = Selector::new("reading-mode.off");
+pub const CHAPTERS_LOADED: Selector<ChaptersInfo> = Selector::new("reading-mode.chapters-loaded");
 const UP_ARROW: &str = " ↑";
 const DOWN_ARROW: &str = " ↓";
 const ROUND_FACTR: f64 = 10.0;
+const STAR: &str = "★";
 
 //Create a global ENV variable
 #[allow(dead_code)]
@@ -50,6 +76,27 @@ pub struct ReadingState {
     text_1: String,
     notes: String,
     is_editing_notes: bool,
+    fit_page: bool,
+    right_to_left: bool,
+    /// length of each chapter of the open book, empty for comics
+    chapter_chars: Arc<Vec<usize>>,
+    /// characters per minute, measured on the open book or on all the books
+    reading_speed: Option<f64>,
+    /// first and last page of each chapter of the open book
+    pages_per_chapter: Arc<Vec<(usize, usize)>>,
+    /// titles of the chapters from the table of contents, empty if unknown
+    chapter_titles: Arc<Vec<String>>,
+    /// part of the book under the progress bar while it is dragged
+    scrub: Option<f64>,
+    /// page typed in the go-to window
+    go_to: String,
+    /// pages of the printed edition typed in the go-to window
+    printed_pages: String,
+    go_to_status: String,
+    /// the text of the chapters is read scrolling it instead of by pages
+    continuous: bool,
+    /// offset in the text of the chapter at the top of the continuous view
+    scroll_offset: usize,
 }
 
 impl ReadingState {
@@ -69,6 +116,11 @@ impl ReadingState {
         self.text_0 = String::default();
         self.text_1 = String::default();
         self.notes = String::default();
+        self.chapter_chars = Arc::default();
+        self.reading_speed = None;
+        self.pages_per_chapter = Arc::default();
+        self.chapter_titles = Arc::default();
+        self.scrub = None;
     }
 }
 
@@ -83,6 +135,18 @@ impl Default for ReadingState {
             text_0: String::default(),
             text_1: String::default(),
             notes: String::default(),
+            fit_page: true,
+            right_to_left: false,
+            chapter_chars: Arc::default(),
+            reading_speed: None,
+            pages_per_chapter: Arc::default(),
+            chapter_titles: Arc::default(),
+            scrub: None,
+            go_to: String::default(),
+            printed_pages: String::default(),
+            go_to_status: String::default(),
+            continuous: false,
+            scroll_offset: 0,
         }
     }
 }
@@ -104,6 +168,23 @@ pub struct CrabReaderState {
     pub theme: CrabTheme,
     pub paint_shadows: bool,
     pub font: FontDescriptor,
+    opds: OpdsState,
+    /// server that shares the library, it is running when it is set
+    opds_server: Option<Arc<OpdsServer>>,
+    kosync: KoSyncState,
+    /// embedded sync server, it is running when it is set
+    kosync_server: Option<Arc<KoSyncServer>>,
+    /// chapters edited both here and on another device
+    sync_conflicts: Vector<Conflict>,
+    backup: BackupState,
+    metadata_editor: MetadataEditorState,
+    /// last action on a book, while it can be undone
+    undo: UndoState,
+    duplicates: DuplicatesState,
+    /// time spent reading the open book
+    session: Option<ReadingSession>,
+    stats: StatsState,
+    goals: GoalsState,
 }
 
 impl Default for CrabReaderState {
@@ -122,6 +203,18 @@ impl Default for CrabReaderState {
             theme: CrabTheme::from(theme),
             paint_shadows: shadows,
             font: font,
+            opds: OpdsState::default(),
+            opds_server: None,
+            kosync: KoSyncState::default(),
+            kosync_server: None,
+            sync_conflicts: sync_log::load_conflicts().into(),
+            backup: BackupState::default(),
+            metadata_editor: MetadataEditorState::default(),
+            undo: UndoState::default(),
+            duplicates: DuplicatesState::default(),
+            session: None,
+            stats: StatsState::default(),
+            goals: GoalsState::default(),
         }
     }
 }
@@ -222,6 +315,37 @@ fn completion_sorter_btn() -> impl Widget<Library<Book>> {
     .padding(5.0)
 }
 
+/// Button that sorts the books by a key, a second click reverses the order
+fn sorter_btn(name: &'static str, by: SortBy, rev: SortBy) -> impl Widget<Library<Book>> {
+    let (label_by, label_rev) = (by.clone(), rev.clone());
+    let (toggle_by, toggle_rev) = (by.clone(), rev.clone());
+    RoundedButton::dynamic(move |data: &Library<Book>, _env: &Env| {
+        let order = data.get_sort_order();
+        let arrow = if order == label_by {
+            DOWN_ARROW
+        } else if order == label_rev {
+            UP_ARROW
+        } else {
+            ""
+        };
+        format!("{}{}", name, arrow)
+    })
+    .with_font(fonts::medium)
+    .with_on_click(move |ctx, data: &mut Library<Book>, _| {
+        if data.get_sort_order() == by {
+            data.sort_by(rev.clone());
+        } else {
+            data.sort_by(by.clone());
+        }
+        ctx.request_update();
+    })
+    .with_toggle(move |data: &Library<Book>, _env: &Env| {
+        let order = data.get_sort_order();
+        order == toggle_by || order == toggle_rev
+    })
+    .padding(5.0)
+}
+
 fn picker_sort_by() -> impl Widget<Library<Book>> {
     let label = Label::new("Ordina")
         .with_text_color(colors::ON_BACKGROUND)
@@ -229,11 +353,26 @@ fn picker_sort_by() -> impl Widget<Library<Book>> {
         .center()
         .expand_width();
 
-    Flex::row()
+    let sorters = Flex::row()
         .with_flex_child(label, 1.0)
         .with_flex_child(completion_sorter_btn(), 1.0)
         .with_flex_child(author_sorter_btn(), 1.0)
-        .with_flex_child(title_sorter_btn(), 1.0)
+        .with_flex_child(title_sorter_btn(), 1.0);
+
+    let reading_sorters = Flex::row()
+        .with_flex_child(sorter_btn("Stato", SortBy::Status, SortBy::StatusRev), 1.0)
+        .with_flex_child(sorter_btn("Aggiunto", SortBy::Added, SortBy::AddedRev), 1.0)
+        .with_flex_child(
+            sorter_btn("Aperto", SortBy::LastOpened, SortBy::LastOpenedRev),
+            1.0,
+        )
+        .with_flex_child(sorter_btn("Finito", SortBy::Finished, SortBy::FinishedRev), 1.0)
+        .with_flex_child(sorter_btn("Voto", SortBy::Rating, SortBy::RatingRev), 1.0)
+        .with_flex_child(sorter_btn("Serie", SortBy::Series, SortBy::SeriesRev), 1.0);
+
+    Flex::column()
+        .with_child(sorters)
+        .with_child(reading_sorters)
         .padding(druid::Insets::uniform_xy(15.0, 5.0))
         .background(colors::BACKGROUND_VARIANT)
         .rounded(ROUND_FACTR)
@@ -263,6 +402,108 @@ fn picker_filter_by() -> impl Widget<Library<Book>> {
         .expand_width()
 }
 
+/// Filters on the status, the rating and the dates of the books,
+/// every click shows the next choice
+fn picker_filter_reading() -> impl Widget<Library<Book>> {
+    let status = RoundedButton::dynamic(|data: &Library<Book>, _env: &Env| {
+        let status = data
+            .get_status_filter()
+            .map_or("Tutti", |status| status.label());
+        format!("Stato: {}", status)
+    })
+    .with_on_click(|ctx, data: &mut Library<Book>, _| {
+        let next = match data.get_status_filter() {
+            None => Some(ReadingStatus::ALL[0]),
+            Some(status) if status == *ReadingStatus::ALL.last().unwrap() => None,
+            Some(status) => Some(status.next()),
+        };
+        data.set_status_filter(next);
+        ctx.request_layout();
+    })
+    .with_toggle(|data: &Library<Book>, _env: &Env| data.get_status_filter().is_some())
+    .with_font(fonts::medium)
+    .padding(5.0);
+
+    let rating = RoundedButton::dynamic(|data: &Library<Book>, _env: &Env| {
+        match data.get_rating_filter() {
+            0 => "Voto: qualsiasi".to_string(),
+            5 => format!("Voto: {}", STAR.repeat(5)),
+            stars => format!("Voto: {} o più", STAR.repeat(stars as usize)),
+        }
+    })
+    .with_on_click(|ctx, data: &mut Library<Book>, _| {
+        data.set_rating_filter((data.get_rating_filter() + 1) % 6);
+        ctx.request_layout();
+    })
+    .with_toggle(|data: &Library<Book>, _env: &Env| data.get_rating_filter() > 0)
+    .with_font(fonts::medium)
+    .padding(5.0);
+
+    let period = RoundedButton::dynamic(|data: &Library<Book>, _env: &Env| {
+        data.get_period_filter().label().to_string()
+    })
+    .with_on_click(|ctx, data: &mut Library<Book>, _| {
+        data.set_period_filter(data.get_period_filter().next());
+        ctx.request_layout();
+    })
+    .with_toggle(|data: &Library<Book>, _env: &Env| {
+        data.get_period_filter() != PeriodFilter::Any
+    })
+    .with_font(fonts::medium)
+    .padding(5.0);
+
+    // the volumes of a series are shown as one stack, on top the first one still to finish
+    let group_series = RoundedButton::from_text("Raggruppa serie")
+        .with_on_click(|ctx, data: &mut Library<Book>, _| {
+            data.toggle_series_grouping();
+            ctx.request_layout();
+        })
+        .with_toggle(|data: &Library<Book>, _env: &Env| data.is_grouped_by_series())
+        .with_font(fonts::medium)
+        .padding(5.0);
+
+    // the archived books are shown instead of the other ones
+    let archived = RoundedButton::from_text("Archivio")
+        .with_on_click(|ctx, data: &mut Library<Book>, _| {
+            data.toggle_archived_view();
+            ctx.request_layout();
+        })
+        .with_toggle(|data: &Library<Book>, _env: &Env| data.shows_archived())
+        .with_font(fonts::medium)
+        .padding(5.0);
+
+    Flex::row()
+        .with_flex_child(status, 1.0)
+        .with_flex_child(rating, 1.0)
+        .with_flex_child(period, 1.5)
+        .with_flex_child(group_series, 1.0)
+        .with_flex_child(archived, 1.0)
+        .padding(druid::Insets::uniform_xy(15.0, 5.0))
+        .background(colors::BACKGROUND_VARIANT)
+        .rounded(ROUND_FACTR)
+        .expand_width()
+}
+
+/// Titles of the columns of the list view, a click sorts the books by that column
+fn list_header() -> impl Widget<Library<Book>> {
+    let [title, authors, fav, status, rating, opened, progress] = COLUMN_WIDTHS;
+    Flex::row()
+        .with_flex_child(sorter_btn("Titolo", SortBy::Title, SortBy::TitleRev), title)
+        .with_flex_child(sorter_btn("Autori", SortBy::Author, SortBy::AuthorRev), authors)
+        .with_flex_spacer(fav)
+        .with_flex_child(sorter_btn("Stato", SortBy::Status, SortBy::StatusRev), status)
+        .with_flex_child(sorter_btn("Voto", SortBy::Rating, SortBy::RatingRev), rating)
+        .with_flex_child(
+            sorter_btn("Aperto", SortBy::LastOpened, SortBy::LastOpenedRev),
+            opened,
+        )
+        .with_flex_child(
+            sorter_btn("Progresso", SortBy::PercRead, SortBy::PercReadRev),
+            progress,
+        )
+        .expand_width()
+}
+
 fn picker_controller() -> impl Widget<Library<Book>> {
     let sort_by = picker_sort_by();
     let filter_by = picker_filter_by();
@@ -270,6 +511,8 @@ fn picker_controller() -> impl Widget<Library<Book>> {
         .with_child(sort_by)
         .with_default_spacer()
         .with_child(filter_by)
+        .with_default_spacer()
+        .with_child(picker_filter_reading())
 }
 
 fn build_ui() -> impl Widget<CrabReaderState> {
@@ -281,7 +524,7 @@ fn build_ui() -> impl Widget<CrabReaderState> {
             //Trigger a FILE PICKER
             let cmd = Command::new(
                 SHOW_OPEN_PANEL,
-                FileDialogOptions::new().allowed_types(vec![FileSpec::new("Epub", &["epub"])]),
+                book_import::open_books_options(),
                 Target::Auto,
             );
             ctx.request_update();
@@ -295,7 +538,11 @@ fn build_ui() -> impl Widget<CrabReaderState> {
         .rounded(ROUND_FACTR)
         .lens(CrabReaderState::library);
 
-    let library_list = ListLibrary::new().lens(CrabReaderState::library);
+    let library_list = Flex::column()
+        .with_child(list_header())
+        .with_default_spacer()
+        .with_child(ListLibrary::new())
+        .lens(CrabReaderState::library);
 
     let view_either = Either::new(
         |data: &CrabReaderState, _env| data.display_mode == DisplayMode::List,
@@ -305,11 +552,26 @@ fn build_ui() -> impl Widget<CrabReaderState> {
     .background(colors::BACKGROUND_VARIANT)
     .rounded(ROUND_FACTR);
 
+    let sidebar = SizedBox::new(shelves_sidebar())
+        .width(200.0)
+        .lens(CrabReaderState::library);
+    let books = Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(sidebar)
+        .with_default_spacer()
+        .with_flex_child(view_either, 1.0);
+
     let ctls = picker_controller();
     let left_panel = Flex::column()
         .with_child(ctls.lens(CrabReaderState::library))
         .with_default_spacer()
-        .with_child(view_either)
+        .with_child(bulk_assign_bar().lens(CrabReaderState::library))
+        .with_default_spacer()
+        .with_child(undo_bar().lens(CrabReaderState::undo))
+        .with_default_spacer()
+        .with_child(goals_bar().lens(CrabReaderState::goals))
+        .with_default_spacer()
+        .with_child(books)
         .padding(15.0);
     let scroll = Scroll::new(left_panel)
         .vertical()
@@ -370,7 +632,7 @@ fn vs_child_builder(mode: &VS, _: &CrabReaderState, _: &Env) -> Box<dyn Widget<C
 }
 
 fn get_viewswitcher() -> impl Widget<CrabReaderState> {
-    ViewSwitcher::new(vs_child_picker, vs_child_builder)
+    ViewSwitcher::new(vs_child_picker, vs_child_builder).controller(OpdsCatalogController)
 }
 
 fn read_book_ui() -> impl Widget<CrabReaderState> {
@@ -411,7 +673,15 @@ fn read_book_ui() -> impl Widget<CrabReaderState> {
         .width(180.0)
         .height(30.0);
 
-    let header_btns = Flex::row().with_child(edit_btn).align_right();
+    let header_btns = Flex::row()
+        .with_child(ReaderBtn::HistoryBack.button())
+        .with_default_spacer()
+        .with_child(ReaderBtn::HistoryForward.button())
+        .with_default_spacer()
+        .with_child(ReaderBtn::Furthest.button())
+        .with_default_spacer()
+        .with_child(edit_btn)
+        .align_right();
 
     let header = Flex::row()
         .with_flex_child(leave_btn, 1.0)
@@ -423,14 +693,19 @@ fn read_book_ui() -> impl Widget<CrabReaderState> {
             .with_child(undo_changes_btn)
             .with_default_spacer()
             .with_child(save_changes_btn),
-        Flex::row()
-            .with_flex_spacer(1.0)
-            .with_child(back_btn)
-            .with_default_spacer()
-            .with_child(container_page_number)
+        Flex::column()
+            .with_child(progress_bar())
             .with_default_spacer()
-            .with_child(next_btn)
-            .with_flex_spacer(1.0),
+            .with_child(
+                Flex::row()
+                    .with_flex_spacer(1.0)
+                    .with_child(back_btn)
+                    .with_default_spacer()
+                    .with_child(container_page_number)
+                    .with_default_spacer()
+                    .with_child(next_btn)
+                    .with_flex_spacer(1.0),
+            ),
     )
     .center();
 
@@ -447,8 +722,15 @@ fn read_book_ui() -> impl Widget<CrabReaderState> {
 }
 
 fn main() -> Result<(), PlatformError> {
+    // the changes made on the other devices are merged before the books are loaded
+    let sync_dir = MYENV.lock().unwrap().sync_dir.clone();
+    sync_log::set_sync_dir(sync_dir.map(PathBuf::from));
+    sync_log::merge();
+    // what is left in the trash can no longer be undone
+    trash::empty_trash();
+
     let crab_state = CrabReaderState::default();
-    AppLauncher::with_window(
+    let launcher = AppLauncher::with_window(
         WindowDesc::new(get_viewswitcher().env_scope(|env, data| {
             update_theme(env, data);
             update_font_family(env, data);
@@ -461,7 +743,28 @@ fn main() -> Result<(), PlatformError> {
     .configure_env(|env, _| {
         env.set(FONT, MYENV.lock().unwrap().font.clone());
     })
-    .delegate(delegates::ReadModeDelegate)
-    .launch(crab_state)?;
+    .delegate(delegates::ReadModeDelegate);
+
+    // and periodically while the application is open
+    let sink = launcher.get_external_handle();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(SYNC_INTERVAL));
+        if sink.submit_command(SYNC_MERGE, (), Target::Auto).is_err() {
+            break;
+        }
+    });
+    webdav_backup::start_scheduler(launcher.get_external_handle());
+    // progress towards the reading goals, the first check is at the start
+    let sink = launcher.get_external_handle();
+    std::thread::spawn(move || loop {
+        if sink.submit_command(GOALS_CHECK, (), Target::Auto).is_err() {
+            break;
+        }
+        std::thread::sleep(Duration::from_secs(GOALS_CHECK_INTERVAL));
+    });
+    // books added or removed in the folders while the application is open
+    dir_watcher::start_watcher(launcher.get_external_handle());
+
+    launcher.launch(crab_state)?;
     Ok(())
 }
@@ -0,0 +1,10 @@
+use druid::Selector;
+
+use crate::utils::archive::{ExportReport, ImportReport};
+
+/// Sent by the thread that writes the archive of the library
+pub const ARCHIVE_EXPORTED: Selector<Result<ExportReport, String>> =
+    Selector::new("archive.exported");
+/// Sent by the thread that merges an archive in the library
+pub const ARCHIVE_IMPORTED: Selector<Result<ImportReport, String>> =
+    Selector::new("archive.imported");
@@ -0,0 +1,81 @@
+use druid::{im::Vector, Data, Lens, Selector};
+
+use crate::utils::{
+    opds_server::format_timestamp,
+    webdav_backup::{BackupReport, Restore, Snapshot, WebDavSettings},
+};
+
+/// Opens the window of the WebDAV backup
+pub const OPEN_BACKUP_WINDOW: Selector<()> = Selector::new("backup.open-window");
+/// Saves the endpoint and the interval of the scheduled backups
+pub const BACKUP_SAVE_SETTINGS: Selector<()> = Selector::new("backup.save-settings");
+/// Backs up the library now
+pub const BACKUP_RUN: Selector<()> = Selector::new("backup.run");
+/// Sent when a manual or scheduled backup ends
+pub const BACKUP_DONE: Selector<Result<BackupReport, String>> = Selector::new("backup.done");
+/// Reads the snapshots saved on the server
+pub const BACKUP_LIST: Selector<()> = Selector::new("backup.list");
+/// Sent by the thread that reads the snapshots
+pub const BACKUP_LISTED: Selector<Result<Vec<Snapshot>, String>> = Selector::new("backup.listed");
+/// Restores a snapshot, all of it or only the selected book
+pub const BACKUP_RESTORE: Selector<(String, RestoreScope)> = Selector::new("backup.restore");
+/// Sent by the restore thread with the number of restored files
+pub const BACKUP_RESTORED: Selector<Result<(Restore, usize), String>> =
+    Selector::new("backup.restored");
+
+/// Part of a snapshot chosen by the user, the book is the selected one
+#[derive(Clone, Copy, Debug, PartialEq, Data)]
+pub enum RestoreScope {
+    All,
+    Book,
+    Notes,
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct SnapshotItem {
+    pub name: String,
+    pub label: String,
+}
+
+impl From<&Snapshot> for SnapshotItem {
+    fn from(snapshot: &Snapshot) -> Self {
+        let date = format_timestamp(snapshot.time / 1000)
+            .replace('T', " ")
+            .replace('Z', "");
+        Self {
+            name: snapshot.name.clone(),
+            label: format!("{} ({})", date, snapshot.device),
+        }
+    }
+}
+
+/// State of the backup window
+#[derive(Clone, Data, Lens, Default)]
+pub struct BackupState {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    /// hours between two automatic backups, empty or 0 disables them
+    pub interval: String,
+    pub status: String,
+    pub loading: bool,
+    pub snapshots: Vector<SnapshotItem>,
+}
+
+impl BackupState {
+    pub fn get_settings(&self) -> WebDavSettings {
+        WebDavSettings::new(
+            &self.url,
+            &self.username,
+            &self.password,
+            self.interval.trim().parse().unwrap_or_default(),
+        )
+    }
+
+    pub fn set_settings(&mut self, settings: &WebDavSettings) {
+        self.url = settings.url.clone();
+        self.username = settings.username.clone();
+        self.password = settings.password.clone();
+        self.interval = settings.interval_hours.to_string();
+    }
+}
@@ -4,7 +4,6 @@ use druid::{
     piet::{Error, ImageFormat, PietImage},
     Data, Lens, PaintCtx, RenderContext,
 };
-use epub::doc::EpubDoc;
 use image::io::Reader as ImageReader;
 use std::{
     cell::{Ref, RefCell},
@@ -12,6 +11,7 @@ use std::{
     rc::Rc,
     string::String,
     sync::Arc,
+    time::UNIX_EPOCH,
 };
 
 use crate::{
@@ -20,18 +20,28 @@ use crate::{
         reader::{BookManagement, BookReading},
     },
     utils::{
+        cbz_utils,
         envmanager::FontSize,
         epub_utils,
         epub_utils::{
             calculate_number_of_pages, edit_chapter, get_cumulative_current_page_number,
             split_chapter_in_vec,
         },
-        saveload::{load_data, remove_edited_chapter, save_favorite},
+        collections::Collections,
+        saveload::{
+            load_data, remove_edited_chapter, save_favorite, save_reading_info, save_series,
+        },
     },
     MYENV,
 };
 
-use super::note::BookNotes;
+use super::{
+    metadata::BookMetadata,
+    navigation::{page_at, page_start, NavHistory, Position},
+    note::BookNotes,
+    reading::{self, ReadingInfo, ReadingStatus},
+    series::Series,
+};
 
 const NUMBER_OF_LINES: usize = 8;
 pub const PAGE_WIDTH: f32 = 1000.0;
@@ -50,9 +60,15 @@ pub struct Book {
     selected: bool,
     title: Rc<String>,
     author: Rc<String>,
+    /// all the contributors, with their role if they are not authors
+    contributors: Rc<String>,
+    /// e.g. "Svevo, Italo", used to sort the books by author
+    author_sort: Rc<String>,
     lang: Rc<String>,
     path: Rc<String>,
     is_favorite: bool,
+    /// hidden from the library, its data is kept
+    archived: bool,
     chapter_text_split: Vector<String>,
     description: Rc<String>,
     cover_buffer: Arc<Vec<u8>>,
@@ -61,6 +77,18 @@ pub struct Book {
     cover_image: RefCell<Option<PietImage>>,
     filtered_out: bool,
     notes: BookNotes,
+    is_comic: bool,
+    /// collections that contain the book
+    collections: Vector<String>,
+    tags: Vector<String>,
+    /// the book is part of a multiple selection
+    marked: bool,
+    reading: ReadingInfo,
+    series: Option<Series>,
+    /// number of volumes of the series shown as a single stack, 0 if not grouped
+    stack_size: usize,
+    /// jumps made while reading, to go back to where they started
+    history: NavHistory,
 }
 
 impl Book {
@@ -76,21 +104,32 @@ impl Book {
             selected: false,
             title: Rc::new(String::new()),
             author: e.clone(),
+            contributors: e.clone(),
+            author_sort: e.clone(),
             lang: e.clone(),
             path: e.clone(),
             is_favorite: false,
+            archived: false,
             chapter_text_split: vec![].into(),
             description: e.clone(),
             cover_buffer: vec![].into(),
             cover_image: None.into(),
             filtered_out: true,
             notes: BookNotes::default(),
+            is_comic: false,
+            collections: Vector::new(),
+            tags: Vector::new(),
+            marked: false,
+            reading: ReadingInfo::default(),
+            series: None,
+            stack_size: 0,
+            history: NavHistory::default(),
         }
     }
 
     /// Method that instantiates a new Book from a epub file
-    /// given its path
-    pub fn new(path: impl Into<String>) -> Book {
+    /// given its path, the collections are read once for all the books
+    pub fn new(path: impl Into<String>, shelves: &Collections) -> Book {
         let path = path.into();
         let path_str = path.as_str();
 
@@ -119,12 +158,22 @@ impl Book {
             .unwrap_or(&"false".to_string())
             .parse::<bool>()
             .unwrap();
+        let archived = book_map.get("archived").is_some_and(|x| x == "true");
         let number_of_chapters = book_map
             .get("chapters")
             .map_or(1, |x| x.parse::<usize>().unwrap_or_default());
 
-        let (chapter_number, current_page, _font_size) =
-            load_data(path_str, false).unwrap_or((1, 0, FontSize::SMALL.to_f64()));
+        let saved_data = load_data(path_str, false).ok();
+        // a book with a saved position has already been opened
+        let has_progress = saved_data.is_some();
+        let (mut chapter_number, current_page, _font_size) =
+            saved_data.unwrap_or((1, 0, FontSize::SMALL.to_f64()));
+
+        // a comic has only one chapter, the default one would be out of range
+        let is_comic = cbz_utils::is_cbz(path_str);
+        if is_comic {
+            chapter_number = 0;
+        }
 
         let number_of_pages = match book_map.get("total_pages") {
             Some(x) => x.parse::<usize>().unwrap_or_default(),
@@ -132,6 +181,16 @@ impl Book {
                 .map_or(0, |(x, _)| x as usize),
         };
 
+        // books that have never been opened count as added when their file was last changed
+        let file_time = std::fs::metadata(path_str)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs());
+        let reading = ReadingInfo::from_metadata(&book_map, file_time, has_progress);
+        let series = Series::from_metadata(&book_map);
+        let metadata = BookMetadata::from_metadata(&book_map);
+
         let cumulative_current_page = get_cumulative_current_page_number(
             path_str,
             chapter_number,
@@ -144,6 +203,8 @@ impl Book {
         Book {
             title: title.into(),
             author: author.into(),
+            contributors: metadata.get_contributors().into(),
+            author_sort: metadata.get_author_sort().into(),
             lang: lang.into(),
             path: path.into(),
             chapter_number: chapter_number,
@@ -153,6 +214,7 @@ impl Book {
             number_of_pages: number_of_pages,
             idx: 0, // How to set early?
             is_favorite: is_fav,
+            archived,
             selected: false,
             description: desc.into(),
             chapter_text_split: Vector::new(),
@@ -160,6 +222,14 @@ impl Book {
             cover_image: None.into(),
             filtered_out: false,
             notes: notes,
+            is_comic: is_comic,
+            collections: shelves.get_collections_of(path_str).into(),
+            tags: shelves.get_tags_of(path_str).into(),
+            marked: false,
+            reading,
+            series,
+            stack_size: 0,
+            history: NavHistory::new(Position::new(chapter_number, current_page)),
         }
     }
 
@@ -167,11 +237,220 @@ impl Book {
         self.lang.clone()
     }
 
+    pub fn get_description(&self) -> Rc<String> {
+        self.description.clone()
+    }
+
+    /// Returns true if the book is a comic book archive,
+    /// i.e. every page is an image instead of text
+    pub fn is_comic(&self) -> bool {
+        self.is_comic
+    }
+
     pub fn get_perc_read(&self) -> f64 {
         let total = self.get_number_of_pages() as f64;
         let read = self.get_number_of_read_pages() as f64;
         (read / total) * 100.0
     }
+
+    pub fn get_collections(&self) -> &Vector<String> {
+        &self.collections
+    }
+
+    pub fn get_tags(&self) -> &Vector<String> {
+        &self.tags
+    }
+
+    pub fn get_reading_status(&self) -> ReadingStatus {
+        self.reading.status
+    }
+
+    /// Changes the status chosen by the user, e.g. to abandon the book
+    pub fn set_reading_status(&mut self, status: ReadingStatus) {
+        self.reading.set_status(status, reading::now());
+        self.save_reading_info();
+    }
+
+    /// Sets the stars given by the user, None removes the rating
+    pub fn set_rating(&mut self, rating: Option<u8>) {
+        self.reading.rating = rating.filter(|rating| (1..=5).contains(rating));
+        self.save_reading_info();
+    }
+
+    /// Sets the day the user plans to finish the book by, None removes it
+    pub fn set_finish_by(&mut self, finish_by: Option<u64>) {
+        self.reading.finish_by = finish_by;
+        self.save_reading_info();
+    }
+
+    /// Sets the pages of the printed edition of the book, None removes them
+    pub fn set_printed_pages(&mut self, printed_pages: Option<usize>) {
+        self.reading.printed_pages = printed_pages.filter(|pages| *pages > 0);
+        self.save_reading_info();
+    }
+
+    /// Updates the progress when the book is opened
+    pub fn mark_opened(&mut self) {
+        self.reading.opened(reading::now());
+        self.save_reading_info();
+    }
+
+    /// Returns true if the last page of the book is shown,
+    /// two pages are shown at once when the view is not single
+    pub fn is_at_last_page(&self, single_view: bool) -> bool {
+        let shown = if single_view { 1 } else { 2 };
+        let last_chapter = self.chapter_number + 1 >= self.number_of_chapters;
+        let last_page = self.current_page + shown >= self.chapter_text_split.len();
+        let last_of_book = self.number_of_pages > 0
+            && self.cumulative_current_page + shown >= self.number_of_pages;
+        (last_chapter && last_page) || last_of_book
+    }
+
+    /// Part of the current chapter before the current page, measured in characters
+    pub fn get_chapter_fraction(&self) -> f64 {
+        let pages = self.chapter_text_split.iter().map(|page| page.len());
+        let total = pages.clone().sum::<usize>();
+        if total == 0 {
+            return 0.0;
+        }
+        pages.take(self.current_page).sum::<usize>() as f64 / total as f64
+    }
+
+    pub fn get_position(&self) -> Position {
+        Position::new(self.chapter_number, self.current_page)
+    }
+
+    /// Moves to a page that isn't next to the current one, e.g. from the list of the chapters,
+    /// the position left is recorded to go back to it
+    pub fn jump_to(&mut self, position: Position) {
+        let from = self.get_position();
+        self.move_to(position);
+        self.history.jumped(from, self.get_position());
+    }
+
+    /// Goes back to where the last jump started, false if there is no jump to undo
+    pub fn go_back(&mut self) -> bool {
+        let Some(position) = self.history.back(self.get_position()) else {
+            return false;
+        };
+        self.move_to(position);
+        true
+    }
+
+    /// Goes again where the reader was before going back
+    pub fn go_forward(&mut self) -> bool {
+        let Some(position) = self.history.forward(self.get_position()) else {
+            return false;
+        };
+        self.move_to(position);
+        true
+    }
+
+    /// Jumps to the furthest position read, false if the reader is already there
+    pub fn go_to_furthest(&mut self) -> bool {
+        let Some(position) = self.history.furthest_after(self.get_position()) else {
+            return false;
+        };
+        self.jump_to(position);
+        true
+    }
+
+    pub fn get_history(&self) -> &NavHistory {
+        &self.history
+    }
+
+    /// Records a page reached turning the pages
+    pub fn page_reached(&mut self) {
+        let position = self.get_position();
+        self.history.reached(position);
+    }
+
+    /// Moves to a page reached scrolling the text, like turning the pages it isn't a jump
+    pub fn scroll_to(&mut self, position: Position) {
+        self.move_to(position);
+        self.check_finished(true);
+        self.page_reached();
+    }
+
+    /// Pages of the current chapter
+    pub fn get_chapter_pages(&self) -> &Vector<String> {
+        &self.chapter_text_split
+    }
+
+    /// Pages of any chapter of a book, split like the ones of the current chapter.
+    /// It doesn't need the book, so it can be called from another thread
+    pub fn get_pages_of_chapter(path: &str, chapter: usize) -> Vector<String> {
+        epub_utils::split_chapter_in_vec(
+            path,
+            None,
+            chapter,
+            NUMBER_OF_LINES,
+            MYENV.lock().unwrap().font.size,
+            PAGE_WIDTH,
+            PAGE_HEIGHT,
+        )
+        .into_iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+
+    /// Offset in the text of the chapter to show in the continuous view:
+    /// the saved one if it is in the current page, otherwise the start of the page,
+    /// e.g. after the page has been turned
+    pub fn get_text_offset(&self, saved: usize) -> usize {
+        let page_lens = self
+            .chapter_text_split
+            .iter()
+            .map(|page| page.len())
+            .collect::<Vec<_>>();
+        if page_at(&page_lens, saved) == self.current_page {
+            saved
+        } else {
+            page_start(&page_lens, self.current_page)
+        }
+    }
+
+    fn move_to(&mut self, position: Position) {
+        if position.chapter != self.chapter_number || self.chapter_text_split.is_empty() {
+            self.set_chapter_number(position.chapter, true);
+        }
+        self.set_chapter_current_page_number(position.page.min(self.get_last_page_number()));
+    }
+
+    /// Marks the book as finished when its last page is reached
+    pub fn check_finished(&mut self, single_view: bool) {
+        if self.reading.status != ReadingStatus::Finished && self.is_at_last_page(single_view) {
+            self.set_reading_status(ReadingStatus::Finished);
+        }
+    }
+
+    fn save_reading_info(&self) {
+        if let Err(e) = save_reading_info(self.path.as_str(), &self.reading) {
+            println!("ERROR: failed to save the reading status of {}: {}", self.path, e);
+        }
+    }
+
+    pub fn get_series(&self) -> Option<&Series> {
+        self.series.as_ref()
+    }
+
+    /// Changes the series of the book and saves it, None removes the book from its series
+    pub fn set_series(&mut self, series: Option<Series>) -> Result<(), String> {
+        save_series(self.path.as_str(), series.as_ref()).map_err(|e| e.to_string())?;
+        self.series = series;
+        Ok(())
+    }
+
+    pub fn set_stack_size(&mut self, stack_size: usize) {
+        self.stack_size = stack_size;
+    }
+
+    /// Reads again the collections and the tags of the book after they have been changed
+    pub fn update_shelves(&mut self, shelves: &Collections) {
+        let path = self.path.as_str();
+        self.collections = shelves.get_collections_of(path).into();
+        self.tags = shelves.get_tags_of(path).into();
+    }
 }
 
 impl BookReading for Book {
@@ -287,19 +566,14 @@ impl BookManagement for Book {
     }
 
     fn split_chapter_in_pages(&self, is_single_view: bool) -> Vector<String> {
+        // the "text" of a comic page is the name of its image in the archive
+        if self.is_comic {
+            return cbz_utils::get_pages_of_comic(self.path.as_str())
+                .unwrap_or_default()
+                .into();
+        }
 
-        epub_utils::split_chapter_in_vec(
-            self.path.as_str(),
-            None,
-            self.chapter_number,
-            NUMBER_OF_LINES,
-            MYENV.lock().unwrap().font.size,
-            PAGE_WIDTH,
-            PAGE_HEIGHT,
-        )
-        .into_iter()
-        .map(|s| s.to_string())
-        .collect()
+        Self::get_pages_of_chapter(self.path.as_str(), self.chapter_number)
     }
 
     fn edit_text<S: Into<Option<String>>>(&mut self, new_text: String, other_new_text: S) {
@@ -442,6 +716,14 @@ impl GUIBook for Book {
         self.author = Rc::new(author.into());
     }
 
+    fn get_contributors(&self) -> String {
+        self.contributors.to_string()
+    }
+
+    fn get_author_sort(&self) -> String {
+        self.author_sort.to_string()
+    }
+
     fn get_number_of_pages(&self) -> usize {
         self.number_of_pages
     }
@@ -511,9 +793,7 @@ impl GUIBook for Book {
     }
 
     fn build_cover_with_size(&self, width: u32, height: u32) -> Result<Box<[u8]>, String> {
-        let epub_path = self.get_path();
-        let mut epub = EpubDoc::new(epub_path.as_str()).map_err(|e| e.to_string())?;
-        let cover = epub.get_cover().map_err(|e| e.to_string())?;
+        let cover = epub_utils::get_cover_of_book(self.get_path().as_str())?;
         let reader = ImageReader::new(ImageCursor::new(cover))
             .with_guessed_format()
             .map_err(|e| e.to_string())?;
@@ -537,12 +817,38 @@ impl GUIBook for Book {
 
     fn set_cover_buffer(&mut self, cover_image: Vec<u8>) {
         self.cover_buffer = cover_image.into();
+        // the image painted from the old buffer would be shown otherwise
+        self.cover_image.replace(None);
     }
 
     fn is_favorite(&self) -> bool {
         self.is_favorite
     }
 
+    fn is_archived(&self) -> bool {
+        self.archived
+    }
+
+    fn set_archived(&mut self, archived: bool) {
+        self.archived = archived;
+    }
+
+    fn is_marked(&self) -> bool {
+        self.marked
+    }
+
+    fn get_reading_info(&self) -> &ReadingInfo {
+        &self.reading
+    }
+
+    fn get_stack_size(&self) -> usize {
+        self.stack_size
+    }
+
+    fn set_marked(&mut self, marked: bool) {
+        self.marked = marked;
+    }
+
     fn set_cover_image(&self, ctx: &mut PaintCtx) -> Result<(), Error> {
         let mut inner = self.cover_image.borrow_mut();
         let buf = self.get_cover_buffer();
@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use druid::{im::Vector, Data, Lens, Selector};
+
+use crate::utils::trash::Snapshot;
+
+/// Asks the confirmation of an action on the book with the given index
+pub const ASK_BOOK_ACTION: Selector<(usize, BookAction)> = Selector::new("book-action.ask");
+/// Runs an action on the book with the given path, it has been confirmed by the user
+pub const RUN_BOOK_ACTION: Selector<(String, BookAction)> = Selector::new("book-action.run");
+pub const UNDO_BOOK_ACTION: Selector<()> = Selector::new("book-action.undo");
+/// The action with the given id can no longer be undone
+pub const COMMIT_BOOK_ACTION: Selector<u64> = Selector::new("book-action.commit");
+
+/// Seconds after which an action on a book can no longer be undone
+pub const UNDO_GRACE_PERIOD: u64 = 10;
+
+/// Actions on a single book from its context menu
+#[derive(Clone, Copy, Data, Debug, PartialEq)]
+pub enum BookAction {
+    /// the book is forgotten with its data, but its file is kept
+    Remove,
+    /// the book is forgotten and its file is deleted
+    DeleteFile,
+    /// the book is hidden, its data is kept
+    Archive,
+    Unarchive,
+    ResetProgress,
+    ClearNotes,
+    DiscardEdits,
+}
+
+impl BookAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BookAction::Remove => "Rimuovi dalla libreria",
+            BookAction::DeleteFile => "Elimina il file",
+            BookAction::Archive => "Archivia",
+            BookAction::Unarchive => "Togli dall'archivio",
+            BookAction::ResetProgress => "Azzera i progressi",
+            BookAction::ClearNotes => "Cancella le note",
+            BookAction::DiscardEdits => "Annulla tutte le modifiche al testo",
+        }
+    }
+
+    /// Question asked before the action is done
+    pub fn question(&self, title: &str) -> String {
+        match self {
+            BookAction::Remove => format!(
+                "Vuoi rimuovere \"{}\" dalla libreria? Il file non verrà eliminato, ma la posizione, le note e le modifiche andranno perse.",
+                title
+            ),
+            BookAction::DeleteFile => format!(
+                "Vuoi eliminare il file di \"{}\"? Verranno perse anche la posizione, le note e le modifiche.",
+                title
+            ),
+            BookAction::Archive => format!(
+                "Vuoi archiviare \"{}\"? Non verrà più mostrato nella libreria, ma i suoi dati verranno mantenuti.",
+                title
+            ),
+            BookAction::Unarchive => format!("Vuoi riportare \"{}\" nella libreria?", title),
+            BookAction::ResetProgress => format!(
+                "Vuoi azzerare i progressi di \"{}\"? La lettura ricomincerà dal primo capitolo.",
+                title
+            ),
+            BookAction::ClearNotes => format!("Vuoi cancellare tutte le note di \"{}\"?", title),
+            BookAction::DiscardEdits => format!(
+                "Vuoi annullare tutte le modifiche al testo di \"{}\"? Tornerà quello originale.",
+                title
+            ),
+        }
+    }
+
+    /// Text shown while the action can be undone
+    pub fn done(&self, title: &str) -> String {
+        match self {
+            BookAction::Remove => format!("\"{}\" è stato rimosso dalla libreria", title),
+            BookAction::DeleteFile => format!("\"{}\" è stato eliminato", title),
+            BookAction::Archive => format!("\"{}\" è stato archiviato", title),
+            BookAction::Unarchive => format!("\"{}\" è tornato nella libreria", title),
+            BookAction::ResetProgress => {
+                format!("I progressi di \"{}\" sono stati azzerati", title)
+            }
+            BookAction::ClearNotes => format!("Le note di \"{}\" sono state cancellate", title),
+            BookAction::DiscardEdits => format!(
+                "Le modifiche al testo di \"{}\" sono state annullate",
+                title
+            ),
+        }
+    }
+
+    /// Returns true if the book leaves the library
+    pub fn removes_book(&self) -> bool {
+        matches!(self, BookAction::Remove | BookAction::DeleteFile)
+    }
+}
+
+/// Last action done on a book, it can be undone until the grace period is over
+#[derive(Clone, Data, Lens, Default)]
+pub struct UndoState {
+    pub label: String,
+    /// one snapshot for each book changed, merging duplicates removes many books
+    pub snapshots: Vector<Arc<Snapshot>>,
+}
+
+impl UndoState {
+    pub fn can_undo(&self) -> bool {
+        !self.snapshots.is_empty()
+    }
+
+    /// Takes the snapshots of the action with the given id, none if it has been replaced
+    pub fn take(&mut self, id: u64) -> Vector<Arc<Snapshot>> {
+        if self.snapshots.front().map(|snapshot| snapshot.id) != Some(id) {
+            return Vector::new();
+        }
+        self.label.clear();
+        std::mem::take(&mut self.snapshots)
+    }
+}
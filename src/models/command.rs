@@ -3,7 +3,16 @@ pub enum Trigger {
     NONE,
     OCR,
     OCRINVERSE,
-    ADDBOOK
+    ADDBOOK,
+    CALIBRE,
+    SYNCDIR,
+    EXPORT,
+    EXPORTBOOKS,
+    IMPORT,
+    COVER,
+    WATCHDIR,
+    IMPORTDIR,
+    EXPORTSTATS,
 }
 
 impl Trigger {
@@ -12,6 +21,15 @@ impl Trigger {
             "ocr" | "OCR" => Trigger::OCR,
             "ocrinverse" | "OCRINVERSE" => Trigger::OCRINVERSE,
             "addbook" | "ADDBOOK" => Trigger::ADDBOOK,
+            "calibre" | "CALIBRE" => Trigger::CALIBRE,
+            "syncdir" | "SYNCDIR" => Trigger::SYNCDIR,
+            "export" | "EXPORT" => Trigger::EXPORT,
+            "exportbooks" | "EXPORTBOOKS" => Trigger::EXPORTBOOKS,
+            "import" | "IMPORT" => Trigger::IMPORT,
+            "cover" | "COVER" => Trigger::COVER,
+            "watchdir" | "WATCHDIR" => Trigger::WATCHDIR,
+            "importdir" | "IMPORTDIR" => Trigger::IMPORTDIR,
+            "exportstats" | "EXPORTSTATS" => Trigger::EXPORTSTATS,
             _ => Trigger::NONE,
         }
     }
@@ -0,0 +1,170 @@
+use druid::{im::Vector, Data, Lens, Selector};
+
+/// Opens the window of the duplicates and searches them
+pub const OPEN_DUPLICATES_WINDOW: Selector<()> = Selector::new("duplicates.open-window");
+pub const DUPLICATES_SEARCH: Selector<()> = Selector::new("duplicates.search");
+/// Sent by the search thread with the groups of books that are the same
+pub const DUPLICATES_FOUND: Selector<Vec<(Vec<String>, Vec<MatchReason>)>> =
+    Selector::new("duplicates.found");
+/// Chooses the book of a group whose file is kept, given the group id and its path
+pub const DUPLICATES_KEEP: Selector<(usize, String)> = Selector::new("duplicates.keep");
+/// Merges the books of a group in the one chosen by the user
+pub const DUPLICATES_MERGE: Selector<usize> = Selector::new("duplicates.merge");
+/// Leaves the books of a group as they are
+pub const DUPLICATES_IGNORE: Selector<usize> = Selector::new("duplicates.ignore");
+
+/// Why two books are taken as the same one
+#[derive(Clone, Copy, Data, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchReason {
+    SameFile,
+    Identifier,
+    TitleAuthor,
+    Content,
+}
+
+impl MatchReason {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MatchReason::SameFile => "file identico",
+            MatchReason::Identifier => "stesso identificativo",
+            MatchReason::TitleAuthor => "stesso titolo e autore",
+            MatchReason::Content => "primi capitoli simili",
+        }
+    }
+}
+
+/// A book of a group, with what is merged in the kept one
+#[derive(Clone, Data, Lens)]
+pub struct DuplicateBook {
+    pub group: usize,
+    pub path: String,
+    pub title: String,
+    pub author: String,
+    /// progress, notes and favorite, as shown to the user
+    pub details: String,
+    pub keep: bool,
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct DuplicateGroup {
+    pub id: usize,
+    pub reasons: String,
+    pub books: Vector<DuplicateBook>,
+}
+
+impl DuplicateGroup {
+    pub fn new(id: usize, reasons: &[MatchReason], books: Vec<DuplicateBook>) -> Self {
+        let reasons = reasons
+            .iter()
+            .map(|reason| reason.label())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Self {
+            id,
+            reasons: format!("Trovati per: {}", reasons),
+            books: books.into(),
+        }
+    }
+
+    pub fn kept(&self) -> Option<&DuplicateBook> {
+        self.books.iter().find(|book| book.keep)
+    }
+
+    /// Paths of the books merged in the kept one
+    pub fn merged(&self) -> Vec<String> {
+        self.books
+            .iter()
+            .filter(|book| !book.keep)
+            .map(|book| book.path.clone())
+            .collect()
+    }
+
+    pub fn choose(&mut self, path: &str) {
+        for book in self.books.iter_mut() {
+            book.keep = book.path == path;
+        }
+    }
+}
+
+/// State of the duplicates window
+#[derive(Clone, Data, Lens, Default)]
+pub struct DuplicatesState {
+    pub groups: Vector<DuplicateGroup>,
+    pub searching: bool,
+    pub status: String,
+}
+
+impl DuplicatesState {
+    pub fn get_group(&self, id: usize) -> Option<&DuplicateGroup> {
+        self.groups.iter().find(|group| group.id == id)
+    }
+
+    pub fn choose(&mut self, id: usize, path: &str) {
+        if let Some(group) = self.groups.iter_mut().find(|group| group.id == id) {
+            group.choose(path);
+        }
+    }
+
+    /// Removes a group that has been merged or ignored
+    pub fn remove(&mut self, id: usize) -> Option<DuplicateGroup> {
+        let idx = self.groups.iter().position(|group| group.id == id)?;
+        let group = self.groups.remove(idx);
+        self.status = self.summary();
+        Some(group)
+    }
+
+    pub fn summary(&self) -> String {
+        match self.groups.len() {
+            0 => "Nessun libro duplicato".to_string(),
+            1 => "Un gruppo di libri duplicati".to_string(),
+            n => format!("{} gruppi di libri duplicati", n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(group: usize, path: &str, keep: bool) -> DuplicateBook {
+        DuplicateBook {
+            group,
+            path: path.to_string(),
+            title: "Pride and Prejudice".to_string(),
+            author: "Jane Austen".to_string(),
+            details: String::new(),
+            keep,
+        }
+    }
+
+    #[test]
+    fn test_choose_and_remove_groups() {
+        let mut state = DuplicatesState::default();
+        state.groups.push_back(DuplicateGroup::new(
+            0,
+            &[MatchReason::Identifier, MatchReason::Content],
+            vec![
+                book(0, "pg1342.epub", true),
+                book(0, "pg1342-images.epub", false),
+            ],
+        ));
+        state.groups.push_back(DuplicateGroup::new(
+            1,
+            &[MatchReason::SameFile],
+            vec![book(1, "a.epub", true), book(1, "b.epub", false)],
+        ));
+        assert_eq!(
+            state.groups[0].reasons,
+            "Trovati per: stesso identificativo, primi capitoli simili"
+        );
+
+        state.choose(0, "pg1342-images.epub");
+        let group = state.get_group(0).unwrap();
+        assert_eq!(group.kept().unwrap().path, "pg1342-images.epub");
+        assert_eq!(group.merged(), vec!["pg1342.epub".to_string()]);
+
+        assert!(state.remove(0).is_some());
+        assert!(state.remove(0).is_none());
+        assert_eq!(state.summary(), "Un gruppo di libri duplicati");
+    }
+}
@@ -0,0 +1,264 @@
+use druid::{Data, Lens, Selector};
+use serde_json::{json, Value};
+
+use super::{reading::format_date, stats::ReadingSession};
+use crate::utils::opds_server::format_timestamp;
+
+/// Opens the window to set the reading goals
+pub const OPEN_GOALS_WINDOW: Selector<()> = Selector::new("goals.open-window");
+/// Saves the goals typed in the window
+pub const GOALS_SAVE: Selector<()> = Selector::new("goals.save");
+/// Sent periodically to update the progress and to remind the daily goal
+pub const GOALS_CHECK: Selector<()> = Selector::new("goals.check");
+/// Seconds between two checks of the goals
+pub const GOALS_CHECK_INTERVAL: u64 = 60;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Goals set by the user, 0 disables one
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReadingGoals {
+    pub daily_minutes: u64,
+    pub yearly_books: u64,
+    /// hour of the local day after which the user is reminded of a daily goal not reached yet
+    pub reminder_hour: Option<u64>,
+}
+
+impl ReadingGoals {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "daily_minutes": self.daily_minutes,
+            "yearly_books": self.yearly_books,
+            "reminder_hour": self.reminder_hour,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            daily_minutes: value.get("daily_minutes")?.as_u64()?,
+            yearly_books: value.get("yearly_books")?.as_u64()?,
+            reminder_hour: value["reminder_hour"].as_u64().filter(|hour| *hour < 24),
+        })
+    }
+
+    /// The daily goal isn't reached yet, it is late enough to remind it
+    /// and it hasn't been reminded today. The time is the local one
+    pub fn needs_reminder(&self, seconds_today: u64, time: u64, reminded_day: u64) -> bool {
+        let Some(hour) = self.reminder_hour else {
+            return false;
+        };
+        self.daily_minutes > 0
+            && seconds_today < self.daily_minutes * 60
+            && time % DAY >= hour * 60 * 60
+            && reminded_day < time / DAY
+    }
+}
+
+/// Local time of a UTC one, the offset is the one of the time zone
+pub fn local_time(time: u64, offset: i64) -> u64 {
+    time.saturating_add_signed(offset)
+}
+
+/// Seconds spent reading in the local day of the given time
+pub fn seconds_on_day(sessions: &[ReadingSession], time: u64, offset: i64) -> u64 {
+    let day = |time: u64| local_time(time, offset) / DAY;
+    sessions
+        .iter()
+        .filter(|session| day(session.start) == day(time))
+        .map(|session| session.seconds)
+        .sum()
+}
+
+/// Year of a time, e.g. "2023"
+pub fn year_of(time: u64) -> String {
+    format_timestamp(time)[0..4].to_string()
+}
+
+/// Books finished in the year of the given time, from their finish dates
+pub fn books_finished_in_year(finished: impl Iterator<Item = u64>, time: u64) -> usize {
+    let year = year_of(time);
+    finished
+        .filter(|finished| year_of(*finished) == year)
+        .count()
+}
+
+/// Pages to read each day, today included, to finish the book by the given day.
+/// None if the day has passed
+pub fn pages_per_day(
+    current_page: usize,
+    total_pages: usize,
+    time: u64,
+    finish_by: u64,
+) -> Option<usize> {
+    let today = time / DAY;
+    let last_day = finish_by / DAY;
+    if last_day < today {
+        return None;
+    }
+    let days = (last_day - today + 1) as usize;
+    Some(total_pages.saturating_sub(current_page).div_ceil(days))
+}
+
+/// Plan shown in the details of a book with a day to finish it by
+pub fn finish_plan(current_page: usize, total_pages: usize, time: u64, finish_by: u64) -> String {
+    let date = format_date(finish_by);
+    match pages_per_day(current_page, total_pages, time, finish_by) {
+        None => format!("La data del {} per finire il libro è passata", date),
+        Some(0) => format!("Libro già finito prima del {}", date),
+        Some(1) => format!("Per finire entro il {}: 1 pagina al giorno", date),
+        Some(pages) => format!("Per finire entro il {}: {} pagine al giorno", date, pages),
+    }
+}
+
+/// Goals as typed in their window and the progress shown in the library
+#[derive(Clone, Data, Lens, Default)]
+pub struct GoalsState {
+    pub daily_minutes: String,
+    pub yearly_books: String,
+    /// empty disables the reminder
+    pub reminder_hour: String,
+    pub status: String,
+    pub today: String,
+    pub today_progress: f64,
+    pub year: String,
+    pub year_progress: f64,
+    /// day of the last reminder, there is at most one a day
+    pub reminded_day: u64,
+}
+
+impl GoalsState {
+    pub fn get_goals(&self) -> Result<ReadingGoals, String> {
+        let number = |text: &str, name: &str| match text.trim() {
+            "" => Ok(0),
+            text => text
+                .parse::<u64>()
+                .map_err(|_| format!("{} non è un numero valido", name)),
+        };
+        let reminder_hour = match self.reminder_hour.trim() {
+            "" => None,
+            hour => Some(
+                hour.parse::<u64>()
+                    .ok()
+                    .filter(|hour| *hour < 24)
+                    .ok_or("l'ora del promemoria va da 0 a 23")?,
+            ),
+        };
+        Ok(ReadingGoals {
+            daily_minutes: number(&self.daily_minutes, "minuti al giorno")?,
+            yearly_books: number(&self.yearly_books, "libri all'anno")?,
+            reminder_hour,
+        })
+    }
+
+    pub fn set_goals(&mut self, goals: &ReadingGoals) {
+        let number = |value: u64| {
+            if value == 0 {
+                String::new()
+            } else {
+                value.to_string()
+            }
+        };
+        self.daily_minutes = number(goals.daily_minutes);
+        self.yearly_books = number(goals.yearly_books);
+        self.reminder_hour = goals
+            .reminder_hour
+            .map(|hour| hour.to_string())
+            .unwrap_or_default();
+    }
+
+    /// True if the user should be reminded of the daily goal now, at the given local time.
+    /// The reminder is then recorded so that it is shown once a day
+    pub fn take_reminder(&mut self, goals: &ReadingGoals, seconds_today: u64, time: u64) -> bool {
+        let remind = goals.needs_reminder(seconds_today, time, self.reminded_day);
+        if remind {
+            self.reminded_day = time / DAY;
+        }
+        remind
+    }
+
+    /// Updates the progress towards the goals
+    pub fn update(
+        &mut self,
+        goals: &ReadingGoals,
+        seconds_today: u64,
+        finished_this_year: usize,
+        time: u64,
+    ) {
+        let minutes = seconds_today / 60;
+        (self.today, self.today_progress) = match goals.daily_minutes {
+            0 => (format!("Oggi: {} min di lettura", minutes), 0.0),
+            goal => (
+                format!("Oggi: {} di {} min", minutes, goal),
+                (minutes as f64 / goal as f64).min(1.0),
+            ),
+        };
+        let year = year_of(time);
+        (self.year, self.year_progress) = match goals.yearly_books {
+            0 => (
+                format!("Libri letti nel {}: {}", year, finished_this_year),
+                0.0,
+            ),
+            goal => (
+                format!(
+                    "Libri letti nel {}: {} di {}",
+                    year, finished_this_year, goal
+                ),
+                (finished_this_year as f64 / goal as f64).min(1.0),
+            ),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_goals_progress_and_plan() {
+        let time = 19_700 * DAY + 21 * 60 * 60;
+        let mut session = ReadingSession::new("dune.epub", time - 60 * 60, 0);
+        session.seconds = 20 * 60;
+        let mut yesterday = ReadingSession::new("dune.epub", time - DAY, 0);
+        yesterday.seconds = 40 * 60;
+        let sessions = [session, yesterday];
+        let seconds = seconds_on_day(&sessions, time, 0);
+        assert_eq!(seconds, 20 * 60);
+        // three hours and a half east the day ends at 20:30 in UTC
+        let east = 3 * 60 * 60 + 30 * 60;
+        assert_eq!(seconds_on_day(&sessions, time, east), 0);
+        assert_eq!(seconds_on_day(&sessions, time - DAY, east), 60 * 60);
+
+        let mut state = GoalsState {
+            daily_minutes: "30".into(),
+            yearly_books: "".into(),
+            reminder_hour: "20".into(),
+            ..Default::default()
+        };
+        let goals = state.get_goals().unwrap();
+        assert_eq!(goals.yearly_books, 0);
+        assert!(state.take_reminder(&goals, seconds, time));
+        assert!(!state.take_reminder(&goals, seconds, time + 60));
+        assert!(!goals.needs_reminder(30 * 60, time, 0));
+        // the hour of the reminder is the local one
+        let utc_hour = 19_700 * DAY + 19 * 60 * 60;
+        assert!(!goals.needs_reminder(seconds, local_time(utc_hour, 0), 0));
+        assert!(goals.needs_reminder(seconds, local_time(utc_hour, 2 * 60 * 60), 0));
+
+        let finished = [time, time - 400 * DAY];
+        let books = books_finished_in_year(finished.into_iter(), time);
+        state.update(&goals, seconds, books, time);
+        assert_eq!(state.today, "Oggi: 20 di 30 min");
+        assert_eq!(state.year, format!("Libri letti nel {}: 1", year_of(time)));
+
+        state.reminder_hour = "25".into();
+        assert!(state.get_goals().is_err());
+
+        assert_eq!(pages_per_day(100, 250, time, time + 9 * DAY), Some(15));
+        assert_eq!(pages_per_day(100, 250, time, time), Some(150));
+        assert_eq!(pages_per_day(100, 250, time, time - DAY), None);
+        assert_eq!(
+            finish_plan(250, 250, time, time),
+            format!("Libro già finito prima del {}", format_date(time))
+        );
+    }
+}
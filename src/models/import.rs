@@ -0,0 +1,199 @@
+use std::{path::Path, rc::Rc};
+
+use druid::{im::Vector, Data, Lens, Selector};
+
+pub const OPEN_IMPORT_WINDOW: Selector<()> = Selector::new("import.open-window");
+/// A book has been checked and copied in the epubs folder, or the reason why it is skipped
+pub const IMPORT_CHECKED: Selector<(String, Result<String, ImportStatus>)> =
+    Selector::new("import.checked");
+pub const IMPORT_CLEAR: Selector<()> = Selector::new("import.clear");
+
+/// Step reached by a book being imported, it is sent by the import thread
+#[derive(Clone, Data, Debug, PartialEq)]
+pub enum ImportStatus {
+    /// the duplicates are searched and the file is copied
+    Checking,
+    /// the chapters and the metadata are being extracted
+    Loading,
+    Added,
+    /// path of the book with the same content already in the library
+    Duplicate(String),
+    Unsupported(String),
+    Failed(String),
+}
+
+impl ImportStatus {
+    pub fn is_done(&self) -> bool {
+        !matches!(self, ImportStatus::Checking | ImportStatus::Loading)
+    }
+
+    pub fn is_failure(&self) -> bool {
+        matches!(self, ImportStatus::Unsupported(_) | ImportStatus::Failed(_))
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            ImportStatus::Checking => "In attesa".to_string(),
+            ImportStatus::Loading => "Estrazione in corso...".to_string(),
+            ImportStatus::Added => "Aggiunto".to_string(),
+            ImportStatus::Duplicate(path) => format!("Già presente: {}", path),
+            ImportStatus::Unsupported(e) => format!("Non supportato: {}", e),
+            ImportStatus::Failed(e) => format!("Errore: {}", e),
+        }
+    }
+}
+
+/// Book chosen for the import, shown in the progress window
+#[derive(Clone, Data, Lens, PartialEq)]
+pub struct ImportItem {
+    pub source: Rc<String>,
+    /// path of the copy in the epubs folder, empty until it is made
+    pub dest: Rc<String>,
+    pub name: String,
+    pub status: ImportStatus,
+}
+
+/// Books imported in the last batches, they stay listed until they are cleared
+#[derive(Clone, Data, Lens, Default, PartialEq)]
+pub struct ImportState {
+    pub items: Vector<ImportItem>,
+}
+
+impl ImportState {
+    /// Adds the books to the import, the ones still being imported are skipped.
+    /// Returns the paths that have to be checked
+    pub fn add(&mut self, paths: &[String]) -> Vec<String> {
+        let mut added = vec![];
+        for path in paths {
+            let running = self
+                .items
+                .iter()
+                .any(|item| item.source.as_str() == path && !item.status.is_done());
+            if running || added.contains(path) {
+                continue;
+            }
+            let name = Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or(path.clone());
+            self.items.push_back(ImportItem {
+                source: Rc::new(path.clone()),
+                dest: Rc::new(String::new()),
+                name,
+                status: ImportStatus::Checking,
+            });
+            added.push(path.clone());
+        }
+        added
+    }
+
+    /// The book has been copied in the epubs folder and it has to be loaded, or it is skipped
+    pub fn checked(&mut self, source: &str, result: Result<String, ImportStatus>) {
+        let Some(item) = self
+            .items
+            .iter_mut()
+            .find(|item| item.source.as_str() == source && item.status == ImportStatus::Checking)
+        else {
+            return;
+        };
+        match result {
+            Ok(dest) => {
+                item.dest = Rc::new(dest);
+                item.status = ImportStatus::Loading;
+            }
+            Err(status) => item.status = status,
+        }
+    }
+
+    /// The library has loaded a book, returns false if it wasn't imported
+    pub fn loaded(&mut self, dest: &str, result: Result<(), String>) -> bool {
+        let Some(item) = self
+            .items
+            .iter_mut()
+            .find(|item| item.dest.as_str() == dest && item.status == ImportStatus::Loading)
+        else {
+            return false;
+        };
+        item.status = match result {
+            Ok(()) => ImportStatus::Added,
+            Err(e) => ImportStatus::Failed(e),
+        };
+        true
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.items.iter().any(|item| !item.status.is_done())
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ImportItem> {
+        self.items.iter().filter(|item| item.status.is_failure())
+    }
+
+    /// Removes the books whose import is over
+    pub fn clear_done(&mut self) {
+        self.items.retain(|item| !item.status.is_done());
+    }
+
+    pub fn summary(&self) -> String {
+        let count =
+            |f: fn(&ImportStatus) -> bool| self.items.iter().filter(|item| f(&item.status)).count();
+        let added = count(|status| *status == ImportStatus::Added);
+        let duplicates = count(|status| matches!(status, ImportStatus::Duplicate(_)));
+        let failed = count(ImportStatus::is_failure);
+        let running = count(|status| !status.is_done());
+
+        let mut text = format!("{} di {} libri aggiunti", added, self.items.len());
+        if duplicates > 0 {
+            text.push_str(&format!(", {} già presenti", duplicates));
+        }
+        if failed > 0 {
+            text.push_str(&format!(", {} non importati", failed));
+        }
+        if running > 0 {
+            text.push_str(&format!(", {} in corso", running));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_progress() {
+        let mut import = ImportState::default();
+        let paths = vec![
+            "/libri/dune.epub".to_string(),
+            "/libri/zeno.epub".to_string(),
+            "/libri/drm.azw3".to_string(),
+            "/libri/dune.epub".to_string(),
+        ];
+        assert_eq!(import.add(&paths).len(), 3);
+        // a book still being imported is not added twice
+        assert!(import.add(&paths[..1]).is_empty());
+
+        import.checked("/libri/dune.epub", Ok("/epubs/dune.epub".to_string()));
+        import.checked(
+            "/libri/zeno.epub",
+            Err(ImportStatus::Duplicate("/epubs/zeno.epub".into())),
+        );
+        import.checked(
+            "/libri/drm.azw3",
+            Err(ImportStatus::Unsupported("DRM".into())),
+        );
+        assert!(import.is_running());
+        assert_eq!(
+            import.summary(),
+            "0 di 3 libri aggiunti, 1 già presenti, 1 non importati, 1 in corso"
+        );
+
+        assert!(!import.loaded("/epubs/altro.epub", Ok(())));
+        assert!(import.loaded("/epubs/dune.epub", Ok(())));
+        assert!(!import.is_running());
+        assert_eq!(import.failures().count(), 1);
+
+        import.clear_done();
+        assert!(import.items.is_empty());
+    }
+}
@@ -0,0 +1,37 @@
+use druid::{Data, Lens, Selector};
+
+use crate::utils::kosync_client::{KoSyncSettings, Progress};
+
+/// Opens the window to log in a KOReader sync server
+pub const OPEN_KOSYNC_WINDOW: Selector<()> = Selector::new("kosync.open-window");
+/// Logs in the server, the flag tells if a new account has to be created
+pub const KOSYNC_LOGIN: Selector<bool> = Selector::new("kosync.login");
+/// Sent by the login thread with the account, if it is valid
+pub const KOSYNC_LOGGED_IN: Selector<Result<KoSyncSettings, String>> =
+    Selector::new("kosync.logged-in");
+/// Forgets the account, the positions aren't synced anymore
+pub const KOSYNC_LOGOUT: Selector<()> = Selector::new("kosync.logout");
+/// Sent by the thread that reads the position of the book that has been opened
+pub const KOSYNC_PROGRESS_FETCHED: Selector<(String, Progress)> =
+    Selector::new("kosync.progress-fetched");
+/// Moves the book to the position read on another device
+pub const KOSYNC_APPLY_PROGRESS: Selector<(String, Progress)> =
+    Selector::new("kosync.apply-progress");
+/// Starts the embedded sync server, or stops it if it is running
+pub const TOGGLE_KOSYNC_SERVER: Selector<()> = Selector::new("kosync.toggle-server");
+
+/// State of the login window of the sync server
+#[derive(Clone, Data, Lens, Default)]
+pub struct KoSyncState {
+    pub server: String,
+    pub username: String,
+    pub password: String,
+    pub status: String,
+    pub loading: bool,
+}
+
+impl KoSyncState {
+    pub fn get_settings(&self) -> KoSyncSettings {
+        KoSyncSettings::new(&self.server, &self.username, &self.password)
+    }
+}
@@ -1,22 +1,48 @@
 use derivative::Derivative;
 use druid::Selector;
 use druid::{im::Vector, Data, Lens};
-use epub::doc::EpubDoc;
 use image::io::Reader as ImageReader;
-use std::{io::Cursor, path::PathBuf, rc::Rc, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+};
 
 use crate::traits::reader::BookManagement;
 use crate::utils::thread_loader::{ThreadLoader, ThreadResult};
 use crate::{
-    models::book::Book,
+    models::{
+        book::Book,
+        import::ImportState,
+        reading::{self, format_date, parse_date, PeriodFilter, ReadingStatus},
+        series::{compare_volumes, Series},
+    },
     traits::gui::{GUIBook, GUILibrary},
     utils::{
+        collections::{normalize_tag, Collections},
         dir_manager::{get_epub_dir, get_saved_books_dir},
-        epub_utils,
+        saveload::load_excluded_books,
+        calibre_utils, dir_watcher, epub_utils,
     },
+    MYENV,
 };
 
 pub const SELECTED_BOOK_SELECTOR: Selector<Option<usize>> = Selector::new("selected-book");
+/// Shows only the books of a collection, or all of them
+pub const FILTER_COLLECTION_SELECTOR: Selector<Option<String>> =
+    Selector::new("filter-collection");
+pub const TOGGLE_TAG_SELECTOR: Selector<String> = Selector::new("toggle-tag");
+pub const DELETE_COLLECTION_SELECTOR: Selector<String> = Selector::new("delete-collection");
+
+/// Collection or tag listed in the sidebar of the library
+#[derive(Clone, Data, Lens, PartialEq)]
+pub struct ShelfEntry {
+    pub name: Rc<String>,
+    /// the books are filtered by this collection or tag
+    pub active: bool,
+}
 
 pub struct LibraryFilterLens;
 
@@ -42,13 +68,42 @@ pub struct Library<B: GUIBook + Data> {
     sorted_by: SortBy,
     filter_by: Rc<String>,
     filter_fav: bool,
+    filter_collection: Option<Rc<String>>,
+    filter_tags: Vector<String>,
+    filter_status: Option<ReadingStatus>,
+    /// minimum number of stars, 0 shows also the books without a rating
+    filter_rating: u8,
+    filter_period: PeriodFilter,
+    /// the volumes of a series are shown as a single stack
+    group_series: bool,
+    /// only the archived books are shown, otherwise they are hidden
+    show_archived: bool,
     visible_books: usize,
+    pub collections: Vector<ShelfEntry>,
+    pub tags: Vector<ShelfEntry>,
+    /// name of the collection or tag assigned to the selected books
+    pub shelf_name: String,
+    /// series of the selected book while it is edited
+    pub series_name: String,
+    pub series_index: String,
+    /// day the selected book should be finished by, as typed by the user
+    pub finish_by: String,
     #[data(ignore)]
     #[derivative(PartialEq = "ignore")]
     cover_loader: Arc<ThreadLoader<Vec<u8>>>,
     #[data(ignore)]
     #[derivative(PartialEq = "ignore")]
-    book_loader: Arc<ThreadLoader<Book>>,
+    book_loader: Arc<ThreadLoader<Result<Book, (String, String)>>>,
+    /// paths of the books scheduled for loading, they are added when they are ready
+    #[data(ignore)]
+    #[derivative(PartialEq = "ignore")]
+    loading_paths: HashSet<String>,
+    /// collections and tags given to the books when they are loaded
+    #[data(ignore)]
+    #[derivative(PartialEq = "ignore")]
+    shelves: Arc<Collections>,
+    /// progress of the books added by the user
+    pub import: ImportState,
     pub do_paint_shadows: bool,
 }
 
@@ -62,19 +117,436 @@ impl Library<Book> {
             visible_books: 0,
             cover_loader: ThreadLoader::default().into(),
             book_loader: ThreadLoader::default().into(),
+            loading_paths: HashSet::new(),
+            shelves: Arc::new(Collections::load()),
+            import: ImportState::default(),
             filter_fav: false,
+            filter_collection: None,
+            filter_tags: Vector::new(),
+            filter_status: None,
+            filter_rating: 0,
+            filter_period: PeriodFilter::Any,
+            group_series: false,
+            show_archived: false,
+            collections: Vector::new(),
+            tags: Vector::new(),
+            shelf_name: String::new(),
+            series_name: String::new(),
+            series_index: String::new(),
+            finish_by: String::new(),
             do_paint_shadows: false,
         };
+        let shelves = lib.shelves.clone();
+        lib.update_shelf_entries(&shelves);
+        // the books removed by the user are skipped, their files are still there
+        let excluded = load_excluded_books();
 
         if let Ok(paths) = lib.epub_paths() {
             for path in paths {
                 let path: String = path.to_str().unwrap().to_string();
-                lib.schedule_book_loading(&path);
+                if !excluded.contains(&path) {
+                    lib.schedule_book_loading(&path);
+                }
+            }
+        }
+
+        // the books of the watched folders are read where they are, like the Calibre ones
+        let watched_dirs = MYENV.lock().unwrap().watched_dirs.clone();
+        for dir in watched_dirs {
+            for path in dir_watcher::list_books(Path::new(&dir), true) {
+                let path = path.to_str().unwrap().to_string();
+                if !excluded.contains(&path) {
+                    lib.schedule_book_loading(&path);
+                }
+            }
+        }
+
+        let calibre_library = MYENV.lock().unwrap().calibre_library.clone();
+        if let Some(root) = calibre_library {
+            if let Err(e) = lib.schedule_calibre_loading(&root) {
+                println!("ERROR: failed to read the Calibre library {}: {}", root, e);
             }
         }
         lib
     }
 
+    /// Loads the books of a Calibre library from their folders,
+    /// without copying them in the epubs folder.
+    /// Returns the number of books that will be loaded
+    pub fn schedule_calibre_loading(&mut self, root: &str) -> Result<usize, String> {
+        let books = calibre_utils::read_library(root).map_err(|e| e.to_string())?;
+        let excluded = load_excluded_books();
+
+        let mut scheduled = 0;
+        for calibre_book in books {
+            let Some(path) = calibre_book.get_file_path(root) else {
+                continue;
+            };
+            let path = path.to_str().unwrap().to_string();
+            if excluded.contains(&path) || self.contains_path(&path) {
+                continue;
+            }
+            self.loading_paths.insert(path.clone());
+            let tx = self.book_loader.tx();
+            let shelves = self.shelves.clone();
+            self.book_loader.execute(move || {
+                let folder = Path::new(&path).file_stem().unwrap().to_str().unwrap();
+                if !get_saved_books_dir().join(folder).exists() {
+                    if let Err(e) = epub_utils::extract_all(&path) {
+                        println!("ERROR: failed to extract {}: {}", path, e);
+                        let _ = tx.send(ThreadResult::new(Err((path, e.to_string())), 0));
+                        return;
+                    }
+                }
+                // the database is updated by Calibre, so it is read at every start
+                if let Err(e) = calibre_utils::apply_metadata(&path, &calibre_book) {
+                    println!("ERROR: failed to read metadata of {}: {}", path, e);
+                }
+                let book = Book::new(&path, &shelves);
+                tx.send(ThreadResult::new(Ok(book), 0))
+                    .expect(format!("Failed to send {}", path).as_str());
+            });
+            scheduled += 1;
+        }
+        Ok(scheduled)
+    }
+
+    /// Removes from the library all the books inside a folder,
+    /// e.g. the ones of a Calibre library that has been closed
+    pub fn remove_books_in_dir(&mut self, dir: &str) {
+        self.unselect_current_book();
+        self.books
+            .retain(|book| !Path::new(book.get_path().as_str()).starts_with(dir));
+        self.books
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, book)| book.set_index(i));
+        self.filter_books();
+    }
+
+    /// Returns true if the book is in the library or it is being loaded
+    pub fn contains_path(&self, path: &str) -> bool {
+        self.loading_paths.contains(path) || self.find_book(path).is_some()
+    }
+
+    /// Paths of the books in the library and of the ones being loaded
+    pub fn book_paths(&self) -> Vec<String> {
+        self.books
+            .iter()
+            .map(|book| book.get_path().to_string())
+            .chain(self.loading_paths.iter().cloned())
+            .collect()
+    }
+
+    /// Index of the book with the given path
+    pub fn find_book(&self, path: &str) -> Option<usize> {
+        self.books
+            .iter()
+            .position(|book| book.get_path().as_str() == path)
+    }
+
+    /// The file of a book has been moved or renamed, it is read again from its new path
+    pub fn move_book(&mut self, from: &str, to: &str) {
+        let Some(idx) = self.find_book(from) else {
+            return;
+        };
+        let old = &self.books[idx];
+        let mut book = Book::new(to, &self.shelves).with_index(idx);
+        book.set_cover_buffer(old.get_cover_buffer().to_vec());
+        book.set_selected(old.is_selected());
+        self.books.set(idx, book);
+        self.filter_books();
+    }
+
+    /// Reads again the books changed on another device,
+    /// except the one that is being read
+    pub fn reload_books(&mut self, paths: &HashSet<String>, except: Option<usize>) {
+        let shelves = Collections::load();
+        for idx in 0..self.books.len() {
+            if Some(idx) == except || !paths.contains(self.books[idx].get_path().as_str()) {
+                continue;
+            }
+            let old = &self.books[idx];
+            let mut book = Book::new(old.get_path().as_str(), &shelves).with_index(idx);
+            book.set_cover_buffer(old.get_cover_buffer().to_vec());
+            book.set_selected(old.is_selected());
+            self.books.set(idx, book);
+        }
+        self.filter_books();
+    }
+
+    /// Reads again the collections and the tags after they have been changed,
+    /// the filters of the ones that no longer exist are removed
+    pub fn reload_shelves(&mut self) {
+        let shelves = Collections::load();
+        self.books
+            .iter_mut()
+            .for_each(|book| book.update_shelves(&shelves));
+        self.update_shelf_entries(&shelves);
+        // the books loaded from now on get the new collections too
+        self.shelves = Arc::new(shelves);
+        self.filter_books();
+    }
+
+    fn update_shelf_entries(&mut self, shelves: &Collections) {
+        let names = shelves.get_collection_names();
+        if let Some(name) = &self.filter_collection {
+            if !names.contains(name) {
+                self.filter_collection = None;
+            }
+        }
+        let all_tags = shelves.get_all_tags();
+        self.filter_tags.retain(|tag| all_tags.contains(tag));
+
+        self.collections = names
+            .into_iter()
+            .map(|name| ShelfEntry {
+                active: self.filter_collection.as_deref() == Some(&name),
+                name: name.into(),
+            })
+            .collect();
+        self.tags = all_tags
+            .into_iter()
+            .map(|tag| ShelfEntry {
+                active: self.filter_tags.contains(&tag),
+                name: tag.into(),
+            })
+            .collect();
+    }
+
+    /// Shows only the books of a collection, or all of them with None
+    pub fn set_collection_filter(&mut self, name: Option<String>) {
+        self.filter_collection = name.map(|name| name.into());
+        let active = self.filter_collection.clone();
+        self.collections
+            .iter_mut()
+            .for_each(|entry| entry.active = Some(&entry.name) == active.as_ref());
+        self.filter_books();
+    }
+
+    pub fn get_collection_filter(&self) -> Option<Rc<String>> {
+        self.filter_collection.clone()
+    }
+
+    /// Adds or removes a tag from the ones that the shown books must have
+    pub fn toggle_tag_filter(&mut self, tag: &str) {
+        let tag = normalize_tag(tag);
+        match self.filter_tags.index_of(&tag) {
+            Some(idx) => {
+                self.filter_tags.remove(idx);
+            }
+            None => self.filter_tags.push_back(tag),
+        }
+        let active = self.filter_tags.clone();
+        self.tags
+            .iter_mut()
+            .for_each(|entry| entry.active = active.contains(&*entry.name));
+        self.filter_books();
+    }
+
+    /// Shows only the books with a status, or all of them with None
+    pub fn set_status_filter(&mut self, status: Option<ReadingStatus>) {
+        self.filter_status = status;
+        self.filter_books();
+    }
+
+    pub fn get_status_filter(&self) -> Option<ReadingStatus> {
+        self.filter_status
+    }
+
+    /// Shows only the books with at least the given stars, 0 shows all of them
+    pub fn set_rating_filter(&mut self, min_rating: u8) {
+        self.filter_rating = min_rating.min(5);
+        self.filter_books();
+    }
+
+    pub fn get_rating_filter(&self) -> u8 {
+        self.filter_rating
+    }
+
+    pub fn set_period_filter(&mut self, period: PeriodFilter) {
+        self.filter_period = period;
+        self.filter_books();
+    }
+
+    pub fn get_period_filter(&self) -> PeriodFilter {
+        self.filter_period
+    }
+
+    /// Shows the volumes of every series as a single stack, or all of them
+    pub fn toggle_series_grouping(&mut self) {
+        self.group_series = !self.group_series;
+        self.filter_books();
+    }
+
+    pub fn is_grouped_by_series(&self) -> bool {
+        self.group_series
+    }
+
+    /// Shows the archived books instead of the other ones, or the other way round
+    pub fn toggle_archived_view(&mut self) {
+        self.show_archived = !self.show_archived;
+        self.filter_books();
+    }
+
+    pub fn shows_archived(&self) -> bool {
+        self.show_archived
+    }
+
+    /// Archives a book or takes it back to the library
+    pub fn set_archived(&mut self, path: &str, archived: bool) {
+        let Some(idx) = self.find_book(path) else {
+            return;
+        };
+        self.books[idx].set_archived(archived);
+        self.filter_books();
+    }
+
+    /// Indexes of the books in the same series of the given one,
+    /// sorted by their position in the series
+    pub fn series_volumes(&self, idx: usize) -> Vec<usize> {
+        let Some(key) = self
+            .books
+            .get(idx)
+            .and_then(|book| book.get_series())
+            .map(Series::key)
+        else {
+            return vec![];
+        };
+        let volumes = self
+            .books
+            .iter()
+            .enumerate()
+            .filter(|(_, book)| book.get_series().is_some_and(|series| series.key() == key))
+            .map(|(idx, _)| idx)
+            .collect();
+        self.sort_volumes(volumes)
+    }
+
+    fn sort_volumes(&self, mut volumes: Vec<usize>) -> Vec<usize> {
+        volumes.sort_by(|one, other| {
+            match (
+                self.books[*one].get_series(),
+                self.books[*other].get_series(),
+            ) {
+                (Some(one), Some(other)) => compare_volumes(one, other),
+                _ => std::cmp::Ordering::Equal,
+            }
+        });
+        volumes
+    }
+
+    /// The volume after the given one in its series, if it is in the library
+    pub fn next_in_series(&self, idx: usize) -> Option<usize> {
+        let volumes = self.series_volumes(idx);
+        let pos = volumes.iter().position(|volume| *volume == idx)?;
+        volumes.get(pos + 1).copied()
+    }
+
+    /// The volume before the given one in its series, if it is in the library
+    pub fn prev_in_series(&self, idx: usize) -> Option<usize> {
+        let volumes = self.series_volumes(idx);
+        let pos = volumes.iter().position(|volume| *volume == idx)?;
+        pos.checked_sub(1).map(|pos| volumes[pos])
+    }
+
+    /// Saves the series typed by the user for the selected book
+    pub fn save_series_edit(&mut self) -> Result<(), String> {
+        let series = Series::parse(&self.series_name, &self.series_index)?;
+        let Some(book) = self.get_selected_book_mut() else {
+            return Err("nessun libro selezionato".into());
+        };
+        book.set_series(series)?;
+        self.reset_series_edit();
+        self.filter_books();
+        Ok(())
+    }
+
+    /// Shows again the series of the selected book in the fields to edit it
+    pub fn reset_series_edit(&mut self) {
+        let series = self
+            .get_selected_book()
+            .and_then(|book| book.get_series().cloned());
+        self.series_name = series.as_ref().map(|s| s.name.clone()).unwrap_or_default();
+        self.series_index = series.map(|s| s.format_index()).unwrap_or_default();
+    }
+
+    /// Saves the day typed by the user to finish the selected book by,
+    /// an empty one removes it
+    pub fn save_finish_by_edit(&mut self) -> Result<(), String> {
+        let finish_by = match self.finish_by.trim() {
+            "" => None,
+            date => Some(parse_date(date).ok_or("data non valida, usa gg/mm/aaaa")?),
+        };
+        let Some(book) = self.get_selected_book_mut() else {
+            return Err("nessun libro selezionato".into());
+        };
+        book.set_finish_by(finish_by);
+        self.reset_finish_by_edit();
+        Ok(())
+    }
+
+    /// Shows again the day to finish the selected book by in the field to edit it
+    pub fn reset_finish_by_edit(&mut self) {
+        self.finish_by = self
+            .get_selected_book()
+            .and_then(|book| book.get_reading_info().finish_by)
+            .map(format_date)
+            .unwrap_or_default();
+    }
+
+    /// Paths of the books of the multiple selection,
+    /// or of the selected book if none is marked
+    pub fn get_marked_paths(&self) -> Vec<String> {
+        let marked = self
+            .books
+            .iter()
+            .filter(|book| book.is_marked())
+            .map(|book| book.get_path())
+            .collect::<Vec<_>>();
+        if marked.is_empty() {
+            self.get_selected_book()
+                .map(|book| vec![book.get_path()])
+                .unwrap_or_default()
+        } else {
+            marked
+        }
+    }
+
+    pub fn number_of_marked_books(&self) -> usize {
+        self.books.iter().filter(|book| book.is_marked()).count()
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.books
+            .iter_mut()
+            .filter(|book| book.is_marked())
+            .for_each(|book| book.set_marked(false));
+    }
+
+    /// Deletes a collection, its books stay in the library
+    pub fn delete_collection(&mut self, name: &str) -> Result<(), String> {
+        let mut shelves = Collections::load();
+        if shelves.delete_collection(name) {
+            shelves.save().map_err(|e| e.to_string())?;
+        }
+        self.reload_shelves();
+        Ok(())
+    }
+
+    /// Changes the collections or the tags of the marked books and saves them
+    pub fn edit_shelves(
+        &mut self,
+        edit: impl FnOnce(&mut Collections, &[String]) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let books = self.get_marked_paths();
+        let mut shelves = Collections::load();
+        edit(&mut shelves, &books)?;
+        shelves.save().map_err(|e| e.to_string())?;
+        self.reload_shelves();
+        Ok(())
+    }
+
     pub fn epub_dir(&self) -> Result<PathBuf, String> {
         let path = get_epub_dir();
         return if path.is_dir() {
@@ -90,7 +562,7 @@ impl Library<Book> {
         let vec: Vector<PathBuf> = files
             .filter(|file| file.is_ok())
             .map(|file| file.unwrap().path())
-            .filter(|filename| filename.extension().unwrap_or_default() == "epub")
+            .filter(|filename| dir_watcher::is_book_file(filename))
             .collect();
         Ok(vec)
     }
@@ -114,7 +586,21 @@ impl GUILibrary for Library<Book> {
     // }
     fn check_books_loaded(&mut self) -> bool {
         if let Some(result) = self.book_loader.try_recv() {
-            self.add_book(result.value());
+            match result.value() {
+                Ok(book) => {
+                    let path = book.get_path();
+                    self.loading_paths.remove(path.as_str());
+                    self.import.loaded(&path, Ok(()));
+                    self.add_book(book);
+                }
+                Err((path, e)) => {
+                    self.loading_paths.remove(&path);
+                    // the copy of an imported book that can't be read is not kept
+                    if self.import.loaded(&path, Err(e)) {
+                        let _ = std::fs::remove_file(&path);
+                    }
+                }
+            }
             true
         } else {
             false
@@ -123,16 +609,22 @@ impl GUILibrary for Library<Book> {
 
     fn schedule_book_loading(&mut self, path: impl Into<String>) {
         let path = path.into();
+        self.loading_paths.insert(path.clone());
         let tx = self.book_loader.tx();
+        let shelves = self.shelves.clone();
         self.book_loader.execute(move || {
             let file_name = path.split("/").last().unwrap();
             let folder = file_name.split(".").next().unwrap();
             if !get_saved_books_dir().join(folder).exists() {
-                let _res = epub_utils::extract_all(&path)
-                    .expect(format!("Failed to extract {}", file_name).as_str());
+                // a book that can't be extracted (e.g. protected by DRM) is skipped
+                if let Err(e) = epub_utils::extract_all(&path) {
+                    println!("ERROR: failed to extract {}: {}", file_name, e);
+                    let _ = tx.send(ThreadResult::new(Err((path.clone(), e.to_string())), 0));
+                    return;
+                }
             }
-            let book = Book::new(&path);
-            let result = ThreadResult::new(book, 0);
+            let book = Book::new(&path, &shelves);
+            let result = ThreadResult::new(Ok(book), 0);
             tx.send(result)
                 .expect(format!("Failed to send {}", file_name).as_str());
         });
@@ -149,6 +641,18 @@ impl GUILibrary for Library<Book> {
     fn remove_book(&mut self, idx: usize) {
         if let Some(_) = self.books.get(idx) {
             self.books.remove(idx);
+            // the indexes of the following books are shifted
+            self.selected_book = match self.selected_book {
+                Some(selected) if selected == idx => None,
+                Some(selected) if selected > idx => Some(selected - 1),
+                selected => selected,
+            };
+            self.books
+                .iter_mut()
+                .enumerate()
+                .skip(idx)
+                .for_each(|(i, book)| book.set_index(i));
+            self.filter_books();
         }
     }
 
@@ -181,6 +685,8 @@ impl GUILibrary for Library<Book> {
             self.unselect_current_book();
             self.selected_book = Some(idx);
             self.books[idx].select();
+            self.reset_series_edit();
+            self.reset_finish_by_edit();
         }
     }
 
@@ -203,8 +709,14 @@ impl GUILibrary for Library<Book> {
         let path = path.into();
         let tx = self.cover_loader.tx();
         self.cover_loader.execute(move || {
-            let mut epub = EpubDoc::new(path).map_err(|e| e.to_string()).unwrap();
-            let cover = epub.get_cover().map_err(|e| e.to_string()).unwrap();
+            // books without a cover (e.g. PDF without images) keep the empty one
+            let cover = match epub_utils::get_cover_of_book(&path) {
+                Ok(cover) => cover,
+                Err(e) => {
+                    println!("ERROR: no cover for {}: {}", path, e);
+                    return;
+                }
+            };
             let reader = ImageReader::new(Cursor::new(cover))
                 .with_guessed_format()
                 .map_err(|e| e.to_string())
@@ -243,6 +755,18 @@ impl GUILibrary for Library<Book> {
         self.filter_fav
     }
 
+    fn filters_changed(&self, other: &Self) -> bool {
+        self.filter_fav != other.filter_fav
+            || !self.filter_by.same(&other.filter_by)
+            || !self.filter_collection.same(&other.filter_collection)
+            || !self.filter_tags.same(&other.filter_tags)
+            || self.filter_status != other.filter_status
+            || self.filter_rating != other.filter_rating
+            || self.filter_period != other.filter_period
+            || self.group_series != other.group_series
+            || self.show_archived != other.show_archived
+    }
+
     fn next_book_idx(&self) -> Option<usize> {
         let Some(idx) = self.get_selected_book_idx() else {
             return self.books.iter().enumerate().find(|(_, book)| !book.is_filtered_out()).map(|(idx, _)| idx)
@@ -283,6 +807,20 @@ pub enum SortBy {
     AuthorRev,
     PercRead,
     PercReadRev,
+    Status,
+    StatusRev,
+    /// dates and rating are sorted from the most recent, or the highest, by default
+    Added,
+    AddedRev,
+    LastOpened,
+    LastOpenedRev,
+    Finished,
+    FinishedRev,
+    Rating,
+    RatingRev,
+    /// by series and position in the series, the books without one by title
+    Series,
+    SeriesRev,
 }
 
 impl Library<Book> {
@@ -300,8 +838,8 @@ impl Library<Book> {
         self.books.sort_by(|one, other| match by {
             SortBy::Title => one.get_title().cmp(&other.get_title()),
             SortBy::TitleRev => other.get_title().cmp(&one.get_title()),
-            SortBy::Author => one.get_author().cmp(&other.get_author()),
-            SortBy::AuthorRev => other.get_author().cmp(&one.get_author()),
+            SortBy::Author => compare_authors(one, other),
+            SortBy::AuthorRev => compare_authors(other, one),
             SortBy::PercRead => one
                 .get_perc_read()
                 .partial_cmp(&other.get_perc_read())
@@ -310,6 +848,36 @@ impl Library<Book> {
                 .get_perc_read()
                 .partial_cmp(&one.get_perc_read())
                 .unwrap(),
+            SortBy::Status => one.get_reading_status().cmp(&other.get_reading_status()),
+            SortBy::StatusRev => other.get_reading_status().cmp(&one.get_reading_status()),
+            SortBy::Added => other.get_reading_info().added.cmp(&one.get_reading_info().added),
+            SortBy::AddedRev => one.get_reading_info().added.cmp(&other.get_reading_info().added),
+            SortBy::LastOpened => other
+                .get_reading_info()
+                .last_opened
+                .cmp(&one.get_reading_info().last_opened),
+            SortBy::LastOpenedRev => one
+                .get_reading_info()
+                .last_opened
+                .cmp(&other.get_reading_info().last_opened),
+            SortBy::Finished => other
+                .get_reading_info()
+                .finished
+                .cmp(&one.get_reading_info().finished),
+            SortBy::FinishedRev => one
+                .get_reading_info()
+                .finished
+                .cmp(&other.get_reading_info().finished),
+            SortBy::Rating => other
+                .get_reading_info()
+                .rating
+                .cmp(&one.get_reading_info().rating),
+            SortBy::RatingRev => one
+                .get_reading_info()
+                .rating
+                .cmp(&other.get_reading_info().rating),
+            SortBy::Series => compare_series(one, other),
+            SortBy::SeriesRev => compare_series(other, one),
         });
         self.books.iter_mut().enumerate().for_each(|(i, book)| {
             book.set_index(i);
@@ -324,30 +892,44 @@ impl Library<Book> {
     pub fn filter_books(&mut self) {
         let filter = self.get_filter_by();
         let only_fav = self.filter_fav;
+        let collection = self.filter_collection.clone();
+        let tags = self.filter_tags.clone();
+        let status = self.filter_status;
+        let min_rating = self.filter_rating;
+        let period = self.filter_period;
+        let show_archived = self.show_archived;
+        let now = reading::now();
         let mut cnt = 0;
         self.books.iter_mut().for_each(|book| {
-            let auth = book.get_author().to_lowercase();
-            let title = book.get_title().to_lowercase();
-            let auth_sim = rust_fuzzy_search::fuzzy_compare(filter.as_str(), &auth.as_str());
-            let title_sim = rust_fuzzy_search::fuzzy_compare(filter.as_str(), &title.as_str());
-            let basic_sim = if auth.contains(&*filter) || title.contains(&*filter) {
-                1.0
-            } else {
-                0.0
-            };
-
-            let sim = auth_sim.max(title_sim).max(basic_sim);
-
-            // what is a good number for this threshold??
-            if sim < 0.3 {
+            book.set_stack_size(0);
+            // any contributor matches, e.g. the translator
+            if book.is_archived() != show_archived {
+                book.set_filtered_out(true);
+            } else if !matches_filter(&filter, &book.get_title(), &book.get_contributors()) {
                 book.set_filtered_out(true);
             } else if only_fav && !book.is_favorite() {
                 book.set_filtered_out(true);
+            } else if !matches_shelves(
+                book.get_collections(),
+                book.get_tags(),
+                collection.as_deref().map(|name| name.as_str()),
+                &tags,
+            ) {
+                book.set_filtered_out(true);
+            } else if status.is_some_and(|status| book.get_reading_status() != status) {
+                book.set_filtered_out(true);
+            } else if book.get_reading_info().rating.unwrap_or(0) < min_rating
+                || !period.matches(book.get_reading_info(), now)
+            {
+                book.set_filtered_out(true);
             } else {
                 book.set_filtered_out(false);
                 cnt += 1;
             }
         });
+        if self.group_series {
+            cnt -= self.stack_series();
+        }
 
         if let Some(book) = self.get_selected_book_mut() {
             if book.is_filtered_out() {
@@ -357,6 +939,100 @@ impl Library<Book> {
         }
         self.visible_books = cnt;
     }
+
+    /// Shows only one volume of every series among the books that are not filtered out,
+    /// the first one that hasn't been finished yet.
+    /// Returns the number of volumes that have been hidden
+    fn stack_series(&mut self) -> usize {
+        let mut stacks: HashMap<String, Vec<usize>> = HashMap::new();
+        for (idx, book) in self.books.iter().enumerate() {
+            if book.is_filtered_out() {
+                continue;
+            }
+            if let Some(series) = book.get_series() {
+                stacks.entry(series.key()).or_default().push(idx);
+            }
+        }
+
+        let mut hidden = 0;
+        for volumes in stacks.into_values() {
+            let volumes = self.sort_volumes(volumes);
+            let top = volumes
+                .iter()
+                .copied()
+                .find(|idx| self.books[*idx].get_reading_status() != ReadingStatus::Finished)
+                .unwrap_or(volumes[0]);
+            for &idx in volumes.iter().filter(|idx| **idx != top) {
+                self.books[idx].set_filtered_out(true);
+                hidden += 1;
+            }
+            self.books[top].set_stack_size(volumes.len());
+        }
+        hidden
+    }
+}
+
+/// Books are sorted by the surname of their first author, ignoring the case
+fn compare_authors(one: &Book, other: &Book) -> std::cmp::Ordering {
+    one.get_author_sort()
+        .to_lowercase()
+        .cmp(&other.get_author_sort().to_lowercase())
+        .then_with(|| one.get_title().cmp(&other.get_title()))
+}
+
+/// Books of the same series are next to each other in the order of the series,
+/// the other books are sorted among them by title
+fn compare_series(one: &Book, other: &Book) -> std::cmp::Ordering {
+    let key = |book: &Book| {
+        book.get_series()
+            .map_or_else(|| book.get_title().to_lowercase(), Series::key)
+    };
+    key(one)
+        .cmp(&key(other))
+        .then_with(|| match (one.get_series(), other.get_series()) {
+            (Some(one), Some(other)) => compare_volumes(one, other),
+            _ => std::cmp::Ordering::Equal,
+        })
+}
+
+/// Returns true if a book with the given title and author
+/// is similar enough to the filter to be shown.
+/// The contributors, separated by commas, are compared one by one
+pub fn matches_filter(filter: &str, title: &str, author: &str) -> bool {
+    let auth = author.to_lowercase();
+    let title = title.to_lowercase();
+    // the role of a contributor is not part of the name
+    let auth_sim = auth
+        .split(", ")
+        .map(|contributor| contributor.split(" (").next().unwrap_or_default())
+        .map(|name| rust_fuzzy_search::fuzzy_compare(filter, name))
+        .fold(0.0, f32::max);
+    let title_sim = rust_fuzzy_search::fuzzy_compare(filter, &title.as_str());
+    let basic_sim = if auth.contains(filter) || title.contains(filter) {
+        1.0
+    } else {
+        0.0
+    };
+
+    let sim = auth_sim.max(title_sim).max(basic_sim);
+
+    // what is a good number for this threshold??
+    sim >= 0.3
+}
+
+/// Returns true if a book is in the collection, if any,
+/// and has all the tags
+pub fn matches_shelves(
+    book_collections: &Vector<String>,
+    book_tags: &Vector<String>,
+    collection: Option<&str>,
+    tags: &Vector<String>,
+) -> bool {
+    let in_collection = match collection {
+        Some(name) => book_collections.iter().any(|c| c == name),
+        None => true,
+    };
+    in_collection && tags.iter().all(|tag| book_tags.contains(tag))
 }
 
 pub struct LibrarySelectedBookLens;
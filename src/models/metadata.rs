@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+
+use druid::{im::Vector, Data, Lens, Selector};
+use serde_json::{json, Value};
+
+use super::series::Series;
+
+/// Opens the window to edit the metadata of the selected book
+pub const OPEN_METADATA_EDITOR: Selector<()> = Selector::new("metadata.open-editor");
+/// Saves the metadata typed in the editor
+pub const METADATA_SAVE: Selector<()> = Selector::new("metadata.save");
+/// Sent by the thread that writes the metadata, with the path of the book
+pub const METADATA_SAVED: Selector<Result<String, String>> = Selector::new("metadata.saved");
+/// Adds an empty author to the editor
+pub const METADATA_ADD_AUTHOR: Selector<()> = Selector::new("metadata.add-author");
+/// Removes an author from the editor, given its id
+pub const METADATA_REMOVE_AUTHOR: Selector<usize> = Selector::new("metadata.remove-author");
+
+/// Role of the authors of the text, the other contributors have another one
+pub const AUTHOR_ROLE: &str = "aut";
+
+/// Contributor of a book, the name used to sort the books is e.g. "Svevo, Italo"
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Author {
+    pub name: String,
+    /// empty if the book doesn't say it
+    pub file_as: String,
+    /// MARC relator code, e.g. aut, edt, trl, ill
+    pub role: String,
+}
+
+impl Author {
+    pub fn new(name: impl Into<String>, file_as: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            file_as: file_as.into(),
+            role: AUTHOR_ROLE.to_string(),
+        }
+    }
+
+    /// An empty role means the author of the text
+    pub fn with_role(mut self, role: &str) -> Self {
+        let role = role.trim().to_lowercase();
+        if !role.is_empty() {
+            self.role = role;
+        }
+        self
+    }
+
+    pub fn is_author(&self) -> bool {
+        self.role == AUTHOR_ROLE
+    }
+
+    /// Name used to sort the books, if the book doesn't have it
+    /// the last word of the name is taken as the surname
+    pub fn sort_key(&self) -> String {
+        if !self.file_as.trim().is_empty() {
+            return self.file_as.trim().to_string();
+        }
+        let name = self.name.trim();
+        match name.rsplit_once(' ') {
+            Some((first, last)) if !name.contains(',') => format!("{}, {}", last, first.trim()),
+            _ => name.to_string(),
+        }
+    }
+
+    pub fn role_label(&self) -> &str {
+        match self.role.as_str() {
+            "aut" => "autore",
+            "edt" => "curatore",
+            "trl" => "traduttore",
+            "ill" => "illustratore",
+            role => role,
+        }
+    }
+
+    /// The name shown in the library, with the role if it is not an author
+    pub fn label(&self) -> String {
+        if self.is_author() {
+            self.name.clone()
+        } else {
+            format!("{} ({})", self.name, self.role_label())
+        }
+    }
+}
+
+/// Metadata of a book that can be changed by the user
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BookMetadata {
+    pub title: String,
+    pub authors: Vec<Author>,
+    pub lang: String,
+    pub description: String,
+    pub publisher: String,
+    pub date: String,
+    pub subjects: Vec<String>,
+    pub series: Option<Series>,
+}
+
+/// Values saved when a book doesn't have them, they are not shown in the editor
+const PLACEHOLDERS: [&str; 7] = [
+    "no title",
+    "no author",
+    "no lang",
+    "no date",
+    "no description",
+    "no publisher",
+    "no source",
+];
+
+fn decode_list(value: Option<&String>) -> Vec<Value> {
+    value
+        .and_then(|value| serde_json::from_str::<Value>(value).ok())
+        .and_then(|value| value.as_array().cloned())
+        .unwrap_or_default()
+}
+
+impl BookMetadata {
+    /// Reads the metadata saved in metadata.json.
+    /// Books extracted before the authors were saved have only the name of the first one
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Self {
+        let get = |key: &str| {
+            metadata
+                .get(key)
+                .map(|value| value.trim())
+                .filter(|value| !PLACEHOLDERS.contains(&value.to_lowercase().as_str()))
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let mut authors = decode_list(metadata.get("authors"))
+            .iter()
+            .map(|author| {
+                Author::new(
+                    author["name"].as_str().unwrap_or_default(),
+                    author["file_as"].as_str().unwrap_or_default(),
+                )
+                .with_role(author["role"].as_str().unwrap_or_default())
+            })
+            .filter(|author| !author.name.is_empty())
+            .collect::<Vec<_>>();
+        if authors.is_empty() && !get("author").is_empty() {
+            authors.push(Author::new(get("author"), ""));
+        }
+
+        Self {
+            title: get("title"),
+            authors,
+            lang: get("lang"),
+            description: get("desc"),
+            publisher: get("publisher"),
+            date: get("date"),
+            subjects: decode_list(metadata.get("subjects"))
+                .iter()
+                .filter_map(|subject| subject.as_str().map(|s| s.to_string()))
+                .collect(),
+            series: Series::from_metadata(metadata),
+        }
+    }
+
+    /// Saves the contributors in the map of metadata.json, "author" has only the authors
+    pub fn write_authors_to(&self, metadata: &mut HashMap<String, String>) {
+        let authors = self
+            .authors
+            .iter()
+            .map(|author| {
+                json!({ "name": author.name, "file_as": author.file_as, "role": author.role })
+            })
+            .collect::<Vec<_>>();
+        metadata.insert("author".to_string(), self.get_author_names());
+        metadata.insert("authors".to_string(), Value::from(authors).to_string());
+    }
+
+    /// Saves the metadata in the map of metadata.json, the other values are kept
+    pub fn write_to(&self, metadata: &mut HashMap<String, String>) {
+        self.write_authors_to(metadata);
+        let mut set = |key: &str, value: String| {
+            metadata.insert(key.to_string(), value);
+        };
+        set("title", self.title.clone());
+        set("lang", self.lang.clone());
+        set("desc", self.description.clone());
+        set("publisher", self.publisher.clone());
+        set("date", self.date.clone());
+        set("subjects", json!(self.subjects).to_string());
+        Series::write_to(self.series.as_ref(), metadata);
+    }
+
+    /// The authors of the text, or all the contributors if none of them is an author
+    fn get_main_authors(&self) -> Vec<&Author> {
+        let authors = self
+            .authors
+            .iter()
+            .filter(|author| author.is_author())
+            .collect::<Vec<_>>();
+        if authors.is_empty() {
+            self.authors.iter().collect()
+        } else {
+            authors
+        }
+    }
+
+    /// Names of the authors as they are shown in the library
+    pub fn get_author_names(&self) -> String {
+        self.get_main_authors()
+            .iter()
+            .map(|author| author.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// All the contributors, with the role of the ones that are not authors
+    pub fn get_contributors(&self) -> String {
+        self.authors
+            .iter()
+            .map(Author::label)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Key used to sort the books by author, the one of the first author
+    pub fn get_author_sort(&self) -> String {
+        self.get_main_authors()
+            .first()
+            .map(|author| author.sort_key())
+            .unwrap_or_default()
+    }
+
+    /// Returns an error if the book would have no title
+    pub fn validate(&self) -> Result<(), String> {
+        if self.title.trim().is_empty() {
+            return Err("il titolo è vuoto".into());
+        }
+        Ok(())
+    }
+}
+
+/// Author shown in the editor, the id identifies it when it is removed
+#[derive(Clone, Data, Lens)]
+pub struct AuthorItem {
+    pub id: usize,
+    pub name: String,
+    pub file_as: String,
+    pub role: String,
+}
+
+/// State of the window that edits the metadata of a book
+#[derive(Clone, Data, Lens, Default)]
+pub struct MetadataEditorState {
+    pub book_path: String,
+    pub title: String,
+    pub authors: Vector<AuthorItem>,
+    pub lang: String,
+    pub description: String,
+    pub publisher: String,
+    pub date: String,
+    /// separated by commas
+    pub subjects: String,
+    pub series: String,
+    pub series_index: String,
+    /// image chosen for the new cover, empty to keep the current one
+    pub cover_path: String,
+    /// the metadata are written also in the EPUB, so that other readers see them
+    pub write_to_epub: bool,
+    pub is_epub: bool,
+    pub status: String,
+    pub saving: bool,
+}
+
+impl MetadataEditorState {
+    /// Shows in the editor the metadata of a book
+    pub fn load(&mut self, book_path: &str, metadata: &BookMetadata) {
+        let is_epub = book_path.to_lowercase().ends_with(".epub");
+        *self = Self {
+            book_path: book_path.to_string(),
+            title: metadata.title.clone(),
+            authors: Vector::new(),
+            lang: metadata.lang.clone(),
+            description: metadata.description.clone(),
+            publisher: metadata.publisher.clone(),
+            date: metadata.date.clone(),
+            subjects: metadata.subjects.join(", "),
+            series: metadata
+                .series
+                .as_ref()
+                .map(|s| s.name.clone())
+                .unwrap_or_default(),
+            series_index: metadata
+                .series
+                .as_ref()
+                .map(|s| s.format_index())
+                .unwrap_or_default(),
+            is_epub,
+            write_to_epub: is_epub,
+            ..Default::default()
+        };
+        metadata
+            .authors
+            .iter()
+            .for_each(|author| self.add_author(&author.name, &author.file_as, &author.role));
+    }
+
+    pub fn add_author(&mut self, name: &str, file_as: &str, role: &str) {
+        let id = self.authors.iter().map(|a| a.id + 1).max().unwrap_or(0);
+        self.authors.push_back(AuthorItem {
+            id,
+            name: name.to_string(),
+            file_as: file_as.to_string(),
+            role: role.to_string(),
+        });
+    }
+
+    pub fn remove_author(&mut self, id: usize) {
+        self.authors.retain(|author| author.id != id);
+    }
+
+    /// The metadata typed by the user, the empty authors and subjects are skipped
+    pub fn get_metadata(&self) -> Result<BookMetadata, String> {
+        let metadata = BookMetadata {
+            title: self.title.trim().to_string(),
+            authors: self
+                .authors
+                .iter()
+                .filter(|author| !author.name.trim().is_empty())
+                .map(|author| {
+                    Author::new(author.name.trim(), author.file_as.trim()).with_role(&author.role)
+                })
+                .collect(),
+            lang: self.lang.trim().to_string(),
+            description: self.description.trim().to_string(),
+            publisher: self.publisher.trim().to_string(),
+            date: self.date.trim().to_string(),
+            subjects: self
+                .subjects
+                .split(',')
+                .map(|subject| subject.trim().to_string())
+                .filter(|subject| !subject.is_empty())
+                .collect(),
+            series: Series::parse(&self.series, &self.series_index)?,
+        };
+        metadata.validate()?;
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_in_json() {
+        let mut map = HashMap::new();
+        map.insert("title".to_string(), "La coscienza di Zeno".to_string());
+        map.insert("author".to_string(), "Italo Svevo".to_string());
+        map.insert("desc".to_string(), "No description".to_string());
+        map.insert("chapters".to_string(), "8".to_string());
+
+        // only the first author is known for books extracted before
+        let metadata = BookMetadata::from_metadata(&map);
+        assert_eq!(metadata.authors, vec![Author::new("Italo Svevo", "")]);
+        assert_eq!(metadata.description, "");
+
+        let mut metadata = metadata;
+        metadata.authors = vec![
+            Author::new("Italo Svevo", "Svevo, Italo"),
+            Author::new("Mario Rossi", ""),
+        ];
+        metadata.publisher = "Cappelli".to_string();
+        metadata.subjects = vec!["Romanzo".to_string(), "Psicoanalisi".to_string()];
+        metadata.series = Series::parse("Classici", "3").unwrap();
+        metadata.write_to(&mut map);
+        assert_eq!(map["author"], "Italo Svevo, Mario Rossi");
+        assert_eq!(map["chapters"], "8");
+        assert_eq!(BookMetadata::from_metadata(&map), metadata);
+    }
+
+    #[test]
+    fn test_editor_state() {
+        let metadata = BookMetadata {
+            title: "Dune".to_string(),
+            authors: vec![Author::new("Frank Herbert", "Herbert, Frank")],
+            subjects: vec!["Fantascienza".to_string()],
+            ..Default::default()
+        };
+        let mut state = MetadataEditorState::default();
+        state.load("/libri/dune.epub", &metadata);
+        assert!(state.write_to_epub);
+        assert_eq!(state.get_metadata(), Ok(metadata.clone()));
+
+        state.add_author(" ", "", "");
+        state.add_author("Brian Herbert", "", "aut");
+        state.subjects = "Fantascienza, , Classici".to_string();
+        let edited = state.get_metadata().unwrap();
+        assert_eq!(edited.authors.len(), 2);
+        assert_eq!(edited.subjects, vec!["Fantascienza", "Classici"]);
+
+        state.remove_author(0);
+        assert_eq!(
+            state.get_metadata().unwrap().authors[0].name,
+            "Brian Herbert"
+        );
+
+        state.title = "  ".to_string();
+        assert!(state.get_metadata().is_err());
+    }
+
+    #[test]
+    fn test_contributors() {
+        let metadata = BookMetadata {
+            authors: vec![
+                Author::new("Lev Tolstoj", "").with_role("trl"),
+                Author::new("Fëdor Dostoevskij", "Dostoevskij, Fëdor"),
+                Author::new("Alfredo Polledro", "").with_role("TRL"),
+                Author::new("Mario Rossi", "").with_role(""),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            metadata.get_author_names(),
+            "Fëdor Dostoevskij, Mario Rossi"
+        );
+        assert_eq!(
+            metadata.get_contributors(),
+            "Lev Tolstoj (traduttore), Fëdor Dostoevskij, Alfredo Polledro (traduttore), Mario Rossi"
+        );
+        assert_eq!(metadata.get_author_sort(), "Dostoevskij, Fëdor");
+
+        // the roles are kept in metadata.json
+        let mut map = HashMap::new();
+        metadata.write_to(&mut map);
+        assert_eq!(BookMetadata::from_metadata(&map), metadata);
+
+        assert_eq!(Author::new("Italo Svevo", "").sort_key(), "Svevo, Italo");
+        assert_eq!(Author::new("Omero", "").sort_key(), "Omero");
+        let editors = BookMetadata {
+            authors: vec![Author::new("Gianni Rodari", "").with_role("edt")],
+            ..Default::default()
+        };
+        assert_eq!(editors.get_author_names(), "Gianni Rodari");
+        assert_eq!(editors.get_author_sort(), "Rodari, Gianni");
+    }
+}
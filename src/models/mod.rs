@@ -1,5 +1,20 @@
+pub mod archive;
+pub mod backup;
 pub mod book;
+pub mod book_actions;
+pub mod duplicates;
+pub mod goals;
+pub mod import;
+pub mod kosync;
 pub mod library;
+pub mod metadata;
+pub mod navigation;
 pub mod note;
+pub mod opds;
+pub mod reading;
 pub mod rich;
+pub mod series;
+pub mod stats;
+pub mod sync;
+pub mod watcher;
 pub mod command;
@@ -0,0 +1,261 @@
+use druid::{im::Vector, Data, Selector};
+
+/// Opens the window to go to a page, a percentage or a page of the printed edition
+pub const OPEN_GO_TO_WINDOW: Selector<()> = Selector::new("navigation.open-go-to-window");
+
+/// Positions kept in each direction, the oldest ones are forgotten
+const MAX_HISTORY: usize = 50;
+
+/// A page of a book
+#[derive(Clone, Copy, Data, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub chapter: usize,
+    pub page: usize,
+}
+
+impl Position {
+    pub fn new(chapter: usize, page: usize) -> Self {
+        Self { chapter, page }
+    }
+
+    /// Chapter and page of a page counted from the start of the book,
+    /// given the first and last page of every chapter. Past the end it is the last page
+    pub fn from_page(page: usize, pages_per_chapter: &[(usize, usize)]) -> Option<Self> {
+        let (_, last) = *pages_per_chapter.last()?;
+        let page = page.min(last);
+        let chapter = pages_per_chapter
+            .iter()
+            .position(|(start, end)| page >= *start && page <= *end)
+            .unwrap_or(pages_per_chapter.len() - 1);
+        let start = pages_per_chapter[chapter].0;
+        Some(Self::new(chapter, page.saturating_sub(start)))
+    }
+}
+
+/// Offset in the text of a chapter where one of its pages starts, given the length of its pages
+pub fn page_start(page_lens: &[usize], page: usize) -> usize {
+    page_lens.iter().take(page).sum()
+}
+
+/// Page of a chapter that holds an offset in its text, the last one past the end of the text
+pub fn page_at(page_lens: &[usize], offset: usize) -> usize {
+    let mut start = 0;
+    for (page, len) in page_lens.iter().enumerate() {
+        start += len;
+        if offset < start {
+            return page;
+        }
+    }
+    page_lens.len().saturating_sub(1)
+}
+
+/// Where the reader asks to go in the go-to window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GoTo {
+    /// page as shown by the page counter
+    Page(usize),
+    Percent(f64),
+    /// page of the printed edition, from 1
+    Printed(usize),
+}
+
+impl GoTo {
+    /// Reads "120" as a page, "45%" as a percentage and "p. 120" as a page of the printed edition
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim().to_lowercase();
+        let invalid = || format!("\"{}\" non è una pagina valida", text);
+        if let Some(percent) = text.strip_suffix('%') {
+            return percent
+                .trim()
+                .replace(',', ".")
+                .parse::<f64>()
+                .ok()
+                .filter(|percent| (0.0..=100.0).contains(percent))
+                .map(GoTo::Percent)
+                .ok_or_else(invalid);
+        }
+        if text.starts_with('p') {
+            // "p120", "p. 120" or "pag. 120"
+            let page = text.trim_start_matches(|c: char| c.is_alphabetic() || c == '.');
+            return page
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|page| *page > 0)
+                .map(GoTo::Printed)
+                .ok_or_else(invalid);
+        }
+        text.parse::<usize>().map(GoTo::Page).map_err(|_| invalid())
+    }
+
+    /// Page counted from the start of the book, given the pages of the book
+    /// and, to go to a printed page, the pages of its printed edition
+    pub fn page(&self, total_pages: usize, printed_pages: Option<usize>) -> Result<usize, String> {
+        let last = total_pages.saturating_sub(1);
+        match *self {
+            GoTo::Page(page) => Ok(page.min(last)),
+            GoTo::Percent(percent) => {
+                Ok(((total_pages as f64 * percent / 100.0) as usize).min(last))
+            }
+            GoTo::Printed(page) => {
+                let printed_pages = printed_pages
+                    .filter(|pages| *pages > 0)
+                    .ok_or("indica le pagine dell'edizione stampata")?;
+                // the pages of the two editions are assumed to hold the same text
+                Ok(((page - 1) * total_pages / printed_pages).min(last))
+            }
+        }
+    }
+}
+
+/// Where the reader asks to go in the history
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Navigation {
+    Back,
+    Forward,
+    /// the furthest position read, after browsing backwards
+    Furthest,
+}
+
+/// Positions the reader jumped from, like the history of a browser.
+/// Turning the pages one at a time isn't a jump and isn't recorded
+#[derive(Clone, Data, Debug, Default, PartialEq)]
+pub struct NavHistory {
+    back: Vector<Position>,
+    forward: Vector<Position>,
+    /// furthest position reached in the book
+    furthest: Option<Position>,
+}
+
+impl NavHistory {
+    pub fn new(start: Position) -> Self {
+        Self {
+            furthest: Some(start),
+            ..Default::default()
+        }
+    }
+
+    /// A jump, the positions after the one left can no longer be reached going forward
+    pub fn jumped(&mut self, from: Position, to: Position) {
+        self.reached(to);
+        if from == to {
+            return;
+        }
+        self.back.push_back(from);
+        if self.back.len() > MAX_HISTORY {
+            self.back.pop_front();
+        }
+        self.forward.clear();
+    }
+
+    /// Goes back to the position before the last jump
+    pub fn back(&mut self, current: Position) -> Option<Position> {
+        let position = self.back.pop_back()?;
+        self.forward.push_back(current);
+        Some(position)
+    }
+
+    /// Goes again to the position left going back
+    pub fn forward(&mut self, current: Position) -> Option<Position> {
+        let position = self.forward.pop_back()?;
+        self.back.push_back(current);
+        Some(position)
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+
+    /// Updates the furthest position with one the reader has reached
+    pub fn reached(&mut self, position: Position) {
+        if self.furthest.is_none_or(|furthest| position > furthest) {
+            self.furthest = Some(position);
+        }
+    }
+
+    /// The furthest position, if the reader is behind it
+    pub fn furthest_after(&self, current: Position) -> Option<Position> {
+        self.furthest.filter(|furthest| *furthest > current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_back_forward_and_furthest() {
+        let mut history = NavHistory::new(Position::new(3, 4));
+        assert_eq!(history.furthest_after(Position::new(3, 4)), None);
+
+        // from the chapter list to the start of the book
+        history.jumped(Position::new(3, 4), Position::new(0, 0));
+        history.jumped(Position::new(0, 2), Position::new(1, 0));
+        assert!(!history.can_go_forward());
+        assert_eq!(history.back(Position::new(1, 1)), Some(Position::new(0, 2)));
+        assert_eq!(history.back(Position::new(0, 2)), Some(Position::new(3, 4)));
+        assert_eq!(history.back(Position::new(3, 4)), None);
+        assert_eq!(
+            history.forward(Position::new(3, 4)),
+            Some(Position::new(0, 2))
+        );
+
+        // a new jump forgets the positions ahead
+        history.jumped(Position::new(0, 2), Position::new(2, 0));
+        assert!(!history.can_go_forward());
+        assert!(history.can_go_back());
+        assert_eq!(
+            history.furthest_after(Position::new(2, 0)),
+            Some(Position::new(3, 4))
+        );
+        history.reached(Position::new(5, 0));
+        assert_eq!(
+            history.furthest_after(Position::new(2, 0)),
+            Some(Position::new(5, 0))
+        );
+    }
+
+    #[test]
+    fn test_go_to_page_percent_and_printed_page() {
+        let pages_per_chapter = [(0, 0), (1, 10), (11, 19)];
+        assert_eq!(
+            Position::from_page(12, &pages_per_chapter),
+            Some(Position::new(2, 1))
+        );
+        assert_eq!(
+            Position::from_page(50, &pages_per_chapter),
+            Some(Position::new(2, 8))
+        );
+        assert_eq!(Position::from_page(1, &[]), None);
+
+        assert_eq!(GoTo::parse(" 12 "), Ok(GoTo::Page(12)));
+        assert_eq!(GoTo::parse("50%"), Ok(GoTo::Percent(50.0)));
+        assert_eq!(GoTo::parse("pag. 31"), Ok(GoTo::Printed(31)));
+        assert!(GoTo::parse("120%").is_err());
+        assert!(GoTo::parse("p0").is_err());
+        assert!(GoTo::parse("dodici").is_err());
+
+        assert_eq!(GoTo::Page(30).page(20, None), Ok(19));
+        assert_eq!(GoTo::Percent(50.0).page(20, None), Ok(10));
+        assert_eq!(GoTo::Percent(100.0).page(20, None), Ok(19));
+        assert_eq!(GoTo::Printed(31).page(20, Some(40)), Ok(15));
+        assert!(GoTo::Printed(31).page(20, None).is_err());
+    }
+
+    #[test]
+    fn test_offsets_of_pages() {
+        let page_lens = [100, 80, 120];
+        assert_eq!(page_start(&page_lens, 0), 0);
+        assert_eq!(page_start(&page_lens, 2), 180);
+        assert_eq!(page_at(&page_lens, 0), 0);
+        assert_eq!(page_at(&page_lens, 99), 0);
+        assert_eq!(page_at(&page_lens, 100), 1);
+        assert_eq!(page_at(&page_lens, 250), 2);
+        assert_eq!(page_at(&page_lens, 1_000), 2);
+        assert_eq!(page_at(&[], 10), 0);
+    }
+}
@@ -79,6 +79,13 @@ impl BookNotes {
     pub fn len(&self) -> usize {
         self.chapter_page_notes.len()
     }
+
+    /// Whether there are notes about a page, not only the current one
+    pub fn has_notes(&self, chapter: usize, page: usize) -> bool {
+        self.all_notes
+            .get(&(chapter, page))
+            .map_or(false, |notes| !notes.is_empty())
+    }
 }
 
 impl Default for BookNotes {
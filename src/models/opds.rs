@@ -0,0 +1,115 @@
+use std::{path::PathBuf, sync::Arc};
+
+use druid::{im::Vector, Data, Lens, Selector};
+
+use crate::utils::opds_client::{OpdsEntry, OpdsFeed};
+
+/// Opens the window of the catalog browser
+pub const OPEN_OPDS_WINDOW: Selector<()> = Selector::new("opds.open-window");
+/// Loads the feed at the given url
+pub const OPDS_OPEN_FEED: Selector<String> = Selector::new("opds.open-feed");
+/// Searches the given terms in the current catalog
+pub const OPDS_SEARCH: Selector<String> = Selector::new("opds.search");
+/// Goes back to the previous feed
+pub const OPDS_BACK: Selector<()> = Selector::new("opds.back");
+/// Opens a navigation entry or downloads a book
+pub const OPDS_ACTIVATE_ENTRY: Selector<Arc<OpdsEntry>> = Selector::new("opds.activate-entry");
+/// Sent by the loading thread when the feed has been downloaded,
+/// the flag tells if the user is going back in the history
+pub const OPDS_FEED_LOADED: Selector<(Result<OpdsFeed, String>, bool)> =
+    Selector::new("opds.feed-loaded");
+/// Sent by the loading thread with the url of the cover and its RGB thumbnail
+pub const OPDS_COVER_LOADED: Selector<(String, Vec<u8>)> = Selector::new("opds.cover-loaded");
+/// Starts the server that shares the library, or stops it if it is running
+pub const TOGGLE_OPDS_SERVER: Selector<()> = Selector::new("opds.toggle-server");
+/// Sent by the download thread with the path of the downloaded book
+pub const OPDS_BOOK_DOWNLOADED: Selector<Result<PathBuf, String>> =
+    Selector::new("opds.book-downloaded");
+
+pub const OPDS_COVER_WIDTH: u32 = 60;
+pub const OPDS_COVER_HEIGHT: u32 = 90;
+
+/// Entry of the feed shown in the catalog browser
+#[derive(Clone, Data, Lens)]
+pub struct OpdsEntryItem {
+    pub title: String,
+    pub author: String,
+    pub summary: String,
+    pub is_book: bool,
+    pub cover_url: Option<String>,
+    /// RGB thumbnail of the cover, empty while it is loading
+    pub cover: Arc<Vec<u8>>,
+    pub entry: Arc<OpdsEntry>,
+}
+
+impl From<&OpdsEntry> for OpdsEntryItem {
+    fn from(entry: &OpdsEntry) -> Self {
+        Self {
+            title: entry.title.clone(),
+            author: entry.author.clone(),
+            summary: entry.summary.clone(),
+            is_book: entry.navigation.is_none() && !entry.acquisitions.is_empty(),
+            cover_url: entry.cover.clone(),
+            cover: Arc::new(vec![]),
+            entry: Arc::new(entry.clone()),
+        }
+    }
+}
+
+/// State of the catalog browser
+#[derive(Clone, Data, Lens, Default)]
+pub struct OpdsState {
+    pub url: String,
+    pub search: String,
+    pub title: String,
+    pub status: String,
+    pub loading: bool,
+    pub entries: Vector<OpdsEntryItem>,
+    pub can_search: bool,
+    pub next: Option<String>,
+    /// feeds visited before the current one
+    history: Vector<String>,
+    #[data(ignore)]
+    feed: Option<Arc<OpdsFeed>>,
+}
+
+impl OpdsState {
+    pub fn get_feed(&self) -> Option<Arc<OpdsFeed>> {
+        self.feed.clone()
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    /// Returns the url of the previous feed, removing it from the history
+    pub fn pop_history(&mut self) -> Option<String> {
+        self.history.pop_back()
+    }
+
+    /// Shows a new feed, the current one is kept in the history
+    /// unless the user is going back
+    pub fn set_feed(&mut self, feed: OpdsFeed, going_back: bool) {
+        if let Some(current) = self.feed.as_ref() {
+            if !going_back && current.url != feed.url {
+                self.history.push_back(current.url.clone());
+            }
+        }
+        self.url = feed.url.clone();
+        self.title = feed.title.clone();
+        self.status = format!("{} elementi", feed.entries.len());
+        self.entries = feed.entries.iter().map(OpdsEntryItem::from).collect();
+        self.can_search = feed.search.is_some();
+        self.next = feed.next.clone();
+        self.loading = false;
+        self.feed = Some(Arc::new(feed));
+    }
+
+    pub fn set_cover(&mut self, url: &str, cover: Vec<u8>) {
+        let cover = Arc::new(cover);
+        self.entries
+            .iter_mut()
+            .filter(|item| item.cover_url.as_deref() == Some(url))
+            .for_each(|item| item.cover = cover.clone());
+    }
+}
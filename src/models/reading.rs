@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use druid::Data;
+
+use crate::utils::opds_server::format_timestamp;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Where the user is with a book
+#[derive(Clone, Copy, Data, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReadingStatus {
+    ToRead,
+    Reading,
+    Finished,
+    Abandoned,
+}
+
+impl ReadingStatus {
+    pub const ALL: [ReadingStatus; 4] = [
+        ReadingStatus::ToRead,
+        ReadingStatus::Reading,
+        ReadingStatus::Finished,
+        ReadingStatus::Abandoned,
+    ];
+
+    /// Value saved in the metadata of the book
+    pub fn key(&self) -> &'static str {
+        match self {
+            ReadingStatus::ToRead => "to_read",
+            ReadingStatus::Reading => "reading",
+            ReadingStatus::Finished => "finished",
+            ReadingStatus::Abandoned => "abandoned",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|status| status.key() == key)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReadingStatus::ToRead => "Da leggere",
+            ReadingStatus::Reading => "In lettura",
+            ReadingStatus::Finished => "Letto",
+            ReadingStatus::Abandoned => "Abbandonato",
+        }
+    }
+
+    /// The status after this one, used by the buttons that cycle through them
+    pub fn next(&self) -> Self {
+        let idx = Self::ALL
+            .iter()
+            .position(|status| status == self)
+            .unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+/// Progress of the user with a book, saved in its metadata.
+/// The dates are seconds since the epoch
+#[derive(Clone, Data, Debug, PartialEq)]
+pub struct ReadingInfo {
+    pub status: ReadingStatus,
+    pub added: u64,
+    pub last_opened: Option<u64>,
+    pub finished: Option<u64>,
+    /// stars from 1 to 5
+    pub rating: Option<u8>,
+    /// day the user plans to finish the book by
+    pub finish_by: Option<u64>,
+    /// pages of the printed edition, to go to one of its pages
+    pub printed_pages: Option<usize>,
+}
+
+impl Default for ReadingInfo {
+    fn default() -> Self {
+        Self {
+            status: ReadingStatus::ToRead,
+            added: 0,
+            last_opened: None,
+            finished: None,
+            rating: None,
+            finish_by: None,
+            printed_pages: None,
+        }
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Seconds to add to a UTC time to get the local one, e.g. 3600 in Italy in the winter.
+/// SQLite reads the time zone of the system, 0 if it can't
+pub fn local_offset() -> i64 {
+    rusqlite::Connection::open_in_memory()
+        .and_then(|conn| {
+            conn.query_row(
+                "SELECT strftime('%s', 'now', 'localtime') - strftime('%s', 'now')",
+                [],
+                |row| row.get(0),
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Formats seconds since the epoch as a date, e.g. 31/01/2023
+pub fn format_date(secs: u64) -> String {
+    let date = format_timestamp(secs);
+    format!("{}/{}/{}", &date[8..10], &date[5..7], &date[0..4])
+}
+
+/// Reads a date typed as 31/01/2023, it is the seconds since the epoch at its start
+pub fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date
+        .trim()
+        .split('/')
+        .map(|part| part.trim().parse::<i64>().ok());
+    let (day, month, year) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some()
+        || year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+    {
+        return None;
+    }
+    // days from the civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let secs = (era * 146097 + doe - 719468) as u64 * DAY;
+    // days like 31/02 don't exist
+    let valid = format_date(secs) == format!("{:02}/{:02}/{:04}", day, month, year);
+    valid.then_some(secs)
+}
+
+impl ReadingInfo {
+    /// Reads the progress from the metadata of a book.
+    /// Books that have never been saved with a status are to read
+    /// or being read, depending on a position being saved, and their
+    /// date added is the one of the file
+    pub fn from_metadata(
+        metadata: &HashMap<String, String>,
+        file_time: u64,
+        has_progress: bool,
+    ) -> Self {
+        let date = |key: &str| metadata.get(key).and_then(|x| x.parse::<u64>().ok());
+        let status = metadata
+            .get("status")
+            .and_then(|status| ReadingStatus::from_key(status))
+            .unwrap_or(if has_progress {
+                ReadingStatus::Reading
+            } else {
+                ReadingStatus::ToRead
+            });
+        Self {
+            status,
+            added: date("added").unwrap_or(file_time),
+            last_opened: date("last_opened"),
+            finished: date("finished"),
+            rating: metadata
+                .get("rating")
+                .and_then(|x| x.parse::<u8>().ok())
+                .filter(|rating| (1..=5).contains(rating)),
+            finish_by: date("finish_by"),
+            printed_pages: metadata
+                .get("printed_pages")
+                .and_then(|x| x.parse::<usize>().ok())
+                .filter(|pages| *pages > 0),
+        }
+    }
+
+    pub fn write_to(&self, metadata: &mut HashMap<String, String>) {
+        let mut set = |key: &str, value: Option<String>| match value {
+            Some(value) => {
+                metadata.insert(key.to_string(), value);
+            }
+            None => {
+                metadata.remove(key);
+            }
+        };
+        set("status", Some(self.status.key().to_string()));
+        set("added", Some(self.added.to_string()));
+        set("last_opened", self.last_opened.map(|x| x.to_string()));
+        set("finished", self.finished.map(|x| x.to_string()));
+        set("rating", self.rating.map(|x| x.to_string()));
+        set("finish_by", self.finish_by.map(|x| x.to_string()));
+        set("printed_pages", self.printed_pages.map(|x| x.to_string()));
+    }
+
+    /// The book has been opened, a book to read or abandoned is being read again
+    pub fn opened(&mut self, time: u64) {
+        self.last_opened = Some(time);
+        if self.status == ReadingStatus::ToRead || self.status == ReadingStatus::Abandoned {
+            self.status = ReadingStatus::Reading;
+        }
+    }
+
+    /// Changes the status, the date of the first time it is finished is kept
+    pub fn set_status(&mut self, status: ReadingStatus, time: u64) {
+        self.status = status;
+        if status == ReadingStatus::Finished && self.finished.is_none() {
+            self.finished = Some(time);
+        }
+    }
+}
+
+/// Filter of the library on the dates of the books
+#[derive(Clone, Copy, Data, Debug, PartialEq)]
+pub enum PeriodFilter {
+    Any,
+    OpenedLastMonth,
+    AddedLastMonth,
+    FinishedLastYear,
+}
+
+impl PeriodFilter {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PeriodFilter::Any => "Sempre",
+            PeriodFilter::OpenedLastMonth => "Aperti negli ultimi 30 giorni",
+            PeriodFilter::AddedLastMonth => "Aggiunti negli ultimi 30 giorni",
+            PeriodFilter::FinishedLastYear => "Finiti nell'ultimo anno",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            PeriodFilter::Any => PeriodFilter::OpenedLastMonth,
+            PeriodFilter::OpenedLastMonth => PeriodFilter::AddedLastMonth,
+            PeriodFilter::AddedLastMonth => PeriodFilter::FinishedLastYear,
+            PeriodFilter::FinishedLastYear => PeriodFilter::Any,
+        }
+    }
+
+    pub fn matches(&self, info: &ReadingInfo, time: u64) -> bool {
+        let since =
+            |date: Option<u64>, days: u64| date.is_some_and(|date| date + days * DAY >= time);
+        match self {
+            PeriodFilter::Any => true,
+            PeriodFilter::OpenedLastMonth => since(info.last_opened, 30),
+            PeriodFilter::AddedLastMonth => since(Some(info.added), 30),
+            PeriodFilter::FinishedLastYear => since(info.finished, 365),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reading_info_in_metadata() {
+        let mut metadata = HashMap::new();
+        metadata.insert("title".to_string(), "Divina Commedia".to_string());
+
+        let info = ReadingInfo::from_metadata(&metadata, 100, false);
+        assert_eq!(info.status, ReadingStatus::ToRead);
+        assert_eq!(info.added, 100);
+        assert_eq!(
+            ReadingInfo::from_metadata(&metadata, 100, true).status,
+            ReadingStatus::Reading
+        );
+
+        let mut info = info;
+        info.opened(200);
+        info.set_status(ReadingStatus::Finished, 300);
+        info.set_status(ReadingStatus::Reading, 400);
+        info.set_status(ReadingStatus::Finished, 500);
+        info.rating = Some(4);
+        info.finish_by = Some(1_000);
+        info.printed_pages = Some(320);
+        info.write_to(&mut metadata);
+        assert_eq!(metadata["status"], "finished");
+        assert_eq!(metadata["title"], "Divina Commedia");
+
+        let read = ReadingInfo::from_metadata(&metadata, 0, false);
+        assert_eq!(read, info);
+        assert_eq!(read.last_opened, Some(200));
+        assert_eq!(read.finished, Some(300));
+
+        info.rating = None;
+        info.write_to(&mut metadata);
+        assert!(!metadata.contains_key("rating"));
+    }
+
+    #[test]
+    fn test_period_filter() {
+        let time = 1_000 * DAY;
+        let info = ReadingInfo {
+            status: ReadingStatus::Finished,
+            added: time - 40 * DAY,
+            last_opened: Some(time - 10 * DAY),
+            finished: Some(time - 200 * DAY),
+            rating: None,
+            finish_by: None,
+            printed_pages: None,
+        };
+        assert!(PeriodFilter::Any.matches(&info, time));
+        assert!(PeriodFilter::OpenedLastMonth.matches(&info, time));
+        assert!(!PeriodFilter::AddedLastMonth.matches(&info, time));
+        assert!(PeriodFilter::FinishedLastYear.matches(&info, time));
+        assert!(!PeriodFilter::FinishedLastYear.matches(&ReadingInfo::default(), time));
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(1_600_000_000), "13/09/2020");
+        assert_eq!(parse_date("13/09/2020"), Some(1_599_955_200));
+        assert_eq!(
+            parse_date(" 1/3/2024 ").map(format_date),
+            Some("01/03/2024".into())
+        );
+        assert_eq!(parse_date("29/02/2023"), None);
+        assert_eq!(parse_date("domani"), None);
+    }
+}
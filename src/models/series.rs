@@ -0,0 +1,176 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use druid::Data;
+
+/// Series a book belongs to, e.g. the volumes of a saga.
+/// It is saved in the metadata of the book like Calibre does
+#[derive(Clone, Data, Debug, PartialEq)]
+pub struct Series {
+    pub name: String,
+    /// position of the book in the series, it can have decimals (e.g. 2.5)
+    pub index: Option<f64>,
+}
+
+impl Series {
+    /// Reads the series from the metadata of a book, if it has one
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Option<Self> {
+        let name = metadata.get("series")?.trim();
+        if name.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name: name.to_string(),
+            index: metadata.get("series_index").and_then(|x| parse_index(x)),
+        })
+    }
+
+    /// Saves the series in the metadata, None removes it
+    pub fn write_to(series: Option<&Series>, metadata: &mut HashMap<String, String>) {
+        metadata.remove("series");
+        metadata.remove("series_index");
+        if let Some(series) = series {
+            metadata.insert("series".to_string(), series.name.clone());
+            if let Some(index) = series.index {
+                metadata.insert("series_index".to_string(), index.to_string());
+            }
+        }
+    }
+
+    /// Builds the series typed by the user, an empty name means no series
+    pub fn parse(name: &str, index: &str) -> Result<Option<Self>, String> {
+        let name = name.trim();
+        let index = index.trim();
+        if name.is_empty() {
+            return Ok(None);
+        }
+        let index = if index.is_empty() {
+            None
+        } else {
+            Some(parse_index(index).ok_or(format!("\"{}\" non è un numero valido", index))?)
+        };
+        Ok(Some(Self {
+            name: name.to_string(),
+            index,
+        }))
+    }
+
+    /// Series are compared ignoring the case and the spaces around their names
+    pub fn key(&self) -> String {
+        self.name.trim().to_lowercase()
+    }
+
+    /// The index as the user writes it, e.g. 2 instead of 2.0
+    pub fn format_index(&self) -> String {
+        self.index
+            .map(|index| index.to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn label(&self) -> String {
+        match self.index {
+            Some(_) => format!("{} #{}", self.name, self.format_index()),
+            None => self.name.clone(),
+        }
+    }
+}
+
+fn parse_index(index: &str) -> Option<f64> {
+    index
+        .trim()
+        .replace(',', ".")
+        .parse::<f64>()
+        .ok()
+        .filter(|index| index.is_finite() && *index >= 0.0)
+}
+
+/// Order of the volumes of a series, the ones without an index go last
+pub fn compare_volumes(one: &Series, other: &Series) -> Ordering {
+    match (one.index, other.index) {
+        (Some(one), Some(other)) => one.total_cmp(&other),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+/// Reads the series from the metadata of an EPUB, given a function returning
+/// the first value of a meta: Calibre saves it as `calibre:series`,
+/// EPUB3 as a `belongs-to-collection` refined by its `group-position`
+pub fn series_from_epub(mdata: impl Fn(&str) -> Option<String>) -> Option<Series> {
+    let (name, index) = match mdata("calibre:series") {
+        Some(name) => (name, mdata("calibre:series_index")),
+        None => (mdata("belongs-to-collection")?, mdata("group-position")),
+    };
+    let mut metadata = HashMap::new();
+    metadata.insert("series".to_string(), name);
+    if let Some(index) = index {
+        metadata.insert("series_index".to_string(), index);
+    }
+    Series::from_metadata(&metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(values: &[(&str, &str)]) -> HashMap<String, String> {
+        values
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_series_from_epub() {
+        let calibre = meta(&[
+            ("calibre:series", "Il Signore degli Anelli"),
+            ("calibre:series_index", "2.0"),
+            ("belongs-to-collection", "Altro"),
+        ]);
+        let series = series_from_epub(|key| calibre.get(key).cloned()).unwrap();
+        assert_eq!(series.name, "Il Signore degli Anelli");
+        assert_eq!(series.index, Some(2.0));
+        assert_eq!(series.label(), "Il Signore degli Anelli #2");
+
+        let epub3 = meta(&[
+            ("belongs-to-collection", "Fondazione "),
+            ("group-position", "1.5"),
+        ]);
+        let series = series_from_epub(|key| epub3.get(key).cloned()).unwrap();
+        assert_eq!(series.name, "Fondazione");
+        assert_eq!(series.format_index(), "1.5");
+
+        let none = meta(&[("title", "Senza serie")]);
+        assert_eq!(series_from_epub(|key| none.get(key).cloned()), None);
+    }
+
+    #[test]
+    fn test_series_edited_by_user() {
+        let mut metadata = meta(&[("title", "Dune")]);
+        let series = Series::parse(" Dune ", "3,5").unwrap();
+        Series::write_to(series.as_ref(), &mut metadata);
+        assert_eq!(metadata["series"], "Dune");
+        assert_eq!(metadata["series_index"], "3.5");
+        assert_eq!(Series::from_metadata(&metadata), series);
+
+        assert!(Series::parse("Dune", "terzo").is_err());
+        assert_eq!(Series::parse("  ", "2"), Ok(None));
+        Series::write_to(None, &mut metadata);
+        assert_eq!(Series::from_metadata(&metadata), None);
+        assert_eq!(metadata["title"], "Dune");
+    }
+
+    #[test]
+    fn test_volumes_order() {
+        let volume = |index: Option<f64>| Series {
+            name: "Saga".to_string(),
+            index,
+        };
+        let mut volumes = [volume(None), volume(Some(3.0)), volume(Some(1.0))];
+        volumes.sort_by(compare_volumes);
+        assert_eq!(
+            volumes.iter().map(|v| v.index).collect::<Vec<_>>(),
+            vec![Some(1.0), Some(3.0), None]
+        );
+    }
+}
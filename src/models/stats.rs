@@ -0,0 +1,448 @@
+use std::collections::{BTreeMap, HashMap};
+
+use druid::{im::Vector, Data, Lens, Selector};
+use serde_json::{json, Value};
+
+use super::reading::format_date;
+
+/// Opens the window with the reading statistics
+pub const OPEN_STATS_WINDOW: Selector<()> = Selector::new("stats.open-window");
+
+const DAY: u64 = 24 * 60 * 60;
+/// Pauses longer than this, e.g. when the reader is left open, aren't counted as reading time
+pub const IDLE_LIMIT: u64 = 5 * 60;
+/// Average length of a word, with the space after it
+pub const CHARS_PER_WORD: f64 = 6.0;
+
+/// Time spent reading a book, from when it is opened to when it is closed
+#[derive(Clone, Data, Debug, PartialEq)]
+pub struct ReadingSession {
+    pub book: String,
+    pub start: u64,
+    pub end: u64,
+    /// seconds spent reading, without the long pauses
+    pub seconds: u64,
+    pub pages_forward: usize,
+    pub pages_back: usize,
+    /// characters between the start and the end position, negative when the reader went back.
+    /// They are measured when the book is closed
+    pub chars: i64,
+    /// characters before the position the session started at
+    start_chars: usize,
+}
+
+impl ReadingSession {
+    pub fn new(book: impl Into<String>, time: u64, start_chars: usize) -> Self {
+        Self {
+            book: book.into(),
+            start: time,
+            end: time,
+            seconds: 0,
+            pages_forward: 0,
+            pages_back: 0,
+            chars: 0,
+            start_chars,
+        }
+    }
+
+    fn add_time(&mut self, time: u64) {
+        self.seconds += time.saturating_sub(self.end).min(IDLE_LIMIT);
+        self.end = self.end.max(time);
+    }
+
+    /// Records pages turned forward, or back if negative
+    pub fn page_turned(&mut self, pages: isize, time: u64) {
+        self.add_time(time);
+        if pages > 0 {
+            self.pages_forward += pages as usize;
+        } else {
+            self.pages_back += pages.unsigned_abs();
+        }
+    }
+
+    /// The position the session started at is known once the chapters have been read
+    pub fn set_start_chars(&mut self, start_chars: usize) {
+        self.start_chars = start_chars;
+    }
+
+    /// The characters aren't known if the book is no longer in the library
+    pub fn finish(&mut self, time: u64, end_chars: Option<usize>) {
+        self.add_time(time);
+        if let Some(end_chars) = end_chars {
+            self.chars = end_chars as i64 - self.start_chars as i64;
+        }
+    }
+
+    /// Sessions where the book has just been opened and closed aren't saved
+    pub fn is_empty(&self) -> bool {
+        self.seconds == 0 && self.pages_forward == 0 && self.pages_back == 0
+    }
+
+    pub fn day(&self) -> u64 {
+        self.start / DAY
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "book": self.book,
+            "start": self.start,
+            "end": self.end,
+            "seconds": self.seconds,
+            "pages_forward": self.pages_forward,
+            "pages_back": self.pages_back,
+            "chars": self.chars,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            book: value["book"].as_str()?.to_string(),
+            start: value["start"].as_u64()?,
+            end: value["end"].as_u64().unwrap_or_default(),
+            seconds: value["seconds"].as_u64().unwrap_or_default(),
+            pages_forward: value["pages_forward"].as_u64().unwrap_or_default() as usize,
+            pages_back: value["pages_back"].as_u64().unwrap_or_default() as usize,
+            chars: value["chars"].as_i64().unwrap_or_default(),
+            start_chars: 0,
+        })
+    }
+}
+
+/// e.g. "1 h 05 min" or "12 min"
+pub fn format_duration(seconds: u64) -> String {
+    let minutes = seconds / 60;
+    if minutes >= 60 {
+        format!("{} h {:02} min", minutes / 60, minutes % 60)
+    } else {
+        format!("{} min", minutes)
+    }
+}
+
+pub fn format_pages(pages: usize) -> String {
+    match pages {
+        1 => "1 pagina".to_string(),
+        n => format!("{} pagine", n),
+    }
+}
+
+/// Characters read per minute in the sessions where they have been measured
+pub fn chars_per_minute<'a>(sessions: impl Iterator<Item = &'a ReadingSession>) -> Option<f64> {
+    let (chars, seconds) = sessions
+        .filter(|session| session.chars > 0 && session.seconds > 0)
+        .fold((0, 0), |(chars, seconds), session| {
+            (chars + session.chars as u64, seconds + session.seconds)
+        });
+    // a few seconds aren't enough to measure the speed
+    (seconds >= 60).then(|| chars as f64 / (seconds as f64 / 60.0))
+}
+
+/// Speed measured on a book, or on all the books when there isn't enough reading of it
+pub fn book_reading_speed(sessions: &[ReadingSession], book: &str) -> Option<f64> {
+    chars_per_minute(sessions.iter().filter(|session| session.book == book))
+        .or_else(|| chars_per_minute(sessions.iter()))
+}
+
+/// Position in a book measured in characters, which doesn't change with the font size
+/// like the number of pages does
+#[derive(Debug, PartialEq)]
+pub struct CharProgress {
+    pub read: f64,
+    pub chapter_left: f64,
+    pub book_left: f64,
+}
+
+impl CharProgress {
+    /// Given the length of each chapter and how much of the current one has been read
+    pub fn new(chapter_chars: &[usize], chapter: usize, chapter_fraction: f64) -> Self {
+        let chapter = chapter.min(chapter_chars.len().saturating_sub(1));
+        let before = chapter_chars.iter().take(chapter).sum::<usize>() as f64;
+        let current = chapter_chars.get(chapter).copied().unwrap_or_default() as f64;
+        let total = chapter_chars.iter().sum::<usize>() as f64;
+        let read = before + current * chapter_fraction.clamp(0.0, 1.0);
+        Self {
+            read,
+            chapter_left: before + current - read,
+            book_left: total - read,
+        }
+    }
+
+    pub fn percent(&self) -> f64 {
+        let total = self.read + self.book_left;
+        if total > 0.0 {
+            self.read / total * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Seconds needed to read some characters at a speed in characters per minute
+pub fn time_left(chars: f64, speed: f64) -> u64 {
+    (chars / speed * 60.0).round() as u64
+}
+
+/// Reading time and pages turned forward for each day with at least a session
+pub fn daily_totals(sessions: &[ReadingSession]) -> BTreeMap<u64, (u64, usize)> {
+    let mut days = BTreeMap::new();
+    for session in sessions {
+        let (seconds, pages) = days.entry(session.day()).or_insert((0, 0));
+        *seconds += session.seconds;
+        *pages += session.pages_forward;
+    }
+    days
+}
+
+/// Current and longest run of consecutive days with some reading,
+/// the current one is still going on if the last reading was yesterday
+pub fn streaks(days: &BTreeMap<u64, (u64, usize)>, today: u64) -> (usize, usize) {
+    let read_days = days
+        .iter()
+        .filter(|(_, (seconds, pages))| *seconds > 0 || *pages > 0)
+        .map(|(day, _)| *day)
+        .collect::<Vec<_>>();
+
+    let mut best = 0;
+    let mut run = 0;
+    let mut previous = None;
+    for day in &read_days {
+        run = if previous.map(|previous| previous + 1) == Some(*day) {
+            run + 1
+        } else {
+            1
+        };
+        best = best.max(run);
+        previous = Some(*day);
+    }
+    let current = match read_days.last() {
+        Some(last) if *last + 1 >= today => run,
+        _ => 0,
+    };
+    (current, best)
+}
+
+/// A line of the statistics, e.g. a day with its reading time
+#[derive(Clone, Data, Lens)]
+pub struct StatsRow {
+    pub label: String,
+    pub value: String,
+}
+
+#[derive(Clone, Data, Lens)]
+pub struct BookStats {
+    pub title: String,
+    pub summary: String,
+    /// sessions of the book, the most recent first
+    pub timeline: Vector<StatsRow>,
+}
+
+/// State of the statistics window
+#[derive(Clone, Data, Lens, Default)]
+pub struct StatsState {
+    pub summary: String,
+    pub days: Vector<StatsRow>,
+    pub weeks: Vector<StatsRow>,
+    pub books: Vector<BookStats>,
+    pub status: String,
+}
+
+impl StatsState {
+    /// Computes the statistics of the sessions, titles has the title of each book path
+    pub fn new(sessions: &[ReadingSession], titles: &HashMap<String, String>, time: u64) -> Self {
+        let today = time / DAY;
+        let totals = daily_totals(sessions);
+        let (current, best) = streaks(&totals, today);
+        let read_days = totals.values().filter(|(seconds, _)| *seconds > 0).count();
+        let pages = totals.values().map(|(_, pages)| pages).sum::<usize>();
+        let row = |label: String, (seconds, pages): (u64, usize)| StatsRow {
+            label,
+            value: format!("{} · {}", format_duration(seconds), format_pages(pages)),
+        };
+        let between = |from: u64, to: u64| {
+            totals
+                .range(from..=to)
+                .fold((0, 0), |(s, p), (_, (seconds, pages))| {
+                    (s + seconds, p + pages)
+                })
+        };
+
+        let mut summary = vec![
+            format!("Oggi: {}", format_duration(between(today, today).0)),
+            format!(
+                "Ultimi 7 giorni: {}",
+                format_duration(between(today.saturating_sub(6), today).0)
+            ),
+            format!("Serie attuale: {} giorni (record: {})", current, best),
+        ];
+        if let Some(average) = pages.checked_div(read_days) {
+            summary.push(format!("Media: {} pagine al giorno di lettura", average));
+        }
+
+        let days = (0..14)
+            .filter_map(|ago| today.checked_sub(ago))
+            .map(|day| row(format_date(day * DAY), between(day, day)))
+            .collect();
+        // the weeks start on Monday, the 1st January 1970 was a Thursday
+        let monday = today - (today + 3) % 7;
+        let weeks = (0..8)
+            .filter_map(|ago| monday.checked_sub(ago * 7))
+            .map(|start| {
+                let label = format!("Settimana del {}", format_date(start * DAY));
+                row(label, between(start, start + 6))
+            })
+            .collect();
+
+        let mut by_book: HashMap<&str, Vec<&ReadingSession>> = HashMap::new();
+        for session in sessions {
+            by_book.entry(&session.book).or_default().push(session);
+        }
+        let mut books = by_book
+            .into_iter()
+            .map(|(book, mut sessions)| {
+                sessions.sort_by_key(|session| std::cmp::Reverse(session.start));
+                (sessions[0].start, book_stats(book, &sessions, titles))
+            })
+            .collect::<Vec<_>>();
+        books.sort_by_key(|(last, _)| std::cmp::Reverse(*last));
+
+        Self {
+            summary: summary.join("\n"),
+            days,
+            weeks,
+            books: books.into_iter().map(|(_, stats)| stats).collect(),
+            status: String::new(),
+        }
+    }
+}
+
+fn book_stats(
+    book: &str,
+    sessions: &[&ReadingSession],
+    titles: &HashMap<String, String>,
+) -> BookStats {
+    let seconds = sessions.iter().map(|session| session.seconds).sum::<u64>();
+    let pages = sessions
+        .iter()
+        .map(|session| session.pages_forward)
+        .sum::<usize>();
+    let mut summary = format!("{} · {}", format_duration(seconds), format_pages(pages));
+    if let Some(speed) = chars_per_minute(sessions.iter().copied()) {
+        summary.push_str(&format!(
+            " · circa {:.0} parole al minuto",
+            speed / CHARS_PER_WORD
+        ));
+    }
+    let timeline = sessions
+        .iter()
+        .map(|session| StatsRow {
+            label: format_date(session.start),
+            value: format!(
+                "{} · {}",
+                format_duration(session.seconds),
+                format_pages(session.pages_forward)
+            ),
+        })
+        .collect();
+    let title = titles.get(book).cloned().unwrap_or_else(|| {
+        // the book is no longer in the library
+        std::path::Path::new(book)
+            .file_stem()
+            .map_or(book.to_string(), |stem| stem.to_string_lossy().to_string())
+    });
+    BookStats {
+        title,
+        summary,
+        timeline,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(book: &str, start: u64, seconds: u64, pages: usize, chars: i64) -> ReadingSession {
+        ReadingSession {
+            seconds,
+            pages_forward: pages,
+            chars,
+            ..ReadingSession::new(book, start, 0)
+        }
+    }
+
+    #[test]
+    fn test_session_time_without_pauses() {
+        let mut session = ReadingSession::new("dune.epub", 1_000, 500);
+        session.page_turned(1, 1_060);
+        session.page_turned(-1, 1_090);
+        // the reader has been left open for an hour
+        session.page_turned(2, 4_690);
+        session.finish(4_750, Some(2_500));
+        assert_eq!(session.seconds, 60 + 30 + IDLE_LIMIT + 60);
+        assert_eq!((session.pages_forward, session.pages_back), (3, 1));
+        assert_eq!(session.chars, 2_000);
+        assert_eq!(session.end, 4_750);
+
+        let read = ReadingSession::from_json(&session.to_json()).unwrap();
+        assert_eq!(read.seconds, session.seconds);
+        assert_eq!(read.chars, 2_000);
+        assert!(ReadingSession::new("dune.epub", 1_000, 0).is_empty());
+    }
+
+    #[test]
+    fn test_streaks_and_speed() {
+        let sessions = [
+            session("a.epub", DAY, 600, 10, 6_000),
+            session("a.epub", 2 * DAY, 600, 5, 0),
+            session("b.epub", 3 * DAY + 100, 300, 4, 3_000),
+            session("b.epub", 3 * DAY + 5_000, 300, 4, 3_000),
+            session("a.epub", 5 * DAY, 60, 1, 0),
+            session("a.epub", 6 * DAY, 60, 1, 0),
+        ];
+        let totals = daily_totals(&sessions);
+        assert_eq!(totals[&3], (600, 8));
+        assert_eq!(streaks(&totals, 7), (2, 3));
+        assert_eq!(streaks(&totals, 8), (0, 3));
+        assert_eq!(chars_per_minute(sessions.iter()), Some(600.0));
+        assert_eq!(chars_per_minute(sessions[4..].iter()), None);
+
+        let titles = HashMap::from([("a.epub".to_string(), "Dune".to_string())]);
+        let stats = StatsState::new(&sessions, &titles, 7 * DAY);
+        assert_eq!(stats.books.len(), 2);
+        assert_eq!(stats.books[0].title, "Dune");
+        assert_eq!(stats.books[1].title, "b");
+        assert_eq!(
+            stats.books[1].summary,
+            "10 min · 8 pagine · circa 100 parole al minuto"
+        );
+        assert_eq!(stats.days[1].value, "1 min · 1 pagina");
+        assert!(stats
+            .summary
+            .contains("Serie attuale: 2 giorni (record: 3)"));
+    }
+
+    #[test]
+    fn test_time_left_from_chars() {
+        let progress = CharProgress::new(&[1_000, 3_000, 2_000], 1, 0.5);
+        assert_eq!(
+            progress,
+            CharProgress {
+                read: 2_500.0,
+                chapter_left: 1_500.0,
+                book_left: 3_500.0,
+            }
+        );
+        assert!((progress.percent() - 41.666).abs() < 0.01);
+        assert_eq!(CharProgress::new(&[], 0, 0.5).percent(), 0.0);
+
+        // too little reading of b.epub, the speed of all the books is used
+        let sessions = [
+            session("a.epub", DAY, 600, 10, 6_000),
+            session("b.epub", 2 * DAY, 30, 1, 500),
+        ];
+        assert_eq!(book_reading_speed(&sessions, "a.epub"), Some(600.0));
+        assert_eq!(
+            book_reading_speed(&sessions, "b.epub"),
+            Some(6_500.0 / 10.5)
+        );
+        assert_eq!(time_left(progress.chapter_left, 600.0), 150);
+    }
+}
@@ -0,0 +1,16 @@
+use druid::Selector;
+
+use crate::utils::sync_log::Conflict;
+
+/// Merges the changes made on the other devices, it is sent periodically
+pub const SYNC_MERGE: Selector<()> = Selector::new("sync.merge");
+/// Disables the sync, the shared folder is forgotten
+pub const SYNC_DISABLE: Selector<()> = Selector::new("sync.disable");
+/// Opens the window with the chapter edits to resolve
+pub const OPEN_SYNC_CONFLICTS: Selector<()> = Selector::new("sync.open-conflicts");
+/// Resolves a conflict, the flag tells if the text of the other device is kept
+pub const SYNC_RESOLVE_CONFLICT: Selector<(Conflict, bool)> =
+    Selector::new("sync.resolve-conflict");
+
+/// Seconds between two merges while the application is open
+pub const SYNC_INTERVAL: u64 = 60;
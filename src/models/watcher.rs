@@ -0,0 +1,11 @@
+use druid::Selector;
+
+use crate::utils::dir_watcher::DirChanges;
+
+/// Sent when books are added, removed or moved in the watched folders
+pub const LIBRARY_FILES_CHANGED: Selector<DirChanges> = Selector::new("watcher.files-changed");
+/// Stops watching the folders chosen by the user, their books are removed from the library
+pub const UNWATCH_DIRS: Selector<()> = Selector::new("watcher.unwatch-dirs");
+
+/// Seconds between two scans of the watched folders
+pub const WATCH_INTERVAL: u64 = 5;
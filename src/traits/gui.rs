@@ -1,4 +1,4 @@
-use crate::models::library::SortBy;
+use crate::models::{library::SortBy, reading::ReadingInfo};
 use druid::{
     piet::{Error, PietImage},
     Data, PaintCtx,
@@ -24,6 +24,12 @@ pub trait GUIBook: PartialEq + Data {
     /// Sets the author for the book
     fn set_author(&mut self, author: impl Into<String>);
 
+    /// Returns all the contributors (authors, editors, translators...)
+    fn get_contributors(&self) -> String;
+
+    /// Returns the name used to sort the books by author, e.g. "Svevo, Italo"
+    fn get_author_sort(&self) -> String;
+
     /// Returns the number of pages
     fn get_number_of_pages(&self) -> usize;
 
@@ -93,6 +99,24 @@ pub trait GUIBook: PartialEq + Data {
 
     fn is_favorite(&self) -> bool;
 
+    /// Returns true if the book is hidden from the library, with its data kept
+    fn is_archived(&self) -> bool;
+
+    fn set_archived(&mut self, archived: bool);
+
+    /// Returns true if the book is part of a multiple selection
+    fn is_marked(&self) -> bool;
+
+    /// Adds or removes the book from the multiple selection
+    fn set_marked(&mut self, marked: bool);
+
+    /// Returns the status, the dates and the rating of the book
+    fn get_reading_info(&self) -> &ReadingInfo;
+
+    /// Returns the number of volumes of a series shown together with this book,
+    /// 0 or 1 if the book is shown alone
+    fn get_stack_size(&self) -> usize;
+
     fn set_cover_image(&self, ctx: &mut PaintCtx) -> Result<(), Error>;
 
     fn get_cover_image(&self) -> Ref<Option<PietImage>>;
@@ -157,6 +181,10 @@ pub trait GUILibrary {
 
     fn only_fav(&self) -> bool;
 
+    /// Returns true if the books are filtered differently than in the other library,
+    /// i.e. the books shown may have changed
+    fn filters_changed(&self, other: &Self) -> bool;
+
     fn next_book_idx(&self) -> Option<usize>;
 
     fn prev_book_idx(&self) -> Option<usize>;
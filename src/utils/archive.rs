@@ -0,0 +1,702 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error,
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::{
+    collections::Collections, epub_utils, sync_log::Conflict, webdav_backup::collect_files,
+};
+
+/// Version of the layout of the archive, checked on import
+const ARCHIVE_VERSION: u64 = 1;
+const MANIFEST: &str = "manifest.json";
+const BOOKS_DIR: &str = "books";
+const SAVEDATA: &str = "conf/books_saved.json";
+const NOTES: &str = "conf/books_notes.json";
+const ENV: &str = "conf/env.json";
+const COLLECTIONS: &str = "conf/collections.json";
+/// Settings that can be moved to another computer,
+/// paths and accounts stay on the one they belong to
+pub const EXPORTED_SETTINGS: [&str; 5] =
+    ["theme", "font_color", "font_size", "font_family", "shadows"];
+/// Device shown in the conflicts of the edits found in an archive
+const ARCHIVE_DEVICE: &str = "archivio";
+
+/// Result of an export
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportReport {
+    pub books: usize,
+    /// books whose file has been put in the archive
+    pub included: usize,
+}
+
+/// Result of an import
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportReport {
+    /// books copied from the archive in the library
+    pub added: Vec<String>,
+    /// books already in the library, their data has been merged
+    pub merged: Vec<String>,
+    /// books that are neither in the archive nor in the library,
+    /// their data is used when they are added
+    pub missing: Vec<String>,
+    /// what has been solved automatically, e.g. the older position of a book
+    pub messages: Vec<String>,
+    /// chapters edited differently in the archive and in the library
+    pub edit_conflicts: Vec<Conflict>,
+}
+
+fn read_json(path: &Path) -> Value {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .filter(|json| json.is_object())
+        .unwrap_or(json!({}))
+}
+
+fn write_json(path: &Path, json: &Value) -> Result<(), Box<dyn error::Error>> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(json)?)?;
+    Ok(())
+}
+
+fn get_file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or(path.to_string(), |name| name.to_string_lossy().to_string())
+}
+
+fn get_stem(name: &str) -> String {
+    Path::new(name)
+        .file_stem()
+        .map_or(name.to_string(), |stem| stem.to_string_lossy().to_string())
+}
+
+/// Replaces the paths of the books with their file names, the identity of a book in the archive
+fn keys_to_names(json: &Value) -> Value {
+    let mut renamed = json!({});
+    if let Some(object) = json.as_object() {
+        for (path, value) in object {
+            renamed[get_file_name(path)] = value.clone();
+        }
+    }
+    renamed
+}
+
+/// Writes the library in a zip archive, with paths relative to the folder of the application
+pub fn export_archive(
+    root: &Path,
+    dest: &Path,
+    include_books: bool,
+) -> Result<ExportReport, Box<dyn error::Error>> {
+    let mut zip = ZipWriter::new(File::create(dest)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let savedata = read_json(&root.join(SAVEDATA));
+    let notes = read_json(&root.join(NOTES));
+    let collections = Collections::load_from(&root.join(COLLECTIONS));
+
+    // the books of the library and the ones outside of it with a position or notes
+    let mut books = BTreeMap::new();
+    if let Ok(files) = std::fs::read_dir(root.join("epubs")) {
+        for file in files.flatten().filter(|file| file.path().is_file()) {
+            books.insert(
+                file.file_name().to_string_lossy().to_string(),
+                Some(file.path()),
+            );
+        }
+    }
+    for json in [&savedata, &notes] {
+        for path in json
+            .as_object()
+            .into_iter()
+            .flat_map(|object| object.keys())
+        {
+            books.entry(get_file_name(path)).or_insert(None);
+        }
+    }
+    for path in collections.get_books() {
+        books.entry(get_file_name(&path)).or_insert(None);
+    }
+
+    let mut included = 0;
+    let mut manifest_books = vec![];
+    for (name, path) in &books {
+        let metadata = read_json(
+            &root
+                .join("saved_books")
+                .join(get_stem(name))
+                .join("metadata.json"),
+        );
+        let include = include_books && path.is_some();
+        if let (true, Some(path)) = (include, path) {
+            zip.start_file(format!("{}/{}", BOOKS_DIR, name), options)?;
+            zip.write_all(&std::fs::read(path)?)?;
+            included += 1;
+        }
+        manifest_books.push(json!({
+            "id": name,
+            "title": metadata["title"],
+            "author": metadata["author"],
+            "included": include,
+        }));
+    }
+
+    for file in collect_files(root) {
+        let bytes = match file.as_str() {
+            SAVEDATA => keys_to_names(&savedata).to_string().into_bytes(),
+            NOTES => keys_to_names(&notes).to_string().into_bytes(),
+            COLLECTIONS => collections
+                .rename_books(get_file_name)
+                .to_json()
+                .to_string()
+                .into_bytes(),
+            ENV => {
+                let env = read_json(&root.join(ENV));
+                let mut settings = json!({});
+                for key in EXPORTED_SETTINGS {
+                    if let Some(value) = env.get(key) {
+                        settings[key] = value.clone();
+                    }
+                }
+                settings.to_string().into_bytes()
+            }
+            // the other files of the configuration only make sense on this computer
+            file if file.starts_with("conf/") => continue,
+            file => std::fs::read(root.join(file))?,
+        };
+        zip.start_file(file, options)?;
+        zip.write_all(&bytes)?;
+    }
+
+    let manifest = json!({ "version": ARCHIVE_VERSION, "books": manifest_books });
+    zip.start_file(MANIFEST, options)?;
+    zip.write_all(manifest.to_string().as_bytes())?;
+    zip.finish()?;
+
+    Ok(ExportReport {
+        books: books.len(),
+        included,
+    })
+}
+
+fn read_entry(zip: &mut ZipArchive<File>, name: &str) -> Option<Vec<u8>> {
+    let mut entry = zip.by_name(name).ok()?;
+    let mut bytes = vec![];
+    entry.read_to_end(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+fn read_entry_json(zip: &mut ZipArchive<File>, name: &str) -> Value {
+    read_entry(zip, name)
+        .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+        .filter(|json| json.is_object())
+        .unwrap_or(json!({}))
+}
+
+/// Finds the path of a book of the library given its file name
+fn find_local_book(root: &Path, name: &str, local_keys: &[String]) -> Option<String> {
+    let path = root.join("epubs").join(name);
+    if path.exists() {
+        return Some(path.to_string_lossy().to_string());
+    }
+    local_keys
+        .iter()
+        .find(|key| get_file_name(key) == name && Path::new(key).exists())
+        .cloned()
+}
+
+/// Returns a file name that isn't used by the library, e.g. divina-2.epub
+fn get_free_name(root: &Path, name: &str) -> String {
+    let stem = get_stem(name);
+    let extension = Path::new(name)
+        .extension()
+        .map_or(String::new(), |ext| format!(".{}", ext.to_string_lossy()));
+    (2..)
+        .map(|i| format!("{}-{}{}", stem, i, extension))
+        .find(|candidate| {
+            !root.join("epubs").join(candidate).exists()
+                && !root.join("saved_books").join(get_stem(candidate)).exists()
+        })
+        .unwrap()
+}
+
+/// Merges an archive in the library, the books are matched by file name
+pub fn import_archive(root: &Path, archive: &Path) -> Result<ImportReport, Box<dyn error::Error>> {
+    let mut zip = ZipArchive::new(File::open(archive)?)?;
+    let manifest = read_entry_json(&mut zip, MANIFEST);
+    match manifest["version"].as_u64() {
+        Some(version) if version <= ARCHIVE_VERSION => {}
+        _ => {
+            return Err(
+                "l'archivio non è stato creato da CrabReader o è di una versione più recente"
+                    .into(),
+            )
+        }
+    }
+
+    let mut report = ImportReport::default();
+    let mut savedata = read_json(&root.join(SAVEDATA));
+    let mut notes = read_json(&root.join(NOTES));
+    let archive_savedata = read_entry_json(&mut zip, SAVEDATA);
+    let archive_notes = read_entry_json(&mut zip, NOTES);
+    let local_keys = [&savedata, &notes]
+        .iter()
+        .flat_map(|json| {
+            json.as_object()
+                .into_iter()
+                .flat_map(|object| object.keys())
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    let entry_names = zip
+        .file_names()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+
+    // path in this library of every book of the archive
+    let mut paths = BTreeMap::new();
+    let manifest_books = manifest["books"].as_array().cloned().unwrap_or_default();
+    for book in manifest_books {
+        let Some(name) = book["id"].as_str() else {
+            continue;
+        };
+        // the name comes from the archive, it must not lead outside of the library
+        if Path::new(name).file_name().and_then(|file| file.to_str()) != Some(name) {
+            report.messages.push(format!(
+                "{} non è un nome di file valido, il libro è stato saltato",
+                name
+            ));
+            continue;
+        }
+        let archive_stem = get_stem(name);
+        let book_file = read_entry(&mut zip, &format!("{}/{}", BOOKS_DIR, name));
+
+        // the path the book has, or will have, in this library
+        let (path, is_new) = match (find_local_book(root, name, &local_keys), book_file) {
+            (Some(path), Some(bytes)) if std::fs::read(&path).is_ok_and(|local| local != bytes) => {
+                // another book with the same name, the one of the archive is renamed
+                let free_name = get_free_name(root, name);
+                let new_path = root.join("epubs").join(&free_name);
+                std::fs::write(&new_path, bytes)?;
+                report.messages.push(format!(
+                    "{} è diverso dal libro con lo stesso nome nella libreria, è stato importato come {}",
+                    name, free_name
+                ));
+                (new_path.to_string_lossy().to_string(), true)
+            }
+            (Some(path), _) => (path, false),
+            (None, Some(bytes)) => {
+                let new_path = root.join("epubs").join(name);
+                std::fs::create_dir_all(root.join("epubs"))?;
+                std::fs::write(&new_path, bytes)?;
+                (new_path.to_string_lossy().to_string(), true)
+            }
+            (None, None) => {
+                report.missing.push(name.to_string());
+                (
+                    root.join("epubs").join(name).to_string_lossy().to_string(),
+                    false,
+                )
+            }
+        };
+        paths.insert(name.to_string(), path.clone());
+        let stem = get_stem(&get_file_name(&path));
+        let exists = Path::new(&path).exists();
+
+        let archive_metadata = read_entry_json(
+            &mut zip,
+            &format!("saved_books/{}/metadata.json", archive_stem),
+        );
+        let is_favorite = archive_metadata["favorite"] == "true";
+        if is_new {
+            // the chapters are extracted here, the library finds the book ready
+            epub_utils::extract_all(&path)?;
+            report.added.push(path.clone());
+        } else if exists {
+            report.merged.push(path.clone());
+        }
+        // a book is favorite if it is favorite on one of the two computers
+        let metadata_path = root.join("saved_books").join(&stem).join("metadata.json");
+        if is_favorite && metadata_path.exists() {
+            let mut metadata = read_json(&metadata_path);
+            metadata["favorite"] = json!("true");
+            write_json(&metadata_path, &metadata)?;
+        }
+
+        // edited chapters, the different ones are resolved by the user
+        let prefix = format!("edited_books/{}/", archive_stem);
+        for entry in entry_names
+            .iter()
+            .filter(|entry| entry.starts_with(&prefix))
+        {
+            let page = &entry[prefix.len()..];
+            let Some(chapter) = page
+                .strip_prefix("page_")
+                .and_then(|page| page.strip_suffix(".txt"))
+                .and_then(|chapter| chapter.parse::<usize>().ok())
+            else {
+                continue;
+            };
+            let Some(text) = read_entry(&mut zip, entry) else {
+                continue;
+            };
+            let local_path = root.join("edited_books").join(&stem).join(page);
+            match std::fs::read(&local_path) {
+                Ok(local) if local == text => {}
+                Ok(local) => report.edit_conflicts.push(Conflict {
+                    book_path: path.clone(),
+                    chapter,
+                    device: ARCHIVE_DEVICE.to_string(),
+                    time: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |duration| duration.as_millis() as u64),
+                    local_text: Some(String::from_utf8_lossy(&local).to_string()),
+                    remote_text: Some(String::from_utf8_lossy(&text).to_string()),
+                }),
+                Err(_) => {
+                    std::fs::create_dir_all(root.join("edited_books").join(&stem))?;
+                    std::fs::write(&local_path, text)?;
+                }
+            }
+        }
+
+        // position, the most recent one is kept
+        if let Some(archive_entry) = archive_savedata.get(name) {
+            let local_entry = &savedata[&path];
+            let position = |entry: &Value| (entry["chapter"].clone(), entry["page"].clone());
+            if local_entry.is_null() {
+                savedata[&path] = archive_entry.clone();
+            } else if position(local_entry) != position(archive_entry) {
+                let archive_time = archive_entry["timestamp"].as_u64().unwrap_or_default();
+                let local_time = local_entry["timestamp"].as_u64().unwrap_or_default();
+                if archive_time > local_time {
+                    savedata[&path] = archive_entry.clone();
+                    report.messages.push(format!(
+                        "{}: è stata mantenuta la posizione dell'archivio, più recente",
+                        name
+                    ));
+                } else {
+                    report.messages.push(format!(
+                        "{}: è stata mantenuta la posizione della libreria, più recente",
+                        name
+                    ));
+                }
+            }
+        }
+        // the edited chapters are the ones with a text in this library
+        if savedata[&path].is_object() {
+            let edited = std::fs::read_dir(root.join("edited_books").join(&stem))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter_map(|file| {
+                    file.file_name()
+                        .to_str()?
+                        .strip_prefix("page_")?
+                        .strip_suffix(".txt")?
+                        .parse::<usize>()
+                        .ok()
+                })
+                .collect::<BTreeSet<_>>();
+            savedata[&path]["edited_chapters"] = json!(edited);
+        }
+
+        // notes, the ones that aren't in the library are added
+        if let Some(archive_chapters) = archive_notes[name].as_array() {
+            if !notes[&path].is_array() {
+                notes[&path] = json!([]);
+            }
+            let chapters = notes[&path].as_array_mut().unwrap();
+            for archive_chapter in archive_chapters {
+                let chapter_number = &archive_chapter["chapter"];
+                let index = match chapters
+                    .iter()
+                    .position(|chapter| &chapter["chapter"] == chapter_number)
+                {
+                    Some(index) => index,
+                    None => {
+                        chapters.push(json!({ "chapter": chapter_number, "notes": [] }));
+                        chapters.len() - 1
+                    }
+                };
+                if !chapters[index]["notes"].is_array() {
+                    chapters[index]["notes"] = json!([]);
+                }
+                let local_notes = chapters[index]["notes"].as_array_mut().unwrap();
+                for note in archive_chapter["notes"].as_array().into_iter().flatten() {
+                    if !local_notes.contains(note) {
+                        local_notes.push(note.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    write_json(&root.join(SAVEDATA), &savedata)?;
+    write_json(&root.join(NOTES), &notes)?;
+
+    // collections and tags are added to the ones of the library
+    let archive_collections = Collections::from_json(
+        &root.join(COLLECTIONS),
+        &read_entry_json(&mut zip, COLLECTIONS),
+    )
+    .rename_books(|name| {
+        paths.get(name).cloned().unwrap_or_else(|| {
+            root.join("epubs").join(name).to_string_lossy().to_string()
+        })
+    });
+    let mut collections = Collections::load_from(&root.join(COLLECTIONS));
+    collections.merge(&archive_collections);
+    collections.save()?;
+
+    // settings of the archive replace the ones of this computer
+    let archive_env = read_entry_json(&mut zip, ENV);
+    let mut env = read_json(&root.join(ENV));
+    for key in EXPORTED_SETTINGS {
+        if let Some(value) = archive_env.get(key) {
+            env[key] = value.clone();
+        }
+    }
+    write_json(&root.join(ENV), &env)?;
+
+    Ok(report)
+}
+
+/// Returns the path of the archive with the zip extension
+pub fn with_zip_extension(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("zip") => path.to_path_buf(),
+        _ => path.with_extension("zip"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::temp_dir;
+
+    fn create_root(name: &str) -> PathBuf {
+        let root = temp_dir(&format!("archive-{}", name));
+        for dir in ["conf", "epubs", "edited_books/divina", "saved_books/divina"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::write(root.join("epubs/divina.epub"), "divina commedia").unwrap();
+        std::fs::write(
+            root.join("saved_books/divina/metadata.json"),
+            json!({ "title": "Divina Commedia", "author": "Dante", "favorite": "false" })
+                .to_string(),
+        )
+        .unwrap();
+        root
+    }
+
+    fn book_path(root: &Path, name: &str) -> String {
+        root.join("epubs").join(name).to_string_lossy().to_string()
+    }
+
+    fn create_source(name: &str) -> PathBuf {
+        let root = create_root(name);
+        let divina = book_path(&root, "divina.epub");
+        let decameron = "/media/calibre/Boccaccio/decameron.epub";
+        write_json(
+            &root.join(SAVEDATA),
+            &json!({
+                divina.clone(): { "chapter": 3, "page": 1, "timestamp": 200, "edited_chapters": [1, 2] },
+                decameron: { "chapter": 5, "page": 0, "timestamp": 100, "edited_chapters": [] },
+            }),
+        )
+        .unwrap();
+        write_json(
+            &root.join(NOTES),
+            &json!({
+                divina.clone(): [{ "chapter": 1, "notes": [{ "start": "Nel mezzo", "note": "inizio" }] }],
+            }),
+        )
+        .unwrap();
+        write_json(
+            &root.join(ENV),
+            &json!({ "theme": "dark", "shadows": true, "sync_dir": "/home/dante/Dropbox" }),
+        )
+        .unwrap();
+        write_json(
+            &root.join(COLLECTIONS),
+            &json!({
+                "collections": { "Classici": [divina.clone(), decameron] },
+                "tags": { divina.clone(): ["poema"] },
+            }),
+        )
+        .unwrap();
+        std::fs::write(root.join("conf/sync_state.json"), "{}").unwrap();
+        std::fs::write(root.join("edited_books/divina/page_1.txt"), "selva oscura").unwrap();
+        std::fs::write(root.join("edited_books/divina/page_2.txt"), "inferno").unwrap();
+        let mut metadata = read_json(&root.join("saved_books/divina/metadata.json"));
+        metadata["favorite"] = json!("true");
+        write_json(&root.join("saved_books/divina/metadata.json"), &metadata).unwrap();
+        root
+    }
+
+    #[test]
+    fn test_export_uses_relative_paths() {
+        let root = create_source("export");
+        let dest = root.join("library.zip");
+
+        let report = export_archive(&root, &dest, false).unwrap();
+        assert_eq!(
+            report,
+            ExportReport {
+                books: 2,
+                included: 0
+            }
+        );
+
+        let mut zip = ZipArchive::new(File::open(&dest).unwrap()).unwrap();
+        let savedata = read_entry_json(&mut zip, SAVEDATA);
+        assert_eq!(
+            savedata.as_object().unwrap().keys().collect::<Vec<_>>(),
+            vec!["decameron.epub", "divina.epub"]
+        );
+        assert_eq!(
+            read_entry_json(&mut zip, ENV),
+            json!({ "theme": "dark", "shadows": true })
+        );
+        assert_eq!(
+            read_entry_json(&mut zip, COLLECTIONS),
+            json!({
+                "collections": { "Classici": ["decameron.epub", "divina.epub"] },
+                "tags": { "divina.epub": ["poema"] },
+            })
+        );
+        assert!(zip.by_name("conf/sync_state.json").is_err());
+        assert!(zip.by_name("books/divina.epub").is_err());
+        assert_eq!(
+            read_entry(&mut zip, "edited_books/divina/page_1.txt").unwrap(),
+            b"selva oscura"
+        );
+
+        let report = export_archive(&root, &dest, true).unwrap();
+        assert_eq!(
+            report,
+            ExportReport {
+                books: 2,
+                included: 1
+            }
+        );
+        let mut zip = ZipArchive::new(File::open(&dest).unwrap()).unwrap();
+        assert_eq!(
+            read_entry(&mut zip, "books/divina.epub").unwrap(),
+            b"divina commedia"
+        );
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_import_merges_library() {
+        let source = create_source("import-source");
+        let dest = source.join("library.zip");
+        export_archive(&source, &dest, false).unwrap();
+
+        let root = create_root("import-dest");
+        let divina = book_path(&root, "divina.epub");
+        write_json(
+            &root.join(SAVEDATA),
+            &json!({ divina.clone(): { "chapter": 1, "page": 4, "timestamp": 100, "edited_chapters": [1] } }),
+        )
+        .unwrap();
+        write_json(
+            &root.join(NOTES),
+            &json!({ divina.clone(): [{ "chapter": 1, "notes": [{ "start": "Lasciate", "note": "porta" }] }] }),
+        )
+        .unwrap();
+        write_json(
+            &root.join(ENV),
+            &json!({ "theme": "light", "sync_dir": "/srv/sync" }),
+        )
+        .unwrap();
+        std::fs::write(root.join("edited_books/divina/page_1.txt"), "selva chiara").unwrap();
+
+        let report = import_archive(&root, &dest).unwrap();
+        assert_eq!(report.added, Vec::<String>::new());
+        assert_eq!(report.merged, vec![divina.clone()]);
+        assert_eq!(report.missing, vec!["decameron.epub"]);
+        assert_eq!(report.edit_conflicts.len(), 1);
+        assert_eq!(report.edit_conflicts[0].chapter, 1);
+        assert_eq!(
+            report.edit_conflicts[0].remote_text.as_deref(),
+            Some("selva oscura")
+        );
+
+        let savedata = read_json(&root.join(SAVEDATA));
+        assert_eq!(savedata[&divina]["chapter"], 3);
+        assert_eq!(savedata[&divina]["edited_chapters"], json!([1, 2]));
+        assert_eq!(savedata[&book_path(&root, "decameron.epub")]["chapter"], 5);
+        assert_eq!(
+            std::fs::read_to_string(root.join("edited_books/divina/page_1.txt")).unwrap(),
+            "selva chiara"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("edited_books/divina/page_2.txt")).unwrap(),
+            "inferno"
+        );
+
+        let notes = read_json(&root.join(NOTES));
+        assert_eq!(notes[&divina][0]["notes"].as_array().unwrap().len(), 2);
+        let collections = Collections::load_from(&root.join(COLLECTIONS));
+        assert_eq!(collections.get_collections_of(&divina), vec!["Classici"]);
+        assert_eq!(
+            collections.get_collections_of(&book_path(&root, "decameron.epub")),
+            vec!["Classici"]
+        );
+        assert_eq!(collections.get_tags_of(&divina), vec!["poema"]);
+        let metadata = read_json(&root.join("saved_books/divina/metadata.json"));
+        assert_eq!(metadata["favorite"], "true");
+        let env = read_json(&root.join(ENV));
+        assert_eq!(
+            env,
+            json!({ "theme": "dark", "shadows": true, "sync_dir": "/srv/sync" })
+        );
+
+        let _ = std::fs::remove_dir_all(source);
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_import_skips_paths() {
+        let root = create_root("import-paths");
+        let dest = root.join("paths.zip");
+        let name = "../../crab-reader-outside.epub";
+        let mut zip = ZipWriter::new(File::create(&dest).unwrap());
+        zip.start_file(MANIFEST, FileOptions::default()).unwrap();
+        let manifest = json!({ "version": ARCHIVE_VERSION, "books": [{ "id": name }] });
+        zip.write_all(manifest.to_string().as_bytes()).unwrap();
+        zip.start_file(format!("{}/{}", BOOKS_DIR, name), FileOptions::default())
+            .unwrap();
+        zip.write_all(b"fuori").unwrap();
+        zip.finish().unwrap();
+
+        let report = import_archive(&root, &dest).unwrap();
+        assert_eq!(report.added, Vec::<String>::new());
+        assert_eq!(report.messages.len(), 1);
+        assert!(!root.join("epubs").join(name).exists());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_free_name() {
+        let root = create_root("free-name");
+        std::fs::write(root.join("epubs/divina-2.epub"), "").unwrap();
+        assert_eq!(get_free_name(&root, "divina.epub"), "divina-3.epub");
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
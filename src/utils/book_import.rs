@@ -0,0 +1,189 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use druid::{ExtEventSink, FileDialogOptions, FileSpec, Target};
+
+use super::{
+    dir_manager::get_epub_dir,
+    dir_watcher::{is_book_file, list_books},
+    kosync_client::partial_md5,
+    mobi_utils,
+};
+use crate::models::import::{ImportStatus, IMPORT_CHECKED};
+
+/// File picker for the books to add, more than one can be chosen
+pub fn open_books_options() -> FileDialogOptions {
+    FileDialogOptions::new()
+        .multi_selection()
+        .allowed_types(vec![
+            FileSpec::new("Epub", &["epub"]),
+            FileSpec::new("Fumetto", &["cbz"]),
+            FileSpec::new("Kindle", &["mobi", "azw3", "azw"]),
+            FileSpec::new("PDF", &["pdf"]),
+        ])
+}
+
+/// File picker for a folder whose books, also in the subfolders, are added
+pub fn open_folder_options() -> FileDialogOptions {
+    FileDialogOptions::new()
+        .select_directories()
+        .title("Seleziona la cartella con i libri da aggiungere")
+}
+
+/// Books chosen by the user, the folders are replaced by the books inside them
+pub fn expand_paths(paths: &[String]) -> Vec<String> {
+    let mut books = vec![];
+    for path in paths.iter().map(Path::new) {
+        if path.is_dir() {
+            let mut found = list_books(path, true);
+            found.sort();
+            books.extend(found);
+        } else if is_book_file(path) {
+            books.push(path.to_path_buf());
+        }
+    }
+    let mut expanded: Vec<String> = vec![];
+    for book in books {
+        let book = book.to_string_lossy().to_string();
+        if !expanded.contains(&book) {
+            expanded.push(book);
+        }
+    }
+    expanded
+}
+
+/// Path in the folder for a new book, a number is added to the name
+/// when it is used by a different book
+fn free_path(dir: &Path, path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().unwrap_or_default().to_string_lossy();
+    let mut dest = dir.join(path.file_name().unwrap_or_default());
+    let mut n = 2;
+    while dest.exists() {
+        dest = dir.join(format!("{} ({}).{}", stem, n, ext));
+        n += 1;
+    }
+    dest
+}
+
+/// Checks a book and copies it in the folder, returning the path of the copy.
+/// The duplicates are found by their content, whatever their name is:
+/// identities has the books already in the library and the new ones are added to it
+pub fn prepare_book(
+    path: &str,
+    dir: &Path,
+    identities: &mut HashMap<String, String>,
+) -> Result<String, ImportStatus> {
+    let identity = partial_md5(path).map_err(|e| ImportStatus::Failed(e.to_string()))?;
+    if let Some(existing) = identities.get(&identity) {
+        return Err(ImportStatus::Duplicate(existing.clone()));
+    }
+    // books protected by DRM can't be read, so they aren't copied
+    if mobi_utils::is_mobi(path) {
+        mobi_utils::check_mobi(path).map_err(|e| ImportStatus::Unsupported(e.to_string()))?;
+    }
+
+    let source = Path::new(path);
+    let dest = if source.parent() == Some(dir) {
+        // already in the folder, e.g. a book whose loading failed
+        source.to_path_buf()
+    } else {
+        let dest = free_path(dir, source);
+        std::fs::copy(source, &dest).map_err(|e| ImportStatus::Failed(e.to_string()))?;
+        dest
+    };
+    let dest = dest.to_string_lossy().to_string();
+    identities.insert(identity, dest.clone());
+    Ok(dest)
+}
+
+/// Checks and copies the books in background, one at a time,
+/// the result of each one is sent with IMPORT_CHECKED.
+/// The temporary files, e.g. the downloaded ones, are removed when they are copied
+pub fn start_import(
+    paths: Vec<String>,
+    library_paths: Vec<String>,
+    temporary: bool,
+    sink: ExtEventSink,
+) {
+    std::thread::spawn(move || {
+        let mut identities = library_paths
+            .into_iter()
+            .filter_map(|path| Some((partial_md5(&path).ok()?, path)))
+            .collect::<HashMap<_, _>>();
+        let dir = get_epub_dir();
+        for path in paths {
+            let result = prepare_book(&path, &dir, &mut identities);
+            if temporary {
+                let _ = std::fs::remove_file(&path);
+            }
+            if sink
+                .submit_command(IMPORT_CHECKED, (path, result), Target::Auto)
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::temp_dir;
+
+    #[test]
+    fn test_import_books() {
+        let dir = temp_dir("import");
+        let chosen = dir.join("scelti");
+        let epubs = dir.join("epubs");
+        std::fs::create_dir_all(chosen.join("sub")).unwrap();
+        std::fs::create_dir_all(&epubs).unwrap();
+        std::fs::write(chosen.join("dune.epub"), "Il deserto di Arrakis").unwrap();
+        std::fs::write(
+            chosen.join("sub").join("copia.epub"),
+            "Il deserto di Arrakis",
+        )
+        .unwrap();
+        std::fs::write(chosen.join("sub").join("zeno.epub"), "La coscienza").unwrap();
+        std::fs::write(chosen.join("note.txt"), "non è un libro").unwrap();
+        // another book with the same name is already in the library
+        std::fs::write(epubs.join("zeno.epub"), "Un altro Zeno").unwrap();
+
+        let to_string = |path: PathBuf| path.to_string_lossy().to_string();
+        let paths = expand_paths(&[
+            to_string(chosen.join("dune.epub")),
+            to_string(chosen.clone()),
+        ]);
+        assert_eq!(
+            paths,
+            vec![
+                to_string(chosen.join("dune.epub")),
+                to_string(chosen.join("sub").join("copia.epub")),
+                to_string(chosen.join("sub").join("zeno.epub")),
+            ]
+        );
+
+        let existing = to_string(epubs.join("zeno.epub"));
+        let mut identities = HashMap::new();
+        identities.insert(partial_md5(&existing).unwrap(), existing);
+        let results = paths
+            .iter()
+            .map(|path| prepare_book(path, &epubs, &mut identities))
+            .collect::<Vec<_>>();
+        assert_eq!(results[0], Ok(to_string(epubs.join("dune.epub"))));
+        assert_eq!(
+            results[1],
+            Err(ImportStatus::Duplicate(to_string(epubs.join("dune.epub"))))
+        );
+        assert_eq!(results[2], Ok(to_string(epubs.join("zeno (2).epub"))));
+        assert_eq!(
+            std::fs::read_to_string(epubs.join("zeno.epub")).unwrap(),
+            "Un altro Zeno"
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{
-    MYENV,
-    models::book::Book,
-    utils::{saveload::{save_data}, envmanager::FontSize}, 
+    MYENV, CHAPTERS_LOADED,
+    models::{book::Book, navigation::{GoTo, Navigation, Position}, reading, stats::book_reading_speed},
+    utils::{saveload::{save_data, save_offset}, envmanager::FontSize, epub_utils, reading_stats}, 
     ReadingState, 
     CrabReaderState, 
     traits::{
@@ -9,7 +9,8 @@ use crate::{
         reader::{BookReading, BookManagement}, note::NoteManagement
     },
 };
-use druid::EventCtx;
+use druid::{im::Vector, EventCtx, ExtEventSink, Target};
+use std::sync::Arc;
 
 /// Activate or deactivate editing mode
 /// return the new value of is_editing
@@ -19,6 +20,11 @@ pub fn edit_btn_fn(
     reading_state: &mut ReadingState,
     book: &Book,
 ) {
+    // pages of a comic are images, there is no text to edit
+    if book.is_comic() {
+        return;
+    }
+
     if !reading_state.is_editing {
         reading_state.is_editing = true;
         if reading_state.single_view {
@@ -33,13 +39,14 @@ pub fn edit_btn_fn(
     }
 }
 
-/// Go to the next or previous page of the book
+/// Go to the next or previous page of the book,
+/// returns the pages turned, negative when going back
 fn change_page(
     book: &mut Book,
     is_editing: bool,
     single_view: bool,
     next: bool,
-) {
+) -> isize {
     if !is_editing {
         let mut increaser = if single_view { 1 } else { 2 };
 
@@ -51,9 +58,13 @@ fn change_page(
         if new_page > book.get_last_page_number() as isize {
             let last_page = book.get_number_of_pages() - 1;
             println!("DEBUG: current page: {}, last page of book: {}", book.get_current_page_number(), last_page);
-            if book.get_cumulative_current_page_number() == last_page {
+            // a comic has a single chapter, there is no next one to go to
+            if book.get_cumulative_current_page_number() == last_page
+                || (book.is_comic()
+                    && book.get_chapter_number() + 1 >= book.get_number_of_chapters())
+            {
                 println!("DEBUG: LAST PAGE, can't go forward");
-                return;
+                return 0;
             }
 
             book.set_chapter_number(book.get_chapter_number() + 1, true);
@@ -61,7 +72,7 @@ fn change_page(
         } else if new_page < 0 {
             if book.get_chapter_number() == 0 {
                 println!("DEBUG: FIRST PAGE, can't go back");
-                return;
+                return 0;
             }
             book.set_chapter_number(book.get_chapter_number() - 1, false);
             println!("DEBUG: First page of chapter, changing chapter");
@@ -79,19 +90,194 @@ fn change_page(
             false,
         )
         .unwrap();
+        book.check_finished(single_view);
+        book.page_reached();
         println!("DEBUG: Chapter: {}", book.get_chapter_number());
+        return increaser;
+    }
+    0
+}
+
+/// Counts the turned pages in the reading session
+fn record_pages(data: &mut CrabReaderState, pages: isize) {
+    let Some(session) = data.session.as_mut() else {
+        return;
+    };
+    if pages != 0 {
+        session.page_turned(pages, reading::now());
+        if let Err(e) = reading_stats::save_session(session) {
+            println!("ERROR: failed to save the reading session: {}", e);
+        }
     }
 }
 
 // function for going to next page
 pub fn go_next(data: &mut CrabReaderState) {
     let book = data.library.get_selected_book_mut().unwrap();
-    change_page(book, data.reading_state.is_editing, data.reading_state.single_view, true);
+    let pages = change_page(book, data.reading_state.is_editing, data.reading_state.single_view, true);
+    record_pages(data, pages);
 }
 // function for going to previous page
 pub fn go_prev(data: &mut CrabReaderState) {
     let book = data.library.get_selected_book_mut().unwrap();
-    change_page(book, data.reading_state.is_editing, data.reading_state.single_view, false);
+    let pages = change_page(book, data.reading_state.is_editing, data.reading_state.single_view, false);
+    record_pages(data, pages);
+}
+
+/// Characters before a page of a book, they measure how much has been read
+fn chars_before(chapter_chars: &[usize], chapter: usize, pages: &Vector<String>, page: usize) -> usize {
+    let before = chapter_chars.iter().take(chapter).sum::<usize>();
+    before + pages.iter().take(page).map(|text| text.len()).sum::<usize>()
+}
+
+/// Characters before the position in the book, from the lengths of its chapters.
+/// None if they haven't been read yet, a comic has no text
+pub fn read_chars(book: &Book, chapter_chars: &[usize]) -> Option<usize> {
+    if book.is_comic() {
+        return Some(0);
+    }
+    if chapter_chars.is_empty() {
+        return None;
+    }
+    let pages = book.get_chapter_pages();
+    Some(chars_before(chapter_chars, book.get_chapter_number(), pages, book.get_current_page_number()))
+}
+
+/// First and last page of each chapter, a comic has a single chapter
+pub fn pages_per_chapter(book: &Book) -> Arc<Vec<(usize, usize)>> {
+    if book.is_comic() {
+        return vec![(0, book.get_number_of_pages().saturating_sub(1))].into();
+    }
+    epub_utils::get_start_end_pages_per_chapter(&book.get_path(), None).into()
+}
+
+/// What is known of the chapters of the book just opened, it is read in background
+/// because the text of every chapter is needed
+pub struct ChaptersInfo {
+    pub path: String,
+    /// characters before the position the book has been opened at
+    pub start_chars: usize,
+    /// length of each chapter, to tell how much of the book is left. Empty for comics
+    pub chapter_chars: Arc<Vec<usize>>,
+    pub pages_per_chapter: Arc<Vec<(usize, usize)>>,
+    /// titles of the chapters, they are shown while dragging the progress bar
+    pub chapter_titles: Arc<Vec<String>>,
+    pub reading_speed: Option<f64>,
+}
+
+/// Reads the chapters of the book just opened in background, they are sent with CHAPTERS_LOADED
+pub fn load_chapters_info(sink: ExtEventSink, book: &Book) {
+    let path = book.get_path();
+    let is_comic = book.is_comic();
+    let pages_per_chapter = is_comic.then(|| pages_per_chapter(book));
+    let chapters = book.get_number_of_chapters();
+    let chapter = book.get_chapter_number();
+    let pages = book.get_chapter_pages().clone();
+    let page = book.get_current_page_number();
+    std::thread::spawn(move || {
+        let (chapter_chars, chapter_titles) = if is_comic {
+            (vec![], vec![])
+        } else {
+            let chars = (0..chapters)
+                .map(|chapter| epub_utils::get_chapter_text(&path, chapter).len())
+                .collect::<Vec<_>>();
+            (chars, epub_utils::get_chapter_titles(&path))
+        };
+        let info = ChaptersInfo {
+            start_chars: if is_comic { 0 } else { chars_before(&chapter_chars, chapter, &pages, page) },
+            chapter_chars: chapter_chars.into(),
+            pages_per_chapter: pages_per_chapter.unwrap_or_else(|| {
+                epub_utils::get_start_end_pages_per_chapter(&path, None).into()
+            }),
+            chapter_titles: chapter_titles.into(),
+            reading_speed: book_reading_speed(&reading_stats::load_sessions(), &path),
+            path,
+        };
+        let _ = sink.submit_command(CHAPTERS_LOADED, info, Target::Auto);
+    });
+}
+
+/// Jumps to a page counted from the start of the book, the position left can be reached going back
+pub fn go_to_page(data: &mut CrabReaderState, page: usize) {
+    if data.reading_state.is_editing {
+        return;
+    }
+    let Some(position) = Position::from_page(page, &data.reading_state.pages_per_chapter) else {
+        return;
+    };
+    let Some(book) = data.library.get_selected_book_mut() else {
+        return;
+    };
+    book.jump_to(position);
+    save_position(book);
+}
+
+/// Follows the text at the top of the continuous view: its page becomes the current one
+/// and its offset in the chapter is kept, to save the position more precisely than the page
+pub fn follow_scroll(data: &mut CrabReaderState, position: Position, offset: usize) {
+    data.reading_state.scroll_offset = offset;
+    let Some(book) = data.library.get_selected_book_mut() else {
+        return;
+    };
+    if book.get_position() == position {
+        return;
+    }
+    let before = book.get_cumulative_current_page_number() as isize;
+    book.scroll_to(position);
+    save_position(book);
+    save_scroll_offset(data);
+    let book = data.library.get_selected_book().unwrap();
+    let pages = book.get_cumulative_current_page_number() as isize - before;
+    record_pages(data, pages);
+}
+
+/// Saves the offset of the text at the top of the continuous view
+pub fn save_scroll_offset(data: &CrabReaderState) {
+    if !data.reading_state.continuous {
+        return;
+    }
+    let Some(book) = data.library.get_selected_book() else {
+        return;
+    };
+    let offset = book.get_text_offset(data.reading_state.scroll_offset);
+    if let Err(e) = save_offset(&book.get_path(), offset) {
+        println!("ERROR: failed to save the offset in the chapter: {}", e);
+    }
+}
+
+/// Switches between the pages and the continuous view, the text read stays in view
+pub fn toggle_continuous(data: &mut CrabReaderState) {
+    // the page shown is the one at the top of the continuous view
+    save_scroll_offset(data);
+    data.reading_state.continuous = !data.reading_state.continuous;
+}
+
+/// Goes to the page typed in the go-to window,
+/// the pages of the printed edition typed there are saved with the book
+pub fn go_to_typed_page(data: &mut CrabReaderState) -> Result<(), String> {
+    let printed_pages = match data.reading_state.printed_pages.trim() {
+        "" => None,
+        pages => Some(
+            pages
+                .parse::<usize>()
+                .ok()
+                .filter(|pages| *pages > 0)
+                .ok_or("le pagine dell'edizione stampata non sono un numero valido")?,
+        ),
+    };
+    let go_to = GoTo::parse(&data.reading_state.go_to)?;
+    let book = data.library.get_selected_book_mut().ok_or("nessun libro aperto")?;
+    if book.get_reading_info().printed_pages != printed_pages {
+        book.set_printed_pages(printed_pages);
+    }
+    let total_pages = data
+        .reading_state
+        .pages_per_chapter
+        .last()
+        .map_or(0, |(_, last)| last + 1);
+    let page = go_to.page(total_pages, printed_pages)?;
+    go_to_page(data, page);
+    Ok(())
 }
 
 pub fn save_btn_fn(
@@ -134,13 +320,20 @@ pub fn undo_btn_fn(
 
 pub fn page_number_switch_button(reading_state: &mut ReadingState) {
     let old = reading_state.pages_btn_style;
-    reading_state.pages_btn_style = (old+1)%3;
+    // time left and percent need the characters, comics have only the page counts
+    let styles = if reading_state.chapter_chars.is_empty() { 3 } else { 6 };
+    reading_state.pages_btn_style = (old+1)%styles;
 }
 
 pub fn change_chapter(book: &mut Book, chapter_number: usize) {
-    // change chapter number in book
-    book.set_chapter_number(chapter_number, true);
+    // change chapter number in book, the position left can be reached going back
+    book.jump_to(Position::new(chapter_number, 0));
     // save the new reading position
+    save_position(book);
+}
+
+/// Saves the page that the user is reading
+pub fn save_position(book: &Book) {
     save_data(
         book.get_path().to_string(),
         book.get_chapter_number(),
@@ -150,4 +343,22 @@ pub fn change_chapter(book: &mut Book, chapter_number: usize) {
         false,
     )
     .unwrap();
+}
+
+/// Moves back or forward in the history of the jumps, or to the furthest position read
+pub fn navigate(data: &mut CrabReaderState, to: Navigation) {
+    if data.reading_state.is_editing {
+        return;
+    }
+    let Some(book) = data.library.get_selected_book_mut() else {
+        return;
+    };
+    let moved = match to {
+        Navigation::Back => book.go_back(),
+        Navigation::Forward => book.go_forward(),
+        Navigation::Furthest => book.go_to_furthest(),
+    };
+    if moved {
+        save_position(book);
+    }
 }
\ No newline at end of file
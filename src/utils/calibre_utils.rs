@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    error,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use rusqlite::{Connection, OpenFlags};
+use serde_json::json;
+
+use super::{dir_manager::get_metadata_path, epub_utils::get_metadata_of_book};
+
+/// Name of the database in the root of a Calibre library
+pub const CALIBRE_DB: &str = "metadata.db";
+
+/// Formats that can be opened, in order of preference
+const SUPPORTED_FORMATS: [&str; 6] = ["EPUB", "AZW3", "MOBI", "AZW", "CBZ", "PDF"];
+
+/// Book of a Calibre library, as read from its database
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CalibreBook {
+    pub id: i64,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: f64,
+    pub tags: Vec<String>,
+    /// rating in stars, from 0 to 5
+    pub rating: Option<u8>,
+    pub comments: Option<String>,
+    pub lang: Option<String>,
+    /// folder of the book, relative to the root of the library
+    pub path: String,
+    pub has_cover: bool,
+    /// available formats with the name of their file (without extension)
+    pub formats: Vec<(String, String)>,
+}
+
+impl CalibreBook {
+    /// Returns the path of the file to open, choosing the preferred format
+    pub fn get_file_path(&self, root: &str) -> Option<PathBuf> {
+        SUPPORTED_FORMATS.iter().find_map(|preferred| {
+            self.formats
+                .iter()
+                .find(|(format, _)| format.eq_ignore_ascii_case(preferred))
+                .map(|(format, name)| {
+                    Path::new(root)
+                        .join(&self.path)
+                        .join(format!("{}.{}", name, format.to_lowercase()))
+                })
+        })
+    }
+}
+
+/// Returns true if the folder is the root of a Calibre library
+pub fn is_calibre_library(root: &str) -> bool {
+    Path::new(root).join(CALIBRE_DB).is_file()
+}
+
+/// Opens the database of the library without ever writing to it:
+/// Calibre could be running and using the same file
+fn open_db(root: &str) -> rusqlite::Result<Connection> {
+    let db_path = Path::new(root).join(CALIBRE_DB);
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+        | OpenFlags::SQLITE_OPEN_URI
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+
+    // characters with a meaning inside an URI must be escaped
+    let db_path = db_path
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23");
+
+    let uri = format!("file:{}?mode=ro", db_path);
+    let conn = Connection::open_with_flags(uri, flags)?;
+    // a database in WAL mode on a read-only folder can't be opened normally,
+    // in that case it is opened as immutable
+    if conn.query_row("SELECT count(*) FROM books", [], |row| row.get::<_, i64>(0)).is_ok() {
+        return Ok(conn);
+    }
+    let uri = format!("file:{}?immutable=1", db_path);
+    Connection::open_with_flags(uri, flags)
+}
+
+/// Returns a map from the id of the book to the values linked to it
+fn get_linked_values(
+    conn: &Connection,
+    query: &str,
+) -> rusqlite::Result<HashMap<i64, Vec<String>>> {
+    let mut stmt = conn.prepare(query)?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+
+    let mut values: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in rows {
+        let (book, value) = row?;
+        values.entry(book).or_default().push(value);
+    }
+    Ok(values)
+}
+
+/// Method that reads all the books of a Calibre library
+pub fn read_library(root: &str) -> Result<Vec<CalibreBook>, Box<dyn error::Error>> {
+    if !is_calibre_library(root) {
+        return Err(format!("{} is not a Calibre library", root).into());
+    }
+    let conn = open_db(root)?;
+
+    let mut authors = get_linked_values(
+        &conn,
+        "SELECT l.book, a.name FROM books_authors_link l
+         JOIN authors a ON a.id = l.author ORDER BY l.id",
+    )?;
+    let mut tags = get_linked_values(
+        &conn,
+        "SELECT l.book, t.name FROM books_tags_link l
+         JOIN tags t ON t.id = l.tag ORDER BY t.name",
+    )?;
+    let mut series = get_linked_values(
+        &conn,
+        "SELECT l.book, s.name FROM books_series_link l JOIN series s ON s.id = l.series",
+    )?;
+    let mut comments = get_linked_values(&conn, "SELECT book, text FROM comments")?;
+    let mut langs = get_linked_values(
+        &conn,
+        "SELECT l.book, g.lang_code FROM books_languages_link l
+         JOIN languages g ON g.id = l.lang_code ORDER BY l.item_order",
+    )?;
+
+    let mut ratings: HashMap<i64, u8> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT l.book, r.rating FROM books_ratings_link l JOIN ratings r ON r.id = l.rating",
+    )?;
+    for row in stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))? {
+        let (book, rating) = row?;
+        // Calibre saves the rating in half stars
+        ratings.insert(book, (rating.clamp(0, 10) / 2) as u8);
+    }
+
+    let mut formats: HashMap<i64, Vec<(String, String)>> = HashMap::new();
+    let mut stmt = conn.prepare("SELECT book, format, name FROM data")?;
+    for row in stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    })? {
+        let (book, format, name) = row?;
+        formats.entry(book).or_default().push((format, name));
+    }
+
+    let mut stmt =
+        conn.prepare("SELECT id, title, path, has_cover, series_index FROM books ORDER BY id")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<bool>>(3)?.unwrap_or(false),
+            row.get::<_, Option<f64>>(4)?.unwrap_or(1.0),
+        ))
+    })?;
+
+    let mut books = vec![];
+    for row in rows {
+        let (id, title, path, has_cover, series_index) = row?;
+        books.push(CalibreBook {
+            id,
+            title,
+            authors: authors.remove(&id).unwrap_or_default(),
+            series: series.remove(&id).and_then(|mut s| s.pop()),
+            series_index,
+            tags: tags.remove(&id).unwrap_or_default(),
+            rating: ratings.remove(&id).filter(|rating| *rating > 0),
+            comments: comments.remove(&id).and_then(|mut c| c.pop()),
+            lang: langs.remove(&id).and_then(|l| l.into_iter().next()),
+            path,
+            has_cover,
+            formats: formats.remove(&id).unwrap_or_default(),
+        });
+    }
+    Ok(books)
+}
+
+/// Method that overwrites the metadata extracted from the file of the book
+/// with the ones of the Calibre database, which are the ones edited by the user
+pub fn apply_metadata(book_path: &str, book: &CalibreBook) -> Result<(), Box<dyn error::Error>> {
+    let mut metadata = get_metadata_of_book(book_path);
+
+    metadata.insert("title".to_string(), book.title.clone());
+    if !book.authors.is_empty() {
+        metadata.insert("author".to_string(), book.authors.join(" & "));
+    }
+    if let Some(lang) = &book.lang {
+        metadata.insert("lang".to_string(), lang.clone());
+    }
+    if let Some(comments) = &book.comments {
+        metadata.insert("desc".to_string(), comments.clone());
+    }
+    if let Some(series) = &book.series {
+        metadata.insert("series".to_string(), series.clone());
+        metadata.insert("series_index".to_string(), book.series_index.to_string());
+    }
+    if !book.tags.is_empty() {
+        metadata.insert("tags".to_string(), book.tags.join(", "));
+    }
+    if let Some(rating) = book.rating {
+        metadata.insert("rating".to_string(), rating.to_string());
+    }
+    metadata.insert("calibre_id".to_string(), book.id.to_string());
+
+    let mut metadata_file = File::create(get_metadata_path(&book_path.to_string()))?;
+    metadata_file.write_all(json!(metadata).to_string().as_bytes())?;
+    Ok(())
+}
+
+/// Method that returns the cover saved by Calibre in the folder of the book
+pub fn get_cover(book_path: &str) -> Option<Vec<u8>> {
+    let folder = Path::new(book_path).parent()?;
+    let root = folder.parent()?.parent()?;
+    if !root.join(CALIBRE_DB).is_file() {
+        return None;
+    }
+    std::fs::read(folder.join("cover.jpg")).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::temp_dir;
+
+    fn create_fake_library(name: &str) -> PathBuf {
+        let root = temp_dir(name);
+        std::fs::create_dir_all(root.join("Dante Alighieri/Commedia (1)")).unwrap();
+
+        let conn = Connection::open(root.join(CALIBRE_DB)).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT, path TEXT,
+                has_cover BOOL, series_index REAL);
+             CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER, author INTEGER);
+             CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE books_tags_link (id INTEGER PRIMARY KEY, book INTEGER, tag INTEGER);
+             CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE books_series_link (id INTEGER PRIMARY KEY, book INTEGER, series INTEGER);
+             CREATE TABLE ratings (id INTEGER PRIMARY KEY, rating INTEGER);
+             CREATE TABLE books_ratings_link (id INTEGER PRIMARY KEY, book INTEGER, rating INTEGER);
+             CREATE TABLE comments (id INTEGER PRIMARY KEY, book INTEGER, text TEXT);
+             CREATE TABLE languages (id INTEGER PRIMARY KEY, lang_code TEXT);
+             CREATE TABLE books_languages_link (id INTEGER PRIMARY KEY, book INTEGER,
+                lang_code INTEGER, item_order INTEGER);
+             CREATE TABLE data (id INTEGER PRIMARY KEY, book INTEGER, format TEXT, name TEXT);
+
+             INSERT INTO books VALUES (1, 'Commedia', 'Dante Alighieri/Commedia (1)', 1, 2.0);
+             INSERT INTO books VALUES (2, 'Senza file', 'Anonimo/Senza file (2)', 0, 1.0);
+             INSERT INTO authors VALUES (1, 'Dante Alighieri'), (2, 'Virgilio');
+             INSERT INTO books_authors_link VALUES (1, 1, 1), (2, 1, 2);
+             INSERT INTO tags VALUES (1, 'Poesia'), (2, 'Classici');
+             INSERT INTO books_tags_link VALUES (1, 1, 1), (2, 1, 2);
+             INSERT INTO series VALUES (1, 'Trilogia');
+             INSERT INTO books_series_link VALUES (1, 1, 1);
+             INSERT INTO ratings VALUES (1, 8);
+             INSERT INTO books_ratings_link VALUES (1, 1, 1);
+             INSERT INTO comments VALUES (1, 1, 'Nel mezzo del cammin');
+             INSERT INTO languages VALUES (1, 'ita');
+             INSERT INTO books_languages_link VALUES (1, 1, 1, 0);
+             INSERT INTO data VALUES (1, 1, 'PDF', 'Commedia - Dante Alighieri'),
+                (2, 1, 'EPUB', 'Commedia - Dante Alighieri');",
+        )
+        .unwrap();
+        root
+    }
+
+    #[test]
+    fn library_is_read() {
+        let root = create_fake_library("calibre");
+        let root_str = root.to_str().unwrap();
+        assert!(is_calibre_library(root_str));
+
+        let books = read_library(root_str).unwrap();
+        assert_eq!(books.len(), 2);
+
+        let book = &books[0];
+        assert_eq!(book.title, "Commedia");
+        assert_eq!(book.authors, vec!["Dante Alighieri", "Virgilio"]);
+        assert_eq!(book.tags, vec!["Classici", "Poesia"]);
+        assert_eq!(book.series, Some("Trilogia".to_string()));
+        assert_eq!(book.series_index, 2.0);
+        assert_eq!(book.rating, Some(4));
+        assert_eq!(book.lang, Some("ita".to_string()));
+        assert!(book.has_cover);
+
+        // EPUB is preferred to PDF
+        assert_eq!(
+            book.get_file_path(root_str),
+            Some(root.join("Dante Alighieri/Commedia (1)/Commedia - Dante Alighieri.epub"))
+        );
+        assert_eq!(books[1].get_file_path(root_str), None);
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn folder_without_database_is_rejected() {
+        let root = temp_dir("not-calibre");
+        assert!(!is_calibre_library(root.to_str().unwrap()));
+        assert!(read_library(root.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn calibre_cover_is_found_next_to_the_book() {
+        let root = create_fake_library("calibre-cover");
+        let folder = root.join("Dante Alighieri/Commedia (1)");
+        std::fs::write(folder.join("cover.jpg"), b"cover").unwrap();
+
+        let book = folder.join("Commedia - Dante Alighieri.epub");
+        assert_eq!(get_cover(book.to_str().unwrap()), Some(b"cover".to_vec()));
+        assert_eq!(get_cover("/tmp/book.epub"), None);
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::HashMap,
+    error,
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+
+use image::io::Reader as ImageReader;
+use serde_json::json;
+use zip::ZipArchive;
+
+use super::{dir_manager::get_metadata_path, envmanager::FontSize};
+
+/// Extensions of the entries that are considered pages of a comic
+const PAGE_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "gif", "webp", "bmp"];
+
+/// Returns true if the path points to a comic book archive
+pub fn is_cbz(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("cbz"))
+}
+
+fn is_page_entry(name: &str) -> bool {
+    // skip hidden files and the resource forks created by macOS
+    if name.starts_with("__MACOSX") || name.split('/').last().unwrap_or("").starts_with('.') {
+        return false;
+    }
+    Path::new(name).extension().map_or(false, |ext| {
+        let ext = ext.to_string_lossy().to_lowercase();
+        PAGE_EXTENSIONS.contains(&ext.as_str())
+    })
+}
+
+/// Compares two entry names so that "page2" comes before "page10"
+fn natural_cmp(one: &str, other: &str) -> std::cmp::Ordering {
+    let mut a = one.chars().peekable();
+    let mut b = other.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return std::cmp::Ordering::Equal,
+            (None, Some(_)) => return std::cmp::Ordering::Less,
+            (Some(_), None) => return std::cmp::Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let mut num_a = String::new();
+                while let Some(c) = a.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    num_a.push(c);
+                    a.next();
+                }
+                let mut num_b = String::new();
+                while let Some(c) = b.peek().copied().filter(|c| c.is_ascii_digit()) {
+                    num_b.push(c);
+                    b.next();
+                }
+                let ord = num_a
+                    .parse::<u64>()
+                    .unwrap_or_default()
+                    .cmp(&num_b.parse::<u64>().unwrap_or_default());
+                if ord != std::cmp::Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.to_ascii_lowercase().cmp(&y.to_ascii_lowercase());
+                if ord != std::cmp::Ordering::Equal {
+                    return ord;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Method that returns the names of the images inside the archive,
+/// ordered as pages of the comic
+pub fn get_pages_of_comic(path: &str) -> Result<Vec<String>, Box<dyn error::Error>> {
+    let file = File::open(path)?;
+    let mut archive = ZipArchive::new(file)?;
+
+    let mut pages = vec![];
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)?;
+        if !entry.is_dir() && is_page_entry(entry.name()) {
+            pages.push(entry.name().to_string());
+        }
+    }
+    pages.sort_by(|a, b| natural_cmp(a, b));
+
+    Ok(pages)
+}
+
+/// Method that returns the raw bytes of a page of the comic
+pub fn get_page_bytes(path: &str, page_name: &str) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let file = File::open(path)?;
+    let mut archive = ZipArchive::new(file)?;
+    let mut entry = archive.by_name(page_name)?;
+
+    let mut bytes = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Method that decodes a page of the comic
+/// returns the RGBA buffer with the width and the height of the image
+pub fn decode_page(path: &str, page_name: &str) -> Result<(Vec<u8>, u32, u32), String> {
+    let bytes = get_page_bytes(path, page_name).map_err(|e| e.to_string())?;
+    let reader = ImageReader::new(std::io::Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    let image = reader.decode().map_err(|e| e.to_string())?.to_rgba8();
+    let (width, height) = image.dimensions();
+    Ok((image.into_raw(), width, height))
+}
+
+/// Method that returns the cover of the comic, i.e. its first page
+pub fn get_cover(path: &str) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let pages = get_pages_of_comic(path)?;
+    let first = pages.first().ok_or("comic without pages")?;
+    get_page_bytes(path, first)
+}
+
+/// Returns the text of the first <tag> found in a ComicInfo.xml file
+fn get_comic_info_tag(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = xml[start..].find(&close)? + start;
+    let text = xml[start..end].trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+fn get_metadata_from_cbz(path: &str) -> Result<HashMap<String, String>, Box<dyn error::Error>> {
+    let pages = get_pages_of_comic(path)?;
+
+    // ComicInfo.xml is optional, it is written by most of the comic taggers
+    let mut comic_info = String::new();
+    let file = File::open(path)?;
+    let mut archive = ZipArchive::new(file)?;
+    if let Ok(mut entry) = archive.by_name("ComicInfo.xml") {
+        let _ = entry.read_to_string(&mut comic_info);
+    };
+
+    let file_stem = Path::new(path)
+        .file_stem()
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .replace('_', " ");
+
+    let mut metadata = HashMap::new();
+    metadata.insert(
+        "title".to_string(),
+        get_comic_info_tag(&comic_info, "Title").unwrap_or(file_stem),
+    );
+    metadata.insert(
+        "author".to_string(),
+        get_comic_info_tag(&comic_info, "Writer").unwrap_or("no author".to_string()),
+    );
+    metadata.insert(
+        "lang".to_string(),
+        get_comic_info_tag(&comic_info, "LanguageISO").unwrap_or("no lang".to_string()),
+    );
+    if let Some(desc) = get_comic_info_tag(&comic_info, "Summary") {
+        metadata.insert("desc".to_string(), desc);
+    }
+
+    // a comic is a single chapter where every image is a page,
+    // so the number of pages doesn't depend on the font size
+    let number_of_pages = pages.len();
+    let last_page = number_of_pages.max(1) - 1;
+    for size in [FontSize::SMALL, FontSize::MEDIUM, FontSize::LARGE] {
+        metadata.insert(
+            format!("pages_per_chapter_{}", size.to_string()),
+            format!("[(0-{})]", last_page),
+        );
+    }
+    metadata.insert("total_pages".to_string(), number_of_pages.to_string());
+    metadata.insert("chapters".to_string(), "1".to_string());
+    metadata.insert("favorite".to_string(), "false".to_string());
+    metadata.insert("format".to_string(), "cbz".to_string());
+
+    Ok(metadata)
+}
+
+/// Method that saves the metadata of the comic in its saved_books folder
+pub fn extract_metadata(path: &str) -> Result<HashMap<String, String>, Box<dyn error::Error>> {
+    let metadata_map = get_metadata_from_cbz(path)?;
+    let path_name = get_metadata_path(&path.to_string());
+    let mut metadata_file = File::create(&path_name)?;
+
+    let json = json!(metadata_map);
+    metadata_file.write_all(json.to_string().as_bytes())?;
+    Ok(metadata_map)
+}
+
+/// Pages are read directly from the archive,
+/// so extracting a comic means only saving its metadata
+pub fn extract_all(path: &str) -> Result<(), Box<dyn error::Error>> {
+    extract_metadata(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::temp_dir;
+    use zip::{write::FileOptions, ZipWriter};
+
+    fn create_fake_comic(name: &str, entries: &[&str]) -> String {
+        let path = temp_dir(name).join("comic.cbz");
+        let file = File::create(&path).unwrap();
+        let mut zip = ZipWriter::new(file);
+        for entry in entries {
+            zip.start_file(*entry, FileOptions::default()).unwrap();
+            zip.write_all(b"not an image").unwrap();
+        }
+        zip.finish().unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn is_cbz_checks_extension() {
+        assert!(is_cbz("/tmp/comic.cbz"));
+        assert!(is_cbz("/tmp/comic.CBZ"));
+        assert!(!is_cbz("/tmp/book.epub"));
+    }
+
+    #[test]
+    fn pages_are_sorted_naturally() {
+        let path = create_fake_comic(
+            "comic-sorted",
+            &["page10.jpg", "page2.jpg", "page1.jpg", "ComicInfo.xml", ".thumb.jpg"],
+        );
+
+        let pages = get_pages_of_comic(&path).unwrap();
+        assert_eq!(pages, vec!["page1.jpg", "page2.jpg", "page10.jpg"]);
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn comic_info_tags_are_read() {
+        let xml = "<ComicInfo><Title>Crab</Title><Writer> Ferris </Writer><Summary></Summary></ComicInfo>";
+        assert_eq!(get_comic_info_tag(xml, "Title"), Some("Crab".to_string()));
+        assert_eq!(get_comic_info_tag(xml, "Writer"), Some("Ferris".to_string()));
+        assert_eq!(get_comic_info_tag(xml, "Summary"), None);
+        assert_eq!(get_comic_info_tag(xml, "Series"), None);
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+use super::dir_manager::get_collections_path;
+
+/// Collections and tags defined by the user to organize the library,
+/// the books are identified by their path like in the other files of the configuration.
+///
+/// A book can be in many collections and have many tags, a collection
+/// exists until it is deleted even when it has no books
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Collections {
+    path: PathBuf,
+    collections: BTreeMap<String, BTreeSet<String>>,
+    tags: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// Tags are compared ignoring the case and the spaces around them
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+impl Collections {
+    /// Reads the collections saved in the folder of the application
+    pub fn load() -> Self {
+        Self::load_from(&get_collections_path())
+    }
+
+    /// Reads the collections from a file, a missing or broken file means no collections
+    pub fn load_from(path: &Path) -> Self {
+        let json = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        Self::from_json(path, &json)
+    }
+
+    /// Reads the collections from their json, they are saved in the given path
+    pub fn from_json(path: &Path, json: &Value) -> Self {
+        let mut collections = Self {
+            path: path.to_path_buf(),
+            ..Default::default()
+        };
+        let to_set = |value: &Value| {
+            value
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|item| item.as_str().map(|item| item.to_string()))
+                .collect::<BTreeSet<_>>()
+        };
+        if let Some(object) = json["collections"].as_object() {
+            for (name, books) in object {
+                collections
+                    .collections
+                    .insert(name.to_string(), to_set(books));
+            }
+        }
+        if let Some(object) = json["tags"].as_object() {
+            for (book, tags) in object {
+                let tags = to_set(tags);
+                if !tags.is_empty() {
+                    collections.tags.insert(book.to_string(), tags);
+                }
+            }
+        }
+        collections
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "collections": self.collections,
+            "tags": self.tags,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn error::Error>> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.to_json())?)?;
+        Ok(())
+    }
+
+    /// Names of all the collections, in alphabetical order
+    pub fn get_collection_names(&self) -> Vec<String> {
+        self.collections.keys().cloned().collect()
+    }
+
+    /// Tags used by at least one book, in alphabetical order
+    pub fn get_all_tags(&self) -> Vec<String> {
+        self.tags
+            .values()
+            .flatten()
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Collections that contain the book
+    pub fn get_collections_of(&self, book_path: &str) -> Vec<String> {
+        self.collections
+            .iter()
+            .filter(|(_, books)| books.contains(book_path))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    pub fn get_tags_of(&self, book_path: &str) -> Vec<String> {
+        self.tags
+            .get(book_path)
+            .map(|tags| tags.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Adds the books to a collection, it is created if it doesn't exist
+    pub fn add_to_collection(&mut self, name: &str, books: &[String]) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("il nome della raccolta è vuoto".into());
+        }
+        self.collections
+            .entry(name.to_string())
+            .or_default()
+            .extend(books.iter().cloned());
+        Ok(())
+    }
+
+    pub fn remove_from_collection(&mut self, name: &str, books: &[String]) {
+        if let Some(collection) = self.collections.get_mut(name.trim()) {
+            books.iter().for_each(|book| {
+                collection.remove(book);
+            });
+        }
+    }
+
+    /// Deletes a collection, its books stay in the library.
+    /// Returns false if the collection doesn't exist
+    pub fn delete_collection(&mut self, name: &str) -> bool {
+        self.collections.remove(name).is_some()
+    }
+
+    pub fn add_tag(&mut self, tag: &str, books: &[String]) -> Result<(), String> {
+        let tag = normalize_tag(tag);
+        if tag.is_empty() {
+            return Err("il tag è vuoto".into());
+        }
+        for book in books {
+            self.tags
+                .entry(book.clone())
+                .or_default()
+                .insert(tag.clone());
+        }
+        Ok(())
+    }
+
+    pub fn remove_tag(&mut self, tag: &str, books: &[String]) {
+        let tag = normalize_tag(tag);
+        for book in books {
+            if let Some(tags) = self.tags.get_mut(book) {
+                tags.remove(&tag);
+                if tags.is_empty() {
+                    self.tags.remove(book);
+                }
+            }
+        }
+    }
+
+    /// Books that are in a collection or have a tag
+    pub fn get_books(&self) -> BTreeSet<String> {
+        self.collections
+            .values()
+            .flatten()
+            .chain(self.tags.keys())
+            .cloned()
+            .collect()
+    }
+
+    /// Changes the identity of the books, e.g. from their paths to their file names
+    pub fn rename_books(&self, rename: impl Fn(&str) -> String) -> Self {
+        Self {
+            path: self.path.clone(),
+            collections: self
+                .collections
+                .iter()
+                .map(|(name, books)| (name.clone(), books.iter().map(|b| rename(b)).collect()))
+                .collect(),
+            tags: self
+                .tags
+                .iter()
+                .map(|(book, tags)| (rename(book), tags.clone()))
+                .collect(),
+        }
+    }
+
+    /// Adds the collections and the tags of another library to these ones
+    pub fn merge(&mut self, other: &Collections) {
+        for (name, books) in &other.collections {
+            self.collections
+                .entry(name.clone())
+                .or_default()
+                .extend(books.iter().cloned());
+        }
+        for (book, tags) in &other.tags {
+            self.tags
+                .entry(book.clone())
+                .or_default()
+                .extend(tags.iter().cloned());
+        }
+    }
+
+    /// Forgets a book that is no longer in the library
+    pub fn remove_book(&mut self, book_path: &str) {
+        self.collections.values_mut().for_each(|books| {
+            books.remove(book_path);
+        });
+        self.tags.remove(book_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::temp_dir;
+
+    fn temp_file(name: &str) -> PathBuf {
+        temp_dir(name).join("collections.json")
+    }
+
+    fn books(paths: &[&str]) -> Vec<String> {
+        paths.iter().map(|path| path.to_string()).collect()
+    }
+
+    #[test]
+    fn test_collections_survive_reload() {
+        let path = temp_file("collections");
+        let mut collections = Collections::load_from(&path);
+        assert_eq!(collections.get_collection_names(), Vec::<String>::new());
+
+        collections
+            .add_to_collection("Da leggere", &books(&["/a.epub", "/b.epub"]))
+            .unwrap();
+        collections
+            .add_to_collection(" Classici ", &books(&["/a.epub"]))
+            .unwrap();
+        collections
+            .add_tag(" Fantasy", &books(&["/b.epub"]))
+            .unwrap();
+        assert!(collections
+            .add_to_collection("  ", &books(&["/a.epub"]))
+            .is_err());
+        collections.save().unwrap();
+
+        let reloaded = Collections::load_from(&path);
+        assert_eq!(reloaded, collections);
+        assert_eq!(
+            reloaded.get_collection_names(),
+            books(&["Classici", "Da leggere"])
+        );
+        assert_eq!(
+            reloaded.get_collections_of("/a.epub"),
+            books(&["Classici", "Da leggere"])
+        );
+        assert_eq!(reloaded.get_tags_of("/b.epub"), books(&["fantasy"]));
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_bulk_assignment() {
+        let mut collections = Collections::load_from(&temp_file("bulk"));
+        let selected = books(&["/a.epub", "/b.epub", "/c.epub"]);
+
+        collections.add_tag("giallo", &selected).unwrap();
+        collections.add_tag("Giallo", &books(&["/d.epub"])).unwrap();
+        collections.add_tag("breve", &books(&["/a.epub"])).unwrap();
+        assert_eq!(collections.get_all_tags(), books(&["breve", "giallo"]));
+
+        collections.remove_tag("GIALLO", &books(&["/a.epub", "/b.epub"]));
+        assert_eq!(collections.get_tags_of("/a.epub"), books(&["breve"]));
+        assert_eq!(collections.get_tags_of("/b.epub"), Vec::<String>::new());
+        assert_eq!(collections.get_tags_of("/c.epub"), books(&["giallo"]));
+
+        collections.add_to_collection("Estate", &selected).unwrap();
+        collections.remove_from_collection("Estate", &books(&["/c.epub"]));
+        assert_eq!(
+            collections.get_collections_of("/c.epub"),
+            Vec::<String>::new()
+        );
+
+        // an empty collection is kept until it is deleted
+        collections.remove_from_collection("Estate", &books(&["/a.epub", "/b.epub"]));
+        assert_eq!(collections.get_collection_names(), books(&["Estate"]));
+        assert!(collections.delete_collection("Estate"));
+        assert!(!collections.delete_collection("Estate"));
+
+        collections.remove_book("/c.epub");
+        assert_eq!(collections.get_all_tags(), books(&["breve", "giallo"]));
+        collections.remove_book("/d.epub");
+        assert_eq!(collections.get_all_tags(), books(&["breve"]));
+    }
+}
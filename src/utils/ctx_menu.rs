@@ -1,16 +1,167 @@
-use crate::{CrabReaderState, utils::fonts::{FONT, self, SET_FONT_SMALL, SET_FONT_MEDIUM, SET_FONT_LARGE}, MYENV};
-use druid::{Menu, MenuItem, Command, Target, Env, FontFamily, FontDescriptor};
+use crate::{CrabReaderState, traits::{gui::GUILibrary, reader::BookManagement}, utils::fonts::{FONT, self, SET_FONT_SMALL, SET_FONT_MEDIUM, SET_FONT_LARGE}, MYENV, models::{backup::OPEN_BACKUP_WINDOW, book_actions::{BookAction, ASK_BOOK_ACTION}, command::Trigger, duplicates::OPEN_DUPLICATES_WINDOW, goals::OPEN_GOALS_WINDOW, import::OPEN_IMPORT_WINDOW, kosync::{OPEN_KOSYNC_WINDOW, TOGGLE_KOSYNC_SERVER}, navigation::OPEN_GO_TO_WINDOW, opds::{OPEN_OPDS_WINDOW, TOGGLE_OPDS_SERVER}, stats::OPEN_STATS_WINDOW, sync::{OPEN_SYNC_CONFLICTS, SYNC_DISABLE, SYNC_MERGE}, watcher::UNWATCH_DIRS}};
+use druid::{Menu, MenuItem, Command, Target, Env, FontFamily, FontDescriptor, FileDialogOptions, FileSpec, commands::{SHOW_OPEN_PANEL, SHOW_SAVE_PANEL}};
 
-use super::{colors::CrabTheme};
+use super::{book_import, colors::CrabTheme, trash};
 
 fn file() -> Menu<CrabReaderState> {
-    let add_file = MenuItem::new("Aggiungi un eBook");
-    let rm_file = MenuItem::new("Rimuovi un eBook");
+    let add_file = MenuItem::new("Aggiungi un eBook")
+        .on_activate(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::ADDBOOK;
+            ctx.submit_command(Command::new(SHOW_OPEN_PANEL, book_import::open_books_options(), Target::Auto));
+        });
+    let add_dir = MenuItem::new("Aggiungi una cartella di eBook...")
+        .on_activate(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::IMPORTDIR;
+            ctx.submit_command(Command::new(SHOW_OPEN_PANEL, book_import::open_folder_options(), Target::Auto));
+        });
+    let import = MenuItem::new("Importazione libri...")
+        .command(Command::new(OPEN_IMPORT_WINDOW, (), Target::Auto));
+    let duplicates = MenuItem::new("Trova libri duplicati...")
+        .command(Command::new(OPEN_DUPLICATES_WINDOW, (), Target::Auto));
+    let rm_file = MenuItem::new("Rimuovi un eBook")
+        .enabled_if(|data: &CrabReaderState, _| data.library.get_selected_book_idx().is_some())
+        .on_activate(|ctx, data: &mut CrabReaderState, _| {
+            if let Some(idx) = data.library.get_selected_book_idx() {
+                ctx.submit_command(Command::new(ASK_BOOK_ACTION, (idx, BookAction::Remove), Target::Auto));
+            }
+        });
     let del_cache = MenuItem::new("Svuota cache");
     Menu::new("File")
         .entry(add_file)
+        .entry(add_dir)
+        .entry(import)
+        .entry(duplicates)
         .entry(rm_file)
         .entry(del_cache)
+        .separator()
+        .entry(calibre())
+        .entry(watched_dirs())
+        .entry(
+            MenuItem::new("Catalogo OPDS")
+                .command(Command::new(OPEN_OPDS_WINDOW, (), Target::Auto)),
+        )
+        .entry(sync())
+        .entry(
+            MenuItem::new("Backup WebDAV...")
+                .command(Command::new(OPEN_BACKUP_WINDOW, (), Target::Auto)),
+        )
+        .entry(archive())
+}
+
+fn archive() -> Menu<CrabReaderState> {
+    fn export_options() -> FileDialogOptions {
+        FileDialogOptions::new()
+            .allowed_types(vec![FileSpec::new("Archivio", &["zip"])])
+            .default_name("crab-reader.zip")
+            .title("Esporta archivio della libreria")
+    }
+    let export_data = MenuItem::new("Esporta archivio libreria...")
+        .on_activate(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::EXPORT;
+            ctx.submit_command(Command::new(SHOW_SAVE_PANEL, export_options(), Target::Auto));
+        });
+    // the eBooks make the archive bigger, but it is enough to move the library
+    let export_books = MenuItem::new("Esporta archivio con eBook...")
+        .on_activate(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::EXPORTBOOKS;
+            ctx.submit_command(Command::new(SHOW_SAVE_PANEL, export_options(), Target::Auto));
+        });
+    let import = MenuItem::new("Importa archivio...")
+        .on_activate(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::IMPORT;
+            let options = FileDialogOptions::new()
+                .allowed_types(vec![FileSpec::new("Archivio", &["zip"])])
+                .title("Importa archivio della libreria");
+            ctx.submit_command(Command::new(SHOW_OPEN_PANEL, options, Target::Auto));
+        });
+    Menu::new("Archivio")
+        .entry(export_data)
+        .entry(export_books)
+        .entry(import)
+}
+
+fn sync() -> Menu<CrabReaderState> {
+    let choose = MenuItem::new("Scegli cartella condivisa...")
+        .on_activate(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::SYNCDIR;
+            let options = FileDialogOptions::new()
+                .select_directories()
+                .title("Seleziona la cartella condivisa con gli altri dispositivi");
+            ctx.submit_command(Command::new(SHOW_OPEN_PANEL, options, Target::Auto));
+        });
+    let folder = MenuItem::new(|_: &CrabReaderState, _: &Env| {
+        MYENV
+            .lock()
+            .unwrap()
+            .sync_dir
+            .clone()
+            .unwrap_or("Sincronizzazione non attiva".to_string())
+    })
+    .enabled(false);
+    let disable = MenuItem::new("Disattiva")
+        .enabled_if(|_, _| MYENV.lock().unwrap().sync_dir.is_some())
+        .command(Command::new(SYNC_DISABLE, (), Target::Auto));
+    let merge = MenuItem::new("Sincronizza ora")
+        .enabled_if(|_, _| MYENV.lock().unwrap().sync_dir.is_some())
+        .command(Command::new(SYNC_MERGE, (), Target::Auto));
+    let conflicts = MenuItem::new(|data: &CrabReaderState, _: &Env| {
+        format!("Conflitti ({})...", data.sync_conflicts.len())
+    })
+    .command(Command::new(OPEN_SYNC_CONFLICTS, (), Target::Auto));
+    Menu::new("Sincronizzazione")
+        .entry(choose)
+        .entry(folder)
+        .entry(disable)
+        .separator()
+        .entry(merge)
+        .entry(conflicts)
+}
+
+fn calibre() -> Menu<CrabReaderState> {
+    let open = MenuItem::new("Apri libreria Calibre")
+        .on_activate(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::CALIBRE;
+            let options = FileDialogOptions::new()
+                .select_directories()
+                .title("Seleziona la cartella della libreria Calibre");
+            ctx.submit_command(Command::new(SHOW_OPEN_PANEL, options, Target::Auto));
+        });
+    let close = MenuItem::new("Chiudi libreria Calibre")
+        .enabled_if(|_, _| MYENV.lock().unwrap().calibre_library.is_some())
+        .on_activate(|_, data: &mut CrabReaderState, _| {
+            let mut my_env = MYENV.lock().unwrap();
+            if let Some(root) = my_env.calibre_library.take() {
+                data.library.remove_books_in_dir(&root);
+            }
+            my_env.save_to_env();
+        });
+    Menu::new("Calibre").entry(open).entry(close)
+}
+
+fn watched_dirs() -> Menu<CrabReaderState> {
+    let add = MenuItem::new("Aggiungi cartella...")
+        .on_activate(|ctx, data: &mut CrabReaderState, _| {
+            data.open_file_trigger = Trigger::WATCHDIR;
+            let options = FileDialogOptions::new()
+                .select_directories()
+                .title("Seleziona una cartella i cui libri verranno aggiunti alla libreria");
+            ctx.submit_command(Command::new(SHOW_OPEN_PANEL, options, Target::Auto));
+        });
+    let count = MenuItem::new(|_: &CrabReaderState, _: &Env| {
+        match MYENV.lock().unwrap().watched_dirs.len() {
+            0 => "Solo la cartella dei libri".to_string(),
+            1 => "1 cartella osservata".to_string(),
+            n => format!("{} cartelle osservate", n),
+        }
+    })
+    .enabled(false);
+    let remove = MenuItem::new("Smetti di osservare le cartelle")
+        .enabled_if(|_, _| !MYENV.lock().unwrap().watched_dirs.is_empty())
+        .command(Command::new(UNWATCH_DIRS, (), Target::Auto));
+    Menu::new("Cartelle osservate")
+        .entry(add)
+        .entry(count)
+        .entry(remove)
 }
 
 fn options() -> Menu<CrabReaderState> {
@@ -19,6 +170,40 @@ fn options() -> Menu<CrabReaderState> {
         .entry(shadows())
         .entry(text())
         .entry(lang())
+        .entry(opds_server())
+        .entry(kosync())
+}
+
+fn kosync() -> Menu<CrabReaderState> {
+    let account = MenuItem::new("Account di sincronizzazione...")
+        .command(Command::new(OPEN_KOSYNC_WINDOW, (), Target::Auto));
+    let toggle = MenuItem::new("Avvia server di sincronizzazione")
+        .selected_if(|data: &CrabReaderState, _| data.kosync_server.is_some())
+        .command(Command::new(TOGGLE_KOSYNC_SERVER, (), Target::Auto));
+    let address = MenuItem::new(|data: &CrabReaderState, _: &Env| {
+        data.kosync_server
+            .as_ref()
+            .map_or("Server non attivo".to_string(), |server| server.get_address())
+    })
+    .enabled(false);
+    Menu::new("Sincronizzazione KOReader")
+        .entry(account)
+        .separator()
+        .entry(toggle)
+        .entry(address)
+}
+
+fn opds_server() -> Menu<CrabReaderState> {
+    let toggle = MenuItem::new("Condividi libreria in rete")
+        .selected_if(|data: &CrabReaderState, _| data.opds_server.is_some())
+        .command(Command::new(TOGGLE_OPDS_SERVER, (), Target::Auto));
+    let address = MenuItem::new(|data: &CrabReaderState, _: &Env| {
+        data.opds_server
+            .as_ref()
+            .map_or("Server non attivo".to_string(), |server| server.get_address())
+    })
+    .enabled(false);
+    Menu::new("Server OPDS").entry(toggle).entry(address)
 }
 
 fn text() -> Menu<CrabReaderState> {
@@ -27,9 +212,47 @@ fn text() -> Menu<CrabReaderState> {
     Menu::new("Testo").entry(sz).entry(font)
 }
 
+fn reading() -> Menu<CrabReaderState> {
+    let stats = MenuItem::new("Statistiche di lettura...")
+        .command(Command::new(OPEN_STATS_WINDOW, (), Target::Auto));
+    let goals = MenuItem::new("Obiettivi di lettura...")
+        .command(Command::new(OPEN_GOALS_WINDOW, (), Target::Auto));
+    let go_to = MenuItem::new("Vai a pagina...")
+        .command(Command::new(OPEN_GO_TO_WINDOW, (), Target::Auto))
+        .enabled_if(|data: &CrabReaderState, _| data.reading);
+    Menu::new("Lettura").entry(stats).entry(goals).entry(go_to)
+}
+
 /// Returns the context menu for the main window
 pub fn main_window() -> Menu<CrabReaderState> {
-    Menu::new("CrabMenù").entry(file()).entry(options())
+    Menu::new("CrabMenù").entry(file()).entry(reading()).entry(options())
+}
+
+/// Returns the context menu of a book in the library, every action asks for a confirmation
+pub fn book_menu(idx: usize, archived: bool) -> Menu<CrabReaderState> {
+    let item = |action: BookAction| {
+        MenuItem::new(action.label())
+            .command(Command::new(ASK_BOOK_ACTION, (idx, action), Target::Auto))
+    };
+    // the books of a Calibre library are only read, their files are never deleted
+    let delete = item(BookAction::DeleteFile).enabled_if(move |data: &CrabReaderState, _| {
+        data.library
+            .get_book(idx)
+            .is_some_and(|book| trash::can_delete_file(&book.get_path()))
+    });
+    let archive = if archived {
+        item(BookAction::Unarchive)
+    } else {
+        item(BookAction::Archive)
+    };
+    Menu::new("Libro")
+        .entry(archive)
+        .entry(item(BookAction::Remove))
+        .entry(delete)
+        .separator()
+        .entry(item(BookAction::ResetProgress))
+        .entry(item(BookAction::ClearNotes))
+        .entry(item(BookAction::DiscardEdits))
 }
 
 fn theme() -> Menu<CrabReaderState> {
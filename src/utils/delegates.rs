@@ -1,26 +1,97 @@
 use druid::{
-    commands::OPEN_FILE,
+    commands::{OPEN_FILE, OPEN_FILES, SAVE_FILE_AS},
     widget::{Align, Flex, Label, LineBreaking},
-    AppDelegate, Code, Env, Event, Handled, KeyEvent, WindowDesc, FontDescriptor, FontFamily, KeyOrValue,
+    im::Vector, AppDelegate, Code, Env, Event, Handled, KeyEvent, Target, WindowDesc, FontDescriptor, FontFamily, KeyOrValue,
 };
-use std::{path::Path, rc::Rc};
+use image::io::Reader as ImageReader;
+use std::{collections::{HashMap, HashSet}, io::Cursor, path::Path, rc::Rc, sync::Arc};
 
 use super::{
-    button_functions::{self, go_next, go_prev},
-    colors::SWITCH_THEME, fonts::{SET_FONT_SMALL, SET_FONT_MEDIUM, SET_FONT_LARGE},
+    button_functions::{self, go_next, go_prev, pages_per_chapter, read_chars},
+    colors::{CrabTheme, SWITCH_THEME}, fonts::{SET_FONT_SMALL, SET_FONT_MEDIUM, SET_FONT_LARGE},
 };
 use crate::{
+    components::views::{
+        backup_view::backup_window_widget,
+        book_actions_view::confirm_action_widget,
+        duplicates_view::duplicates_window_widget,
+        goals_view::goals_window_widget,
+        import_view::import_window_widget,
+        kosync_view::{kosync_window_widget, progress_offer_widget},
+        metadata_view::metadata_window_widget,
+        navigation_view::go_to_window_widget,
+        opds_view::opds_window_widget,
+        stats_view::stats_window_widget,
+        sync_view::sync_conflicts_widget,
+    },
     models::{
+        archive::{ARCHIVE_EXPORTED, ARCHIVE_IMPORTED},
+        backup::{
+            RestoreScope, SnapshotItem, BACKUP_DONE, BACKUP_LIST, BACKUP_LISTED, BACKUP_RESTORE,
+            BACKUP_RESTORED, BACKUP_RUN, BACKUP_SAVE_SETTINGS, OPEN_BACKUP_WINDOW,
+        },
         book::Book,
+        book_actions::{
+            BookAction, UndoState, ASK_BOOK_ACTION, COMMIT_BOOK_ACTION, RUN_BOOK_ACTION,
+            UNDO_BOOK_ACTION, UNDO_GRACE_PERIOD,
+        },
         command::Trigger,
+        duplicates::{
+            DuplicateBook, DuplicateGroup, DUPLICATES_FOUND, DUPLICATES_IGNORE, DUPLICATES_KEEP,
+            DUPLICATES_MERGE, DUPLICATES_SEARCH, OPEN_DUPLICATES_WINDOW,
+        },
+        goals::{
+            books_finished_in_year, local_time, seconds_on_day, GOALS_CHECK, GOALS_SAVE,
+            OPEN_GOALS_WINDOW,
+        },
+        import::{IMPORT_CHECKED, IMPORT_CLEAR, OPEN_IMPORT_WINDOW},
+        kosync::{
+            KOSYNC_APPLY_PROGRESS, KOSYNC_LOGGED_IN, KOSYNC_LOGIN, KOSYNC_LOGOUT,
+            KOSYNC_PROGRESS_FETCHED, OPEN_KOSYNC_WINDOW, TOGGLE_KOSYNC_SERVER,
+        },
         library::{Library, SortBy},
+        metadata::{
+            BookMetadata, AUTHOR_ROLE, METADATA_ADD_AUTHOR, METADATA_REMOVE_AUTHOR, METADATA_SAVE,
+            METADATA_SAVED, OPEN_METADATA_EDITOR,
+        },
+        navigation::{Navigation, Position, OPEN_GO_TO_WINDOW},
+        opds::{
+            OPDS_ACTIVATE_ENTRY, OPDS_BACK, OPDS_BOOK_DOWNLOADED, OPDS_COVER_HEIGHT,
+            OPDS_COVER_LOADED, OPDS_COVER_WIDTH, OPDS_FEED_LOADED, OPDS_OPEN_FEED, OPDS_SEARCH,
+            OPEN_OPDS_WINDOW, TOGGLE_OPDS_SERVER,
+        },
+        reading::{self, ReadingStatus},
+        stats::{ReadingSession, StatsState, OPEN_STATS_WINDOW},
+        sync::{OPEN_SYNC_CONFLICTS, SYNC_DISABLE, SYNC_MERGE, SYNC_RESOLVE_CONFLICT},
+        watcher::{LIBRARY_FILES_CHANGED, UNWATCH_DIRS},
     },
     traits::{
         gui::{GUIBook, GUILibrary},
         reader::{BookManagement, BookReading},
     },
-    utils::{dir_manager::get_epub_dir, ocrmanager, saveload::copy_book_in_folder, fonts::FONT},
-    CrabReaderState, DisplayMode, ENTERING_READING_MODE, MYENV,
+    utils::{
+        archive, book_import,
+        dir_manager::{get_app_dir, get_kosync_server_path},
+        duplicates::{self, Candidate},
+        envmanager::{FontSize, MyEnv},
+        epub_utils,
+        fonts::FONT,
+        kosync_client::{self, Progress},
+        kosync_server::{KoSyncServer, KOSYNC_SERVER_PORT},
+        ocrmanager,
+        opds_client::{self, OpdsFeed},
+        opds_server::{self, OpdsServer, OPDS_SERVER_PORT},
+        reading_stats,
+        rich_text_fn::OPEN_LINK,
+        saveload::{
+            load_excluded_books, load_offset, load_timestamp, move_book_data, save_data,
+            save_edited_metadata, set_book_excluded,
+        },
+        sync_log,
+        trash::{self, Snapshot},
+        webdav_backup::{self, Restore},
+    },
+    CrabReaderState, DisplayMode, CHAPTERS_LOADED, ENTERING_READING_MODE, LEAVING_READING_MODE, MYENV,
 };
 
 pub struct ReadModeDelegate;
@@ -36,18 +107,85 @@ impl AppDelegate<CrabReaderState> for ReadModeDelegate {
     ) -> Handled {
         match cmd {
             notif if notif.is(ENTERING_READING_MODE) => {
+                finish_session(data);
                 data.reading = true;
+                data.library.get_selected_book_mut().unwrap().mark_opened();
                 data.reading_state.enable(Rc::new(
                     data.library
                         .get_selected_book()
                         .unwrap()
                         .get_page_of_chapter(),
                 ));
+                let book = data.library.get_selected_book().unwrap();
+                // the position is measured once the chapters have been read
+                data.session = Some(ReadingSession::new(book.get_path(), reading::now(), 0));
+                data.reading_state.chapter_chars = Arc::default();
+                data.reading_state.pages_per_chapter = Arc::default();
+                data.reading_state.chapter_titles = Arc::default();
+                data.reading_state.reading_speed = None;
+                data.reading_state.scroll_offset = load_offset(&book.get_path()).unwrap_or(0);
+                button_functions::load_chapters_info(delegate_ctx.get_external_handle(), book);
+                // the book may have been read further on another device
+                let path = data.library.get_selected_book().unwrap().get_path();
+                fetch_remote_progress(delegate_ctx, path);
                 Handled::Yes
             }
-            notif if notif.is(ENTERING_READING_MODE) => {
+            notif if notif.is(CHAPTERS_LOADED) => {
+                let info = cmd.get_unchecked(CHAPTERS_LOADED);
+                // the reader may have moved to another book meanwhile
+                let selected = data.library.get_selected_book().map(|book| book.get_path());
+                if !data.reading || selected.as_ref() != Some(&info.path) {
+                    return Handled::Yes;
+                }
+                data.reading_state.chapter_chars = info.chapter_chars.clone();
+                data.reading_state.pages_per_chapter = info.pages_per_chapter.clone();
+                data.reading_state.chapter_titles = info.chapter_titles.clone();
+                data.reading_state.reading_speed = info.reading_speed;
+                if let Some(session) = data.session.as_mut().filter(|session| session.book == info.path) {
+                    session.set_start_chars(info.start_chars);
+                }
+                Handled::Yes
+            }
+            notif if notif.is(LEAVING_READING_MODE) => {
                 data.reading = false;
+                button_functions::save_scroll_offset(data);
+                // the characters read are measured with the chapters still loaded
+                finish_session(data);
                 data.reading_state.disable();
+                kosync_client::flush_positions();
+                update_goals(data);
+                Handled::Yes
+            }
+            cmd if cmd.is(OPEN_LINK) => {
+                let target = cmd.get_unchecked(OPEN_LINK);
+                if !data.reading || data.reading_state.is_editing {
+                    return Handled::Yes;
+                }
+                let book = data.library.get_selected_book_mut().unwrap();
+                // the position of the link can be reached going back
+                if let Some(chapter) = epub_utils::get_chapter_of_link(&book.get_path(), target) {
+                    book.jump_to(Position::new(chapter, 0));
+                    button_functions::save_position(book);
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPEN_GO_TO_WINDOW) => {
+                if !data.reading || data.reading_state.is_editing {
+                    return Handled::Yes;
+                }
+                let book = data.library.get_selected_book().unwrap();
+                data.reading_state.printed_pages = book
+                    .get_reading_info()
+                    .printed_pages
+                    .map(|pages| pages.to_string())
+                    .unwrap_or_default();
+                data.reading_state.go_to = String::new();
+                data.reading_state.go_to_status = String::new();
+                let win_desc = WindowDesc::new(go_to_window_widget())
+                    .title("Vai a")
+                    .window_size((450.0, 250.0));
+                delegate_ctx.new_window(win_desc);
                 Handled::Yes
             }
 
@@ -127,9 +265,8 @@ impl AppDelegate<CrabReaderState> for ReadModeDelegate {
 
                     match ocr_result {
                         Some(ocr_result) => {
-                            //move to the found page
-                            selected_book_mut.set_chapter_number(ocr_result.0, true);
-                            selected_book_mut.set_chapter_current_page_number(ocr_result.1);
+                            //move to the found page, the position left can be reached going back
+                            selected_book_mut.jump_to(Position::new(ocr_result.0, ocr_result.1));
                         }
                         None => {
                             show_alert_dialog(
@@ -173,52 +310,37 @@ impl AppDelegate<CrabReaderState> for ReadModeDelegate {
                     );
                 }
 
-                // function to do if open file is triggered for add book
-                fn add_book_fn(
+                // function to do if open file is triggered for a calibre library
+                fn calibre_fn(
                     file_path: &Path,
                     library: &mut Library<Book>,
                     delegate_ctx: &mut druid::DelegateCtx,
                 ) {
-                    let book_str = file_path.to_str().unwrap();
-                    let book_path = Path::new(book_str);
-
-                    let mut label_text =
-                        format!("Il libro {} è stato aggiunto alla libreria", book_str);
-                    let mut title = "Libro aggiunto".to_string();
-
-                    // check if book_path is a file from the epub folder
-                    let epub_dir = get_epub_dir();
-                    let file_name = book_path.file_name().unwrap();
+                    let root = file_path.to_str().unwrap().to_string();
+                    let mut my_env = MYENV.lock().unwrap();
 
-                    let mut exists = false;
-
-                    for path in epub_dir.read_dir().unwrap() {
-                        if path.unwrap().file_name() == file_name {
-                            exists = true;
-                            break;
-                        }
-                    }
-
-                    // if exists a book with the same name in the epub folder
-                    // or if the book is already in the library
-                    // then don't add it
-                    if exists || book_path == epub_dir.join(file_name) {
-                        //Book already in epub folder
-                        title = "Libro già presente".to_string();
-                        label_text = "Il libro è già presente nella libreria, non puoi aggiungerlo"
-                            .to_string();
+                    let (title, label_text) = if my_env.calibre_library.as_ref() == Some(&root) {
+                        ("Libreria Calibre".to_string(), "La libreria Calibre è già aperta".to_string())
                     } else {
-                        if let Ok(_) = copy_book_in_folder(&book_str.to_string()) {
-
-                            let real_path = get_epub_dir().join(book_path.file_name().unwrap());
-                            if real_path.exists() {
-                                // library.add_book(real_path.to_str().unwrap());
-                                library.schedule_book_loading(real_path.to_str().unwrap());
+                        match library.schedule_calibre_loading(&root) {
+                            Ok(number_of_books) => {
+                                // the books of the old library are replaced by the new ones
+                                if let Some(old_root) = my_env.calibre_library.replace(root.clone()) {
+                                    library.remove_books_in_dir(&old_root);
+                                }
+                                my_env.save_to_env();
+                                (
+                                    "Libreria Calibre".to_string(),
+                                    format!("{} libri della libreria Calibre verranno aggiunti", number_of_books),
+                                )
                             }
-                        } else {
-                            println!("Error copying book in folder");
+                            Err(e) => (
+                                "Errore".to_string(),
+                                format!("Non è stato possibile aprire la libreria Calibre: {}", e),
+                            ),
                         }
-                    }
+                    };
+                    drop(my_env);
 
                     show_alert_dialog(
                         delegate_ctx, 
@@ -240,7 +362,48 @@ impl AppDelegate<CrabReaderState> for ReadModeDelegate {
                         data.font.size
                     ),
 
-                    Trigger::ADDBOOK => add_book_fn(file_path, &mut data.library, delegate_ctx),
+                    Trigger::ADDBOOK | Trigger::IMPORTDIR => {
+                        let path = file_path.to_str().unwrap().to_string();
+                        import_books_fn(&[path], false, data, delegate_ctx);
+                    }
+
+                    Trigger::CALIBRE => calibre_fn(file_path, &mut data.library, delegate_ctx),
+
+                    Trigger::IMPORT => {
+                        let archive = file_path.to_path_buf();
+                        let sink = delegate_ctx.get_external_handle();
+                        std::thread::spawn(move || {
+                            let result = archive::import_archive(&get_app_dir(), &archive)
+                                .map_err(|e| e.to_string());
+                            let _ = sink.submit_command(ARCHIVE_IMPORTED, result, Target::Auto);
+                        });
+                    }
+
+                    Trigger::COVER => {
+                        data.metadata_editor.cover_path = file_path.to_str().unwrap().to_string();
+                    }
+
+                    Trigger::WATCHDIR => {
+                        // the watcher finds its books at the next scan
+                        let dir = file_path.to_str().unwrap().to_string();
+                        let mut my_env = MYENV.lock().unwrap();
+                        if !my_env.watched_dirs.contains(&dir) {
+                            my_env.watched_dirs.push(dir);
+                            my_env.save_to_env();
+                        }
+                    }
+
+                    Trigger::SYNCDIR => {
+                        let dir = file_path.to_str().unwrap().to_string();
+                        let mut my_env = MYENV.lock().unwrap();
+                        my_env.set_property("sync_dir".to_string(), dir.clone());
+                        my_env.save_to_env();
+                        drop(my_env);
+                        sync_log::set_sync_dir(Some(dir.into()));
+                        // the changes already made on the other devices are merged
+                        delegate_ctx.submit_command(SYNC_MERGE);
+                    }
+
                     _ => {}
                 } //end match
 
@@ -248,6 +411,967 @@ impl AppDelegate<CrabReaderState> for ReadModeDelegate {
 
                 Handled::Yes
             }
+            cmd if cmd.is(OPEN_FILES) => {
+                let Trigger::ADDBOOK = data.open_file_trigger else {
+                    return Handled::No;
+                };
+                data.open_file_trigger = Trigger::NONE;
+                let paths = cmd
+                    .get_unchecked(OPEN_FILES)
+                    .iter()
+                    .map(|file| file.path().to_str().unwrap().to_string())
+                    .collect::<Vec<_>>();
+                import_books_fn(&paths, false, data, delegate_ctx);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPEN_IMPORT_WINDOW) => {
+                let win_desc = WindowDesc::new(import_window_widget())
+                    .title("Importazione libri")
+                    .window_size((600.0, 500.0));
+                delegate_ctx.new_window(win_desc);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(IMPORT_CHECKED) => {
+                let (source, result) = cmd.get_unchecked(IMPORT_CHECKED).clone();
+                let dest = result.clone().ok();
+                data.library.import.checked(&source, result);
+                if let Some(dest) = dest {
+                    // a book removed from the library can be added again
+                    if let Err(e) = set_book_excluded(&dest, false) {
+                        println!("ERROR: failed to add {} to the library again: {}", dest, e);
+                    }
+                    if data.library.find_book(&dest).is_some() {
+                        data.library.import.loaded(&dest, Ok(()));
+                    } else if !data.library.contains_path(&dest) {
+                        data.library.schedule_book_loading(dest);
+                    }
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(IMPORT_CLEAR) => {
+                data.library.import.clear_done();
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPEN_STATS_WINDOW) => {
+                // the session of the open book is saved at every page
+                let titles = book_titles(&data.library);
+                data.stats = StatsState::new(&reading_stats::load_sessions(), &titles, reading::now());
+                let win_desc = WindowDesc::new(stats_window_widget())
+                    .title("Statistiche di lettura")
+                    .window_size((700.0, 600.0));
+                delegate_ctx.new_window(win_desc);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPEN_GOALS_WINDOW) => {
+                let goals = MYENV.lock().unwrap().goals.clone().unwrap_or_default();
+                data.goals.set_goals(&goals);
+                data.goals.status = String::new();
+                let win_desc = WindowDesc::new(goals_window_widget())
+                    .title("Obiettivi di lettura")
+                    .window_size((500.0, 300.0));
+                delegate_ctx.new_window(win_desc);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(GOALS_SAVE) => {
+                match data.goals.get_goals() {
+                    Ok(goals) => {
+                        let mut my_env = MYENV.lock().unwrap();
+                        my_env.goals = Some(goals);
+                        my_env.save_to_env();
+                        drop(my_env);
+                        data.goals.status = "Obiettivi salvati".to_string();
+                        update_goals(data);
+                    }
+                    Err(e) => data.goals.status = format!("Obiettivi non salvati: {}", e),
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(GOALS_CHECK) => {
+                let seconds_today = update_goals(data);
+                let goals = MYENV.lock().unwrap().goals.clone().unwrap_or_default();
+                // the hour of the reminder and the days are the local ones
+                let time = local_time(reading::now(), reading::local_offset());
+                // while reading the goal is being worked on
+                if !data.reading && data.goals.take_reminder(&goals, seconds_today, time) {
+                    let text = format!(
+                        "Oggi hai letto {} minuti, il tuo obiettivo è di {} minuti al giorno.",
+                        seconds_today / 60,
+                        goals.daily_minutes
+                    );
+                    let label = Label::<CrabReaderState>::new(text).with_line_break_mode(LineBreaking::WordWrap);
+                    show_alert_dialog(delegate_ctx, label, "Promemoria", (400.0, 100.0));
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPEN_DUPLICATES_WINDOW) => {
+                let win_desc = WindowDesc::new(duplicates_window_widget())
+                    .title("Libri duplicati")
+                    .window_size((800.0, 600.0));
+                delegate_ctx.new_window(win_desc);
+                delegate_ctx.submit_command(DUPLICATES_SEARCH);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(DUPLICATES_SEARCH) => {
+                if data.duplicates.searching {
+                    return Handled::Yes;
+                }
+                data.duplicates.searching = true;
+                data.duplicates.groups.clear();
+                let books = (0..data.library.number_of_books())
+                    .filter_map(|idx| data.library.get_book(idx))
+                    .map(|book| Candidate {
+                        path: book.get_path(),
+                        title: book.get_title(),
+                        author: book.get_author(),
+                    })
+                    .collect();
+                duplicates::start_search(books, delegate_ctx.get_external_handle());
+                Handled::Yes
+            }
+
+            cmd if cmd.is(DUPLICATES_FOUND) => {
+                let found = cmd.get_unchecked(DUPLICATES_FOUND);
+                let library = &data.library;
+                data.duplicates.groups = found
+                    .iter()
+                    .enumerate()
+                    .map(|(id, (paths, reasons))| {
+                        let books = paths
+                            .iter()
+                            .filter_map(|path| library.find_book(path).and_then(|idx| library.get_book(idx)))
+                            .collect::<Vec<_>>();
+                        let mut group = DuplicateGroup::new(id, reasons, books.iter().map(|book| duplicate_book(id, book)).collect());
+                        // by default the furthest read is kept
+                        if let Some(book) = books.iter().max_by(|a, b| read_perc(a).total_cmp(&read_perc(b))) {
+                            group.choose(&book.get_path());
+                        }
+                        group
+                    })
+                    .filter(|group| group.books.len() > 1)
+                    .collect();
+                data.duplicates.searching = false;
+                data.duplicates.status = data.duplicates.summary();
+                Handled::Yes
+            }
+
+            cmd if cmd.is(DUPLICATES_KEEP) => {
+                let (id, path) = cmd.get_unchecked(DUPLICATES_KEEP);
+                data.duplicates.choose(*id, path);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(DUPLICATES_IGNORE) => {
+                data.duplicates.remove(*cmd.get_unchecked(DUPLICATES_IGNORE));
+                Handled::Yes
+            }
+
+            cmd if cmd.is(DUPLICATES_MERGE) => {
+                let id = *cmd.get_unchecked(DUPLICATES_MERGE);
+                let Some(group) = data.duplicates.get_group(id).cloned() else {
+                    return Handled::Yes;
+                };
+                let Some(keep) = group.kept().map(|book| book.path.clone()) else {
+                    return Handled::Yes;
+                };
+                let reading = data.library.get_selected_book().map(|book| book.get_path());
+                if data.reading && group.books.iter().any(|book| Some(&book.path) == reading.as_ref()) {
+                    data.duplicates.status = "Chiudi il libro che stai leggendo prima di unirlo agli altri".to_string();
+                    return Handled::Yes;
+                }
+
+                let others = group.merged();
+                let books = group
+                    .books
+                    .iter()
+                    .filter_map(|book| data.library.find_book(&book.path).and_then(|idx| data.library.get_book(idx)))
+                    .collect::<Vec<_>>();
+                let favorite = books.iter().any(|book| book.is_favorite());
+                let furthest = books
+                    .iter()
+                    .max_by(|a, b| read_perc(a).total_cmp(&read_perc(b)))
+                    .map_or(keep.clone(), |book| book.get_path());
+                let title = books
+                    .iter()
+                    .find(|book| book.get_path() == keep)
+                    .map(|book| book.get_title())
+                    .unwrap_or_default();
+
+                // only the last action can be undone
+                commit_snapshots(std::mem::take(&mut data.undo.snapshots));
+                let action_id = trash::new_action_id();
+                let snapshots = match duplicates::merge_books(&keep, &others, favorite, &furthest, action_id) {
+                    Ok(snapshots) => snapshots,
+                    Err(e) => {
+                        data.undo = UndoState::default();
+                        show_alert_dialog(
+                            delegate_ctx,
+                            Label::<CrabReaderState>::new(format!("Non è stato possibile unire i libri: {}", e))
+                                .with_line_break_mode(LineBreaking::WordWrap),
+                            "Errore",
+                            (400.0, 100.0)
+                        );
+                        return Handled::Yes;
+                    }
+                };
+                for other in &others {
+                    if let Some(idx) = data.library.find_book(other) {
+                        data.library.remove_book(idx);
+                    }
+                }
+                data.library.reload_shelves();
+                reload_synced_books(data, &HashSet::from([keep]));
+                data.duplicates.remove(id);
+
+                // the duplicates can be brought back until the grace period is over
+                data.undo = UndoState {
+                    label: format!("I duplicati di \"{}\" sono stati uniti", title),
+                    snapshots: snapshots.into_iter().map(Arc::new).collect(),
+                };
+                schedule_commit(delegate_ctx, action_id);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(ASK_BOOK_ACTION) => {
+                let (idx, action) = *cmd.get_unchecked(ASK_BOOK_ACTION);
+                let Some(book) = data.library.get_book(idx) else {
+                    return Handled::Yes;
+                };
+                let win_desc = WindowDesc::new(confirm_action_widget(book.get_path(), book.get_title(), action))
+                    .title(action.label())
+                    .window_size((400.0, 150.0))
+                    .resizable(false);
+                delegate_ctx.new_window(win_desc);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(RUN_BOOK_ACTION) => {
+                let (path, action) = cmd.get_unchecked(RUN_BOOK_ACTION).clone();
+                let Some(idx) = data.library.find_book(&path) else {
+                    return Handled::Yes;
+                };
+                let title = data.library.get_book(idx).map(|book| book.get_title()).unwrap_or_default();
+                // only the last action can be undone
+                commit_snapshots(std::mem::take(&mut data.undo.snapshots));
+
+                let snapshot = match Snapshot::apply(action, &path, trash::new_action_id()) {
+                    Ok(snapshot) => snapshot,
+                    Err(e) => {
+                        data.undo = UndoState::default();
+                        show_alert_dialog(
+                            delegate_ctx,
+                            Label::<CrabReaderState>::new(format!("Non è stato possibile completare l'operazione: {}", e))
+                                .with_line_break_mode(LineBreaking::WordWrap),
+                            "Errore",
+                            (400.0, 100.0)
+                        );
+                        return Handled::Yes;
+                    }
+                };
+                if action.removes_book() {
+                    data.library.remove_book(idx);
+                    data.library.reload_shelves();
+                } else if let BookAction::Archive | BookAction::Unarchive = action {
+                    data.library.set_archived(&path, action == BookAction::Archive);
+                } else {
+                    reload_synced_books(data, &HashSet::from([path]));
+                }
+
+                let id = snapshot.id;
+                data.undo = UndoState {
+                    label: action.done(&title),
+                    snapshots: Vector::unit(Arc::new(snapshot)),
+                };
+                schedule_commit(delegate_ctx, id);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(UNDO_BOOK_ACTION) => {
+                data.undo.label.clear();
+                for snapshot in std::mem::take(&mut data.undo.snapshots) {
+                    if let Err(e) = snapshot.restore() {
+                        show_alert_dialog(
+                            delegate_ctx,
+                            Label::<CrabReaderState>::new(format!("Non è stato possibile annullare l'operazione: {}", e))
+                                .with_line_break_mode(LineBreaking::WordWrap),
+                            "Errore",
+                            (400.0, 100.0)
+                        );
+                    }
+
+                    let path = snapshot.book_path.clone();
+                    match snapshot.action {
+                        action if action.removes_book() => {
+                            if !data.library.contains_path(&path) {
+                                data.library.schedule_book_loading(path);
+                            }
+                            data.library.reload_shelves();
+                        }
+                        BookAction::Archive => data.library.set_archived(&path, false),
+                        BookAction::Unarchive => data.library.set_archived(&path, true),
+                        _ => reload_synced_books(data, &HashSet::from([path])),
+                    }
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(COMMIT_BOOK_ACTION) => {
+                commit_snapshots(data.undo.take(*cmd.get_unchecked(COMMIT_BOOK_ACTION)));
+                Handled::Yes
+            }
+
+            cmd if cmd.is(SAVE_FILE_AS) && matches!(data.open_file_trigger, Trigger::EXPORTSTATS) => {
+                data.open_file_trigger = Trigger::NONE;
+                let dest = reading_stats::with_csv_extension(cmd.get_unchecked(SAVE_FILE_AS).path());
+                let titles = book_titles(&data.library);
+                let csv = reading_stats::to_csv(&reading_stats::load_sessions(), |book| {
+                    titles.get(book).cloned().unwrap_or_default()
+                });
+                data.stats.status = match std::fs::write(&dest, csv) {
+                    Ok(()) => format!("Statistiche esportate in {}", dest.display()),
+                    Err(e) => format!("Non è stato possibile esportare le statistiche: {}", e),
+                };
+                Handled::Yes
+            }
+
+            cmd if cmd.is(SAVE_FILE_AS) => {
+                let include_books = match data.open_file_trigger {
+                    Trigger::EXPORT => false,
+                    Trigger::EXPORTBOOKS => true,
+                    _ => return Handled::No,
+                };
+                data.open_file_trigger = Trigger::NONE;
+                let dest = archive::with_zip_extension(cmd.get_unchecked(SAVE_FILE_AS).path());
+                let sink = delegate_ctx.get_external_handle();
+                std::thread::spawn(move || {
+                    let result = archive::export_archive(&get_app_dir(), &dest, include_books)
+                        .map_err(|e| e.to_string());
+                    let _ = sink.submit_command(ARCHIVE_EXPORTED, result, Target::Auto);
+                });
+                Handled::Yes
+            }
+
+            cmd if cmd.is(ARCHIVE_EXPORTED) => {
+                let (title, text) = match cmd.get_unchecked(ARCHIVE_EXPORTED) {
+                    Ok(report) if report.included > 0 => (
+                        "Archivio esportato",
+                        format!("Sono stati esportati i dati di {} libri e {} eBook", report.books, report.included),
+                    ),
+                    Ok(report) => (
+                        "Archivio esportato",
+                        format!("Sono stati esportati i dati di {} libri", report.books),
+                    ),
+                    Err(e) => ("Errore", format!("Non è stato possibile esportare l'archivio: {}", e)),
+                };
+                show_alert_dialog(
+                    delegate_ctx,
+                    Label::<CrabReaderState>::new(text).with_line_break_mode(LineBreaking::WordWrap),
+                    title,
+                    (400.0, 100.0)
+                );
+                Handled::Yes
+            }
+
+            cmd if cmd.is(ARCHIVE_IMPORTED) => {
+                let (title, text) = match cmd.get_unchecked(ARCHIVE_IMPORTED) {
+                    Ok(report) => {
+                        for path in &report.added {
+                            data.library.schedule_book_loading(path.as_str());
+                        }
+                        reload_synced_books(data, &report.merged.iter().cloned().collect());
+                        if !report.edit_conflicts.is_empty() {
+                            sync_log::add_conflicts(report.edit_conflicts.clone());
+                            data.sync_conflicts = sync_log::load_conflicts().into();
+                        }
+                        // the settings of the archive are read again
+                        let mut my_env = MYENV.lock().unwrap();
+                        *my_env = MyEnv::new();
+                        data.theme = CrabTheme::from(my_env.theme.clone());
+                        data.paint_shadows = my_env.shadows;
+                        drop(my_env);
+
+                        let mut lines = vec![format!(
+                            "{} libri aggiunti, {} libri aggiornati",
+                            report.added.len(),
+                            report.merged.len()
+                        )];
+                        if !report.missing.is_empty() {
+                            lines.push(format!(
+                                "Libri da aggiungere per ritrovarne posizione e note: {}",
+                                report.missing.join(", ")
+                            ));
+                        }
+                        lines.extend(report.messages.iter().cloned());
+                        if !report.edit_conflicts.is_empty() {
+                            lines.push(format!(
+                                "{} capitoli modificati in modo diverso, risolvili da File > Sincronizzazione > Conflitti",
+                                report.edit_conflicts.len()
+                            ));
+                        }
+                        ("Archivio importato", lines.join("\n"))
+                    }
+                    Err(e) => ("Errore", format!("Non è stato possibile importare l'archivio: {}", e)),
+                };
+                show_alert_dialog(
+                    delegate_ctx,
+                    Label::<CrabReaderState>::new(text).with_line_break_mode(LineBreaking::WordWrap),
+                    title,
+                    (500.0, 250.0)
+                );
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPEN_OPDS_WINDOW) => {
+                let win_desc = WindowDesc::new(opds_window_widget())
+                    .title("Catalogo OPDS")
+                    .window_size((700.0, 700.0));
+                delegate_ctx.new_window(win_desc);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPDS_OPEN_FEED) => {
+                let url = cmd.get_unchecked(OPDS_OPEN_FEED).clone();
+                data.opds.loading = true;
+                load_opds_feed(delegate_ctx, false, move || opds_client::fetch_feed(&url));
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPDS_BACK) => {
+                if let Some(url) = data.opds.pop_history() {
+                    data.opds.loading = true;
+                    load_opds_feed(delegate_ctx, true, move || opds_client::fetch_feed(&url));
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPDS_SEARCH) => {
+                let terms = cmd.get_unchecked(OPDS_SEARCH).clone();
+                if let Some(feed) = data.opds.get_feed() {
+                    data.opds.loading = true;
+                    load_opds_feed(delegate_ctx, false, move || {
+                        let url = opds_client::get_search_url(&feed, &terms)?;
+                        opds_client::fetch_feed(&url)
+                    });
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPDS_ACTIVATE_ENTRY) => {
+                let entry = cmd.get_unchecked(OPDS_ACTIVATE_ENTRY).clone();
+                if let Some(url) = entry.navigation.clone() {
+                    data.opds.loading = true;
+                    load_opds_feed(delegate_ctx, false, move || opds_client::fetch_feed(&url));
+                } else {
+                    data.opds.status = format!("Download di {}...", entry.title);
+                    let sink = delegate_ctx.get_external_handle();
+                    std::thread::spawn(move || {
+                        let result = opds_client::download_book(&entry).map_err(|e| e.to_string());
+                        let _ = sink.submit_command(OPDS_BOOK_DOWNLOADED, result, Target::Auto);
+                    });
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPDS_FEED_LOADED) => {
+                let (result, going_back) = cmd.get_unchecked(OPDS_FEED_LOADED).clone();
+                match result {
+                    Ok(feed) => data.opds.set_feed(feed, going_back),
+                    Err(e) => {
+                        data.opds.loading = false;
+                        data.opds.status = format!("Errore: {}", e);
+                    }
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPDS_COVER_LOADED) => {
+                let (url, cover) = cmd.get_unchecked(OPDS_COVER_LOADED).clone();
+                data.opds.set_cover(&url, cover);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPDS_BOOK_DOWNLOADED) => {
+                match cmd.get_unchecked(OPDS_BOOK_DOWNLOADED) {
+                    Ok(path) => {
+                        data.opds.status = String::new();
+                        // the downloaded book goes through the same checks of a local file
+                        let path = path.to_str().unwrap().to_string();
+                        import_books_fn(&[path], true, data, delegate_ctx);
+                    }
+                    Err(e) => data.opds.status = format!("Errore durante il download: {}", e),
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(TOGGLE_OPDS_SERVER) => {
+                if let Some(server) = data.opds_server.take() {
+                    server.stop();
+                    return Handled::Yes;
+                }
+                let (title, text) = match OpdsServer::start(OPDS_SERVER_PORT) {
+                    Ok(server) => {
+                        server.set_catalog(opds_server::catalog_of(&data.library));
+                        let text = format!(
+                            "La libreria è disponibile all'indirizzo {}",
+                            server.get_address()
+                        );
+                        data.opds_server = Some(Arc::new(server));
+                        ("Server OPDS avviato", text)
+                    }
+                    Err(e) => (
+                        "Errore",
+                        format!("Non è stato possibile avviare il server OPDS: {}", e),
+                    ),
+                };
+                show_alert_dialog(
+                    delegate_ctx,
+                    Label::<CrabReaderState>::new(text).with_line_break_mode(LineBreaking::WordWrap),
+                    title,
+                    (400.0, 100.0)
+                );
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPEN_KOSYNC_WINDOW) => {
+                if let Some(settings) = MYENV.lock().unwrap().kosync.clone() {
+                    data.kosync.server = settings.server;
+                    data.kosync.status = format!("Connesso come {}", settings.username);
+                    data.kosync.username = settings.username;
+                }
+                let win_desc = WindowDesc::new(kosync_window_widget())
+                    .title("Sincronizzazione KOReader")
+                    .window_size((450.0, 280.0));
+                delegate_ctx.new_window(win_desc);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(KOSYNC_LOGIN) => {
+                let create_account = *cmd.get_unchecked(KOSYNC_LOGIN);
+                let settings = data.kosync.get_settings();
+                data.kosync.loading = true;
+                let sink = delegate_ctx.get_external_handle();
+                std::thread::spawn(move || {
+                    let result = if create_account {
+                        kosync_client::register(&settings)
+                    } else {
+                        Ok(())
+                    }
+                    .and_then(|_| kosync_client::authorize(&settings))
+                    .map(|_| settings)
+                    .map_err(|e| e.to_string());
+                    let _ = sink.submit_command(KOSYNC_LOGGED_IN, result, Target::Auto);
+                });
+                Handled::Yes
+            }
+
+            cmd if cmd.is(KOSYNC_LOGGED_IN) => {
+                data.kosync.loading = false;
+                match cmd.get_unchecked(KOSYNC_LOGGED_IN) {
+                    Ok(settings) => {
+                        data.kosync.password = String::new();
+                        data.kosync.status = format!("Connesso come {}", settings.username);
+                        let mut my_env = MYENV.lock().unwrap();
+                        my_env.kosync = Some(settings.clone());
+                        my_env.save_to_env();
+                    }
+                    Err(e) => data.kosync.status = format!("Errore: {}", e),
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(KOSYNC_LOGOUT) => {
+                let mut my_env = MYENV.lock().unwrap();
+                my_env.kosync = None;
+                my_env.save_to_env();
+                data.kosync.status = "Le posizioni non vengono più sincronizzate".to_string();
+                Handled::Yes
+            }
+
+            cmd if cmd.is(KOSYNC_PROGRESS_FETCHED) => {
+                let (path, progress) = cmd.get_unchecked(KOSYNC_PROGRESS_FETCHED).clone();
+                let Some(book) = data.library.get_selected_book() else {
+                    return Handled::Yes;
+                };
+                // the user may have closed the book in the meantime
+                if !data.reading || book.get_path() != path {
+                    return Handled::Yes;
+                }
+                // positions sent by this computer or older than the local one are ignored
+                let saved_at = load_timestamp(path.clone()).unwrap_or_default();
+                if progress.device_id == kosync_client::get_device_id()
+                    || progress.timestamp <= saved_at
+                {
+                    return Handled::Yes;
+                }
+                let current = (book.get_chapter_number(), book.get_current_page_number());
+                match get_remote_position(book, &progress) {
+                    Some(position) if position != current => {}
+                    _ => return Handled::Yes,
+                }
+                let win_desc = WindowDesc::new(progress_offer_widget(path, progress))
+                    .title("Sincronizzazione KOReader")
+                    .window_size((400.0, 150.0))
+                    .resizable(false);
+                delegate_ctx.new_window(win_desc);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(KOSYNC_APPLY_PROGRESS) => {
+                let (path, progress) = cmd.get_unchecked(KOSYNC_APPLY_PROGRESS).clone();
+                let font_size = FontSize::from(MYENV.lock().unwrap().font.size);
+                let Some(book) = data.library.get_selected_book_mut() else {
+                    return Handled::Yes;
+                };
+                if book.get_path() != path {
+                    return Handled::Yes;
+                }
+                let Some((chapter, page)) = get_remote_position(book, &progress) else {
+                    return Handled::Yes;
+                };
+                // the chapter of a comic is always the same
+                let chapter = if book.is_comic() { book.get_chapter_number() } else { chapter };
+                book.jump_to(Position::new(chapter, page));
+                let _ = save_data(
+                    book.get_path(),
+                    book.get_chapter_number(),
+                    book.get_current_page_number(),
+                    book.get_page_of_chapter(),
+                    font_size,
+                    false,
+                );
+                Handled::Yes
+            }
+
+            cmd if cmd.is(TOGGLE_KOSYNC_SERVER) => {
+                if let Some(server) = data.kosync_server.take() {
+                    server.stop();
+                    return Handled::Yes;
+                }
+                let (title, text) = match KoSyncServer::start(KOSYNC_SERVER_PORT, get_kosync_server_path()) {
+                    Ok(server) => {
+                        let text = format!(
+                            "Imposta l'indirizzo {} come server di sincronizzazione sui dispositivi KOReader",
+                            server.get_address()
+                        );
+                        data.kosync_server = Some(Arc::new(server));
+                        ("Server di sincronizzazione avviato", text)
+                    }
+                    Err(e) => (
+                        "Errore",
+                        format!("Non è stato possibile avviare il server di sincronizzazione: {}", e),
+                    ),
+                };
+                show_alert_dialog(
+                    delegate_ctx,
+                    Label::<CrabReaderState>::new(text).with_line_break_mode(LineBreaking::WordWrap),
+                    title,
+                    (400.0, 100.0)
+                );
+                Handled::Yes
+            }
+
+            cmd if cmd.is(SYNC_DISABLE) => {
+                let mut my_env = MYENV.lock().unwrap();
+                my_env.set_property("sync_dir".to_string(), String::new());
+                my_env.save_to_env();
+                drop(my_env);
+                sync_log::set_sync_dir(None);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(SYNC_MERGE) => {
+                let result = sync_log::merge();
+                if !result.changed_books.is_empty() {
+                    reload_synced_books(data, &result.changed_books);
+                }
+                if result.new_conflicts > 0 {
+                    data.sync_conflicts = sync_log::load_conflicts().into();
+                    let text = format!(
+                        "{} capitoli sono stati modificati sia qui che su un altro dispositivo, scegli quale versione mantenere",
+                        result.new_conflicts
+                    );
+                    show_alert_dialog(
+                        delegate_ctx,
+                        Label::<CrabReaderState>::new(text).with_line_break_mode(LineBreaking::WordWrap),
+                        "Sincronizzazione",
+                        (400.0, 100.0)
+                    );
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPEN_SYNC_CONFLICTS) => {
+                let win_desc = WindowDesc::new(sync_conflicts_widget())
+                    .title("Conflitti di sincronizzazione")
+                    .window_size((800.0, 600.0));
+                delegate_ctx.new_window(win_desc);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(SYNC_RESOLVE_CONFLICT) => {
+                let (conflict, keep_remote) = cmd.get_unchecked(SYNC_RESOLVE_CONFLICT);
+                match sync_log::resolve_conflict(conflict, *keep_remote) {
+                    Ok(()) => {
+                        data.sync_conflicts = sync_log::load_conflicts().into();
+                        reload_synced_books(data, &HashSet::from([conflict.book_path.clone()]));
+                    }
+                    Err(e) => show_alert_dialog(
+                        delegate_ctx,
+                        Label::<CrabReaderState>::new(format!("Non è stato possibile risolvere il conflitto: {}", e))
+                            .with_line_break_mode(LineBreaking::WordWrap),
+                        "Errore",
+                        (400.0, 100.0)
+                    ),
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPEN_BACKUP_WINDOW) => {
+                if let Some(settings) = MYENV.lock().unwrap().webdav.clone() {
+                    data.backup.set_settings(&settings);
+                    delegate_ctx.submit_command(BACKUP_LIST);
+                }
+                let win_desc = WindowDesc::new(backup_window_widget())
+                    .title("Backup WebDAV")
+                    .window_size((650.0, 550.0));
+                delegate_ctx.new_window(win_desc);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(BACKUP_SAVE_SETTINGS) => {
+                let settings = data.backup.get_settings();
+                data.backup.status = if settings.interval_hours == 0 {
+                    "Impostazioni salvate, il backup verrà eseguito solo manualmente".to_string()
+                } else {
+                    format!("Impostazioni salvate, backup automatico ogni {} ore", settings.interval_hours)
+                };
+                let mut my_env = MYENV.lock().unwrap();
+                my_env.webdav = Some(settings);
+                my_env.save_to_env();
+                Handled::Yes
+            }
+
+            cmd if cmd.is(BACKUP_RUN) => {
+                let settings = data.backup.get_settings();
+                data.backup.loading = true;
+                let sink = delegate_ctx.get_external_handle();
+                std::thread::spawn(move || {
+                    let result = webdav_backup::run_backup(&settings);
+                    let _ = sink.submit_command(BACKUP_DONE, result, Target::Auto);
+                });
+                Handled::Yes
+            }
+
+            cmd if cmd.is(BACKUP_DONE) => {
+                data.backup.loading = false;
+                match cmd.get_unchecked(BACKUP_DONE) {
+                    Ok(report) => {
+                        data.backup.status = format!(
+                            "Backup completato: {} file, {} caricati",
+                            report.files, report.uploaded
+                        );
+                        data.backup.snapshots.push_front(SnapshotItem::from(&report.snapshot));
+                    }
+                    Err(e) => data.backup.status = format!("Errore durante il backup: {}", e),
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(BACKUP_LIST) => {
+                let settings = data.backup.get_settings();
+                data.backup.loading = true;
+                let sink = delegate_ctx.get_external_handle();
+                std::thread::spawn(move || {
+                    let result = webdav_backup::list_snapshots(&settings);
+                    let _ = sink.submit_command(BACKUP_LISTED, result, Target::Auto);
+                });
+                Handled::Yes
+            }
+
+            cmd if cmd.is(BACKUP_LISTED) => {
+                data.backup.loading = false;
+                match cmd.get_unchecked(BACKUP_LISTED) {
+                    Ok(snapshots) => {
+                        data.backup.snapshots = snapshots.iter().map(SnapshotItem::from).collect();
+                        if snapshots.is_empty() {
+                            data.backup.status = "Non ci sono backup sul server".to_string();
+                        }
+                    }
+                    Err(e) => data.backup.status = format!("Errore: {}", e),
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(BACKUP_RESTORE) => {
+                let (snapshot, scope) = cmd.get_unchecked(BACKUP_RESTORE).clone();
+                let book_path = data.library.get_selected_book().map(|book| book.get_path());
+                let what = match (scope, book_path) {
+                    (RestoreScope::All, _) => Restore::All,
+                    (RestoreScope::Book, Some(path)) => Restore::Book(path),
+                    (RestoreScope::Notes, Some(path)) => Restore::Notes(path),
+                    (_, None) => {
+                        data.backup.status = "Seleziona un libro nella libreria".to_string();
+                        return Handled::Yes;
+                    }
+                };
+                let settings = data.backup.get_settings();
+                data.backup.loading = true;
+                let sink = delegate_ctx.get_external_handle();
+                std::thread::spawn(move || {
+                    let root = get_app_dir();
+                    let result = webdav_backup::restore(&settings, &root, &snapshot, &what)
+                        .map(|restored| (what, restored));
+                    let _ = sink.submit_command(BACKUP_RESTORED, result, Target::Auto);
+                });
+                Handled::Yes
+            }
+
+            cmd if cmd.is(BACKUP_RESTORED) => {
+                data.backup.loading = false;
+                match cmd.get_unchecked(BACKUP_RESTORED) {
+                    Ok((Restore::All, restored)) => {
+                        data.backup.status = format!(
+                            "{} file ripristinati, riavvia l'applicazione per applicare le modifiche",
+                            restored
+                        );
+                    }
+                    Ok((Restore::Book(path) | Restore::Notes(path), restored)) => {
+                        data.backup.status = format!("{} file ripristinati", restored);
+                        reload_synced_books(data, &HashSet::from([path.clone()]));
+                    }
+                    Err(e) => data.backup.status = format!("Errore durante il ripristino: {}", e),
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(OPEN_METADATA_EDITOR) => {
+                let Some(path) = data.library.get_selected_book().map(|book| book.get_path()) else {
+                    return Handled::Yes;
+                };
+                let metadata = BookMetadata::from_metadata(&epub_utils::get_metadata_of_book(&path));
+                data.metadata_editor.load(&path, &metadata);
+                let win_desc = WindowDesc::new(metadata_window_widget())
+                    .title("Modifica metadati")
+                    .window_size((550.0, 700.0));
+                delegate_ctx.new_window(win_desc);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(METADATA_ADD_AUTHOR) => {
+                data.metadata_editor.add_author("", "", AUTHOR_ROLE);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(METADATA_REMOVE_AUTHOR) => {
+                let id = *cmd.get_unchecked(METADATA_REMOVE_AUTHOR);
+                data.metadata_editor.remove_author(id);
+                Handled::Yes
+            }
+
+            cmd if cmd.is(METADATA_SAVE) => {
+                let editor = &mut data.metadata_editor;
+                let metadata = match editor.get_metadata() {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        editor.status = format!("Errore: {}", e);
+                        return Handled::Yes;
+                    }
+                };
+                let path = editor.book_path.clone();
+                let cover_path = Some(editor.cover_path.clone()).filter(|path| !path.is_empty());
+                let write_to_epub = editor.is_epub && editor.write_to_epub;
+                editor.saving = true;
+                editor.status = "Salvataggio in corso...".to_string();
+                let sink = delegate_ctx.get_external_handle();
+                std::thread::spawn(move || {
+                    let result =
+                        save_edited_metadata(&path, &metadata, cover_path.as_deref(), write_to_epub)
+                            .map(|_| path)
+                            .map_err(|e| e.to_string());
+                    let _ = sink.submit_command(METADATA_SAVED, result, Target::Auto);
+                });
+                Handled::Yes
+            }
+
+            cmd if cmd.is(METADATA_SAVED) => {
+                data.metadata_editor.saving = false;
+                match cmd.get_unchecked(METADATA_SAVED) {
+                    Ok(path) => {
+                        data.metadata_editor.status = "Metadati salvati".to_string();
+                        data.metadata_editor.cover_path.clear();
+                        reload_synced_books(data, &HashSet::from([path.clone()]));
+                        let idx = (0..data.library.number_of_books()).find(|idx| {
+                            data.library
+                                .get_book(*idx)
+                                .is_some_and(|book| book.get_path() == *path)
+                        });
+                        if let Some(idx) = idx {
+                            data.library.schedule_cover_loading(path.clone(), idx);
+                        }
+                    }
+                    Err(e) => data.metadata_editor.status = format!("Errore durante il salvataggio: {}", e),
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(LIBRARY_FILES_CHANGED) => {
+                let changes = cmd.get_unchecked(LIBRARY_FILES_CHANGED);
+                // the book being read is kept until it is closed
+                let reading = if data.reading {
+                    data.library.get_selected_book().map(|book| book.get_path())
+                } else {
+                    None
+                };
+
+                for (from, to) in &changes.moved {
+                    if reading.as_deref() == Some(from.as_str()) {
+                        continue;
+                    }
+                    if let Err(e) = move_book_data(from, to) {
+                        println!("ERROR: failed to move the data of {}: {}", from, e);
+                    }
+                    data.library.move_book(from, to);
+                }
+                for path in &changes.removed {
+                    if reading.as_deref() == Some(path.as_str()) {
+                        continue;
+                    }
+                    // the data of the book is kept: the position, the notes
+                    // and the edits are found again if the file comes back
+                    if let Some(idx) = data.library.find_book(path) {
+                        data.library.remove_book(idx);
+                    }
+                }
+                // the books removed from the library are left in their folders
+                let excluded = load_excluded_books();
+                for path in &changes.added {
+                    if !data.library.contains_path(path) && !excluded.contains(path) {
+                        data.library.schedule_book_loading(path);
+                    }
+                }
+                if !changes.moved.is_empty() || !changes.removed.is_empty() {
+                    data.library.reload_shelves();
+                }
+                Handled::Yes
+            }
+
+            cmd if cmd.is(UNWATCH_DIRS) => {
+                let mut my_env = MYENV.lock().unwrap();
+                for dir in std::mem::take(&mut my_env.watched_dirs) {
+                    data.library.remove_books_in_dir(&dir);
+                }
+                my_env.save_to_env();
+                Handled::Yes
+            }
+
             cmd if cmd.is(SWITCH_THEME) => {
                 if let Some(theme) = cmd.get(SWITCH_THEME) {
                     data.theme = theme.clone();
@@ -321,6 +1445,23 @@ impl AppDelegate<CrabReaderState> for ReadModeDelegate {
                         handle_u(ctx, window_id, key_event, data, env);
                         None
                     }
+                    // like the back and forward of a browser
+                    Code::BracketLeft if data.reading => {
+                        button_functions::navigate(data, Navigation::Back);
+                        None
+                    }
+                    Code::BracketRight if data.reading => {
+                        button_functions::navigate(data, Navigation::Forward);
+                        None
+                    }
+                    Code::End if data.reading => {
+                        button_functions::navigate(data, Navigation::Furthest);
+                        None
+                    }
+                    Code::KeyG if data.reading => {
+                        ctx.submit_command(OPEN_GO_TO_WINDOW);
+                        None
+                    }
                     _ => Some(event),
                 }
             }
@@ -341,7 +1482,11 @@ fn handle_arrow_right(
     }
 
     if data.reading {
-        go_next(data);
+        if is_reading_right_to_left(data) {
+            go_prev(data);
+        } else {
+            go_next(data);
+        }
         return;
     }
 
@@ -369,7 +1514,11 @@ fn handle_arrow_left(
     }
 
     if data.reading {
-        go_prev(data);
+        if is_reading_right_to_left(data) {
+            go_next(data);
+        } else {
+            go_prev(data);
+        }
         return;
     }
 
@@ -385,8 +1534,17 @@ fn handle_arrow_left(
     data.library.set_selected_book_idx(idx);
 }
 
+/// In manga order the arrows follow the direction of the pages
+fn is_reading_right_to_left(data: &CrabReaderState) -> bool {
+    data.reading_state.right_to_left
+        && data
+            .library
+            .get_selected_book()
+            .map_or(false, |book| book.is_comic())
+}
+
 fn handle_esc(
-    _ctx: &mut druid::DelegateCtx,
+    ctx: &mut druid::DelegateCtx,
     _window_id: druid::WindowId,
     _event: &KeyEvent,
     data: &mut CrabReaderState,
@@ -398,7 +1556,7 @@ fn handle_esc(
     }
 
     if data.reading {
-        data.reading = false;
+        ctx.submit_command(LEAVING_READING_MODE);
         return;
     }
 
@@ -558,6 +1716,193 @@ fn handle_u(
     }
 }
 
+/// Reads in background the position of the book saved on the sync server,
+/// it is sent with KOSYNC_PROGRESS_FETCHED
+fn fetch_remote_progress(ctx: &mut druid::DelegateCtx, path: String) {
+    let Some(settings) = MYENV.lock().unwrap().kosync.clone() else {
+        return;
+    };
+    let sink = ctx.get_external_handle();
+    std::thread::spawn(move || {
+        let result = kosync_client::partial_md5(&path)
+            .and_then(|document| kosync_client::get_progress(&settings, &document));
+        match result {
+            Ok(Some(progress)) => {
+                let _ = sink.submit_command(KOSYNC_PROGRESS_FETCHED, (path, progress), Target::Auto);
+            }
+            Ok(None) => {}
+            Err(e) => println!("ERROR: failed to read the synced position of {}: {}", path, e),
+        }
+    });
+}
+
+/// Saves the session of the book that has been closed, with the characters read in it
+fn finish_session(data: &mut CrabReaderState) {
+    let Some(mut session) = data.session.take() else {
+        return;
+    };
+    let book = data.library.find_book(&session.book).and_then(|idx| data.library.get_book(idx));
+    session.finish(reading::now(), book.and_then(|book| read_chars(book, &data.reading_state.chapter_chars)));
+    if let Err(e) = reading_stats::save_session(&session) {
+        println!("ERROR: failed to save the reading session: {}", e);
+    }
+}
+
+/// Updates the progress towards the reading goals, returns the seconds read today
+fn update_goals(data: &mut CrabReaderState) -> u64 {
+    let goals = MYENV.lock().unwrap().goals.clone().unwrap_or_default();
+    let time = reading::now();
+    let sessions = reading_stats::load_sessions();
+    let seconds_today = seconds_on_day(&sessions, time, reading::local_offset());
+    let finished = (0..data.library.number_of_books())
+        .filter_map(|idx| data.library.get_book(idx))
+        .filter(|book| book.get_reading_status() == ReadingStatus::Finished)
+        .filter_map(|book| book.get_reading_info().finished);
+    let finished_this_year = books_finished_in_year(finished, time);
+    data.goals.update(&goals, seconds_today, finished_this_year, time);
+    seconds_today
+}
+
+/// Title of each book of the library, by its path
+fn book_titles(library: &Library<Book>) -> HashMap<String, String> {
+    (0..library.number_of_books())
+        .filter_map(|idx| library.get_book(idx))
+        .map(|book| (book.get_path(), book.get_title()))
+        .collect()
+}
+
+/// Read pages of a book, 0 if it has never been opened
+fn read_perc(book: &Book) -> f64 {
+    let perc = book.get_perc_read();
+    if perc.is_nan() {
+        0.0
+    } else {
+        perc
+    }
+}
+
+/// A book of a group of duplicates, with what would be merged in the kept one
+fn duplicate_book(group: usize, book: &Book) -> DuplicateBook {
+    let mut details = vec![format!("Letto al {:.0}%", read_perc(book))];
+    match duplicates::count_notes(&book.get_path()) {
+        0 => {}
+        1 => details.push("1 nota".to_string()),
+        n => details.push(format!("{} note", n)),
+    }
+    if book.is_favorite() {
+        details.push("preferito".to_string());
+    }
+    DuplicateBook {
+        group,
+        path: book.get_path(),
+        title: book.get_title(),
+        author: book.get_author(),
+        details: details.join(" · "),
+        keep: false,
+    }
+}
+
+/// Reads again the books changed by the sync or by a restore,
+/// the one that is being read keeps its state until it is closed
+fn reload_synced_books(data: &mut CrabReaderState, paths: &HashSet<String>) {
+    let except = if data.reading {
+        data.library.get_selected_book_idx()
+    } else {
+        None
+    };
+    data.library.reload_books(paths, except);
+}
+
+/// Completes the actions of the snapshots, they can no longer be undone
+fn commit_snapshots(snapshots: Vector<Arc<Snapshot>>) {
+    for snapshot in snapshots {
+        if let Err(e) = snapshot.commit() {
+            println!("ERROR: failed to complete the action on {}: {}", snapshot.book_path, e);
+        }
+    }
+}
+
+/// The action with the given id is completed when the grace period to undo it is over
+fn schedule_commit(ctx: &mut druid::DelegateCtx, id: u64) {
+    let sink = ctx.get_external_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_secs(UNDO_GRACE_PERIOD));
+        let _ = sink.submit_command(COMMIT_BOOK_ACTION, id, Target::Auto);
+    });
+}
+
+/// Returns the chapter and the page of the book at the position read on another device
+fn get_remote_position(book: &Book, progress: &Progress) -> Option<(usize, usize)> {
+    kosync_client::get_position(progress, &pages_per_chapter(book), book.is_comic())
+}
+
+/// Loads a feed of a catalog in background,
+/// the feed is sent with OPDS_FEED_LOADED and then its covers with OPDS_COVER_LOADED
+fn load_opds_feed(
+    ctx: &mut druid::DelegateCtx,
+    going_back: bool,
+    load: impl FnOnce() -> Result<OpdsFeed, Box<dyn std::error::Error>> + Send + 'static,
+) {
+    let sink = ctx.get_external_handle();
+    std::thread::spawn(move || {
+        let result = load().map_err(|e| e.to_string());
+        let covers = result.as_ref().map_or(vec![], |feed| {
+            feed.entries
+                .iter()
+                .filter_map(|entry| entry.cover.clone())
+                .collect::<Vec<String>>()
+        });
+        let _ = sink.submit_command(OPDS_FEED_LOADED, (result, going_back), Target::Auto);
+
+        for url in covers {
+            let Ok(bytes) = opds_client::fetch_bytes(&url) else {
+                continue;
+            };
+            let Ok(image) = ImageReader::new(Cursor::new(bytes))
+                .with_guessed_format()
+                .map_err(|e| e.to_string())
+                .and_then(|reader| reader.decode().map_err(|e| e.to_string()))
+            else {
+                continue;
+            };
+            let thumbnail = image.thumbnail_exact(OPDS_COVER_WIDTH, OPDS_COVER_HEIGHT);
+            let rgb = thumbnail.to_rgb8().to_vec();
+            let _ = sink.submit_command(OPDS_COVER_LOADED, (url, rgb), Target::Auto);
+        }
+    });
+}
+
+/// Starts the import of some books or folders, the progress window is opened
+/// when the import begins and it isn't closed by the following batches
+fn import_books_fn(
+    paths: &[String],
+    temporary: bool,
+    data: &mut CrabReaderState,
+    delegate_ctx: &mut druid::DelegateCtx,
+) {
+    let books = book_import::expand_paths(paths);
+    if books.is_empty() {
+        show_alert_dialog(
+            delegate_ctx,
+            Label::<CrabReaderState>::new("Non è stato trovato nessun libro da aggiungere")
+                .with_line_break_mode(LineBreaking::WordWrap),
+            "Aggiungi libri",
+            (400.0, 100.0),
+        );
+        return;
+    }
+    if !data.library.import.is_running() {
+        delegate_ctx.submit_command(OPEN_IMPORT_WINDOW);
+    }
+    let books = data.library.import.add(&books);
+    book_import::start_import(
+        books,
+        data.library.book_paths(),
+        temporary,
+        delegate_ctx.get_external_handle(),
+    );
+}
+
 fn show_alert_dialog<T: druid::Data>(ctx: &mut druid::DelegateCtx, msg: impl druid::Widget<T> + 'static, title: &str, window_size: (f64, f64)) {
     //get coordinates of the center of the monitor
     let monitor = &druid::Screen::get_monitors()[0];
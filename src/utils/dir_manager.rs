@@ -4,7 +4,8 @@ use dirs;
 
 const APP_NAME: &str = "crab-reader";
 
-fn get_app_dir() -> PathBuf {
+/// Get the path to the folder of the application, it contains all its data
+pub fn get_app_dir() -> PathBuf {
     let mut dir = dirs::home_dir().unwrap();
     dir.push(APP_NAME);
     let _ = std::fs::create_dir_all(&dir);
@@ -50,6 +51,14 @@ pub fn get_epub_dir() -> PathBuf {
     data_dir
 }
 
+/// Get path of the folder where the data of the books is kept
+/// while the action that removed it can be undone
+pub fn get_trash_dir() -> PathBuf {
+    let mut data_dir = get_app_dir();
+    data_dir.push("trash");
+    data_dir
+}
+
 /// Get path of the folder where cover images are stored
 pub fn get_saved_covers_dir() -> PathBuf {
     let mut data_dir = get_app_dir();
@@ -72,12 +81,67 @@ pub fn get_savedata_path() -> PathBuf {
     config_file
 }
 
+/// Get path of the lines of the change logs already merged from the other devices
+pub fn get_sync_state_path() -> PathBuf {
+    let mut config_file = get_config_dir();
+    config_file.push("sync_state.json");
+    config_file
+}
+
+/// Get path of the chapter edits that have to be resolved by the user
+pub fn get_sync_conflicts_path() -> PathBuf {
+    let mut config_file = get_config_dir();
+    config_file.push("sync_conflicts.json");
+    config_file
+}
+
+/// Get path of the time of the last backup made by this device
+pub fn get_backup_state_path() -> PathBuf {
+    let mut config_file = get_config_dir();
+    config_file.push("backup_state.json");
+    config_file
+}
+
+/// Get path of the collections and tags defined by the user
+pub fn get_collections_path() -> PathBuf {
+    let mut config_file = get_config_dir();
+    config_file.push("collections.json");
+    config_file
+}
+
+/// Get path of the accounts and positions of the embedded sync server
+pub fn get_kosync_server_path() -> PathBuf {
+    let mut config_file = get_config_dir();
+    config_file.push("kosync_server.json");
+    config_file
+}
+
+/// Get path of the books removed from the library whose file is kept
+pub fn get_excluded_books_path() -> PathBuf {
+    let mut config_file = get_config_dir();
+    config_file.push("excluded_books.json");
+    config_file
+}
+
+/// Get path of the reading sessions, used for the statistics
+pub fn get_reading_sessions_path() -> PathBuf {
+    let mut config_file = get_config_dir();
+    config_file.push("reading_sessions.json");
+    config_file
+}
+
 pub fn get_books_notes_path() -> PathBuf {
     let mut config_file = get_config_dir();
     config_file.push("books_notes.json");
     config_file
 }
 
+/// Get path of the cover chosen by the user for a book, it replaces the one of the book
+pub fn get_custom_cover_path(book_path: &str) -> PathBuf {
+    let book_name = Path::new(book_path).file_stem().unwrap().to_str().unwrap();
+    get_saved_books_dir().join(book_name).join("cover.png")
+}
+
 /// Get path of the metadata file given a book path
 pub fn get_metadata_path(book_path: &String) -> PathBuf {
     let book_name = Path::new(book_path).file_stem().unwrap().to_str().unwrap();
@@ -0,0 +1,288 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use druid::{ExtEventSink, Target};
+
+use super::{dir_manager::get_epub_dir, kosync_client::partial_md5, mobi_utils, pdf_utils};
+use crate::{
+    models::watcher::{LIBRARY_FILES_CHANGED, WATCH_INTERVAL},
+    MYENV,
+};
+
+/// Books added, removed or moved in the watched folders since the last scan
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DirChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// old and new path of the books moved or renamed
+    pub moved: Vec<(String, String)>,
+}
+
+impl DirChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.moved.is_empty()
+    }
+}
+
+/// Size and time of the last change, a file is rewritten when they change
+type FileStamp = (u64, Option<SystemTime>);
+
+/// Book file seen in a watched folder, the identity is the same
+/// when the file is moved or renamed
+struct WatchedFile {
+    stamp: FileStamp,
+    identity: String,
+}
+
+/// Finds the books that change in some folders, scanning them periodically
+#[derive(Default)]
+pub struct DirWatcher {
+    files: HashMap<PathBuf, WatchedFile>,
+    /// new files that may still be copied, they are reported when they stop changing
+    pending: HashMap<PathBuf, FileStamp>,
+    scanned: bool,
+}
+
+/// Returns true for the files that can be opened as books
+pub fn is_book_file(path: &Path) -> bool {
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let path = path.to_string_lossy();
+    ext == "epub" || ext == "cbz" || mobi_utils::is_mobi(&path) || pdf_utils::is_pdf(&path)
+}
+
+/// Books inside a folder, and in its subfolders if recursive
+pub fn list_books(dir: &Path, recursive: bool) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut books = vec![];
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() {
+            if recursive {
+                books.extend(list_books(&path, true));
+            }
+        } else if is_book_file(&path) {
+            books.push(path);
+        }
+    }
+    books
+}
+
+/// Folders watched: the epubs folder and, with their subfolders, the ones chosen by the user
+pub fn get_watched_dirs() -> Vec<(PathBuf, bool)> {
+    let mut dirs = vec![(get_epub_dir(), false)];
+    let my_env = MYENV.lock().unwrap();
+    dirs.extend(
+        my_env
+            .watched_dirs
+            .iter()
+            .map(|dir| (PathBuf::from(dir), true)),
+    );
+    dirs
+}
+
+/// Returns true if the path is inside one of the folders
+pub fn is_watched(path: &Path, dirs: &[(PathBuf, bool)]) -> bool {
+    dirs.iter().any(|(dir, recursive)| {
+        if *recursive {
+            path.starts_with(dir)
+        } else {
+            path.parent() == Some(dir.as_path())
+        }
+    })
+}
+
+fn get_stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+fn to_string(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+impl DirWatcher {
+    /// Reads the books in the folders and returns what changed since the last scan.
+    /// The first scan only remembers the books, they are loaded by the library at start.
+    /// The books of the folders no longer watched are forgotten without being removed,
+    /// the ones of a folder that is missing, e.g. on a drive not connected, are kept as they are
+    pub fn scan(&mut self, dirs: &[(PathBuf, bool)]) -> DirChanges {
+        let missing = dirs
+            .iter()
+            .filter(|(dir, _)| !dir.is_dir())
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut current = HashMap::new();
+        for (dir, recursive) in dirs {
+            for path in list_books(dir, *recursive) {
+                if let Some(stamp) = get_stamp(&path) {
+                    current.insert(path, stamp);
+                }
+            }
+        }
+        self.files.retain(|path, _| is_watched(path, dirs));
+        self.pending.retain(|path, _| current.contains_key(path));
+
+        let removed_paths = self
+            .files
+            .keys()
+            .filter(|path| !current.contains_key(*path) && !is_watched(path, &missing))
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut removed = removed_paths
+            .into_iter()
+            .map(|path| {
+                let file = self.files.remove(&path).unwrap();
+                (path, file.identity)
+            })
+            .collect::<Vec<_>>();
+
+        let mut changes = DirChanges::default();
+        let mut new_paths = current
+            .iter()
+            .filter(|(path, _)| !self.files.contains_key(*path))
+            .collect::<Vec<_>>();
+        new_paths.sort();
+        for (path, stamp) in new_paths {
+            let identity = partial_md5(&to_string(path)).unwrap_or_default();
+            // a book removed and added with the same content has been moved,
+            // a rename is done at once so there is no need to wait for it
+            let moved_from = removed
+                .iter()
+                .position(|(_, old)| !identity.is_empty() && *old == identity);
+            if let Some(idx) = moved_from {
+                let (old_path, _) = removed.remove(idx);
+                changes.moved.push((to_string(&old_path), to_string(path)));
+            } else if self.scanned && self.pending.get(path) != Some(stamp) {
+                self.pending.insert(path.clone(), *stamp);
+                continue;
+            } else if self.scanned {
+                changes.added.push(to_string(path));
+            }
+            self.pending.remove(path);
+            let stamp = *stamp;
+            self.files
+                .insert(path.clone(), WatchedFile { stamp, identity });
+        }
+
+        // rewritten, e.g. with new metadata, but it is the same book
+        for (path, file) in self.files.iter_mut() {
+            let Some(&stamp) = current.get(path) else {
+                continue;
+            };
+            if file.stamp != stamp {
+                file.stamp = stamp;
+                file.identity = partial_md5(&to_string(path)).unwrap_or_default();
+            }
+        }
+
+        changes.removed = removed
+            .into_iter()
+            .map(|(path, _)| to_string(&path))
+            .collect();
+        self.scanned = true;
+        changes
+    }
+}
+
+/// Scans the watched folders in background while the application is open,
+/// the changes are sent with LIBRARY_FILES_CHANGED
+pub fn start_watcher(sink: ExtEventSink) {
+    std::thread::spawn(move || {
+        let mut watcher = DirWatcher::default();
+        watcher.scan(&get_watched_dirs());
+        loop {
+            std::thread::sleep(Duration::from_secs(WATCH_INTERVAL));
+            let changes = watcher.scan(&get_watched_dirs());
+            if changes.is_empty() {
+                continue;
+            }
+            if sink
+                .submit_command(LIBRARY_FILES_CHANGED, changes, Target::Auto)
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::temp_dir;
+
+    #[test]
+    fn test_scan_changes() {
+        let dir = temp_dir("watcher");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let dirs = vec![(dir.clone(), true)];
+        std::fs::write(dir.join("divina.epub"), "Nel mezzo del cammin").unwrap();
+        std::fs::write(dir.join("note.txt"), "non è un libro").unwrap();
+
+        let mut watcher = DirWatcher::default();
+        assert!(watcher.scan(&dirs).is_empty());
+
+        // a new book is reported when it is no longer being copied
+        std::fs::write(dir.join("sub").join("zeno.epub"), "La coscienza").unwrap();
+        assert!(watcher.scan(&dirs).is_empty());
+        let changes = watcher.scan(&dirs);
+        assert_eq!(
+            changes.added,
+            vec![to_string(&dir.join("sub").join("zeno.epub"))]
+        );
+
+        std::fs::rename(dir.join("divina.epub"), dir.join("commedia.epub")).unwrap();
+        std::fs::remove_file(dir.join("sub").join("zeno.epub")).unwrap();
+        let changes = watcher.scan(&dirs);
+        assert_eq!(
+            changes.moved,
+            vec![(
+                to_string(&dir.join("divina.epub")),
+                to_string(&dir.join("commedia.epub"))
+            )]
+        );
+        assert_eq!(
+            changes.removed,
+            vec![to_string(&dir.join("sub").join("zeno.epub"))]
+        );
+        assert!(changes.added.is_empty());
+
+        // the books of a folder that is missing, e.g. on a drive not connected, are kept
+        let unmounted = dir.with_extension("unmounted");
+        std::fs::rename(&dir, &unmounted).unwrap();
+        assert!(watcher.scan(&dirs).is_empty());
+        std::fs::rename(&unmounted, &dir).unwrap();
+        assert!(watcher.scan(&dirs).is_empty());
+
+        // the books of a folder no longer watched are not removed
+        assert!(watcher.scan(&[]).is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_watched_folders() {
+        let dir = PathBuf::from("/libri");
+        let dirs = vec![(dir.join("epubs"), false), (dir.join("calibre"), true)];
+        assert!(is_watched(&dir.join("epubs").join("a.epub"), &dirs));
+        assert!(!is_watched(
+            &dir.join("epubs").join("sub").join("a.epub"),
+            &dirs
+        ));
+        assert!(is_watched(
+            &dir.join("calibre").join("sub").join("a.epub"),
+            &dirs
+        ));
+        assert!(!is_watched(&dir.join("a.epub"), &dirs));
+    }
+}
@@ -0,0 +1,475 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    error::Error,
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+use druid::{ExtEventSink, Target};
+use epub::doc::EpubDoc;
+
+use super::{
+    cbz_utils,
+    collections::Collections,
+    dir_manager::{get_books_notes_path, get_metadata_path, get_savedata_path},
+    envmanager::FontSize,
+    kosync_client::partial_md5,
+    mobi_utils, pdf_utils,
+    saveload::{
+        add_note, get_chapter, get_chapter_bytes, has_note, read_book_entry, save_data,
+        save_favorite, write_edited_chapter, FileExtension,
+    },
+    sync_log::{self, Change},
+    trash::Snapshot,
+};
+use crate::models::duplicates::{MatchReason, DUPLICATES_FOUND};
+
+/// Words of the first chapters compared to find the same text in different editions
+const COMPARED_WORDS: usize = 3000;
+/// Books with less text, e.g. the comics, aren't compared by their content
+const MIN_COMPARED_WORDS: usize = 200;
+/// Shared part of the first chapters over which two books have the same text
+const CONTENT_SIMILARITY: f64 = 0.6;
+
+/// Book of the library searched among the duplicates
+pub struct Candidate {
+    pub path: String,
+    pub title: String,
+    pub author: String,
+}
+
+/// What identifies a book, read from its file and from its metadata
+#[derive(Default)]
+pub struct BookKeys {
+    pub md5: Option<String>,
+    pub identifier: Option<String>,
+    pub title_author: Option<String>,
+    /// hashes of the groups of words of the first chapters
+    pub content: HashSet<u64>,
+}
+
+/// Lowercase words without accents and punctuation
+pub fn normalize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'à' | 'á' | 'â' | 'ä' | 'ã' | 'å' => 'a',
+            'è' | 'é' | 'ê' | 'ë' => 'e',
+            'ì' | 'í' | 'î' | 'ï' => 'i',
+            'ò' | 'ó' | 'ô' | 'ö' | 'õ' => 'o',
+            'ù' | 'ú' | 'û' | 'ü' => 'u',
+            'ç' => 'c',
+            'ñ' => 'n',
+            c if c.is_alphanumeric() => c,
+            _ => ' ',
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+/// The words of the author are sorted, so "Austen, Jane" is the same as "Jane Austen"
+pub fn title_author_key(title: &str, author: &str) -> Option<String> {
+    let title = normalize(title);
+    if title.is_empty() {
+        return None;
+    }
+    let mut author = normalize(author);
+    author.sort();
+    Some(format!("{}|{}", title.join(" "), author.join(" ")))
+}
+
+/// The same book can have its identifier written in different ways,
+/// e.g. http://www.gutenberg.org/ebooks/1012 and http://www.gutenberg.org/1012
+pub fn normalize_identifier(identifier: &str) -> Option<String> {
+    let identifier = identifier.trim().to_lowercase();
+    if identifier.contains("gutenberg") {
+        let number = identifier
+            .trim_end_matches(|c: char| !c.is_ascii_digit())
+            .rsplit(|c: char| !c.is_ascii_digit())
+            .next()
+            .filter(|number| !number.is_empty())?;
+        return Some(format!("gutenberg:{}", number));
+    }
+
+    let identifier = identifier
+        .trim_start_matches("urn:")
+        .trim_start_matches("isbn:")
+        .trim();
+    let isbn = identifier.replace(['-', ' '], "");
+    if (isbn.len() == 10 || isbn.len() == 13)
+        && isbn.chars().all(|c| c.is_ascii_digit() || c == 'x')
+    {
+        return Some(format!("isbn:{}", isbn));
+    }
+    // too short to tell a book from another one
+    (identifier.len() >= 4).then(|| identifier.to_string())
+}
+
+fn hash_words(words: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    words.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Hashes of the groups of four consecutive words of a text
+pub fn fingerprint(words: &[String]) -> HashSet<u64> {
+    if words.len() < MIN_COMPARED_WORDS {
+        return HashSet::new();
+    }
+    words.windows(4).map(hash_words).collect()
+}
+
+/// Shared part of two texts, from 0 to 1
+pub fn similarity(a: &HashSet<u64>, b: &HashSet<u64>) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let shared = a.intersection(b).count() as f64;
+    shared / (a.len() + b.len()) as f64 * 2.0
+}
+
+/// Words of the first chapters of a book, read from the file for the EPUBs
+/// and from the extracted chapters for the other formats
+fn first_words(path: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut add_chapter = |html: &str| {
+        words.extend(normalize(&rhtml2md::parse_html(html)));
+        words.len() >= COMPARED_WORDS
+    };
+
+    if cbz_utils::is_cbz(path) {
+        return vec![];
+    } else if mobi_utils::is_mobi(path) || pdf_utils::is_pdf(path) {
+        let folder = Path::new(path).file_stem().unwrap_or_default();
+        let folder = folder.to_string_lossy().to_string();
+        let mut chapter = 0;
+        while let Ok(html) = get_chapter_bytes(folder.as_str(), chapter, FileExtension::HTML) {
+            if add_chapter(&String::from_utf8_lossy(&html)) {
+                break;
+            }
+            chapter += 1;
+        }
+    } else if let Ok(mut epub) = EpubDoc::new(path) {
+        for chapter in 0..epub.get_num_pages() {
+            if epub.set_current_page(chapter).is_err() {
+                break;
+            }
+            let Ok(html) = epub.get_current_str() else {
+                continue;
+            };
+            if add_chapter(&html) {
+                break;
+            }
+        }
+    }
+    words.truncate(COMPARED_WORDS);
+    words
+}
+
+/// Reads what identifies a book, it is slow since the file is read
+pub fn keys_of(book: &Candidate) -> BookKeys {
+    let metadata: HashMap<String, String> = std::fs::read_to_string(get_metadata_path(&book.path))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default();
+    BookKeys {
+        md5: partial_md5(&book.path).ok(),
+        identifier: metadata
+            .get("identifier")
+            .and_then(|identifier| normalize_identifier(identifier)),
+        title_author: title_author_key(&book.title, &book.author),
+        content: fingerprint(&first_words(&book.path)),
+    }
+}
+
+fn find_root(parents: &mut [usize], mut idx: usize) -> usize {
+    while parents[idx] != idx {
+        parents[idx] = parents[parents[idx]];
+        idx = parents[idx];
+    }
+    idx
+}
+
+/// Groups the books that are the same for at least one of their keys,
+/// returning the indexes of the books in each group and why they have been found
+pub fn match_books(keys: &[BookKeys]) -> Vec<(Vec<usize>, Vec<MatchReason>)> {
+    let mut parents = (0..keys.len()).collect::<Vec<_>>();
+    let mut reasons: Vec<(usize, MatchReason)> = vec![];
+    for i in 0..keys.len() {
+        for j in i + 1..keys.len() {
+            let (a, b) = (&keys[i], &keys[j]);
+            let same = |a: &Option<String>, b: &Option<String>| a.is_some() && a == b;
+            let found = [
+                (same(&a.md5, &b.md5), MatchReason::SameFile),
+                (same(&a.identifier, &b.identifier), MatchReason::Identifier),
+                (
+                    same(&a.title_author, &b.title_author),
+                    MatchReason::TitleAuthor,
+                ),
+                (
+                    similarity(&a.content, &b.content) >= CONTENT_SIMILARITY,
+                    MatchReason::Content,
+                ),
+            ];
+            for (_, reason) in found.into_iter().filter(|(found, _)| *found) {
+                reasons.push((i, reason));
+                let (root_i, root_j) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                parents[root_j] = root_i;
+            }
+        }
+    }
+
+    let mut groups: Vec<(Vec<usize>, Vec<MatchReason>)> = vec![];
+    let mut group_of: HashMap<usize, usize> = HashMap::new();
+    for idx in 0..keys.len() {
+        let root = find_root(&mut parents, idx);
+        let group = *group_of.entry(root).or_insert_with(|| {
+            groups.push((vec![], vec![]));
+            groups.len() - 1
+        });
+        groups[group].0.push(idx);
+    }
+    for (i, reason) in reasons {
+        let root = find_root(&mut parents, i);
+        let group_reasons = &mut groups[group_of[&root]].1;
+        if !group_reasons.contains(&reason) {
+            group_reasons.push(reason);
+        }
+    }
+    groups
+        .into_iter()
+        .filter(|(books, _)| books.len() > 1)
+        .map(|(books, mut reasons)| {
+            reasons.sort();
+            (books, reasons)
+        })
+        .collect()
+}
+
+/// Searches the duplicates in background, the groups are sent with DUPLICATES_FOUND
+pub fn start_search(books: Vec<Candidate>, sink: ExtEventSink) {
+    std::thread::spawn(move || {
+        let keys = books.iter().map(keys_of).collect::<Vec<_>>();
+        let groups = match_books(&keys)
+            .into_iter()
+            .map(|(idxs, reasons)| {
+                let paths = idxs.into_iter().map(|idx| books[idx].path.clone());
+                (paths.collect(), reasons)
+            })
+            .collect::<Vec<_>>();
+        let _ = sink.submit_command(DUPLICATES_FOUND, groups, Target::Auto);
+    });
+}
+
+/// Chapter, start and text of the notes of a book, as they are saved in books_notes.json
+fn notes_of(book_path: &str) -> Vec<(usize, String, String)> {
+    let notes = read_book_entry(&get_books_notes_path(), book_path).unwrap_or_default();
+    let mut found = vec![];
+    for item in notes.as_array().unwrap_or(&vec![]) {
+        let chapter = item["chapter"].as_u64().unwrap_or_default() as usize;
+        for note in item["notes"].as_array().unwrap_or(&vec![]) {
+            if let (Some(start), Some(text)) = (note["start"].as_str(), note["note"].as_str()) {
+                found.push((chapter, start.to_string(), text.to_string()));
+            }
+        }
+    }
+    found
+}
+
+pub fn count_notes(book_path: &str) -> usize {
+    notes_of(book_path).len()
+}
+
+/// Merges the duplicates of a book in it: their notes, collections, tags and the chapters
+/// edited only in them are added to it, it becomes a favorite if one of them is and it takes
+/// the position of the furthest one. The duplicates leave the library through the trash,
+/// the returned snapshots can undo their removal
+pub fn merge_books(
+    keep: &str,
+    others: &[String],
+    favorite: bool,
+    furthest: &str,
+    id: u64,
+) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+    for other in others {
+        for (chapter, start, note) in notes_of(other) {
+            if !has_note(keep, chapter, &start, &note) {
+                add_note(keep, chapter, &start, &note)?;
+                sync_log::record(
+                    keep,
+                    Change::NoteAdd {
+                        chapter,
+                        start,
+                        note,
+                    },
+                );
+            }
+        }
+    }
+    if favorite {
+        save_favorite(keep, true)?;
+    }
+    if furthest != keep {
+        if let Some(position) = read_book_entry(&get_savedata_path(), furthest) {
+            let chapter = position["chapter"].as_u64().unwrap_or(1) as usize;
+            let page = position["page"].as_u64().unwrap_or_default() as usize;
+            let content = position["content"].as_str().unwrap_or_default();
+            let font_size = FontSize::from(position["font_size"].as_str().unwrap_or("medium"));
+            save_data(keep, chapter, page, content, font_size, false)?;
+        }
+    }
+    for other in others {
+        merge_edits(keep, other)?;
+    }
+
+    let mut shelves = Collections::load();
+    let kept = [keep.to_string()];
+    for other in others {
+        for name in shelves.get_collections_of(other) {
+            shelves.add_to_collection(&name, &kept)?;
+        }
+        for tag in shelves.get_tags_of(other) {
+            shelves.add_tag(&tag, &kept)?;
+        }
+    }
+    shelves.save()?;
+
+    // every snapshot has its own folder in the trash
+    others
+        .iter()
+        .enumerate()
+        .map(|(i, other)| Snapshot::remove_duplicate(other, keep, id + i as u64))
+        .collect()
+}
+
+fn edited_chapters(book_path: &str) -> Vec<usize> {
+    read_book_entry(&get_savedata_path(), book_path)
+        .and_then(|position| position["edited_chapters"].as_array().cloned())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|chapter| chapter.as_u64().map(|chapter| chapter as usize))
+        .collect()
+}
+
+/// Copies in the kept book the chapters edited only in a duplicate,
+/// the chapters edited in both keep the text of the kept book
+fn merge_edits(keep: &str, other: &str) -> Result<(), Box<dyn Error>> {
+    let kept = edited_chapters(keep);
+    let merged = edited_chapters(other)
+        .into_iter()
+        .filter(|chapter| !kept.contains(chapter))
+        .collect::<Vec<_>>();
+    if merged.is_empty() {
+        return Ok(());
+    }
+    if read_book_entry(&get_savedata_path(), keep).is_none() {
+        // the edited chapters are listed in the position of the book
+        save_data(keep, 1, 0, "", FontSize::MEDIUM, false)?;
+    }
+
+    let folder = Path::new(other)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let shared = Path::new(other).file_stem() == Path::new(keep).file_stem();
+    for chapter in merged {
+        let Ok(text) = get_chapter(&folder, chapter, FileExtension::TXT) else {
+            continue;
+        };
+        let base = sync_log::get_chapter_hash(keep, chapter);
+        write_edited_chapter(keep, chapter, Some(&text))?;
+        // with the same file name the edit was already read as the one of the kept book
+        if !shared {
+            sync_log::record(
+                keep,
+                Change::Edit {
+                    chapter,
+                    base,
+                    text: Some(text),
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(md5: &str, identifier: &str, title: &str, author: &str, text: &str) -> BookKeys {
+        let some = |s: &str| (!s.is_empty()).then(|| s.to_string());
+        BookKeys {
+            md5: some(md5),
+            identifier: normalize_identifier(identifier),
+            title_author: title_author_key(title, author),
+            content: fingerprint(&normalize(text)),
+        }
+    }
+
+    #[test]
+    fn test_normalize_identifier() {
+        assert_eq!(
+            normalize_identifier("http://www.gutenberg.org/ebooks/1012"),
+            Some("gutenberg:1012".to_string())
+        );
+        assert_eq!(
+            normalize_identifier("http://www.gutenberg.org/1012/"),
+            Some("gutenberg:1012".to_string())
+        );
+        assert_eq!(
+            normalize_identifier("urn:isbn:978-88-07-90011-6"),
+            Some("isbn:9788807900116".to_string())
+        );
+        assert_eq!(normalize_identifier(" 12 "), None);
+    }
+
+    #[test]
+    fn test_match_books() {
+        let inferno =
+            "Nel mezzo del cammin di nostra vita mi ritrovai per una selva oscura ".repeat(30);
+        let other = "Quel ramo del lago di Como che volge a mezzogiorno tra due catene ".repeat(30);
+        let books = [
+            keys(
+                "a",
+                "http://www.gutenberg.org/ebooks/1012",
+                "La Divina Commedia",
+                "Dante Alighieri",
+                &inferno,
+            ),
+            keys(
+                "b",
+                "http://www.gutenberg.org/1012",
+                "Divina commedia",
+                "Alighieri, Dante",
+                "",
+            ),
+            keys("c", "", "I promessi sposi", "Alessandro Manzoni", &other),
+            keys(
+                "d",
+                "",
+                "La divina commedia!",
+                "Alighieri Dante",
+                &format!("Illustrata. {}", inferno),
+            ),
+            keys("c", "", "Promessi sposi", "Manzoni", ""),
+            keys("e", "", "Il fu Mattia Pascal", "Luigi Pirandello", ""),
+        ];
+        assert_eq!(
+            match_books(&books),
+            vec![
+                (
+                    vec![0, 1, 3],
+                    vec![
+                        MatchReason::Identifier,
+                        MatchReason::TitleAuthor,
+                        MatchReason::Content
+                    ]
+                ),
+                (vec![2, 4], vec![MatchReason::SameFile]),
+            ]
+        );
+    }
+}
@@ -2,7 +2,8 @@
 use druid::{Color, FontDescriptor, FontFamily};
 use serde_json::{self, json};
 
-use super::{fonts, dir_manager::get_env_path};
+use super::{fonts, dir_manager::get_env_path, kosync_client::KoSyncSettings, webdav_backup::WebDavSettings};
+use crate::models::goals::ReadingGoals;
 
 #[derive(Debug)]
 pub struct MyEnv {
@@ -10,6 +11,13 @@ pub struct MyEnv {
     pub font_color: Color,
     pub font: FontDescriptor,
     pub shadows: bool,
+    pub calibre_library: Option<String>,
+    pub kosync: Option<KoSyncSettings>,
+    pub sync_dir: Option<String>,
+    pub webdav: Option<WebDavSettings>,
+    pub goals: Option<ReadingGoals>,
+    /// folders whose books are added to the library, with their subfolders
+    pub watched_dirs: Vec<String>,
 }
 
 impl MyEnv {
@@ -19,6 +27,12 @@ impl MyEnv {
             font_color: Color::rgb8(0, 0, 0),
             font: FontDescriptor::new(FontFamily::SYSTEM_UI).with_size(FontSize::MEDIUM.to_f64()),
             shadows: false,
+            calibre_library: None,
+            kosync: None,
+            sync_dir: None,
+            webdav: None,
+            goals: None,
+            watched_dirs: vec![],
         };
 
         let env_path = get_env_path();
@@ -58,6 +72,38 @@ impl MyEnv {
 
         new_env.shadows = json.get("shadows").unwrap().as_bool().unwrap();
 
+        // optional, it is set only when the user opens a Calibre library
+        new_env.calibre_library = json
+            .get("calibre_library")
+            .and_then(|path| path.as_str())
+            .map(|path| path.to_string());
+
+        // optional, it is set when the user logs in a KOReader sync server
+        new_env.kosync = json.get("kosync").and_then(KoSyncSettings::from_json);
+
+        // optional, it is set when the user chooses the folder shared with the other devices
+        new_env.sync_dir = json
+            .get("sync_dir")
+            .and_then(|path| path.as_str())
+            .map(|path| path.to_string());
+
+        // optional, it is set when the user configures the backup
+        new_env.webdav = json.get("webdav").and_then(WebDavSettings::from_json);
+
+        // optional, it is set when the user sets the reading goals
+        new_env.goals = json.get("goals").and_then(ReadingGoals::from_json);
+
+        // optional, it is set when the user chooses folders to watch
+        new_env.watched_dirs = json
+            .get("watched_dirs")
+            .and_then(|dirs| dirs.as_array())
+            .map(|dirs| {
+                dirs.iter()
+                    .filter_map(|dir| dir.as_str().map(|dir| dir.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
         return new_env;
     }
 
@@ -90,6 +136,30 @@ impl MyEnv {
             "shadows".to_string(),
             serde_json::Value::Bool(self.shadows.clone()),
         );
+        if let Some(calibre_library) = &self.calibre_library {
+            json.insert(
+                "calibre_library".to_string(),
+                serde_json::Value::String(calibre_library.clone()),
+            );
+        }
+        if let Some(kosync) = &self.kosync {
+            json.insert("kosync".to_string(), kosync.to_json());
+        }
+        if let Some(webdav) = &self.webdav {
+            json.insert("webdav".to_string(), webdav.to_json());
+        }
+        if let Some(goals) = &self.goals {
+            json.insert("goals".to_string(), goals.to_json());
+        }
+        if !self.watched_dirs.is_empty() {
+            json.insert("watched_dirs".to_string(), json!(self.watched_dirs));
+        }
+        if let Some(sync_dir) = &self.sync_dir {
+            json.insert(
+                "sync_dir".to_string(),
+                serde_json::Value::String(sync_dir.clone()),
+            );
+        }
 
         //write the json object to the file
         serde_json::to_writer_pretty(file, &json).unwrap();
@@ -109,6 +179,10 @@ impl MyEnv {
                     FontDescriptor::new(MyEnv::get_font_family(value)).with_size(self.font.size)
             }
             "shadows" => self.shadows = value.parse::<bool>().unwrap(),
+            "calibre_library" => {
+                self.calibre_library = if value.is_empty() { None } else { Some(value) }
+            }
+            "sync_dir" => self.sync_dir = if value.is_empty() { None } else { Some(value) },
             _ => (),
         }
     }
@@ -1,6 +1,6 @@
-use crate::{MYENV, utils::{envmanager::FontSize, dir_manager::get_edited_books_dir}, models::book::{PAGE_WIDTH, PAGE_HEIGHT}};
+use crate::{MYENV, utils::{envmanager::FontSize, dir_manager::{get_custom_cover_path, get_edited_books_dir}}, models::{book::{PAGE_WIDTH, PAGE_HEIGHT}, series::{series_from_epub, Series}}};
 
-use super::{saveload::{get_chapter_bytes, FileExtension, remove_edited_chapter}, dir_manager::{get_saved_books_dir, get_saved_covers_dir, get_metadata_path}};
+use super::{saveload::{get_chapter_bytes, FileExtension, remove_edited_chapter}, dir_manager::{get_saved_books_dir, get_saved_covers_dir, get_metadata_path}, calibre_utils, cbz_utils, mobi_utils, opf_utils, pdf_utils, sync_log};
 use epub::doc::EpubDoc;
 use serde_json::json;
 use std::{
@@ -23,9 +23,15 @@ use std::{
 /// date: date of the book
 /// rights: rights of the book
 /// identifier: identifier of the book
+/// authors: all the contributors with their role and sort name, as json
+/// series, series_index: series of the book and its position in it, if any
+/// desc: description of the book
+/// publisher: publisher of the book
+/// subjects: subjects of the book as a json list
 
 
 fn get_metadata_from_epub(
+    path: &str,
     book: &EpubDoc<File>,
 ) -> Result<HashMap<String, String>, Box<dyn error::Error>> {
     for key in book.metadata.keys() {
@@ -74,6 +80,28 @@ fn get_metadata_from_epub(
             .unwrap_or("no indetifier".to_string()),
     );
 
+    metadata.insert(
+        "desc".to_string(),
+        book.mdata("description").unwrap_or("no description".to_string()),
+    );
+
+    metadata.insert(
+        "publisher".to_string(),
+        book.mdata("publisher").unwrap_or("no publisher".to_string()),
+    );
+
+    let subjects = book.metadata.get("subject").cloned().unwrap_or_default();
+    metadata.insert("subjects".to_string(), json!(subjects).to_string());
+
+    // the epub crate keeps only the text of the creators, not their roles
+    match opf_utils::read_metadata(path) {
+        Ok(opf) => opf.write_authors_to(&mut metadata),
+        Err(e) => println!("ERROR: failed to read the authors of {}: {}", path, e),
+    }
+
+    let series = series_from_epub(|key| book.mdata(key));
+    Series::write_to(series.as_ref(), &mut metadata);
+
     metadata.insert("chapters".to_string(), book.get_num_pages().to_string());
     metadata.insert("favorite".to_string(), "false".to_string());
 
@@ -104,6 +132,9 @@ pub fn edit_chapter(
     chapter_number: usize,
     text: impl Into<String>,
 ) -> Result<(), Box<dyn error::Error>> {
+    // the other devices apply the edit only if they have the same text
+    let base = sync_log::get_chapter_hash(path, chapter_number);
+    let text: String = text.into();
     let folder_name = Path::new(path).file_stem().unwrap().to_str().unwrap();
     let mut path_name: PathBuf = get_edited_books_dir().join(folder_name);
     println!("DEBUG: Folder path: {:?}", path_name);
@@ -116,18 +147,36 @@ pub fn edit_chapter(
         .truncate(true)
         .open(&path_name)?;
 
-    file.write_all(text.into().as_bytes())?;
+    file.write_all(text.as_bytes())?;
+
+    sync_log::record(
+        path,
+        sync_log::Change::Edit {
+            chapter: chapter_number,
+            base,
+            text: Some(text),
+        },
+    );
 
     Ok(())
 }
 
 pub fn extract_all(path: &str) -> Result<(), Box<dyn error::Error>> {
+    if cbz_utils::is_cbz(path) {
+        return cbz_utils::extract_all(path);
+    }
+    if mobi_utils::is_mobi(path) {
+        return mobi_utils::extract_all(path);
+    }
+    if pdf_utils::is_pdf(path) {
+        return pdf_utils::extract_all(path);
+    }
 
     let mut book = EpubDoc::new(path)?;
     let path_name = get_metadata_path(&path.to_string());
 
     let mut metadata_file = File::create(&path_name).unwrap();
-    let metadata_map = get_metadata_from_epub(&book)?;
+    let metadata_map = get_metadata_from_epub(path, &book)?;
 
     let json = json!(metadata_map);
     //json["cover"] = json!(book.get_cover().unwrap_or_default());
@@ -160,10 +209,20 @@ pub fn extract_all(path: &str) -> Result<(), Box<dyn error::Error>> {
 }
 
 pub fn extract_metadata(path: &str) -> Result<HashMap<String, String>, Box<dyn error::Error>> {
+    if cbz_utils::is_cbz(path) {
+        return cbz_utils::extract_metadata(path);
+    }
+    if mobi_utils::is_mobi(path) {
+        return mobi_utils::extract_metadata(path);
+    }
+    if pdf_utils::is_pdf(path) {
+        return pdf_utils::extract_metadata(path);
+    }
+
     let path_name = get_metadata_path(&path.to_string());
     let mut metadata_file = File::create(&path_name).unwrap();
     let book = EpubDoc::new(path)?;
-    let metadata_map = get_metadata_from_epub(&book)?;
+    let metadata_map = get_metadata_from_epub(path, &book)?;
 
     let json = json!(metadata_map);
     //json["cover"] = json!(book.get_cover().unwrap_or_default());
@@ -206,6 +265,29 @@ pub fn extract_chapters(path: &str) -> Result<(), Box<dyn error::Error>> {
     Ok(())
 }
 
+/// Method that returns the raw bytes of the cover of the book,
+/// whatever is the format of the book
+pub fn get_cover_of_book(path: &str) -> Result<Vec<u8>, String> {
+    if let Ok(cover) = std::fs::read(get_custom_cover_path(path)) {
+        return Ok(cover);
+    }
+    if let Some(cover) = calibre_utils::get_cover(path) {
+        return Ok(cover);
+    }
+    if cbz_utils::is_cbz(path) {
+        return cbz_utils::get_cover(path).map_err(|e| e.to_string());
+    }
+    if mobi_utils::is_mobi(path) {
+        return mobi_utils::get_cover(path).map_err(|e| e.to_string());
+    }
+    if pdf_utils::is_pdf(path) {
+        return pdf_utils::get_cover(path).map_err(|e| e.to_string());
+    }
+
+    let mut epub = EpubDoc::new(path).map_err(|e| e.to_string())?;
+    epub.get_cover().map_err(|e| e.to_string())
+}
+
 pub fn get_chapter_text(path: &str, chapter_number: usize) -> Rc<String> {
     let slice = get_chapter_text_utf8(path, chapter_number);
     let text = std::str::from_utf8(&slice).unwrap();
@@ -238,6 +320,14 @@ pub fn get_chapter_text_utf8(path: impl Into<String>, chapter_number: usize) ->
         let first_back = parsed.find("\n").unwrap_or(0);
         return parsed[first_back+1..].as_bytes().to_vec();
     }
+    // the html pages of MOBI and PDF books can only be rebuilt from the whole file
+    else if mobi_utils::is_mobi(&path) || pdf_utils::is_pdf(&path) {
+        if extract_all(&path).is_ok()
+            && get_chapter_bytes(folder_name, chapter_number, FileExtension::HTML).is_ok()
+        {
+            return get_chapter_text_utf8(path.clone(), chapter_number);
+        }
+    }
     // if it fails, read from epub and save html page
     else if let Ok(mut book) = EpubDoc::new(&path) {
         println!("DEBUG: reading from epub file");
@@ -294,6 +384,14 @@ pub fn calculate_number_of_pages(
     let mut metadata = get_metadata_of_book(path);
     let number_of_chapters = metadata["chapters"].parse::<usize>().unwrap_or_default();
 
+    // images don't reflow, the pages of a comic are fixed at extraction time
+    if cbz_utils::is_cbz(path) {
+        let number_of_pages = metadata
+            .get("total_pages")
+            .map_or(0, |x| x.parse::<usize>().unwrap_or_default());
+        return Ok((number_of_pages, vec![(0, number_of_pages.max(1) - 1)]));
+    }
+
     let pool = threadpool::Builder::new().build();
 
 
@@ -371,6 +469,48 @@ pub fn calculate_number_of_pages(
     Ok((number_of_pages, pages_per_chapter_start_end))
 }
 
+/// Chapter a link of the text points to, None if it points outside the book
+pub fn get_chapter_of_link(path: &str, target: &str) -> Option<usize> {
+    if target.contains("://") || target.starts_with("mailto:") {
+        return None;
+    }
+    // the anchor inside the chapter is not followed, the chapter opens at its start
+    let file = target.split('#').next()?;
+    let name = Path::new(file).file_name()?;
+    let doc = EpubDoc::new(path).ok()?;
+    doc.spine.iter().position(|id| {
+        doc.resources
+            .get(id)
+            .is_some_and(|(resource, _)| resource.file_name() == Some(name))
+    })
+}
+
+/// Titles of the chapters from the table of contents of the book,
+/// empty for the chapters that are not in it
+pub fn get_chapter_titles(path: &str) -> Vec<String> {
+    let Ok(doc) = EpubDoc::new(path) else {
+        return vec![];
+    };
+    let file_name = |path: &Path| {
+        let path = path.to_str()?.split('#').next()?;
+        Path::new(path).file_name().map(|name| name.to_os_string())
+    };
+    doc.spine
+        .iter()
+        .map(|id| {
+            let Some(name) = doc.resources.get(id).and_then(|(resource, _)| file_name(resource))
+            else {
+                return String::new();
+            };
+            doc.toc
+                .iter()
+                .find(|point| file_name(&point.content).as_ref() == Some(&name))
+                .map(|point| point.label.trim().to_string())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
 // get total number of pages in the book
 pub fn get_number_of_pages(path: &str) -> usize {
     let metadata = get_metadata_of_book(path);
@@ -0,0 +1,449 @@
+use std::{
+    collections::HashMap,
+    error,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    sync::{Mutex, Once},
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+
+use super::{cbz_utils, dir_manager::get_savedata_path, epub_utils};
+use crate::{models::navigation::Position, MYENV};
+
+const ACCEPT: &str = "application/vnd.koreader.v1+json";
+pub const DEVICE_NAME: &str = "CrabReader";
+/// Seconds between two pushes of the positions of the books read
+const PUSH_INTERVAL: u64 = 30;
+
+/// Latest position of each book that hasn't been sent yet
+static PENDING_POSITIONS: Lazy<Mutex<HashMap<String, (usize, usize)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static PUSH_THREAD: Once = Once::new();
+
+/// Account on a KOReader sync server
+#[derive(Clone, Debug, PartialEq)]
+pub struct KoSyncSettings {
+    pub server: String,
+    pub username: String,
+    /// MD5 of the password, the server never receives the password itself
+    pub key: String,
+}
+
+impl KoSyncSettings {
+    pub fn new(server: &str, username: &str, password: &str) -> Self {
+        Self {
+            server: server.trim().trim_end_matches('/').to_string(),
+            username: username.trim().to_string(),
+            key: hash_password(password),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "server": self.server,
+            "username": self.username,
+            "key": self.key,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            server: value.get("server")?.as_str()?.to_string(),
+            username: value.get("username")?.as_str()?.to_string(),
+            key: value.get("key")?.as_str()?.to_string(),
+        })
+    }
+}
+
+/// Reading position of a document as exchanged with the server
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Progress {
+    /// partial MD5 of the file
+    pub document: String,
+    /// xpointer for reflowable documents, page number for comics
+    pub progress: String,
+    pub percentage: f64,
+    pub device: String,
+    pub device_id: String,
+    /// seconds since the epoch, set by the server
+    pub timestamp: u64,
+}
+
+impl Progress {
+    fn to_json(&self) -> Value {
+        json!({
+            "document": self.document,
+            "progress": self.progress,
+            "percentage": self.percentage,
+            "device": self.device,
+            "device_id": self.device_id,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            document: value.get("document")?.as_str()?.to_string(),
+            progress: value
+                .get("progress")
+                .map(|progress| match progress {
+                    Value::String(progress) => progress.clone(),
+                    progress => progress.to_string(),
+                })
+                .unwrap_or_default(),
+            percentage: value.get("percentage")?.as_f64()?,
+            device: value
+                .get("device")
+                .and_then(|device| device.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            device_id: value
+                .get("device_id")
+                .and_then(|device_id| device_id.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            timestamp: value
+                .get("timestamp")
+                .and_then(|timestamp| timestamp.as_u64())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+pub fn hash_password(password: &str) -> String {
+    format!("{:x}", md5::compute(password.as_bytes()))
+}
+
+/// Identifier of this installation, the same for every start of the application
+pub fn get_device_id() -> String {
+    let config_path = get_savedata_path();
+    format!(
+        "{:x}",
+        md5::compute(config_path.to_string_lossy().as_bytes())
+    )
+    .to_uppercase()
+}
+
+/// Computes the identifier of a document like KOReader does:
+/// the MD5 of 1 KB samples taken at growing offsets of the file
+pub fn partial_md5(path: &str) -> Result<String, Box<dyn error::Error>> {
+    let mut file = File::open(path)?;
+    let mut context = md5::Context::new();
+    let mut sample = [0u8; 1024];
+
+    for i in -1..=10 {
+        // KOReader shifts 1024 by -2 for the first sample, which overflows to 0
+        let offset = if i < 0 { 0 } else { 1024u64 << (2 * i) };
+        file.seek(SeekFrom::Start(offset))?;
+        let read = file.read(&mut sample)?;
+        if read == 0 {
+            break;
+        }
+        context.consume(&sample[..read]);
+    }
+    Ok(format!("{:x}", context.compute()))
+}
+
+fn request(settings: &KoSyncSettings, method: &str, path: &str) -> ureq::Request {
+    ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .request(method, &format!("{}{}", settings.server, path))
+        .set("Accept", ACCEPT)
+        .set("Content-Type", "application/json")
+        .set("x-auth-user", &settings.username)
+        .set("x-auth-key", &settings.key)
+}
+
+/// Converts the errors of the server in readable messages
+fn call(request: ureq::Request, body: Option<Value>) -> Result<Value, Box<dyn error::Error>> {
+    let result = match body {
+        Some(body) => request.send_string(&body.to_string()),
+        None => request.call(),
+    };
+    match result {
+        Ok(response) => {
+            let body = response.into_string()?;
+            Ok(serde_json::from_str(&body).unwrap_or(Value::Null))
+        }
+        Err(ureq::Error::Status(401, _)) => Err("utente o password errati".into()),
+        Err(ureq::Error::Status(402, _)) => Err("utente già registrato".into()),
+        Err(ureq::Error::Status(code, response)) => {
+            let message = response
+                .into_string()
+                .ok()
+                .and_then(|body| serde_json::from_str::<Value>(&body).ok())
+                .and_then(|body| body["message"].as_str().map(|m| m.to_string()))
+                .unwrap_or(format!("errore {}", code));
+            Err(message.into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Creates a new account on the server
+pub fn register(settings: &KoSyncSettings) -> Result<(), Box<dyn error::Error>> {
+    let body = json!({
+        "username": settings.username,
+        "password": settings.key,
+    });
+    call(request(settings, "POST", "/users/create"), Some(body))?;
+    Ok(())
+}
+
+/// Checks that the account exists and the password is right
+pub fn authorize(settings: &KoSyncSettings) -> Result<(), Box<dyn error::Error>> {
+    call(request(settings, "GET", "/users/auth"), None)?;
+    Ok(())
+}
+
+/// Sends the reading position, returns the time it was saved at
+pub fn push_progress(
+    settings: &KoSyncSettings,
+    progress: &Progress,
+) -> Result<u64, Box<dyn error::Error>> {
+    let response = call(
+        request(settings, "PUT", "/syncs/progress"),
+        Some(progress.to_json()),
+    )?;
+    Ok(response["timestamp"].as_u64().unwrap_or_default())
+}
+
+/// Returns the last reading position of the document, if it was ever synced
+pub fn get_progress(
+    settings: &KoSyncSettings,
+    document: &str,
+) -> Result<Option<Progress>, Box<dyn error::Error>> {
+    let response = call(
+        request(settings, "GET", &format!("/syncs/progress/{}", document)),
+        None,
+    )?;
+    Ok(Progress::from_json(&response))
+}
+
+/// Builds the position sent to the server, in a format KOReader understands:
+/// an xpointer to the chapter for books and the page number for comics
+pub fn make_progress(
+    document: &str,
+    chapter: usize,
+    page: usize,
+    percentage: f64,
+    is_comic: bool,
+) -> Progress {
+    let progress = if is_comic {
+        (page + 1).to_string()
+    } else {
+        format!("/body/DocFragment[{}]/body", chapter + 1)
+    };
+    Progress {
+        document: document.to_string(),
+        progress,
+        percentage: percentage.clamp(0.0, 1.0),
+        device: DEVICE_NAME.to_string(),
+        device_id: get_device_id(),
+        timestamp: 0,
+    }
+}
+
+/// Queues the position of a book to be sent, if the user is logged in a server.
+/// Only the latest position of each book is sent, every PUSH_INTERVAL seconds
+/// and when the book is closed, not at every page turned
+pub fn push_position(book_path: String, chapter: usize, page: usize) {
+    PENDING_POSITIONS
+        .lock()
+        .unwrap()
+        .insert(book_path, (chapter, page));
+    PUSH_THREAD.call_once(|| {
+        std::thread::spawn(|| loop {
+            std::thread::sleep(Duration::from_secs(PUSH_INTERVAL));
+            push_pending_positions();
+        });
+    });
+}
+
+/// Sends in background the positions not sent yet, e.g. when a book is closed
+pub fn flush_positions() {
+    std::thread::spawn(push_pending_positions);
+}
+
+fn push_pending_positions() {
+    let pending = std::mem::take(&mut *PENDING_POSITIONS.lock().unwrap());
+    if pending.is_empty() {
+        return;
+    }
+    // the env is locked here and not by the caller of push_position, that may be holding it
+    let Some(settings) = MYENV.lock().unwrap().kosync.clone() else {
+        return;
+    };
+    for (book_path, (chapter, page)) in pending {
+        let document = match partial_md5(&book_path) {
+            Ok(document) => document,
+            Err(e) => {
+                println!("ERROR: can't compute the hash of {}: {}", book_path, e);
+                continue;
+            }
+        };
+        let total_pages = epub_utils::get_number_of_pages(&book_path).max(1);
+        let current_page =
+            epub_utils::get_cumulative_current_page_number(&book_path, chapter, page, None);
+        let percentage = current_page as f64 / total_pages as f64;
+        let is_comic = cbz_utils::is_cbz(&book_path);
+
+        let progress = make_progress(&document, chapter, page, percentage, is_comic);
+        if let Err(e) = push_progress(&settings, &progress) {
+            println!("ERROR: failed to sync the position of {}: {}", book_path, e);
+        }
+    }
+}
+
+/// Returns the chapter of an xpointer like /body/DocFragment[12]/body/p[3]/text().0
+fn get_xpointer_chapter(xpointer: &str) -> Option<usize> {
+    let start = xpointer.find("DocFragment[")? + "DocFragment[".len();
+    let end = xpointer[start..].find(']')? + start;
+    xpointer[start..end]
+        .parse::<usize>()
+        .ok()
+        .and_then(|fragment| fragment.checked_sub(1))
+}
+
+/// Finds the chapter and the page of a remote position,
+/// given the first and last page of every chapter
+pub fn get_position(
+    progress: &Progress,
+    pages_per_chapter: &[(usize, usize)],
+    is_comic: bool,
+) -> Option<(usize, usize)> {
+    let (_, last) = *pages_per_chapter.last()?;
+    if is_comic {
+        if let Ok(page) = progress.progress.parse::<usize>() {
+            return Some((0, page.saturating_sub(1).min(last)));
+        }
+    }
+
+    let page = ((last + 1) as f64 * progress.percentage.clamp(0.0, 1.0)) as usize;
+    let position = Position::from_page(page, pages_per_chapter)?;
+
+    match get_xpointer_chapter(&progress.progress) {
+        // the percentage is computed by KOReader with its own pagination,
+        // the chapter of the xpointer is more precise
+        Some(xpointer_chapter) if xpointer_chapter != position.chapter => {
+            (xpointer_chapter < pages_per_chapter.len()).then_some((xpointer_chapter, 0))
+        }
+        _ => Some((position.chapter, position.page)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{kosync_server::KoSyncServer, test_utils::temp_dir};
+
+    fn start_server(name: &str) -> (KoSyncServer, KoSyncSettings) {
+        let storage = temp_dir(&format!("kosync-{}", name)).join("users.json");
+        let server = KoSyncServer::start(0, storage).unwrap();
+        let settings = KoSyncSettings::new(
+            &format!("http://127.0.0.1:{}/", server.port()),
+            "dante",
+            "password",
+        );
+        (server, settings)
+    }
+
+    #[test]
+    fn test_partial_md5() {
+        let path = temp_dir("partial-md5").join("book.bin");
+        let bytes = (0..300000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        std::fs::write(&path, bytes).unwrap();
+        assert_eq!(
+            partial_md5(path.to_str().unwrap()).unwrap(),
+            "c43e7af7c64be64ff8765e78ee771294"
+        );
+        assert_eq!(
+            hash_password("password"),
+            "5f4dcc3b5aa765d61d8327deb882cf99"
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_register_and_authorize() {
+        let (server, settings) = start_server("auth");
+        assert_eq!(
+            settings.server,
+            format!("http://127.0.0.1:{}", server.port())
+        );
+
+        assert!(authorize(&settings).is_err());
+        register(&settings).unwrap();
+        authorize(&settings).unwrap();
+
+        // the same user can't be registered twice
+        assert_eq!(
+            register(&settings).unwrap_err().to_string(),
+            "utente già registrato"
+        );
+        let wrong = KoSyncSettings::new(&settings.server, "dante", "beatrice");
+        assert!(authorize(&wrong).is_err());
+        server.stop();
+    }
+
+    #[test]
+    fn test_push_and_get_progress() {
+        let (server, settings) = start_server("progress");
+        register(&settings).unwrap();
+
+        assert_eq!(get_progress(&settings, "0123abcd").unwrap(), None);
+
+        let progress = make_progress("0123abcd", 4, 2, 0.42, false);
+        let timestamp = push_progress(&settings, &progress).unwrap();
+        assert!(timestamp > 0);
+
+        let remote = get_progress(&settings, "0123abcd").unwrap().unwrap();
+        assert_eq!(remote.progress, "/body/DocFragment[5]/body");
+        assert_eq!(remote.percentage, 0.42);
+        assert_eq!(remote.device, DEVICE_NAME);
+        assert_eq!(remote.device_id, get_device_id());
+        assert_eq!(remote.timestamp, timestamp);
+        server.stop();
+    }
+
+    #[test]
+    fn test_large_body_is_refused() {
+        use std::{
+            io::{Read, Write},
+            net::TcpStream,
+        };
+
+        let (server, _) = start_server("large-body");
+        let mut stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+        stream
+            .write_all(b"POST /users/create HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 413"));
+        server.stop();
+    }
+
+    #[test]
+    fn test_get_position() {
+        let pages = [(0, 9), (10, 19), (20, 39)];
+        let mut progress = make_progress("doc", 0, 0, 0.5, false);
+
+        // the percentage falls in the third chapter, but the xpointer is in the second one
+        progress.progress = "/body/DocFragment[2]/body/p[3]/text().0".to_string();
+        assert_eq!(get_position(&progress, &pages, false), Some((1, 0)));
+
+        progress.progress = "/body/DocFragment[3]/body".to_string();
+        assert_eq!(get_position(&progress, &pages, false), Some((2, 0)));
+        progress.percentage = 0.75;
+        assert_eq!(get_position(&progress, &pages, false), Some((2, 10)));
+
+        let comic = make_progress("doc", 0, 6, 0.2, true);
+        assert_eq!(comic.progress, "7");
+        assert_eq!(get_position(&comic, &[(0, 30)], true), Some((0, 6)));
+        assert_eq!(get_position(&comic, &[], true), None);
+    }
+}
@@ -0,0 +1,278 @@
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+use super::opds_server::get_local_ip;
+
+/// Port used by the official KOReader sync server
+pub const KOSYNC_SERVER_PORT: u16 = 7200;
+/// Largest body accepted, the requests of the protocol are a few hundred bytes
+const MAX_BODY_LENGTH: usize = 64 * 1024;
+
+/// Embedded KOReader sync server, the accounts and the positions
+/// are saved in a JSON file so that they survive a restart
+#[derive(Debug)]
+pub struct KoSyncServer {
+    port: u16,
+    running: Arc<AtomicBool>,
+}
+
+/// Accounts and positions of the server, shared by the connection threads
+struct Storage {
+    path: PathBuf,
+    json: Value,
+}
+
+impl Storage {
+    fn load(path: PathBuf) -> Self {
+        let json = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or(json!({ "users": {}, "progress": {} }));
+        Self { path, json }
+    }
+
+    fn save(&self) {
+        if let Err(e) = std::fs::write(&self.path, self.json.to_string()) {
+            println!("ERROR: failed to save the sync server data: {}", e);
+        }
+    }
+}
+
+impl KoSyncServer {
+    /// Starts listening on all the interfaces at the given port,
+    /// 0 chooses a free one
+    pub fn start(port: u16, storage_path: PathBuf) -> Result<Self, String> {
+        let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|e| e.to_string())?;
+        // the listener is polled, so that the thread sees when the server is stopped
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+
+        let server = Self {
+            port,
+            running: Arc::new(AtomicBool::new(true)),
+        };
+
+        let running = server.running.clone();
+        let storage = Arc::new(Mutex::new(Storage::load(storage_path)));
+        thread::spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let storage = storage.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = handle_connection(stream, &storage) {
+                                println!("ERROR: sync server request failed: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => println!("ERROR: sync server connection failed: {}", e),
+                }
+            }
+        });
+
+        Ok(server)
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the url of the server that has to be set on the devices
+    pub fn get_address(&self) -> String {
+        format!("http://{}:{}", get_local_ip(), self.port)
+    }
+}
+
+impl Drop for KoSyncServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Request parsed from the connection, only what the protocol needs
+struct Request {
+    method: String,
+    path: String,
+    user: Option<String>,
+    key: Option<String>,
+    body: Value,
+}
+
+/// The request declares a body larger than the server accepts
+#[derive(Debug)]
+struct BodyTooLarge;
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "request body too large")
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+fn read_request(stream: &TcpStream) -> Result<Request, Box<dyn std::error::Error>> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut user = None;
+    let mut key = None;
+    let mut length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? <= 2 {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match name.trim().to_lowercase().as_str() {
+            "x-auth-user" => user = Some(value),
+            "x-auth-key" => key = Some(value),
+            "content-length" => length = value.parse::<usize>().unwrap_or_default(),
+            _ => {}
+        }
+    }
+
+    // the length comes from any client on the network, it isn't trusted
+    if length > MAX_BODY_LENGTH {
+        return Err(BodyTooLarge.into());
+    }
+    let mut body = Vec::with_capacity(length);
+    reader.take(length as u64).read_to_end(&mut body)?;
+    let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    Ok(Request {
+        method,
+        path,
+        user,
+        key,
+        body,
+    })
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    storage: &Mutex<Storage>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+    let (status, body) = match read_request(&stream) {
+        Ok(request) => handle_request(&request, &mut storage.lock().unwrap()),
+        Err(e) if e.is::<BodyTooLarge>() => {
+            error("413 Payload Too Large", 2000, "Request too large")
+        }
+        Err(e) => return Err(e),
+    };
+
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
+
+fn error(status: &'static str, code: u32, message: &str) -> (&'static str, Value) {
+    (status, json!({ "code": code, "message": message }))
+}
+
+fn is_authorized(request: &Request, storage: &Storage) -> bool {
+    match (&request.user, &request.key) {
+        (Some(user), Some(key)) => storage.json["users"][user].as_str() == Some(key.as_str()),
+        _ => false,
+    }
+}
+
+fn handle_request(request: &Request, storage: &mut Storage) -> (&'static str, Value) {
+    let path = request.path.split('?').next().unwrap_or_default();
+
+    match (request.method.as_str(), path) {
+        ("GET", "/healthcheck") => ("200 OK", json!({ "state": "OK" })),
+        ("POST", "/users/create") => {
+            let (Some(username), Some(password)) = (
+                request.body["username"].as_str(),
+                request.body["password"].as_str(),
+            ) else {
+                return error("403 Forbidden", 2003, "Invalid request");
+            };
+            if username.is_empty() || password.is_empty() {
+                return error("403 Forbidden", 2003, "Invalid request");
+            }
+            if storage.json["users"].get(username).is_some() {
+                return error(
+                    "402 Payment Required",
+                    2002,
+                    "Username is already registered.",
+                );
+            }
+            storage.json["users"][username] = json!(password);
+            storage.save();
+            ("201 Created", json!({ "username": username }))
+        }
+        _ if !is_authorized(request, storage) => error("401 Unauthorized", 2001, "Unauthorized"),
+        ("GET", "/users/auth") => ("200 OK", json!({ "authorized": "OK" })),
+        ("PUT", "/syncs/progress") => {
+            let Some(document) = request.body["document"].as_str() else {
+                return error("403 Forbidden", 2004, "Field 'document' not provided.");
+            };
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs());
+            let user = request.user.clone().unwrap_or_default();
+            storage.json["progress"][&user][document] = json!({
+                "progress": request.body["progress"],
+                "percentage": request.body["percentage"],
+                "device": request.body["device"],
+                "device_id": request.body["device_id"],
+                "timestamp": timestamp,
+            });
+            storage.save();
+            (
+                "200 OK",
+                json!({ "document": document, "timestamp": timestamp }),
+            )
+        }
+        ("GET", path) if path.starts_with("/syncs/progress/") => {
+            let document = path.trim_start_matches("/syncs/progress/");
+            let user = request.user.clone().unwrap_or_default();
+            match storage.json["progress"][&user].get(document) {
+                Some(progress) => {
+                    let mut progress = progress.clone();
+                    progress["document"] = json!(document);
+                    ("200 OK", progress)
+                }
+                None => ("200 OK", json!({})),
+            }
+        }
+        _ => error("404 Not Found", 2000, "Unknown request"),
+    }
+}
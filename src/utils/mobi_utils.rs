@@ -0,0 +1,1026 @@
+use std::{collections::HashMap, error, fs::File, io::Write, path::Path};
+
+use serde_json::json;
+
+use super::{
+    dir_manager::{get_metadata_path, get_saved_books_dir, get_saved_covers_dir},
+    epub_utils::save_book_cover,
+};
+
+const NO_COMPRESSION: u16 = 1;
+const PALMDOC_COMPRESSION: u16 = 2;
+const HUFFCDIC_COMPRESSION: u16 = 17480;
+
+const EXTH_AUTHOR: u32 = 100;
+const EXTH_PUBLISHER: u32 = 101;
+const EXTH_DESCRIPTION: u32 = 103;
+const EXTH_ISBN: u32 = 104;
+const EXTH_SUBJECT: u32 = 105;
+const EXTH_DATE: u32 = 106;
+const EXTH_ASIN: u32 = 113;
+const EXTH_KF8_BOUNDARY: u32 = 121;
+const EXTH_COVER_OFFSET: u32 = 201;
+const EXTH_THUMB_OFFSET: u32 = 202;
+const EXTH_UPDATED_TITLE: u32 = 503;
+const EXTH_LANGUAGE: u32 = 524;
+
+const NO_INDEX: u32 = 0xFFFFFFFF;
+
+/// Tags of the entries of the KF8 skeleton and fragment indexes
+const TAG_FRAGMENT_COUNT: u8 = 1;
+const TAG_POSITION: u8 = 6;
+
+pub const DRM_ERROR: &str =
+    "Il libro è protetto da DRM e non può essere aperto, importa una copia senza DRM";
+
+/// Returns true if the path points to a MOBI/AZW3 file
+pub fn is_mobi(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|ext| {
+        let ext = ext.to_string_lossy().to_lowercase();
+        ext == "mobi" || ext == "azw3" || ext == "azw"
+    })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Palm database: the container of MOBI files, a list of records
+struct PdbFile {
+    data: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdbFile {
+    fn parse(data: Vec<u8>) -> Result<PdbFile, Box<dyn error::Error>> {
+        let ident = data.get(60..68).ok_or("file too short")?;
+        if ident != b"BOOKMOBI" && ident != b"TEXtREAd" {
+            return Err("not a MOBI file".into());
+        }
+
+        let number_of_records = read_u16(&data, 76).ok_or("missing record list")? as usize;
+        let mut offsets = Vec::with_capacity(number_of_records + 1);
+        for i in 0..number_of_records {
+            let offset = read_u32(&data, 78 + i * 8).ok_or("corrupted record list")?;
+            offsets.push(offset as usize);
+        }
+        offsets.push(data.len());
+
+        Ok(PdbFile { data, offsets })
+    }
+
+    fn record(&self, idx: usize) -> Option<&[u8]> {
+        let start = *self.offsets.get(idx)?;
+        let end = *self.offsets.get(idx + 1)?;
+        self.data.get(start..end.max(start))
+    }
+}
+
+/// Fields of the PalmDOC and MOBI headers stored in the first record of a section
+struct MobiHeader {
+    /// index of the first record of the section (not 0 for the KF8 part of a combo file)
+    start: usize,
+    compression: u16,
+    text_length: usize,
+    text_records: usize,
+    encryption: u16,
+    encoding: u32,
+    version: u32,
+    first_image_index: u32,
+    huff_index: u32,
+    huff_count: u32,
+    extra_flags: u16,
+    fdst_index: u32,
+    /// indexes that tell where the fragments of KF8 text go in their skeletons
+    fragment_index: u32,
+    skeleton_index: u32,
+    full_name: String,
+    exth: HashMap<u32, Vec<Vec<u8>>>,
+}
+
+impl MobiHeader {
+    fn parse(pdb: &PdbFile, start: usize) -> Result<MobiHeader, Box<dyn error::Error>> {
+        let record = pdb.record(start).ok_or("missing header record")?;
+
+        let compression = read_u16(record, 0).ok_or("missing PalmDOC header")?;
+        let text_length = read_u32(record, 4).unwrap_or_default() as usize;
+        let text_records = read_u16(record, 8).unwrap_or_default() as usize;
+        let encryption = read_u16(record, 12).unwrap_or_default();
+
+        let mut header = MobiHeader {
+            start,
+            compression,
+            text_length,
+            text_records,
+            encryption,
+            encoding: 1252,
+            version: 0,
+            first_image_index: NO_INDEX,
+            huff_index: NO_INDEX,
+            huff_count: 0,
+            extra_flags: 0,
+            fdst_index: NO_INDEX,
+            fragment_index: NO_INDEX,
+            skeleton_index: NO_INDEX,
+            full_name: String::new(),
+            exth: HashMap::new(),
+        };
+
+        // plain PalmDOC files have no MOBI header
+        if record.get(16..20) != Some(b"MOBI".as_slice()) {
+            return Ok(header);
+        }
+
+        let header_length = read_u32(record, 20).unwrap_or_default() as usize;
+        header.encoding = read_u32(record, 28).unwrap_or(1252);
+        header.version = read_u32(record, 36).unwrap_or_default();
+        header.first_image_index = read_u32(record, 108).unwrap_or(NO_INDEX);
+        header.huff_index = read_u32(record, 112).unwrap_or(NO_INDEX);
+        header.huff_count = read_u32(record, 116).unwrap_or_default();
+        if header_length >= 0xE4 {
+            header.extra_flags = read_u16(record, 0xF2).unwrap_or_default();
+        }
+        if header.version >= 8 {
+            header.fdst_index = read_u32(record, 0xC0).unwrap_or(NO_INDEX);
+            header.fragment_index = read_u32(record, 0xF8).unwrap_or(NO_INDEX);
+            header.skeleton_index = read_u32(record, 0xFC).unwrap_or(NO_INDEX);
+        }
+
+        let name_offset = read_u32(record, 84).unwrap_or_default() as usize;
+        let name_length = read_u32(record, 88).unwrap_or_default() as usize;
+        if let Some(name) = record.get(name_offset..name_offset + name_length) {
+            header.full_name = decode_text(name, header.encoding);
+        }
+
+        let exth_flags = read_u32(record, 128).unwrap_or_default();
+        if exth_flags & 0x40 != 0 {
+            header.exth = parse_exth(record, 16 + header_length);
+        }
+
+        Ok(header)
+    }
+
+    fn exth_string(&self, key: u32) -> Option<String> {
+        let value = self.exth.get(&key)?.first()?;
+        let text = decode_text(value, self.encoding).trim().to_string();
+        if text.is_empty() {
+            None
+        } else {
+            Some(text)
+        }
+    }
+
+    fn exth_strings(&self, key: u32) -> Vec<String> {
+        self.exth.get(&key).map_or(vec![], |values| {
+            values
+                .iter()
+                .map(|v| decode_text(v, self.encoding).trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect()
+        })
+    }
+
+    fn exth_u32(&self, key: u32) -> Option<u32> {
+        read_u32(self.exth.get(&key)?.first()?, 0)
+    }
+
+    fn is_kf8(&self) -> bool {
+        self.version >= 8
+    }
+}
+
+fn parse_exth(record: &[u8], offset: usize) -> HashMap<u32, Vec<Vec<u8>>> {
+    let mut exth: HashMap<u32, Vec<Vec<u8>>> = HashMap::new();
+    if record.get(offset..offset + 4) != Some(b"EXTH".as_slice()) {
+        return exth;
+    }
+
+    let count = read_u32(record, offset + 8).unwrap_or_default();
+    let mut pos = offset + 12;
+    for _ in 0..count {
+        let (Some(key), Some(length)) = (read_u32(record, pos), read_u32(record, pos + 4)) else {
+            break;
+        };
+        let length = length as usize;
+        if length < 8 {
+            break;
+        }
+        if let Some(value) = record.get(pos + 8..pos + length) {
+            exth.entry(key).or_default().push(value.to_vec());
+        }
+        pos += length;
+    }
+    exth
+}
+
+/// Decodes the text of the book, MOBI files are either UTF-8 or CP1252
+fn decode_text(bytes: &[u8], encoding: u32) -> String {
+    if encoding == 65001 {
+        return String::from_utf8_lossy(bytes).to_string();
+    }
+
+    // CP1252 is Latin-1 apart from the 0x80..0x9F range
+    const CP1252_HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž',
+        '\u{8f}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}',
+        'ž', 'Ÿ',
+    ];
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x80..=0x9F => CP1252_HIGH[(b - 0x80) as usize],
+            _ => b as char,
+        })
+        .collect()
+}
+
+/// Returns the number of bytes appended at the end of a text record
+/// that don't belong to the text
+fn get_trailing_size(record: &[u8], flags: u16) -> usize {
+    fn get_entry_size(record: &[u8], end: usize) -> usize {
+        let mut result = 0;
+        let mut bitpos = 0;
+        let mut end = end;
+        while end > 0 {
+            let v = record[end - 1] as usize;
+            result |= (v & 0x7F) << bitpos;
+            bitpos += 7;
+            end -= 1;
+            if v & 0x80 != 0 || bitpos >= 28 {
+                break;
+            }
+        }
+        result
+    }
+
+    let mut size = 0;
+    let mut test_flags = flags >> 1;
+    while test_flags != 0 {
+        if test_flags & 1 != 0 && size < record.len() {
+            size += get_entry_size(record, record.len() - size);
+        }
+        test_flags >>= 1;
+    }
+
+    // multibyte characters that overlap with the next record
+    if flags & 1 != 0 && size < record.len() {
+        size += (record[record.len() - size - 1] & 0x3) as usize + 1;
+    }
+    size.min(record.len())
+}
+
+/// LZ77 variant used by PalmDOC
+fn palmdoc_decompress(data: &[u8]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(data.len() * 2);
+    let mut i = 0;
+    while i < data.len() {
+        let c = data[i];
+        i += 1;
+        match c {
+            1..=8 => {
+                let end = (i + c as usize).min(data.len());
+                out.extend_from_slice(&data[i..end]);
+                i = end;
+            }
+            0 | 9..=0x7F => out.push(c),
+            0xC0..=0xFF => {
+                out.push(b' ');
+                out.push(c ^ 0x80);
+            }
+            _ => {
+                let Some(&next) = data.get(i) else {
+                    break;
+                };
+                i += 1;
+                let pair = ((c as usize) << 8) | next as usize;
+                let distance = (pair >> 3) & 0x07FF;
+                let length = (pair & 0x7) + 3;
+                if distance == 0 || distance > out.len() {
+                    continue;
+                }
+                // the copy can overlap with itself, so it's done byte by byte
+                let start = out.len() - distance;
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Decompressor for the HUFF/CDIC compression of the Mobipocket Creator
+struct HuffCdicReader {
+    dict1: Vec<(u32, bool, u64)>,
+    mincode: Vec<u64>,
+    maxcode: Vec<u64>,
+    dictionary: Vec<Option<(Vec<u8>, bool)>>,
+}
+
+impl HuffCdicReader {
+    fn new(huff: &[u8], cdics: &[&[u8]]) -> Result<HuffCdicReader, Box<dyn error::Error>> {
+        if huff.get(0..8) != Some(b"HUFF\x00\x00\x00\x18".as_slice()) {
+            return Err("invalid HUFF record".into());
+        }
+        let off1 = read_u32(huff, 8).ok_or("invalid HUFF record")? as usize;
+        let off2 = read_u32(huff, 12).ok_or("invalid HUFF record")? as usize;
+
+        let mut dict1 = Vec::with_capacity(256);
+        for i in 0..256 {
+            let v = read_u32(huff, off1 + i * 4).ok_or("invalid HUFF table")?;
+            let codelen = v & 0x1F;
+            let term = v & 0x80 != 0;
+            let maxcode = (((v >> 8) as u64 + 1) << (32 - codelen)) - 1;
+            dict1.push((codelen, term, maxcode));
+        }
+
+        let mut mincode = vec![0u64];
+        let mut maxcode = vec![(1u64 << 32) - 1];
+        for codelen in 1..=32u32 {
+            let idx = off2 + (codelen as usize - 1) * 8;
+            let min = read_u32(huff, idx).ok_or("invalid HUFF table")? as u64;
+            let max = read_u32(huff, idx + 4).ok_or("invalid HUFF table")? as u64;
+            mincode.push(min << (32 - codelen));
+            maxcode.push(((max + 1) << (32 - codelen)) - 1);
+        }
+
+        let mut reader = HuffCdicReader {
+            dict1,
+            mincode,
+            maxcode,
+            dictionary: vec![],
+        };
+        for cdic in cdics {
+            reader.load_cdic(cdic)?;
+        }
+        Ok(reader)
+    }
+
+    fn load_cdic(&mut self, cdic: &[u8]) -> Result<(), Box<dyn error::Error>> {
+        if cdic.get(0..8) != Some(b"CDIC\x00\x00\x00\x10".as_slice()) {
+            return Err("invalid CDIC record".into());
+        }
+        let phrases = read_u32(cdic, 8).ok_or("invalid CDIC record")? as usize;
+        let bits = read_u32(cdic, 12).ok_or("invalid CDIC record")?;
+        let n = (1usize << bits).min(phrases.saturating_sub(self.dictionary.len()));
+
+        for i in 0..n {
+            let off = read_u16(cdic, 16 + i * 2).ok_or("invalid CDIC record")? as usize;
+            let blen = read_u16(cdic, 16 + off).ok_or("invalid CDIC record")? as usize;
+            let slice = cdic
+                .get(18 + off..18 + off + (blen & 0x7FFF))
+                .ok_or("invalid CDIC record")?;
+            self.dictionary
+                .push(Some((slice.to_vec(), blen & 0x8000 != 0)));
+        }
+        Ok(())
+    }
+
+    fn unpack(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut bitsleft = data.len() as i64 * 8;
+        let mut padded = data.to_vec();
+        padded.extend_from_slice(&[0u8; 8]);
+
+        let read_u64 = |pos: usize| {
+            let mut buf = [0u8; 8];
+            buf.copy_from_slice(&padded[pos..pos + 8]);
+            u64::from_be_bytes(buf)
+        };
+
+        let mut out = vec![];
+        let mut pos = 0;
+        let mut x = read_u64(pos);
+        let mut n: i64 = 32;
+
+        loop {
+            if n <= 0 {
+                pos += 4;
+                if pos + 8 > padded.len() {
+                    break;
+                }
+                x = read_u64(pos);
+                n += 32;
+            }
+            let code = (x >> n) & 0xFFFFFFFF;
+
+            let (mut codelen, term, mut maxcode) = self.dict1[(code >> 24) as usize];
+            if !term {
+                while (codelen as usize) < 32 && code < self.mincode[codelen as usize] {
+                    codelen += 1;
+                }
+                maxcode = self.maxcode[codelen as usize];
+            }
+
+            n -= codelen as i64;
+            bitsleft -= codelen as i64;
+            if bitsleft < 0 || codelen == 0 {
+                break;
+            }
+
+            let r = (maxcode.wrapping_sub(code) >> (32 - codelen)) as usize;
+            match self.dictionary.get(r) {
+                Some(Some((slice, true))) => out.extend_from_slice(slice),
+                Some(Some((slice, false))) => {
+                    // phrases can be compressed too, they are unpacked once and cached
+                    let slice = slice.clone();
+                    self.dictionary[r] = None;
+                    let unpacked = self.unpack(&slice);
+                    out.extend_from_slice(&unpacked);
+                    self.dictionary[r] = Some((unpacked, true));
+                }
+                _ => break,
+            }
+        }
+        out
+    }
+}
+
+/// Returns the uncompressed text (the raw markup) of a section
+fn read_text(pdb: &PdbFile, header: &MobiHeader) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let mut huff_reader = if header.compression == HUFFCDIC_COMPRESSION {
+        let first = header.start + header.huff_index as usize;
+        let huff = pdb.record(first).ok_or("missing HUFF record")?;
+        let cdics = (1..header.huff_count as usize)
+            .filter_map(|i| pdb.record(first + i))
+            .collect::<Vec<&[u8]>>();
+        Some(HuffCdicReader::new(huff, &cdics)?)
+    } else {
+        None
+    };
+
+    let mut text = Vec::with_capacity(header.text_length);
+    for i in 1..=header.text_records {
+        let record = pdb.record(header.start + i).ok_or("missing text record")?;
+        let record = &record[..record.len() - get_trailing_size(record, header.extra_flags)];
+
+        match header.compression {
+            NO_COMPRESSION => text.extend_from_slice(record),
+            PALMDOC_COMPRESSION => text.extend(palmdoc_decompress(record)),
+            HUFFCDIC_COMPRESSION => {
+                text.extend(huff_reader.as_mut().unwrap().unpack(record));
+            }
+            other => return Err(format!("unknown compression {}", other).into()),
+        }
+    }
+    text.truncate(header.text_length);
+    Ok(text)
+}
+
+/// Chapters of a MOBI 6 book are separated by page breaks
+fn split_mobi6_chapters(markup: &str) -> Vec<String> {
+    let body = markup
+        .find("<body")
+        .and_then(|start| markup[start..].find('>').map(|end| start + end + 1))
+        .unwrap_or(0);
+    let body_end = markup.rfind("</body>").unwrap_or(markup.len()).max(body);
+
+    markup[body..body_end]
+        .split("<mbp:pagebreak")
+        .map(|chunk| {
+            // drop the rest of the page break tag
+            let chunk = if chunk.starts_with("/>") || chunk.starts_with(" />") {
+                &chunk[chunk.find('>').unwrap() + 1..]
+            } else {
+                chunk
+            };
+            chunk.to_string()
+        })
+        .filter(|chunk| chunk.chars().any(|c| c.is_alphanumeric()))
+        .map(|chunk| wrap_chapter(&chunk))
+        .collect()
+}
+
+/// An entry of a KF8 index: its name and the values of its tags
+struct IndexEntry {
+    ident: String,
+    tags: HashMap<u8, Vec<usize>>,
+}
+
+/// Reads a forward encoded variable width integer,
+/// returns the value and the number of bytes it takes
+fn read_vwi(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0;
+    for (i, byte) in data.iter().enumerate().take(5) {
+        value = (value << 7) | (byte & 0x7F) as usize;
+        if byte & 0x80 != 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// How many values of a tag an index entry holds
+enum TagCount {
+    Values(usize),
+    Bytes(usize),
+}
+
+/// Values of the tags of an index entry, the TAGX table of the index
+/// tells which control bits mark each tag and how many values it has
+fn read_tags(tagx: &[[u8; 4]], control_bytes: usize, entry: &[u8]) -> HashMap<u8, Vec<usize>> {
+    let (controls, mut data) = entry.split_at(control_bytes.min(entry.len()));
+    let mut control = 0;
+    let mut counts = vec![];
+    for &[tag, values_per_entry, mask, end] in tagx {
+        if end == 1 {
+            control += 1;
+            continue;
+        }
+        let Some(byte) = controls.get(control) else {
+            break;
+        };
+        let value = byte & mask;
+        if value == 0 {
+            continue;
+        }
+        if value == mask && mask.count_ones() > 1 {
+            // the number of bytes of the values follows the control bytes
+            let Some((bytes, used)) = read_vwi(data) else {
+                break;
+            };
+            data = &data[used..];
+            counts.push((tag, TagCount::Bytes(bytes)));
+        } else {
+            let entries = (value >> mask.trailing_zeros()) as usize;
+            counts.push((tag, TagCount::Values(entries * values_per_entry as usize)));
+        }
+    }
+
+    let mut tags = HashMap::new();
+    for (tag, count) in counts {
+        let mut values = vec![];
+        let mut read = 0;
+        while match count {
+            TagCount::Values(count) => values.len() < count,
+            TagCount::Bytes(bytes) => read < bytes,
+        } {
+            let Some((value, used)) = read_vwi(data) else {
+                break;
+            };
+            data = &data[used..];
+            read += used;
+            values.push(value);
+        }
+        tags.insert(tag, values);
+    }
+    tags
+}
+
+/// Reads a KF8 index: a header record with the TAGX table
+/// followed by the records that hold the entries
+fn read_index(pdb: &PdbFile, first: usize) -> Option<Vec<IndexEntry>> {
+    let header = pdb.record(first).filter(|r| r.starts_with(b"INDX"))?;
+    let header_length = read_u32(header, 4)? as usize;
+    let records = read_u32(header, 24)? as usize;
+    let tagx = header
+        .get(header_length..)
+        .filter(|tagx| tagx.starts_with(b"TAGX"))?;
+    let tagx_length = read_u32(tagx, 4)? as usize;
+    let control_bytes = read_u32(tagx, 8)? as usize;
+    let table = tagx
+        .get(12..tagx_length)?
+        .chunks_exact(4)
+        .map(|tag| [tag[0], tag[1], tag[2], tag[3]])
+        .collect::<Vec<[u8; 4]>>();
+
+    let mut entries = vec![];
+    for i in 1..=records {
+        let record = pdb.record(first + i).filter(|r| r.starts_with(b"INDX"))?;
+        let idxt = read_u32(record, 20)? as usize;
+        let count = read_u32(record, 24)? as usize;
+        // the IDXT table lists where each entry starts, the last one ends at the table
+        let mut offsets = (0..count)
+            .map(|j| read_u16(record, idxt + 4 + 2 * j).map(|offset| offset as usize))
+            .collect::<Option<Vec<usize>>>()?;
+        offsets.push(idxt);
+        for w in offsets.windows(2) {
+            let entry = record.get(w[0]..w[1])?;
+            let ident_length = *entry.first()? as usize;
+            let ident = entry.get(1..1 + ident_length)?;
+            entries.push(IndexEntry {
+                ident: String::from_utf8_lossy(ident).to_string(),
+                tags: read_tags(&table, control_bytes, &entry[1 + ident_length..]),
+            });
+        }
+    }
+    Some(entries)
+}
+
+/// Builds the html files of a KF8 book: each skeleton is followed in the text
+/// by its fragments, which are inserted in it at the positions of the fragment index.
+/// Returns None if the indexes don't match the text
+fn assemble_kf8_files(
+    text: &[u8],
+    skeletons: &[IndexEntry],
+    fragments: &[IndexEntry],
+) -> Option<Vec<Vec<u8>>> {
+    let mut fragments = fragments.iter();
+    let mut files = vec![];
+    for skeleton in skeletons {
+        let count = *skeleton.tags.get(&TAG_FRAGMENT_COUNT)?.first()?;
+        let [start, length, ..] = skeleton.tags.get(&TAG_POSITION)?.as_slice() else {
+            return None;
+        };
+        let mut file = text.get(*start..start + length)?.to_vec();
+        let mut next = start + length;
+        for fragment in fragments.by_ref().take(count) {
+            // the position is in the whole text, counting the fragments already inserted
+            let insert = fragment.ident.parse::<usize>().ok()?.checked_sub(*start)?;
+            let length = *fragment.tags.get(&TAG_POSITION)?.get(1)?;
+            let data = text.get(next..next + length)?;
+            if insert > file.len() {
+                return None;
+            }
+            file.splice(insert..insert, data.iter().copied());
+            next += length;
+        }
+        files.push(file);
+    }
+    Some(files)
+}
+
+/// Chapter made of a KF8 html file, None if it has no text
+fn kf8_chapter(html: &str) -> Option<String> {
+    let html = html.find("<html").map_or(html, |start| &html[start..]);
+    if html.chars().any(|c| c.is_alphanumeric()) {
+        Some(format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n{}",
+            html
+        ))
+    } else {
+        None
+    }
+}
+
+/// Chapters of a KF8 book that has no skeleton and fragment indexes.
+/// Its text is a list of skeleton files, each followed by the fragments
+/// that have to be inserted in it. Without the indexes the insert positions
+/// aren't known, so the fragments are moved at the end of the body of their skeleton:
+/// fragments nested in the structure of the skeleton come out in the wrong place
+fn split_kf8_chapters(markup: &str) -> Vec<String> {
+    let mut starts = markup
+        .match_indices("<html")
+        .map(|(i, _)| i)
+        .collect::<Vec<usize>>();
+    if starts.is_empty() {
+        return split_mobi6_chapters(markup);
+    }
+    starts.push(markup.len());
+
+    starts
+        .windows(2)
+        .map(|w| &markup[w[0]..w[1]])
+        .filter_map(|part| {
+            let skeleton_end = part
+                .find("</html>")
+                .map_or(part.len(), |i| i + "</html>".len());
+            let (skeleton, fragments) = part.split_at(skeleton_end);
+            let body_end = skeleton.rfind("</body>").unwrap_or(skeleton.len());
+            kf8_chapter(&format!(
+                "{}{}{}",
+                &skeleton[..body_end],
+                fragments,
+                &skeleton[body_end..]
+            ))
+        })
+        .collect()
+}
+
+fn wrap_chapter(body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<html><body>{}</body></html>",
+        body
+    )
+}
+
+/// A MOBI/AZW3 book converted in the same shape of an extracted EPUB
+pub struct MobiBook {
+    pub metadata: HashMap<String, String>,
+    pub chapters: Vec<String>,
+    pub cover: Option<Vec<u8>>,
+}
+
+/// Method that checks if a MOBI file can be read,
+/// returns an error if the file is protected by DRM
+pub fn check_mobi(path: &str) -> Result<(), Box<dyn error::Error>> {
+    let pdb = PdbFile::parse(std::fs::read(path)?)?;
+    let header = MobiHeader::parse(&pdb, 0)?;
+    if header.encryption != 0 {
+        return Err(DRM_ERROR.into());
+    }
+    Ok(())
+}
+
+/// Method that parses a MOBI/AZW3 file
+pub fn read_mobi(path: &str) -> Result<MobiBook, Box<dyn error::Error>> {
+    let pdb = PdbFile::parse(std::fs::read(path)?)?;
+    let mobi6 = MobiHeader::parse(&pdb, 0)?;
+    if mobi6.encryption != 0 {
+        return Err(DRM_ERROR.into());
+    }
+
+    // combo files contain an old MOBI 6 section followed by the KF8 one
+    let kf8_start = if mobi6.is_kf8() {
+        Some(0)
+    } else {
+        mobi6
+            .exth_u32(EXTH_KF8_BOUNDARY)
+            .filter(|&boundary| boundary != NO_INDEX)
+            .map(|boundary| boundary as usize + 1)
+    };
+    let header = match kf8_start {
+        Some(0) | None => mobi6,
+        Some(start) => match MobiHeader::parse(&pdb, start) {
+            Ok(kf8) if kf8.is_kf8() => kf8,
+            _ => mobi6,
+        },
+    };
+
+    let text = read_text(&pdb, &header)?;
+    let kf8_index = |index: u32| {
+        Some(index)
+            .filter(|&index| index != NO_INDEX)
+            .and_then(|index| read_index(&pdb, header.start + index as usize))
+    };
+    let kf8_files = if header.is_kf8() {
+        kf8_index(header.skeleton_index)
+            .zip(kf8_index(header.fragment_index))
+            .and_then(|(skeletons, fragments)| assemble_kf8_files(&text, &skeletons, &fragments))
+    } else {
+        None
+    };
+    let chapters = if let Some(files) = kf8_files {
+        files
+            .iter()
+            .filter_map(|file| kf8_chapter(&decode_text(file, header.encoding)))
+            .collect()
+    } else if header.is_kf8() {
+        // the first flow is the html, the others are css and svg
+        let flow = Some(header.fdst_index)
+            .filter(|&index| index != NO_INDEX)
+            .and_then(|index| pdb.record(header.start + index as usize))
+            .filter(|fdst| fdst.starts_with(b"FDST"))
+            .and_then(|fdst| Some((read_u32(fdst, 12)?, read_u32(fdst, 16)?)))
+            .map(|(start, end)| (start as usize, (end as usize).min(text.len())))
+            .filter(|(start, end)| start < end)
+            .map_or(&text[..], |(start, end)| &text[start..end]);
+        split_kf8_chapters(&decode_text(flow, header.encoding))
+    } else {
+        split_mobi6_chapters(&decode_text(&text, header.encoding))
+    };
+    if chapters.is_empty() {
+        return Err("the book has no text".into());
+    }
+
+    let cover = header
+        .exth_u32(EXTH_COVER_OFFSET)
+        .or_else(|| header.exth_u32(EXTH_THUMB_OFFSET))
+        .filter(|_| header.first_image_index != NO_INDEX)
+        .and_then(|offset| {
+            pdb.record(header.start + header.first_image_index as usize + offset as usize)
+        })
+        .map(|record| record.to_vec());
+
+    let mut metadata = HashMap::new();
+    let title = header
+        .exth_string(EXTH_UPDATED_TITLE)
+        .or_else(|| Some(header.full_name.trim().to_string()).filter(|name| !name.is_empty()))
+        .unwrap_or_else(|| {
+            Path::new(path)
+                .file_stem()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .replace('_', " ")
+        });
+    metadata.insert("title".to_string(), title);
+    metadata.insert(
+        "author".to_string(),
+        header
+            .exth_string(EXTH_AUTHOR)
+            .unwrap_or("no author".to_string()),
+    );
+    metadata.insert(
+        "lang".to_string(),
+        header
+            .exth_string(EXTH_LANGUAGE)
+            .unwrap_or("no lang".to_string()),
+    );
+    if let Some(desc) = header.exth_string(EXTH_DESCRIPTION) {
+        metadata.insert("desc".to_string(), desc);
+    }
+    metadata.insert(
+        "source".to_string(),
+        header
+            .exth_string(EXTH_PUBLISHER)
+            .unwrap_or("no source".to_string()),
+    );
+    metadata.insert(
+        "date".to_string(),
+        header
+            .exth_string(EXTH_DATE)
+            .unwrap_or("no date".to_string()),
+    );
+    metadata.insert(
+        "identifier".to_string(),
+        header
+            .exth_string(EXTH_ISBN)
+            .or_else(|| header.exth_string(EXTH_ASIN))
+            .unwrap_or("no indetifier".to_string()),
+    );
+    let subjects = header.exth_strings(EXTH_SUBJECT);
+    if !subjects.is_empty() {
+        metadata.insert("subjects".to_string(), subjects.join(", "));
+    }
+    metadata.insert("chapters".to_string(), chapters.len().to_string());
+    metadata.insert("favorite".to_string(), "false".to_string());
+    metadata.insert("format".to_string(), "mobi".to_string());
+
+    Ok(MobiBook {
+        metadata,
+        chapters,
+        cover,
+    })
+}
+
+/// Method that saves the metadata of the book in its saved_books folder
+pub fn extract_metadata(path: &str) -> Result<HashMap<String, String>, Box<dyn error::Error>> {
+    let book = read_mobi(path)?;
+    save_metadata(path, &book.metadata)?;
+    Ok(book.metadata)
+}
+
+fn save_metadata(
+    path: &str,
+    metadata: &HashMap<String, String>,
+) -> Result<(), Box<dyn error::Error>> {
+    let path_name = get_metadata_path(&path.to_string());
+    let mut metadata_file = File::create(&path_name)?;
+    let json = json!(metadata);
+    metadata_file.write_all(json.to_string().as_bytes())?;
+    Ok(())
+}
+
+/// Method that extracts metadata, chapters and cover of the book,
+/// chapters are saved as html pages like the ones of an EPUB
+pub fn extract_all(path: &str) -> Result<(), Box<dyn error::Error>> {
+    let book = read_mobi(path)?;
+    save_metadata(path, &book.metadata)?;
+
+    let folder_name = Path::new(path).file_stem().unwrap().to_str().unwrap();
+    let folder = get_saved_books_dir().join(folder_name);
+    std::fs::create_dir_all(&folder)?;
+    for (i, chapter) in book.chapters.iter().enumerate() {
+        let mut file = File::create(folder.join(format!("page_{}.html", i)))?;
+        file.write_all(chapter.as_bytes())?;
+    }
+
+    if let Some(cover) = book.cover {
+        save_book_cover(&cover, &folder_name.to_string())?;
+    }
+    Ok(())
+}
+
+/// Method that returns the cover saved during the extraction
+pub fn get_cover(path: &str) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let folder_name = Path::new(path).file_stem().unwrap().to_str().unwrap();
+    let cover_path = get_saved_covers_dir().join(format!("{}.png", folder_name));
+    if let Ok(cover) = std::fs::read(cover_path) {
+        return Ok(cover);
+    }
+    read_mobi(path)?.cover.ok_or("the book has no cover".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::temp_dir;
+
+    fn fake_pdb(record0: Vec<u8>) -> Vec<u8> {
+        let mut data = vec![0u8; 78];
+        data[60..68].copy_from_slice(b"BOOKMOBI");
+        data[76..78].copy_from_slice(&1u16.to_be_bytes());
+        let offset = 78 + 8 + 2;
+        data.extend_from_slice(&(offset as u32).to_be_bytes());
+        data.extend_from_slice(&[0u8; 4]);
+        data.extend_from_slice(&[0u8; 2]);
+        data.extend(record0);
+        data
+    }
+
+    #[test]
+    fn palmdoc_literals_and_back_references() {
+        // "abc" + copy of 3 bytes at distance 3 + " d" encoded as 0xC0 ^ 0x80
+        let compressed = [b'a', b'b', b'c', 0x80, 0x18, 0xE4];
+        assert_eq!(palmdoc_decompress(&compressed), b"abcabc d".to_vec());
+
+        let literals = [3, 0xF0, 0x9F, 0xA6, b'!'];
+        assert_eq!(palmdoc_decompress(&literals), vec![0xF0, 0x9F, 0xA6, b'!']);
+    }
+
+    #[test]
+    fn trailing_entries_are_removed() {
+        // multibyte flag: the last byte tells how many bytes overlap
+        let record = [b'a', b'b', 0xC3, 0x01];
+        assert_eq!(get_trailing_size(&record, 0b1), 2);
+
+        // one trailing entry whose size is encoded in the last byte
+        let record = [b'a', b'b', b'x', 0x82];
+        assert_eq!(get_trailing_size(&record, 0b10), 2);
+    }
+
+    #[test]
+    fn cp1252_is_decoded() {
+        assert_eq!(decode_text(&[0x93, b'c', 0xE0, 0x94], 1252), "“cà”");
+        assert_eq!(decode_text("città".as_bytes(), 65001), "città");
+    }
+
+    #[test]
+    fn drm_is_rejected() {
+        let mut record0 = vec![0u8; 16];
+        record0[0..2].copy_from_slice(&PALMDOC_COMPRESSION.to_be_bytes());
+        record0[12..14].copy_from_slice(&2u16.to_be_bytes());
+
+        let pdb = PdbFile::parse(fake_pdb(record0)).unwrap();
+        let header = MobiHeader::parse(&pdb, 0).unwrap();
+        assert_eq!(header.encryption, 2);
+
+        let path = temp_dir("mobi-drm").join("drm.mobi");
+        std::fs::write(
+            &path,
+            fake_pdb({
+                let mut record0 = vec![0u8; 16];
+                record0[12..14].copy_from_slice(&1u16.to_be_bytes());
+                record0
+            }),
+        )
+        .unwrap();
+        let result = check_mobi(path.to_str().unwrap());
+        assert_eq!(result.unwrap_err().to_string(), DRM_ERROR);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn mobi6_chapters_are_split_on_page_breaks() {
+        let markup = "<html><head></head><body><p>Uno</p><mbp:pagebreak/><p>Due</p><mbp:pagebreak /> <mbp:pagebreak/></body></html>";
+        let chapters = split_mobi6_chapters(markup);
+        assert_eq!(chapters.len(), 2);
+        assert!(chapters[0].contains("<p>Uno</p>"));
+        assert!(chapters[1].contains("<p>Due</p>"));
+    }
+
+    #[test]
+    fn kf8_index_tags_are_read() {
+        let tagx = [[1, 1, 0x01, 0], [6, 2, 0x02, 0], [0, 0, 0, 1]];
+        let tags = read_tags(&tagx, 1, &[0x03, 0x82, 0x80, 0x02, 0xAC]);
+        assert_eq!(tags[&1], vec![2]);
+        assert_eq!(tags[&6], vec![0, 300]);
+    }
+
+    #[test]
+    fn kf8_fragments_are_inserted_at_their_positions() {
+        let skeleton = "<html><body><div id=\"a\"></div><div id=\"b\"></div></body></html>";
+        let text = format!("{}<p>Uno</p><p>Due</p>", skeleton);
+        let entry = |ident: String, tags: Vec<(u8, Vec<usize>)>| IndexEntry {
+            ident,
+            tags: tags.into_iter().collect(),
+        };
+        let skeletons = [entry(
+            "SKEL0000000".to_string(),
+            vec![
+                (TAG_FRAGMENT_COUNT, vec![2]),
+                (TAG_POSITION, vec![0, skeleton.len()]),
+            ],
+        )];
+        // the second position counts the first fragment, already inserted
+        let first = skeleton.find("</div>").unwrap();
+        let second = skeleton.rfind("</div>").unwrap() + "<p>Uno</p>".len();
+        let fragments = [
+            entry(first.to_string(), vec![(TAG_POSITION, vec![0, 10])]),
+            entry(second.to_string(), vec![(TAG_POSITION, vec![10, 10])]),
+        ];
+
+        let files = assemble_kf8_files(text.as_bytes(), &skeletons, &fragments).unwrap();
+        assert_eq!(
+            String::from_utf8(files[0].clone()).unwrap(),
+            "<html><body><div id=\"a\"><p>Uno</p></div><div id=\"b\"><p>Due</p></div></body></html>"
+        );
+        assert!(assemble_kf8_files(b"<html>", &skeletons, &fragments).is_none());
+    }
+
+    #[test]
+    fn kf8_fragments_are_moved_in_the_body() {
+        let markup =
+            "<html><body><div></div></body></html><p>Uno</p><html><body></body></html><p>Due</p>";
+        let chapters = split_kf8_chapters(markup);
+        assert_eq!(chapters.len(), 2);
+        assert!(chapters[0].ends_with("<div></div><p>Uno</p></body></html>"));
+        assert!(chapters[1].ends_with("<body><p>Due</p></body></html>"));
+    }
+}
@@ -1,12 +1,32 @@
+pub mod archive;
+pub mod book_import;
 pub mod button_functions;
+pub mod calibre_utils;
+pub mod cbz_utils;
+pub mod collections;
 pub mod colors;
 pub mod ctx_menu;
 pub mod delegates;
 pub mod dir_manager;
+pub mod dir_watcher;
+pub mod duplicates;
 pub mod envmanager;
 pub mod epub_utils;
 pub mod fonts;
+pub mod kosync_client;
+pub mod kosync_server;
+pub mod mobi_utils;
 pub mod ocrmanager;
+pub mod opds_client;
+pub mod opds_server;
+pub mod opf_utils;
+pub mod pdf_utils;
+pub mod reading_stats;
 pub mod rich_text_fn;
 pub mod saveload;
+pub mod sync_log;
+#[cfg(test)]
+pub mod test_utils;
 pub mod thread_loader;
+pub mod trash;
+pub mod webdav_backup;
@@ -0,0 +1,633 @@
+use std::{
+    error,
+    io::Read,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use roxmltree::{Document, Node};
+use serde_json::Value;
+use url::Url;
+
+const ACQUISITION_REL: &str = "http://opds-spec.org/acquisition";
+const IMAGE_REL: &str = "http://opds-spec.org/image";
+const THUMBNAIL_REL: &str = "http://opds-spec.org/image/thumbnail";
+const OPENSEARCH_TYPE: &str = "application/opensearchdescription+xml";
+
+/// Formats that can be downloaded, in order of preference, with their extension
+pub const SUPPORTED_TYPES: [(&str, &str); 6] = [
+    ("application/epub+zip", "epub"),
+    ("application/x-mobipocket-ebook", "mobi"),
+    ("application/vnd.amazon.ebook", "azw3"),
+    ("application/vnd.comicbook+zip", "cbz"),
+    ("application/x-cbz", "cbz"),
+    ("application/pdf", "pdf"),
+];
+
+/// Entry of a feed: a link to another feed or a book that can be downloaded
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OpdsEntry {
+    pub title: String,
+    pub author: String,
+    pub summary: String,
+    /// feed to open when the entry is a navigation entry
+    pub navigation: Option<String>,
+    /// files of the book with their mime type
+    pub acquisitions: Vec<(String, String)>,
+    pub cover: Option<String>,
+}
+
+impl OpdsEntry {
+    /// Returns the url and the extension of the best file that can be downloaded
+    pub fn get_download(&self) -> Option<(String, String)> {
+        SUPPORTED_TYPES.iter().find_map(|(mime, ext)| {
+            self.acquisitions
+                .iter()
+                .find(|(_, entry_mime)| entry_mime.starts_with(mime))
+                .map(|(href, _)| (href.clone(), ext.to_string()))
+        })
+    }
+}
+
+/// How the catalog can be searched
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpdsSearch {
+    /// url of an OpenSearch description that contains the template
+    Description(String),
+    /// url template with {searchTerms} or {?query}
+    Template(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OpdsFeed {
+    pub url: String,
+    pub title: String,
+    pub entries: Vec<OpdsEntry>,
+    pub search: Option<OpdsSearch>,
+    pub next: Option<String>,
+}
+
+fn resolve_url(base: &str, href: &str) -> String {
+    Url::parse(base)
+        .and_then(|base| base.join(href))
+        .map(|url| url.to_string())
+        .unwrap_or(href.to_string())
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(20))
+        .user_agent("crab-reader")
+        .build()
+}
+
+fn fetch_string(url: &str) -> Result<String, Box<dyn error::Error>> {
+    Ok(agent().get(url).call()?.into_string()?)
+}
+
+/// Method that downloads a resource, e.g. a cover
+pub fn fetch_bytes(url: &str) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let mut bytes = vec![];
+    agent()
+        .get(url)
+        .call()?
+        .into_reader()
+        .read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Method that downloads and parses a feed
+pub fn fetch_feed(url: &str) -> Result<OpdsFeed, Box<dyn error::Error>> {
+    let body = fetch_string(url)?;
+    parse_feed(url, &body)
+}
+
+/// Parses an OPDS 1.2 (Atom) or an OPDS 2.0 (JSON) feed
+pub fn parse_feed(url: &str, body: &str) -> Result<OpdsFeed, Box<dyn error::Error>> {
+    if body.trim_start().starts_with('{') {
+        parse_json_feed(url, body)
+    } else {
+        parse_atom_feed(url, body)
+    }
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name() == name)
+        .map(|child| {
+            child
+                .descendants()
+                .filter(|d| d.is_text())
+                .filter_map(|d| d.text())
+                .collect::<String>()
+                .trim()
+                .to_string()
+        })
+        .filter(|text| !text.is_empty())
+}
+
+fn parse_atom_feed(url: &str, body: &str) -> Result<OpdsFeed, Box<dyn error::Error>> {
+    let doc = Document::parse(body)?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "feed" {
+        return Err("the document is not an OPDS feed".into());
+    }
+
+    let mut feed = OpdsFeed {
+        url: url.to_string(),
+        title: child_text(root, "title").unwrap_or_default(),
+        ..Default::default()
+    };
+
+    let links = root
+        .children()
+        .filter(|child| child.is_element() && child.tag_name().name() == "link");
+    for link in links {
+        let (Some(rel), Some(href)) = (link.attribute("rel"), link.attribute("href")) else {
+            continue;
+        };
+        let href = resolve_url(url, href);
+        let mime = link.attribute("type").unwrap_or_default();
+        match rel {
+            "search" if mime.starts_with(OPENSEARCH_TYPE) => {
+                feed.search = Some(OpdsSearch::Description(href));
+            }
+            // a template is better than a description, it doesn't need another request
+            "search" if href.contains("{searchTerms}") => {
+                feed.search = Some(OpdsSearch::Template(href));
+            }
+            "next" => feed.next = Some(href),
+            _ => {}
+        }
+    }
+
+    let entries = root
+        .children()
+        .filter(|child| child.is_element() && child.tag_name().name() == "entry");
+    for node in entries {
+        let mut entry = OpdsEntry {
+            title: child_text(node, "title").unwrap_or_default(),
+            summary: child_text(node, "summary")
+                .or_else(|| child_text(node, "content"))
+                .unwrap_or_default(),
+            author: node
+                .children()
+                .filter(|child| child.is_element() && child.tag_name().name() == "author")
+                .filter_map(|author| child_text(author, "name"))
+                .collect::<Vec<String>>()
+                .join(", "),
+            ..Default::default()
+        };
+
+        let links = node
+            .children()
+            .filter(|child| child.is_element() && child.tag_name().name() == "link");
+        for link in links {
+            let Some(href) = link.attribute("href") else {
+                continue;
+            };
+            let href = resolve_url(url, href);
+            let rel = link.attribute("rel").unwrap_or_default();
+            let mime = link.attribute("type").unwrap_or_default();
+
+            if rel.starts_with(ACQUISITION_REL) {
+                entry.acquisitions.push((href, mime.to_string()));
+            } else if rel == THUMBNAIL_REL {
+                entry.cover = Some(href);
+            } else if rel == IMAGE_REL {
+                // the thumbnail is preferred because it is smaller
+                entry.cover.get_or_insert(href);
+            } else if mime.contains("profile=opds-catalog")
+                || mime.starts_with("application/atom+xml")
+            {
+                entry.navigation.get_or_insert(href);
+            }
+        }
+        feed.entries.push(entry);
+    }
+
+    Ok(feed)
+}
+
+fn json_links(value: &Value) -> Vec<&Value> {
+    value
+        .as_array()
+        .map(|links| links.iter().collect())
+        .unwrap_or_default()
+}
+
+fn json_rels(link: &Value) -> Vec<String> {
+    match &link["rel"] {
+        Value::String(rel) => vec![rel.clone()],
+        Value::Array(rels) => rels
+            .iter()
+            .filter_map(|rel| rel.as_str().map(|rel| rel.to_string()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Authors in OPDS 2.0 can be a string, an object with a name or a list of them
+fn json_contributors(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(name) => vec![name.clone()],
+        Value::Object(_) => value["name"]
+            .as_str()
+            .map(|name| vec![name.to_string()])
+            .unwrap_or_default(),
+        Value::Array(values) => values.iter().flat_map(json_contributors).collect(),
+        _ => vec![],
+    }
+}
+
+fn parse_json_publication(url: &str, publication: &Value) -> OpdsEntry {
+    let metadata = &publication["metadata"];
+    let mut entry = OpdsEntry {
+        title: metadata["title"].as_str().unwrap_or_default().to_string(),
+        author: json_contributors(&metadata["author"]).join(", "),
+        summary: metadata["description"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        ..Default::default()
+    };
+
+    for link in json_links(&publication["links"]) {
+        let Some(href) = link["href"].as_str() else {
+            continue;
+        };
+        if json_rels(link)
+            .iter()
+            .any(|rel| rel.starts_with(ACQUISITION_REL))
+        {
+            let mime = link["type"].as_str().unwrap_or_default().to_string();
+            entry.acquisitions.push((resolve_url(url, href), mime));
+        }
+    }
+
+    // images are listed from the biggest, the smallest one is enough for the cover
+    entry.cover = json_links(&publication["images"])
+        .into_iter()
+        .filter_map(|image| image["href"].as_str())
+        .last()
+        .map(|href| resolve_url(url, href));
+
+    entry
+}
+
+fn parse_json_navigation(url: &str, link: &Value) -> Option<OpdsEntry> {
+    let href = link["href"].as_str()?;
+    Some(OpdsEntry {
+        title: link["title"].as_str().unwrap_or(href).to_string(),
+        navigation: Some(resolve_url(url, href)),
+        ..Default::default()
+    })
+}
+
+fn parse_json_feed(url: &str, body: &str) -> Result<OpdsFeed, Box<dyn error::Error>> {
+    let json: Value = serde_json::from_str(body)?;
+    let mut feed = OpdsFeed {
+        url: url.to_string(),
+        title: json["metadata"]["title"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        ..Default::default()
+    };
+
+    for link in json_links(&json["links"]) {
+        let Some(href) = link["href"].as_str() else {
+            continue;
+        };
+        let rels = json_rels(link);
+        if rels.iter().any(|rel| rel == "search") {
+            let href = resolve_url(url, href);
+            feed.search = Some(if link["type"].as_str() == Some(OPENSEARCH_TYPE) {
+                OpdsSearch::Description(href)
+            } else {
+                OpdsSearch::Template(href)
+            });
+        } else if rels.iter().any(|rel| rel == "next") {
+            feed.next = Some(resolve_url(url, href));
+        }
+    }
+
+    // groups are flattened, their content follows the one of the feed
+    let groups = std::iter::once(&json)
+        .chain(json_links(&json["groups"]))
+        .collect::<Vec<&Value>>();
+    for group in groups {
+        for link in json_links(&group["navigation"]) {
+            feed.entries.extend(parse_json_navigation(url, link));
+        }
+        for publication in json_links(&group["publications"]) {
+            feed.entries.push(parse_json_publication(url, publication));
+        }
+    }
+
+    Ok(feed)
+}
+
+/// Returns the search template of an OpenSearch description
+fn parse_opensearch(url: &str, body: &str) -> Result<String, Box<dyn error::Error>> {
+    let doc = Document::parse(body)?;
+    let templates = doc
+        .descendants()
+        .filter(|node| node.is_element() && node.tag_name().name() == "Url")
+        .filter_map(|node| Some((node.attribute("type")?, node.attribute("template")?)))
+        .collect::<Vec<(&str, &str)>>();
+
+    templates
+        .iter()
+        .find(|(mime, _)| mime.contains("opds-catalog"))
+        .or_else(|| {
+            templates
+                .iter()
+                .find(|(mime, _)| mime.starts_with("application/atom+xml"))
+        })
+        .or(templates.first())
+        .map(|(_, template)| resolve_url(url, template))
+        .ok_or("the OpenSearch description has no template".into())
+}
+
+/// Fills a search template with the terms to search
+fn fill_template(template: &str, terms: &str) -> String {
+    let encoded = url::form_urlencoded::byte_serialize(terms.as_bytes()).collect::<String>();
+    let filled = template.replace("{searchTerms}", &encoded);
+
+    // URI template of OPDS 2.0, e.g. /search{?query}
+    if let Some((start, end)) = filled
+        .find("{?")
+        .and_then(|start| Some((start, start + filled[start..].find('}')?)))
+    {
+        let names = filled[start + 2..end]
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .collect::<Vec<String>>();
+        let path = remove_parameters(&filled[..start]);
+        let separator = if path.contains('?') { '&' } else { '?' };
+        let query = names
+            .first()
+            .map(|name| format!("{}{}={}", separator, name, encoded))
+            .unwrap_or_default();
+        return format!("{}{}{}", path, query, remove_parameters(&filled[end + 1..]));
+    }
+
+    remove_parameters(&filled)
+}
+
+/// Leaves empty the optional parameters of OpenSearch, e.g. {startPage?}
+fn remove_parameters(template: &str) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = rest[start..]
+            .find('}')
+            .map_or("", |end| &rest[start + end + 1..]);
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Method that returns the url of the results of a search in a feed
+pub fn get_search_url(feed: &OpdsFeed, terms: &str) -> Result<String, Box<dyn error::Error>> {
+    let template = match feed
+        .search
+        .as_ref()
+        .ok_or("the catalog can't be searched")?
+    {
+        OpdsSearch::Template(template) => template.clone(),
+        OpdsSearch::Description(url) => parse_opensearch(url, &fetch_string(url)?)?,
+    };
+    Ok(fill_template(&template, terms))
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim_matches('_')
+        .to_string()
+}
+
+/// Method that downloads a book in a temporary folder,
+/// it is then added to the library like any other file
+pub fn download_book(entry: &OpdsEntry) -> Result<PathBuf, Box<dyn error::Error>> {
+    let (url, ext) = entry
+        .get_download()
+        .ok_or("the book has no format that can be read")?;
+
+    let response = agent().get(&url).call()?;
+
+    // the name proposed by the server is used if there is one
+    let name = response
+        .header("Content-Disposition")
+        .and_then(|header| header.split("filename=").nth(1))
+        .map(|name| {
+            name.trim_matches(|c| c == '"' || c == ';' || c == ' ')
+                .to_string()
+        })
+        .and_then(|name| {
+            Path::new(&name)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+        })
+        .unwrap_or(entry.title.clone());
+    let name = match sanitize_file_name(&name) {
+        name if name.is_empty() => "libro".to_string(),
+        name => name,
+    };
+
+    let folder = std::env::temp_dir().join("crab-reader-downloads");
+    std::fs::create_dir_all(&folder)?;
+    let path = folder.join(format!("{}.{}", name, ext));
+
+    let mut bytes = vec![];
+    response.into_reader().read_to_end(&mut bytes)?;
+    std::fs::write(&path, bytes)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    const FIXTURES: &str = "./test_opds_feeds";
+
+    /// Starts a local server that serves the fixture feeds,
+    /// returns its base url
+    fn start_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let _ = reader.read_line(&mut request_line);
+                // skip the headers of the request
+                let mut line = String::new();
+                while matches!(reader.read_line(&mut line), Ok(n) if n > 2) {
+                    line.clear();
+                }
+
+                let target = request_line.split(' ').nth(1).unwrap_or("/").to_string();
+                let (path, query) = target.split_once('?').unwrap_or((target.as_str(), ""));
+                let file = match (path, query) {
+                    ("/search", "q=divina+commedia") => "search.xml".to_string(),
+                    ("/download/1", _) => "book.epub".to_string(),
+                    (path, _) => path.trim_start_matches('/').to_string(),
+                };
+
+                let response = match std::fs::read(Path::new(FIXTURES).join(&file)) {
+                    Ok(body) => {
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n",
+                            body.len()
+                        )
+                        .into_bytes();
+                        if file == "book.epub" {
+                            response.extend_from_slice(
+                                b"Content-Disposition: attachment; filename=\"Divina Commedia.epub\"\r\n",
+                            );
+                        }
+                        response.extend_from_slice(b"\r\n");
+                        response.extend(body);
+                        response
+                    }
+                    Err(_) => {
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_vec()
+                    }
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+        base
+    }
+
+    #[test]
+    fn atom_navigation_feed_is_parsed() {
+        let base = start_server();
+        let feed = fetch_feed(&format!("{}/root.xml", base)).unwrap();
+
+        assert_eq!(feed.title, "Catalogo di prova");
+        assert_eq!(feed.entries.len(), 2);
+        assert_eq!(feed.entries[0].title, "Ultimi arrivi");
+        assert_eq!(
+            feed.entries[0].navigation,
+            Some(format!("{}/books.xml", base))
+        );
+        assert_eq!(
+            feed.search,
+            Some(OpdsSearch::Description(format!("{}/opensearch.xml", base)))
+        );
+    }
+
+    #[test]
+    fn atom_acquisition_feed_is_parsed() {
+        let base = start_server();
+        let feed = fetch_feed(&format!("{}/books.xml", base)).unwrap();
+
+        let book = &feed.entries[0];
+        assert_eq!(book.title, "Divina Commedia");
+        assert_eq!(book.author, "Dante Alighieri");
+        assert_eq!(book.cover, Some(format!("{}/covers/1-thumb.jpg", base)));
+        // EPUB is preferred to PDF
+        assert_eq!(
+            book.get_download(),
+            Some((format!("{}/download/1", base), "epub".to_string()))
+        );
+        assert_eq!(feed.next, Some(format!("{}/books-2.xml", base)));
+    }
+
+    #[test]
+    fn opensearch_is_used_to_search() {
+        let base = start_server();
+        let feed = fetch_feed(&format!("{}/root.xml", base)).unwrap();
+
+        let url = get_search_url(&feed, "divina commedia").unwrap();
+        assert_eq!(url, format!("{}/search?q=divina+commedia", base));
+
+        let results = fetch_feed(&url).unwrap();
+        assert_eq!(results.entries.len(), 1);
+        assert_eq!(results.entries[0].title, "Divina Commedia");
+    }
+
+    #[test]
+    fn json_feed_is_parsed() {
+        let base = start_server();
+        let feed = fetch_feed(&format!("{}/root.json", base)).unwrap();
+
+        assert_eq!(feed.title, "Catalogo JSON");
+        assert_eq!(feed.entries.len(), 3);
+        assert_eq!(
+            feed.entries[0].navigation,
+            Some(format!("{}/books.json", base))
+        );
+
+        let book = &feed.entries[1];
+        assert_eq!(book.title, "Il Principe");
+        assert_eq!(book.author, "Niccolò Machiavelli");
+        assert_eq!(book.cover, Some(format!("{}/covers/2-small.jpg", base)));
+        assert_eq!(
+            book.get_download(),
+            Some((format!("{}/download/2.epub", base), "epub".to_string()))
+        );
+        assert_eq!(feed.entries[2].title, "La coscienza di Zeno");
+
+        assert_eq!(
+            get_search_url(&feed, "zeno").unwrap(),
+            format!("{}/search?query=zeno", base)
+        );
+    }
+
+    #[test]
+    fn book_is_downloaded() {
+        let base = start_server();
+        let feed = fetch_feed(&format!("{}/books.xml", base)).unwrap();
+
+        let path = download_book(&feed.entries[0]).unwrap();
+        assert_eq!(path.file_name().unwrap(), "Divina_Commedia.epub");
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            std::fs::read(Path::new(FIXTURES).join("book.epub")).unwrap()
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn templates_are_filled() {
+        assert_eq!(
+            fill_template("http://x/s?q={searchTerms}&p={startPage?}", "a b"),
+            "http://x/s?q=a+b&p="
+        );
+        assert_eq!(
+            fill_template("http://x/s{?query,page}", "a"),
+            "http://x/s?query=a"
+        );
+        // braces before the query template
+        assert_eq!(
+            fill_template("http://x/search/{searchTerms}{?lang}", "a"),
+            "http://x/search/a?lang=a"
+        );
+        assert_eq!(
+            fill_template("http://x/{lang?}/search{?query}", "a"),
+            "http://x//search?query=a"
+        );
+    }
+}
@@ -0,0 +1,705 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use druid::{widget::Controller, Data, Env, UpdateCtx, Widget};
+use url::form_urlencoded;
+
+use crate::{
+    models::{
+        book::Book,
+        library::{matches_filter, Library},
+    },
+    traits::{
+        gui::{GUIBook, GUILibrary},
+        reader::BookManagement,
+    },
+    utils::{epub_utils, opds_client::SUPPORTED_TYPES},
+    CrabReaderState,
+};
+
+pub const OPDS_SERVER_PORT: u16 = 8080;
+/// Number of books shown in the feed of the recent books
+const RECENT_BOOKS: usize = 25;
+const ATOM_TYPE: &str = "application/atom+xml;charset=utf-8";
+const NAVIGATION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+const ACQUISITION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+
+/// Copy of the metadata of a book of the library,
+/// the server thread can't access the library directly
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CatalogBook {
+    /// name of the file without extension, it identifies the book
+    pub id: String,
+    pub title: String,
+    pub author: String,
+    pub lang: String,
+    pub description: String,
+    pub path: String,
+    pub favorite: bool,
+    /// seconds since the epoch of when the book was added to the library
+    pub added: u64,
+}
+
+impl From<&Book> for CatalogBook {
+    fn from(book: &Book) -> Self {
+        let path = book.get_path();
+        let added = book.get_reading_info().added;
+        Self {
+            id: Path::new(&path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            title: book.get_title(),
+            author: book.get_author(),
+            lang: book.get_lang().to_string(),
+            description: book.get_description().to_string(),
+            path,
+            favorite: book.is_favorite(),
+            added,
+        }
+    }
+}
+
+/// Returns the books of the library that can be shared
+pub fn catalog_of(library: &Library<Book>) -> Vec<CatalogBook> {
+    (0..library.number_of_books())
+        .filter_map(|idx| library.get_book(idx))
+        .map(CatalogBook::from)
+        .collect()
+}
+
+/// Response to a request of a device
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: String,
+    pub body: Vec<u8>,
+    /// name of the file for the downloads
+    pub file_name: Option<String>,
+}
+
+impl Response {
+    fn feed(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: ATOM_TYPE.to_string(),
+            body: body.into_bytes(),
+            file_name: None,
+        }
+    }
+
+    fn not_found() -> Self {
+        Self {
+            status: "404 Not Found",
+            content_type: "text/plain;charset=utf-8".to_string(),
+            body: b"Not found".to_vec(),
+            file_name: None,
+        }
+    }
+}
+
+/// OPDS catalog of the library served on the local network,
+/// it runs in background until it is stopped
+#[derive(Debug)]
+pub struct OpdsServer {
+    port: u16,
+    running: Arc<AtomicBool>,
+    catalog: Arc<Mutex<Vec<CatalogBook>>>,
+}
+
+impl OpdsServer {
+    /// Starts listening on all the interfaces at the given port,
+    /// 0 chooses a free one
+    pub fn start(port: u16) -> Result<Self, String> {
+        let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|e| e.to_string())?;
+        // the listener is polled, so that the thread sees when the server is stopped
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+
+        let server = Self {
+            port,
+            running: Arc::new(AtomicBool::new(true)),
+            catalog: Arc::new(Mutex::new(vec![])),
+        };
+
+        let running = server.running.clone();
+        let catalog = server.catalog.clone();
+        thread::spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let catalog = catalog.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = handle_connection(stream, &catalog) {
+                                println!("ERROR: OPDS server request failed: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    Err(e) => println!("ERROR: OPDS server connection failed: {}", e),
+                }
+            }
+        });
+
+        Ok(server)
+    }
+
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the url of the catalog that has to be set on the devices
+    pub fn get_address(&self) -> String {
+        format!("http://{}/opds", SocketAddr::new(get_local_ip(), self.port))
+    }
+
+    /// Replaces the books served by the catalog
+    pub fn set_catalog(&self, books: Vec<CatalogBook>) {
+        *self.catalog.lock().unwrap() = books;
+    }
+}
+
+/// Returns the address of this computer on the local network
+pub fn get_local_ip() -> IpAddr {
+    // connecting an UDP socket sends nothing, it only chooses the interface
+    UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| {
+            socket.connect("8.8.8.8:80")?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip())
+        .unwrap_or(IpAddr::from([127, 0, 0, 1]))
+}
+
+impl Drop for OpdsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Keeps the books of the catalog updated with the library while the server is running
+pub struct OpdsCatalogController;
+
+impl<W: Widget<CrabReaderState>> Controller<CrabReaderState, W> for OpdsCatalogController {
+    fn update(
+        &mut self,
+        child: &mut W,
+        ctx: &mut UpdateCtx,
+        old_data: &CrabReaderState,
+        data: &CrabReaderState,
+        env: &Env,
+    ) {
+        if let Some(server) = &data.opds_server {
+            if !old_data.opds_server.same(&data.opds_server)
+                || !old_data.library.same(&data.library)
+            {
+                server.set_catalog(catalog_of(&data.library));
+            }
+        }
+        child.update(ctx, old_data, data, env)
+    }
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    catalog: &Mutex<Vec<CatalogBook>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // the headers aren't needed
+    let mut line = String::new();
+    while matches!(reader.read_line(&mut line), Ok(n) if n > 2) {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+
+    let response = if method == "GET" || method == "HEAD" {
+        let books = catalog.lock().unwrap().clone();
+        handle_request(&books, target)
+    } else {
+        Response::not_found()
+    };
+
+    let mut headers = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    if let Some(file_name) = &response.file_name {
+        headers.push_str(&format!(
+            "Content-Disposition: attachment; filename=\"{}\"\r\n",
+            file_name.replace('"', "")
+        ));
+    }
+    headers.push_str("\r\n");
+
+    stream.write_all(headers.as_bytes())?;
+    if method != "HEAD" {
+        stream.write_all(&response.body)?;
+    }
+    stream.flush()?;
+    Ok(())
+}
+
+/// Builds the response for the requested path, e.g. "/opds/search?q=dante"
+pub fn handle_request(books: &[CatalogBook], target: &str) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let param = |name: &str| {
+        form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
+    let find_book = |id: Option<String>| id.and_then(|id| books.iter().find(|book| book.id == id));
+
+    match path.trim_end_matches('/') {
+        "" | "/opds" => Response::feed(root_feed(books)),
+        "/opds/books" => Response::feed(books_feed(
+            "/opds/books",
+            "Tutti i libri",
+            sorted_by_title(books.iter()),
+        )),
+        "/opds/recent" => {
+            let mut recent = books.iter().collect::<Vec<&CatalogBook>>();
+            recent.sort_by(|one, other| other.added.cmp(&one.added));
+            recent.truncate(RECENT_BOOKS);
+            Response::feed(books_feed("/opds/recent", "Aggiunti di recente", recent))
+        }
+        "/opds/favorites" => Response::feed(books_feed(
+            "/opds/favorites",
+            "Preferiti",
+            sorted_by_title(books.iter().filter(|book| book.favorite)),
+        )),
+        "/opds/authors" => Response::feed(authors_feed(books)),
+        "/opds/author" => {
+            let Some(name) = param("name") else {
+                return Response::not_found();
+            };
+            let self_href = format!("/opds/author?name={}", encode(&name));
+            Response::feed(books_feed(
+                &self_href,
+                &name,
+                sorted_by_title(books.iter().filter(|book| book.author == name)),
+            ))
+        }
+        "/opds/search" => {
+            let terms = param("q").unwrap_or_default();
+            let filter = terms.to_lowercase();
+            let self_href = format!("/opds/search?q={}", encode(&terms));
+            Response::feed(books_feed(
+                &self_href,
+                &format!("Risultati per \"{}\"", terms),
+                sorted_by_title(
+                    books
+                        .iter()
+                        .filter(|book| matches_filter(&filter, &book.title, &book.author)),
+                ),
+            ))
+        }
+        "/opds/opensearch.xml" => Response {
+            status: "200 OK",
+            content_type: "application/opensearchdescription+xml".to_string(),
+            body: opensearch_description().into_bytes(),
+            file_name: None,
+        },
+        "/opds/download" => {
+            let Some(book) = find_book(param("id")) else {
+                return Response::not_found();
+            };
+            let Ok(body) = std::fs::read(&book.path) else {
+                return Response::not_found();
+            };
+            Response {
+                status: "200 OK",
+                content_type: get_mime_type(&book.path).to_string(),
+                body,
+                file_name: Path::new(&book.path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string()),
+            }
+        }
+        "/opds/cover" => {
+            let Some(Ok(body)) =
+                find_book(param("id")).map(|book| epub_utils::get_cover_of_book(&book.path))
+            else {
+                return Response::not_found();
+            };
+            Response {
+                status: "200 OK",
+                content_type: get_image_type(&body).to_string(),
+                body,
+                file_name: None,
+            }
+        }
+        _ => Response::not_found(),
+    }
+}
+
+fn encode(value: &str) -> String {
+    form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn sorted_by_title<'a>(books: impl Iterator<Item = &'a CatalogBook>) -> Vec<&'a CatalogBook> {
+    let mut books = books.collect::<Vec<&CatalogBook>>();
+    books.sort_by(|one, other| one.title.cmp(&other.title));
+    books
+}
+
+fn get_mime_type(path: &str) -> &'static str {
+    let ext = Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    SUPPORTED_TYPES
+        .iter()
+        .find(|(_, supported)| *supported == ext)
+        .map_or("application/octet-stream", |(mime, _)| *mime)
+}
+
+fn get_image_type(image: &[u8]) -> &'static str {
+    if image.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if image.starts_with(b"GIF8") {
+        "image/gif"
+    } else {
+        "image/jpeg"
+    }
+}
+
+fn now() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    format_timestamp(secs)
+}
+
+/// Formats seconds since the epoch as an Atom date, e.g. 2023-01-31T10:00:00Z
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+    // civil date from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+fn feed(self_href: &str, title: &str, kind: &str, entries: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/terms/" xmlns:opds="http://opds-spec.org/2010/catalog">
+<id>urn:crab-reader:{id}</id>
+<title>{title}</title>
+<updated>{updated}</updated>
+<author><name>CrabReader</name></author>
+<link rel="self" href="{self_href}" type="{kind}"/>
+<link rel="start" href="/opds" type="{navigation}"/>
+<link rel="search" href="/opds/opensearch.xml" type="application/opensearchdescription+xml"/>
+{entries}</feed>
+"#,
+        id = escape_xml(self_href),
+        title = escape_xml(title),
+        updated = now(),
+        self_href = escape_xml(self_href),
+        kind = kind,
+        navigation = NAVIGATION_TYPE,
+        entries = entries
+    )
+}
+
+fn navigation_entry(href: &str, kind: &str, title: &str, content: &str) -> String {
+    format!(
+        r#"<entry>
+<id>urn:crab-reader:{id}</id>
+<title>{title}</title>
+<updated>{updated}</updated>
+<content type="text">{content}</content>
+<link rel="subsection" href="{href}" type="{kind}"/>
+</entry>
+"#,
+        id = escape_xml(href),
+        title = escape_xml(title),
+        updated = now(),
+        content = escape_xml(content),
+        href = escape_xml(href),
+        kind = kind
+    )
+}
+
+fn book_entry(book: &CatalogBook) -> String {
+    let id = encode(&book.id);
+    format!(
+        r#"<entry>
+<id>urn:crab-reader:book:{urn}</id>
+<title>{title}</title>
+<author><name>{author}</name></author>
+<updated>{updated}</updated>
+<dc:language>{lang}</dc:language>
+<summary type="text">{summary}</summary>
+<link rel="http://opds-spec.org/image" href="/opds/cover?id={id}"/>
+<link rel="http://opds-spec.org/image/thumbnail" href="/opds/cover?id={id}"/>
+<link rel="http://opds-spec.org/acquisition" href="/opds/download?id={id}" type="{mime}"/>
+</entry>
+"#,
+        urn = escape_xml(&book.id),
+        title = escape_xml(&book.title),
+        author = escape_xml(&book.author),
+        updated = format_timestamp(book.added),
+        lang = escape_xml(&book.lang),
+        summary = escape_xml(&book.description),
+        id = id,
+        mime = get_mime_type(&book.path)
+    )
+}
+
+fn root_feed(books: &[CatalogBook]) -> String {
+    let favorites = books.iter().filter(|book| book.favorite).count();
+    let entries = [
+        navigation_entry(
+            "/opds/books",
+            ACQUISITION_TYPE,
+            "Tutti i libri",
+            &format!("{} libri", books.len()),
+        ),
+        navigation_entry(
+            "/opds/authors",
+            NAVIGATION_TYPE,
+            "Per autore",
+            "Libri raggruppati per autore",
+        ),
+        navigation_entry(
+            "/opds/recent",
+            ACQUISITION_TYPE,
+            "Aggiunti di recente",
+            "Gli ultimi libri aggiunti alla libreria",
+        ),
+        navigation_entry(
+            "/opds/favorites",
+            ACQUISITION_TYPE,
+            "Preferiti",
+            &format!("{} libri", favorites),
+        ),
+    ]
+    .concat();
+    feed("/opds", "Libreria CrabReader", NAVIGATION_TYPE, &entries)
+}
+
+fn authors_feed(books: &[CatalogBook]) -> String {
+    let mut authors = BTreeMap::<&str, usize>::new();
+    for book in books {
+        *authors.entry(book.author.as_str()).or_default() += 1;
+    }
+    let entries = authors
+        .iter()
+        .map(|(author, count)| {
+            navigation_entry(
+                &format!("/opds/author?name={}", encode(author)),
+                ACQUISITION_TYPE,
+                author,
+                &format!("{} libri", count),
+            )
+        })
+        .collect::<String>();
+    feed("/opds/authors", "Per autore", NAVIGATION_TYPE, &entries)
+}
+
+fn books_feed(self_href: &str, title: &str, books: Vec<&CatalogBook>) -> String {
+    let entries = books.into_iter().map(book_entry).collect::<String>();
+    feed(self_href, title, ACQUISITION_TYPE, &entries)
+}
+
+fn opensearch_description() -> String {
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+<ShortName>CrabReader</ShortName>
+<Description>Cerca nella libreria CrabReader</Description>
+<InputEncoding>UTF-8</InputEncoding>
+<OutputEncoding>UTF-8</OutputEncoding>
+<Url type="application/atom+xml;profile=opds-catalog;kind=acquisition" template="/opds/search?q={searchTerms}"/>
+</OpenSearchDescription>
+"#
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::utils::opds_client::{self, OpdsSearch};
+
+    fn test_books() -> Vec<CatalogBook> {
+        vec![
+            CatalogBook {
+                id: "divina-commedia".to_string(),
+                title: "La Divina Commedia".to_string(),
+                author: "Dante Alighieri".to_string(),
+                lang: "it".to_string(),
+                description: "Viaggio nei tre regni & oltre".to_string(),
+                path: "./test_opds_feeds/book.epub".to_string(),
+                favorite: true,
+                added: 1_600_000_000,
+            },
+            CatalogBook {
+                id: "promessi-sposi".to_string(),
+                title: "I Promessi Sposi".to_string(),
+                author: "Alessandro Manzoni".to_string(),
+                lang: "it".to_string(),
+                description: String::new(),
+                path: "./missing/promessi-sposi.epub".to_string(),
+                favorite: false,
+                added: 1_700_000_000,
+            },
+        ]
+    }
+
+    fn get_feed(target: &str) -> opds_client::OpdsFeed {
+        let response = handle_request(&test_books(), target);
+        assert_eq!(response.status, "200 OK");
+        let body = String::from_utf8(response.body).unwrap();
+        opds_client::parse_feed(&format!("http://localhost{}", target), &body).unwrap()
+    }
+
+    #[test]
+    fn test_root_feed() {
+        let feed = get_feed("/opds");
+        assert_eq!(feed.title, "Libreria CrabReader");
+        assert_eq!(feed.entries.len(), 4);
+        assert!(feed.entries.iter().all(|entry| entry.navigation.is_some()));
+        assert_eq!(
+            feed.search,
+            Some(OpdsSearch::Description(
+                "http://localhost/opds/opensearch.xml".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_books_feeds() {
+        let all = get_feed("/opds/books");
+        assert_eq!(all.entries.len(), 2);
+        assert_eq!(all.entries[0].title, "I Promessi Sposi");
+        assert_eq!(
+            all.entries[1].get_download(),
+            Some((
+                "http://localhost/opds/download?id=divina-commedia".to_string(),
+                "epub".to_string()
+            ))
+        );
+        assert_eq!(all.entries[1].summary, "Viaggio nei tre regni & oltre");
+
+        let recent = get_feed("/opds/recent");
+        assert_eq!(recent.entries[0].title, "I Promessi Sposi");
+
+        let favorites = get_feed("/opds/favorites");
+        assert_eq!(favorites.entries.len(), 1);
+        assert_eq!(favorites.entries[0].author, "Dante Alighieri");
+    }
+
+    #[test]
+    fn test_authors_feed() {
+        let authors = get_feed("/opds/authors");
+        assert_eq!(authors.entries.len(), 2);
+        assert_eq!(authors.entries[0].title, "Alessandro Manzoni");
+
+        let url = authors.entries[1].navigation.clone().unwrap();
+        let target = url.trim_start_matches("http://localhost");
+        let books = get_feed(target);
+        assert_eq!(books.entries.len(), 1);
+        assert_eq!(books.entries[0].title, "La Divina Commedia");
+    }
+
+    #[test]
+    fn test_search() {
+        let feed = get_feed("/opds/search?q=Commedia");
+        assert_eq!(feed.entries.len(), 1);
+        assert_eq!(feed.entries[0].title, "La Divina Commedia");
+
+        let feed = get_feed("/opds/search?q=zzzzzzzz");
+        assert!(feed.entries.is_empty());
+    }
+
+    #[test]
+    fn test_download() {
+        let response = handle_request(&test_books(), "/opds/download?id=divina-commedia");
+        assert_eq!(response.content_type, "application/epub+zip");
+        assert_eq!(response.file_name, Some("book.epub".to_string()));
+        assert_eq!(
+            response.body,
+            std::fs::read("./test_opds_feeds/book.epub").unwrap()
+        );
+
+        let missing = handle_request(&test_books(), "/opds/download?id=promessi-sposi");
+        assert_eq!(missing, Response::not_found());
+        let unknown = handle_request(&test_books(), "/opds/unknown");
+        assert_eq!(unknown, Response::not_found());
+    }
+
+    #[test]
+    fn test_server() {
+        let server = OpdsServer::start(0).unwrap();
+        server.set_catalog(test_books());
+
+        let mut stream = TcpStream::connect(("127.0.0.1", server.port())).unwrap();
+        stream
+            .write_all(b"GET /opds/favorites HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("La Divina Commedia"));
+        assert!(!response.contains("I Promessi Sposi"));
+
+        server.stop();
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(1_600_000_000), "2020-09-13T12:26:40Z");
+    }
+}
@@ -0,0 +1,614 @@
+use std::{
+    collections::HashMap,
+    error,
+    fs::File,
+    io::{Cursor, Read, Write},
+    path::Path,
+};
+
+use roxmltree::{Document, Node};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::models::{
+    metadata::{Author, BookMetadata},
+    series::{series_from_epub, Series},
+};
+
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+const OPF_NS: &str = "http://www.idpf.org/2007/opf";
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+/// Metadata of the Dublin Core that are replaced when the book is edited
+const EDITED_DC: [&str; 7] = [
+    "title",
+    "creator",
+    "language",
+    "description",
+    "publisher",
+    "date",
+    "subject",
+];
+/// Id and name of the cover added to the books without one
+const NEW_COVER_ID: &str = "crab-cover";
+const NEW_COVER_HREF: &str = "crab-cover.png";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn find_element<'a, 'input>(doc: &'a Document<'input>, name: &str) -> Option<Node<'a, 'input>> {
+    doc.descendants()
+        .find(|node| node.is_element() && node.tag_name().name() == name)
+}
+
+/// Path of the OPF inside the EPUB, read from its container
+pub fn get_opf_path(container: &str) -> Result<String, String> {
+    let doc = Document::parse(container).map_err(|e| e.to_string())?;
+    find_element(&doc, "rootfile")
+        .and_then(|node| node.attribute("full-path"))
+        .map(|path| path.to_string())
+        .ok_or("the container doesn't point to the OPF".into())
+}
+
+/// Properties of the meta elements that refine another element, by the id of the refined one
+fn get_refines<'a>(metadata: Node<'a, '_>) -> HashMap<&'a str, Vec<(&'a str, String)>> {
+    let mut refines: HashMap<&str, Vec<(&str, String)>> = HashMap::new();
+    for meta in metadata.children().filter(|node| node.is_element()) {
+        if let (Some(id), Some(property)) = (meta.attribute("refines"), meta.attribute("property"))
+        {
+            refines
+                .entry(id.trim_start_matches('#'))
+                .or_default()
+                .push((property, meta.text().unwrap_or_default().trim().to_string()));
+        }
+    }
+    refines
+}
+
+/// Reads the metadata of an OPF, both EPUB2 and EPUB3
+pub fn parse_metadata(opf: &str) -> Result<BookMetadata, String> {
+    let doc = Document::parse(opf).map_err(|e| e.to_string())?;
+    let metadata = find_element(&doc, "metadata").ok_or("the OPF has no metadata")?;
+    let refines = get_refines(metadata);
+    let refined = |node: Node, property: &str| {
+        let id = node.attribute("id")?;
+        refines
+            .get(id)?
+            .iter()
+            .find(|(p, _)| *p == property)
+            .map(|(_, value)| value.clone())
+    };
+
+    let mut book = BookMetadata::default();
+    let mut metas = HashMap::new();
+    for node in metadata.children().filter(|node| node.is_element()) {
+        let text = node.text().unwrap_or_default().trim().to_string();
+        if node.tag_name().namespace() == Some(DC_NS) {
+            match node.tag_name().name() {
+                "title" if book.title.is_empty() => book.title = text,
+                "creator" if !text.is_empty() => {
+                    let file_as = node
+                        .attribute((OPF_NS, "file-as"))
+                        .map(|x| x.to_string())
+                        .or_else(|| refined(node, "file-as"))
+                        .unwrap_or_default();
+                    let role = node
+                        .attribute((OPF_NS, "role"))
+                        .map(|x| x.to_string())
+                        .or_else(|| refined(node, "role"))
+                        .unwrap_or_default();
+                    book.authors
+                        .push(Author::new(text, file_as).with_role(&role));
+                }
+                "language" if book.lang.is_empty() => book.lang = text,
+                "description" if book.description.is_empty() => book.description = text,
+                "publisher" if book.publisher.is_empty() => book.publisher = text,
+                "date" if book.date.is_empty() => book.date = text,
+                "subject" if !text.is_empty() => book.subjects.push(text),
+                _ => {}
+            }
+        } else if node.tag_name().name() == "meta" {
+            if let (Some(name), Some(content)) = (node.attribute("name"), node.attribute("content"))
+            {
+                metas.entry(name.to_string()).or_insert(content.to_string());
+            } else if node.attribute("property") == Some("belongs-to-collection") {
+                metas.entry("belongs-to-collection".into()).or_insert(text);
+                if let Some(position) = refined(node, "group-position") {
+                    metas.entry("group-position".into()).or_insert(position);
+                }
+            }
+        }
+    }
+    book.series = series_from_epub(|key| metas.get(key).cloned());
+    Ok(book)
+}
+
+/// Returns true if the element is replaced when the metadata are edited
+fn is_edited(node: Node) -> bool {
+    if node.tag_name().namespace() == Some(DC_NS) {
+        return EDITED_DC.contains(&node.tag_name().name());
+    }
+    node.tag_name().name() == "meta"
+        && (matches!(
+            node.attribute("name"),
+            Some("calibre:series") | Some("calibre:series_index")
+        ) || node.attribute("property") == Some("belongs-to-collection"))
+}
+
+/// Elements of the metadata written in an OPF
+struct OpfWriter {
+    epub3: bool,
+    dc: String,
+    /// declaration added to the elements when the OPF doesn't declare the namespace
+    dc_decl: String,
+    opf: String,
+    opf_decl: String,
+    elements: Vec<String>,
+}
+
+impl OpfWriter {
+    fn new(metadata: Node, epub3: bool) -> Self {
+        let prefix = |ns: &str, default: &str| match metadata.lookup_prefix(ns) {
+            Some(prefix) if !prefix.is_empty() => (prefix.to_string(), String::new()),
+            _ => (
+                default.to_string(),
+                format!(" xmlns:{}=\"{}\"", default, ns),
+            ),
+        };
+        let (dc, dc_decl) = prefix(DC_NS, "dc");
+        let (opf, opf_decl) = prefix(OPF_NS, "opf");
+        Self {
+            epub3,
+            dc,
+            dc_decl,
+            opf,
+            opf_decl,
+            elements: vec![],
+        }
+    }
+
+    fn dc(&mut self, name: &str, attributes: &str, text: &str) {
+        if text.trim().is_empty() {
+            return;
+        }
+        self.elements.push(format!(
+            "<{dc}:{name}{decl}{attributes}>{text}</{dc}:{name}>",
+            dc = self.dc,
+            decl = self.dc_decl,
+            text = escape(text),
+        ));
+    }
+
+    fn meta(&mut self, name: &str, content: &str) {
+        self.elements.push(format!(
+            "<meta name=\"{}\" content=\"{}\"/>",
+            name,
+            escape(content)
+        ));
+    }
+
+    fn refine(&mut self, id: &str, property: &str, value: &str) {
+        self.elements.push(format!(
+            "<meta refines=\"#{}\" property=\"{}\">{}</meta>",
+            id,
+            property,
+            escape(value)
+        ));
+    }
+
+    fn author(&mut self, idx: usize, author: &Author) {
+        if self.epub3 {
+            let id = format!("crab-creator-{}", idx);
+            self.dc("creator", &format!(" id=\"{}\"", id), &author.name);
+            if !author.file_as.is_empty() {
+                self.refine(&id, "file-as", &author.file_as);
+            }
+            self.elements.push(format!(
+                "<meta refines=\"#{}\" property=\"role\" scheme=\"marc:relators\">{}</meta>",
+                id,
+                escape(&author.role)
+            ));
+        } else {
+            let mut attributes = format!(
+                "{} {}:role=\"{}\"",
+                self.opf_decl,
+                self.opf,
+                escape(&author.role)
+            );
+            if !author.file_as.is_empty() {
+                attributes.push_str(&format!(
+                    " {}:file-as=\"{}\"",
+                    self.opf,
+                    escape(&author.file_as)
+                ));
+            }
+            self.dc("creator", &attributes, &author.name);
+        }
+    }
+
+    fn series(&mut self, series: &Series) {
+        if self.epub3 {
+            self.elements.push(format!(
+                "<meta property=\"belongs-to-collection\" id=\"crab-series\">{}</meta>",
+                escape(&series.name)
+            ));
+            self.refine("crab-series", "collection-type", "series");
+            if series.index.is_some() {
+                self.refine("crab-series", "group-position", &series.format_index());
+            }
+        }
+        // Calibre and most of the readers know only these ones
+        self.meta("calibre:series", &series.name);
+        if series.index.is_some() {
+            self.meta("calibre:series_index", &series.format_index());
+        }
+    }
+}
+
+/// Replaces in the OPF the metadata that can be edited, the other ones
+/// (e.g. the identifiers and the cover) are kept as they are
+pub fn update_metadata(opf: &str, book: &BookMetadata) -> Result<String, String> {
+    let doc = Document::parse(opf).map_err(|e| e.to_string())?;
+    let metadata = find_element(&doc, "metadata").ok_or("the OPF has no metadata")?;
+    let epub3 = doc
+        .root_element()
+        .attribute("version")
+        .is_some_and(|version| version.starts_with('3'));
+
+    let range = metadata.range();
+    let element = &opf[range.clone()];
+    if element.ends_with("/>") {
+        return Err("the metadata of the OPF are empty".into());
+    }
+    let inner_start = range.start + element.find('>').ok_or("broken metadata")? + 1;
+    let inner_end = range.start + element.rfind("</").ok_or("broken metadata")?;
+
+    // the properties of the replaced elements are replaced too
+    let removed_ids = metadata
+        .children()
+        .filter(|node| node.is_element() && is_edited(*node))
+        .filter_map(|node| node.attribute("id"))
+        .collect::<Vec<_>>();
+    let kept = metadata.children().filter(|node| {
+        (node.is_element() || node.is_comment())
+            && !is_edited(*node)
+            && !node
+                .attribute("refines")
+                .is_some_and(|id| removed_ids.contains(&id.trim_start_matches('#')))
+    });
+
+    let mut writer = OpfWriter::new(metadata, epub3);
+    writer.dc("title", "", &book.title);
+    for (idx, author) in book.authors.iter().enumerate() {
+        writer.author(idx, author);
+    }
+    writer.dc("language", "", &book.lang);
+    writer.dc("description", "", &book.description);
+    writer.dc("publisher", "", &book.publisher);
+    writer.dc("date", "", &book.date);
+    for subject in &book.subjects {
+        writer.dc("subject", "", subject);
+    }
+    if let Some(series) = &book.series {
+        writer.series(series);
+    }
+
+    let mut inner = String::new();
+    for element in writer
+        .elements
+        .iter()
+        .map(|element| element.as_str())
+        .chain(kept.map(|node| &opf[node.range()]))
+    {
+        inner.push_str("\n    ");
+        inner.push_str(element);
+    }
+    inner.push_str("\n  ");
+
+    Ok(format!(
+        "{}{}{}",
+        &opf[..inner_start],
+        inner,
+        &opf[inner_end..]
+    ))
+}
+
+/// Href of the cover image, relative to the OPF, if the book declares one
+pub fn get_cover_href(opf: &str) -> Option<String> {
+    let doc = Document::parse(opf).ok()?;
+    let items = find_element(&doc, "manifest")?
+        .children()
+        .filter(|node| node.is_element() && node.tag_name().name() == "item")
+        .collect::<Vec<_>>();
+    let cover_id = find_element(&doc, "metadata")?
+        .children()
+        .find(|node| node.attribute("name") == Some("cover"))
+        .and_then(|node| node.attribute("content"));
+
+    items
+        .iter()
+        .find(|item| {
+            item.attribute("properties")
+                .is_some_and(|properties| properties.split_whitespace().any(|p| p == "cover-image"))
+        })
+        .or_else(|| items.iter().find(|item| item.attribute("id") == cover_id))
+        .and_then(|item| item.attribute("href"))
+        .map(|href| href.to_string())
+}
+
+/// Declares a new cover image in an OPF that doesn't have one
+fn add_cover_item(opf: &str) -> Result<String, String> {
+    let doc = Document::parse(opf).map_err(|e| e.to_string())?;
+    let epub3 = doc
+        .root_element()
+        .attribute("version")
+        .is_some_and(|version| version.starts_with('3'));
+    let closing_tag = |name: &str| -> Result<usize, String> {
+        let range = find_element(&doc, name)
+            .ok_or(format!("the OPF has no {}", name))?
+            .range();
+        opf[range.clone()]
+            .rfind("</")
+            .map(|pos| range.start + pos)
+            .ok_or(format!("the {} of the OPF is empty", name))
+    };
+    let manifest_end = closing_tag("manifest")?;
+    let metadata_end = closing_tag("metadata")?;
+
+    let item = format!(
+        "  <item id=\"{}\" href=\"{}\" media-type=\"image/png\"{}/>\n  ",
+        NEW_COVER_ID,
+        NEW_COVER_HREF,
+        if epub3 {
+            " properties=\"cover-image\""
+        } else {
+            ""
+        }
+    );
+    let meta = format!("  <meta name=\"cover\" content=\"{}\"/>\n  ", NEW_COVER_ID);
+
+    // the metadata come before the manifest
+    let mut opf = opf.to_string();
+    opf.insert_str(manifest_end, &item);
+    opf.insert_str(metadata_end, &meta);
+    Ok(opf)
+}
+
+/// Path inside the EPUB of a file referenced by the OPF
+fn resolve_href(opf_path: &str, href: &str) -> String {
+    let mut parts = opf_path.split('/').collect::<Vec<_>>();
+    parts.pop();
+    for part in href.split('/') {
+        match part {
+            ".." => {
+                parts.pop();
+            }
+            "." | "" => {}
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Converts the new cover to the format of the one it replaces
+fn convert_cover(image: &[u8], href: &str) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let ext = Path::new(href)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let format = match ext.as_str() {
+        "jpg" | "jpeg" => image::ImageOutputFormat::Jpeg(90),
+        "png" => image::ImageOutputFormat::Png,
+        _ => return Ok(image.to_vec()),
+    };
+    let mut converted = Cursor::new(vec![]);
+    image::load_from_memory(image)?.write_to(&mut converted, format)?;
+    Ok(converted.into_inner())
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<String, Box<dyn error::Error>> {
+    let mut text = String::new();
+    archive.by_name(name)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Reads the metadata from the OPF of an EPUB
+pub fn read_metadata(epub_path: &str) -> Result<BookMetadata, Box<dyn error::Error>> {
+    let mut archive = ZipArchive::new(File::open(epub_path)?)?;
+    let opf_path = get_opf_path(&read_entry(&mut archive, CONTAINER_PATH)?)?;
+    Ok(parse_metadata(&read_entry(&mut archive, &opf_path)?)?)
+}
+
+/// Writes the metadata in the OPF of an EPUB and, if given, a new cover,
+/// so that the other readers see them too. The other files are copied as they are
+pub fn write_to_epub(
+    epub_path: &str,
+    book: &BookMetadata,
+    cover: Option<&[u8]>,
+) -> Result<(), Box<dyn error::Error>> {
+    let mut archive = ZipArchive::new(File::open(epub_path)?)?;
+    let opf_path = get_opf_path(&read_entry(&mut archive, CONTAINER_PATH)?)?;
+    let mut opf = update_metadata(&read_entry(&mut archive, &opf_path)?, book)?;
+
+    let mut cover_entry = None;
+    if let Some(cover) = cover {
+        let href = match get_cover_href(&opf) {
+            Some(href) => href,
+            None => {
+                opf = add_cover_item(&opf)?;
+                NEW_COVER_HREF.to_string()
+            }
+        };
+        cover_entry = Some((resolve_href(&opf_path, &href), convert_cover(cover, &href)?));
+    }
+
+    // the new EPUB replaces the old one only when it is complete
+    let tmp_path = format!("{}.tmp", epub_path);
+    let mut writer = ZipWriter::new(File::create(&tmp_path)?);
+    for idx in 0..archive.len() {
+        let entry = archive.by_index_raw(idx)?;
+        let replaced = entry.name() == opf_path
+            || cover_entry
+                .as_ref()
+                .is_some_and(|(name, _)| entry.name() == name.as_str());
+        if !replaced {
+            writer.raw_copy_file(entry)?;
+        }
+    }
+    writer.start_file(
+        opf_path,
+        FileOptions::default().compression_method(CompressionMethod::Deflated),
+    )?;
+    writer.write_all(opf.as_bytes())?;
+    if let Some((name, image)) = cover_entry {
+        writer.start_file(
+            name,
+            FileOptions::default().compression_method(CompressionMethod::Stored),
+        )?;
+        writer.write_all(&image)?;
+    }
+    writer.finish()?;
+    drop(archive);
+
+    std::fs::rename(&tmp_path, epub_path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPF2: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:title>La coscienza di Zeno</dc:title>
+    <dc:creator opf:role="aut" opf:file-as="Svevo, Italo">Italo Svevo</dc:creator>
+    <dc:language>it</dc:language>
+    <dc:identifier id="id">http://www.gutenberg.org/ebooks/12345</dc:identifier>
+    <dc:subject>Romanzo</dc:subject>
+    <meta name="cover" content="cover-img"/>
+    <meta name="calibre:series" content="Classici"/>
+    <meta name="calibre:series_index" content="1.0"/>
+  </metadata>
+  <manifest>
+    <item id="cover-img" href="images/cover.jpg" media-type="image/jpeg"/>
+  </manifest>
+</package>"#;
+
+    const OPF3: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">urn:uuid:1234</dc:identifier>
+    <dc:title id="t1">Dune</dc:title>
+    <meta refines="#t1" property="title-type">main</meta>
+    <dc:creator id="c1">Frank Herbert</dc:creator>
+    <meta refines="#c1" property="file-as">Herbert, Frank</meta>
+    <dc:creator id="c2">Giampaolo Cossato</dc:creator>
+    <meta refines="#c2" property="role" scheme="marc:relators">trl</meta>
+    <meta property="belongs-to-collection" id="s1">Dune</meta>
+    <meta refines="#s1" property="group-position">1</meta>
+    <meta property="dcterms:modified">2020-01-01T00:00:00Z</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+  </manifest>
+</package>"##;
+
+    #[test]
+    fn test_parse_metadata() {
+        let book = parse_metadata(OPF2).unwrap();
+        assert_eq!(book.title, "La coscienza di Zeno");
+        assert_eq!(
+            book.authors,
+            vec![Author::new("Italo Svevo", "Svevo, Italo")]
+        );
+        assert_eq!(book.subjects, vec!["Romanzo"]);
+        assert_eq!(book.series.unwrap().label(), "Classici #1");
+
+        let book = parse_metadata(OPF3).unwrap();
+        assert_eq!(
+            book.authors,
+            vec![
+                Author::new("Frank Herbert", "Herbert, Frank"),
+                Author::new("Giampaolo Cossato", "").with_role("trl"),
+            ]
+        );
+        assert_eq!(book.series.unwrap().label(), "Dune #1");
+        assert_eq!(get_cover_href(OPF2), Some("images/cover.jpg".to_string()));
+        assert_eq!(get_cover_href(OPF3), None);
+    }
+
+    #[test]
+    fn test_update_metadata() {
+        for opf in [OPF2, OPF3] {
+            let mut book = parse_metadata(opf).unwrap();
+            book.title = "Titolo & sottotitolo".to_string();
+            book.authors
+                .push(Author::new("Mario Rossi", "Rossi, Mario").with_role("ill"));
+            book.description = "Una <b>storia</b>".to_string();
+            book.publisher = "Crab".to_string();
+            book.series = Series::parse("Saga", "2.5").unwrap();
+
+            let updated = update_metadata(opf, &book).unwrap();
+            assert_eq!(parse_metadata(&updated).unwrap(), book);
+            // the metadata that can't be edited are kept
+            assert!(updated.contains("<dc:identifier id=\"id\">"));
+            assert!(updated.contains("</manifest>"));
+            // and the old properties of the replaced ones are removed
+            assert!(!updated.contains("title-type"));
+            assert_eq!(updated.matches("file-as").count(), 2);
+        }
+        let updated = update_metadata(OPF3, &parse_metadata(OPF3).unwrap()).unwrap();
+        assert!(updated.contains("dcterms:modified"));
+    }
+
+    #[test]
+    fn test_write_to_epub() {
+        let path = std::env::temp_dir().join(format!("crab_opf_{}.epub", std::process::id()));
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("mimetype", stored).unwrap();
+        zip.write_all(b"application/epub+zip").unwrap();
+        zip.start_file(CONTAINER_PATH, FileOptions::default())
+            .unwrap();
+        zip.write_all(
+            br#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#,
+        )
+        .unwrap();
+        zip.start_file("OEBPS/content.opf", FileOptions::default())
+            .unwrap();
+        zip.write_all(OPF3.as_bytes()).unwrap();
+        zip.start_file("OEBPS/nav.xhtml", FileOptions::default())
+            .unwrap();
+        zip.write_all(b"<html/>").unwrap();
+        zip.finish().unwrap();
+
+        let path = path.to_str().unwrap();
+        let mut book = parse_metadata(OPF3).unwrap();
+        book.lang = "en".to_string();
+        write_to_epub(path, &book, None).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        assert_eq!(
+            read_entry(&mut archive, "OEBPS/nav.xhtml").unwrap(),
+            "<html/>"
+        );
+        let opf = read_entry(&mut archive, "OEBPS/content.opf").unwrap();
+        assert_eq!(parse_metadata(&opf).unwrap().lang, "en");
+
+        let with_cover = add_cover_item(&opf).unwrap();
+        assert_eq!(
+            get_cover_href(&with_cover),
+            Some(NEW_COVER_HREF.to_string())
+        );
+        assert_eq!(
+            resolve_href("OEBPS/content.opf", "../images/cover.jpg"),
+            "images/cover.jpg"
+        );
+
+        let _ = std::fs::remove_file(path);
+    }
+}
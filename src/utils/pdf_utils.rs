@@ -0,0 +1,486 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error,
+    fs::File,
+    io::Write,
+    ops::Range,
+    path::Path,
+};
+
+use lopdf::{Document, Object, ObjectId};
+use serde_json::json;
+
+use super::{
+    dir_manager::{get_metadata_path, get_saved_books_dir, get_saved_covers_dir},
+    epub_utils::save_book_cover,
+};
+
+/// Number of pages grouped in a chapter when the PDF has no outline
+const PAGES_PER_CHAPTER: usize = 10;
+
+/// Returns true if the path points to a PDF file
+pub fn is_pdf(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+/// Follows a reference to the object it points to
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    match object {
+        Object::Reference(id) => doc.get_object(*id).ok(),
+        _ => Some(object),
+    }
+}
+
+fn get_dict_entry<'a>(
+    doc: &'a Document,
+    dict: &'a lopdf::Dictionary,
+    key: &[u8],
+) -> Option<&'a Object> {
+    dict.get(key).ok().and_then(|object| resolve(doc, object))
+}
+
+/// Decodes a PDF text string, which is either UTF-16BE with BOM or PDFDocEncoding
+fn decode_pdf_string(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        let units = bytes[2..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect::<Vec<u16>>();
+        return String::from_utf16_lossy(&units);
+    }
+    // PDFDocEncoding matches Latin-1 for the printable characters
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn get_string_entry(doc: &Document, dict: &lopdf::Dictionary, key: &[u8]) -> Option<String> {
+    let text = get_dict_entry(doc, dict, key)?.as_str().ok()?;
+    let text = decode_pdf_string(text).trim().to_string();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Returns the title and the first page (starting from 0)
+/// of the top level entries of the outline
+fn get_outline(doc: &Document) -> Vec<(String, usize)> {
+    let page_numbers: HashMap<ObjectId, usize> = doc
+        .get_pages()
+        .into_iter()
+        .map(|(number, id)| (id, number as usize - 1))
+        .collect();
+
+    let Some(outlines) = doc
+        .trailer
+        .get(b"Root")
+        .ok()
+        .and_then(|root| resolve(doc, root))
+        .and_then(|root| root.as_dict().ok())
+        .and_then(|root| get_dict_entry(doc, root, b"Outlines"))
+        .and_then(|outlines| outlines.as_dict().ok())
+    else {
+        return vec![];
+    };
+
+    let mut outline = vec![];
+    let mut visited = HashSet::new();
+    let mut item = outlines
+        .get(b"First")
+        .ok()
+        .and_then(|first| first.as_reference().ok());
+
+    // malformed files can have loops in the list of entries
+    while let Some(id) = item.filter(|id| visited.insert(*id)) {
+        let Ok(dict) = doc.get_object(id).and_then(|object| object.as_dict()) else {
+            break;
+        };
+
+        // the destination is either in the entry or in its GoTo action
+        let dest = get_dict_entry(doc, dict, b"Dest").or_else(|| {
+            get_dict_entry(doc, dict, b"A")
+                .and_then(|action| action.as_dict().ok())
+                .and_then(|action| get_dict_entry(doc, action, b"D"))
+        });
+        let page = dest
+            .and_then(|dest| dest.as_array().ok())
+            .and_then(|dest| dest.first())
+            .and_then(|page| page.as_reference().ok())
+            .and_then(|page| page_numbers.get(&page));
+
+        if let Some(&page) = page {
+            let title = get_string_entry(doc, dict, b"Title").unwrap_or_default();
+            outline.push((title, page));
+        }
+
+        item = dict
+            .get(b"Next")
+            .ok()
+            .and_then(|next| next.as_reference().ok());
+    }
+    outline
+}
+
+/// Splits the pages of the book in chapters,
+/// following the outline if there is one or in groups of fixed size
+fn group_pages(
+    number_of_pages: usize,
+    outline: &[(String, usize)],
+) -> Vec<(Option<String>, Range<usize>)> {
+    let mut starts = outline
+        .iter()
+        .filter(|(_, page)| *page < number_of_pages)
+        .cloned()
+        .collect::<Vec<(String, usize)>>();
+    starts.sort_by_key(|(_, page)| *page);
+    starts.dedup_by_key(|(_, page)| *page);
+
+    if starts.is_empty() {
+        return (0..number_of_pages)
+            .step_by(PAGES_PER_CHAPTER)
+            .map(|start| {
+                (
+                    None,
+                    start..(start + PAGES_PER_CHAPTER).min(number_of_pages),
+                )
+            })
+            .collect();
+    }
+
+    let mut chapters = vec![];
+    // pages before the first entry, e.g. the front matter
+    if starts[0].1 > 0 {
+        chapters.push((None, 0..starts[0].1));
+    }
+    for (i, (title, start)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(number_of_pages, |(_, next)| *next);
+        let title = Some(title.clone()).filter(|title| !title.is_empty());
+        chapters.push((title, *start..end));
+    }
+    chapters
+}
+
+/// Joins the lines of the text layer of a page in paragraphs
+fn reflow_text(text: &str) -> Vec<String> {
+    let lines = text
+        .lines()
+        .map(str::trim)
+        // lines made only of digits are page numbers
+        .filter(|line| !line.chars().all(|c| c.is_ascii_digit()) || line.is_empty())
+        .collect::<Vec<&str>>();
+
+    let filled = lines.iter().filter(|line| !line.is_empty());
+    let average_length = filled
+        .clone()
+        .map(|line| line.chars().count())
+        .sum::<usize>() as f64
+        / filled.count().max(1) as f64;
+
+    let mut paragraphs = vec![];
+    let mut current = String::new();
+    for line in lines {
+        if line.is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+
+        let starts_lowercase = line.chars().next().is_some_and(char::is_lowercase);
+        if current.ends_with('-') && starts_lowercase {
+            // word split at the end of the line
+            current.pop();
+        } else if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(line);
+
+        // a short line closed by a punctuation mark ends the paragraph
+        let ends_sentence = line.ends_with(['.', '!', '?', ':', '"', '»', '”']);
+        if ends_sentence && (line.chars().count() as f64) < average_length * 0.8 {
+            paragraphs.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+    paragraphs
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn build_chapter(title: Option<&String>, paragraphs: &[String]) -> String {
+    let mut html = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<html><body>");
+    if let Some(title) = title {
+        html.push_str(&format!("<h1>{}</h1>", escape_html(title)));
+    }
+    for paragraph in paragraphs {
+        html.push_str(&format!("<p>{}</p>", escape_html(paragraph)));
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+/// Returns the biggest JPEG image drawn in a page,
+/// that for scanned books is the page itself
+fn get_page_image(doc: &Document, page_id: ObjectId) -> Option<Vec<u8>> {
+    // resources can be inherited from the parent nodes of the page tree,
+    // malformed files can have loops in it
+    let mut visited = HashSet::new();
+    let mut node = Some(page_id);
+    let mut xobjects = None;
+    while let Some(id) = node.filter(|id| visited.insert(*id)) {
+        let Ok(dict) = doc.get_object(id).and_then(|object| object.as_dict()) else {
+            break;
+        };
+        xobjects = get_dict_entry(doc, dict, b"Resources")
+            .and_then(|resources| resources.as_dict().ok())
+            .and_then(|resources| get_dict_entry(doc, resources, b"XObject"))
+            .and_then(|xobjects| xobjects.as_dict().ok());
+        if xobjects.is_some() {
+            break;
+        }
+        node = dict
+            .get(b"Parent")
+            .and_then(|parent| parent.as_reference())
+            .ok();
+    }
+
+    xobjects?
+        .iter()
+        .filter_map(|(_, object)| resolve(doc, object)?.as_stream().ok())
+        .filter(|stream| {
+            let is_image = stream
+                .dict
+                .get(b"Subtype")
+                .and_then(|subtype| subtype.as_name())
+                .is_ok_and(|subtype| subtype == b"Image");
+            let is_jpeg = match stream.dict.get(b"Filter") {
+                Ok(Object::Name(name)) => name == b"DCTDecode",
+                Ok(Object::Array(filters)) => {
+                    filters.len() == 1 && filters[0].as_name().ok() == Some(b"DCTDecode".as_slice())
+                }
+                _ => false,
+            };
+            is_image && is_jpeg
+        })
+        .max_by_key(|stream| stream.content.len())
+        .map(|stream| stream.content.clone())
+}
+
+/// A PDF book converted in the same shape of an extracted EPUB
+pub struct PdfBook {
+    pub metadata: HashMap<String, String>,
+    pub chapters: Vec<String>,
+    pub cover: Option<Vec<u8>>,
+}
+
+/// Method that reads the text layer of a PDF file
+pub fn read_pdf(path: &str) -> Result<PdfBook, Box<dyn error::Error>> {
+    let doc = Document::load(path)?;
+    let pages = doc.get_pages();
+    if pages.is_empty() {
+        return Err("the PDF has no pages".into());
+    }
+
+    let page_texts = pages
+        .keys()
+        .map(|number| doc.extract_text(&[*number]).unwrap_or_default())
+        .collect::<Vec<String>>();
+    if page_texts.iter().all(|text| text.trim().is_empty()) {
+        return Err("the PDF has no text layer".into());
+    }
+
+    let chapters = group_pages(page_texts.len(), &get_outline(&doc))
+        .into_iter()
+        .map(|(title, range)| {
+            let paragraphs = page_texts[range]
+                .iter()
+                .flat_map(|text| reflow_text(text))
+                .collect::<Vec<String>>();
+            build_chapter(title.as_ref(), &paragraphs)
+        })
+        .collect::<Vec<String>>();
+
+    let cover = pages
+        .values()
+        .next()
+        .and_then(|first| get_page_image(&doc, *first));
+
+    let info = doc
+        .trailer
+        .get(b"Info")
+        .ok()
+        .and_then(|info| resolve(&doc, info))
+        .and_then(|info| info.as_dict().ok());
+    let info_entry = |key: &[u8]| info.and_then(|info| get_string_entry(&doc, info, key));
+    let lang = doc
+        .trailer
+        .get(b"Root")
+        .ok()
+        .and_then(|root| resolve(&doc, root))
+        .and_then(|root| root.as_dict().ok())
+        .and_then(|root| get_string_entry(&doc, root, b"Lang"));
+
+    let mut metadata = HashMap::new();
+    metadata.insert(
+        "title".to_string(),
+        info_entry(b"Title").unwrap_or_else(|| {
+            Path::new(path)
+                .file_stem()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .replace('_', " ")
+        }),
+    );
+    metadata.insert(
+        "author".to_string(),
+        info_entry(b"Author").unwrap_or("no author".to_string()),
+    );
+    metadata.insert("lang".to_string(), lang.unwrap_or("no lang".to_string()));
+    if let Some(desc) = info_entry(b"Subject") {
+        metadata.insert("desc".to_string(), desc);
+    }
+    metadata.insert(
+        "date".to_string(),
+        info_entry(b"CreationDate").unwrap_or("no date".to_string()),
+    );
+    metadata.insert("chapters".to_string(), chapters.len().to_string());
+    metadata.insert("favorite".to_string(), "false".to_string());
+    metadata.insert("format".to_string(), "pdf".to_string());
+
+    Ok(PdfBook {
+        metadata,
+        chapters,
+        cover,
+    })
+}
+
+fn save_metadata(
+    path: &str,
+    metadata: &HashMap<String, String>,
+) -> Result<(), Box<dyn error::Error>> {
+    let path_name = get_metadata_path(&path.to_string());
+    let mut metadata_file = File::create(&path_name)?;
+    let json = json!(metadata);
+    metadata_file.write_all(json.to_string().as_bytes())?;
+    Ok(())
+}
+
+/// Method that saves the metadata of the book in its saved_books folder
+pub fn extract_metadata(path: &str) -> Result<HashMap<String, String>, Box<dyn error::Error>> {
+    let book = read_pdf(path)?;
+    save_metadata(path, &book.metadata)?;
+    Ok(book.metadata)
+}
+
+/// Method that extracts metadata, chapters and cover of the book,
+/// the reflowed text of the chapters is saved as html pages like the ones of an EPUB
+pub fn extract_all(path: &str) -> Result<(), Box<dyn error::Error>> {
+    let book = read_pdf(path)?;
+    save_metadata(path, &book.metadata)?;
+
+    let folder_name = Path::new(path).file_stem().unwrap().to_str().unwrap();
+    let folder = get_saved_books_dir().join(folder_name);
+    std::fs::create_dir_all(&folder)?;
+    for (i, chapter) in book.chapters.iter().enumerate() {
+        let mut file = File::create(folder.join(format!("page_{}.html", i)))?;
+        file.write_all(chapter.as_bytes())?;
+    }
+
+    if let Some(cover) = book.cover {
+        save_book_cover(&cover, &folder_name.to_string())?;
+    }
+    Ok(())
+}
+
+/// Method that returns the cover saved during the extraction
+pub fn get_cover(path: &str) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    let folder_name = Path::new(path).file_stem().unwrap().to_str().unwrap();
+    let cover_path = get_saved_covers_dir().join(format!("{}.png", folder_name));
+    if let Ok(cover) = std::fs::read(cover_path) {
+        return Ok(cover);
+    }
+
+    let doc = Document::load(path)?;
+    let first = *doc
+        .get_pages()
+        .values()
+        .next()
+        .ok_or("the PDF has no pages")?;
+    get_page_image(&doc, first).ok_or("the first page of the PDF is not an image".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pdf_strings_are_decoded() {
+        assert_eq!(decode_pdf_string(b"Citt\xe0"), "Città");
+        assert_eq!(
+            decode_pdf_string(&[0xFE, 0xFF, 0x00, b'C', 0x00, 0xE0]),
+            "Cà"
+        );
+    }
+
+    #[test]
+    fn pages_are_grouped_by_outline() {
+        let outline = vec![
+            ("Secondo".to_string(), 5),
+            ("Primo".to_string(), 2),
+            ("Duplicato".to_string(), 5),
+        ];
+        let chapters = group_pages(8, &outline);
+        assert_eq!(
+            chapters,
+            vec![
+                (None, 0..2),
+                (Some("Primo".to_string()), 2..5),
+                (Some("Secondo".to_string()), 5..8),
+            ]
+        );
+    }
+
+    #[test]
+    fn pages_without_outline_are_grouped_in_fixed_size() {
+        let chapters = group_pages(23, &[]);
+        let ranges = chapters.into_iter().map(|(_, r)| r).collect::<Vec<_>>();
+        assert_eq!(ranges, vec![0..10, 10..20, 20..23]);
+    }
+
+    #[test]
+    fn page_tree_loops_are_left() {
+        let mut doc = Document::with_version("1.5");
+        let node_id = doc.new_object_id();
+        let mut page = lopdf::Dictionary::new();
+        page.set("Type", Object::Name(b"Page".to_vec()));
+        page.set("Parent", node_id);
+        let page_id = doc.add_object(page);
+        let mut node = lopdf::Dictionary::new();
+        node.set("Type", Object::Name(b"Pages".to_vec()));
+        node.set("Parent", page_id);
+        doc.objects.insert(node_id, node.into());
+        assert_eq!(get_page_image(&doc, page_id), None);
+    }
+
+    #[test]
+    fn lines_are_reflowed_in_paragraphs() {
+        let text = "Nel mezzo del cammin di nostra vita mi ri-\ntrovai per una selva oscura\nché la diritta via era smarrita.\n12\n\nAhi quanto a dir qual era è cosa dura";
+        let paragraphs = reflow_text(text);
+        assert_eq!(
+            paragraphs,
+            vec![
+                "Nel mezzo del cammin di nostra vita mi ritrovai per una selva oscura ché la diritta via era smarrita.",
+                "Ahi quanto a dir qual era è cosa dura",
+            ]
+        );
+    }
+}
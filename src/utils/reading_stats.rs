@@ -0,0 +1,128 @@
+use std::{
+    error::Error,
+    fs::create_dir_all,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use super::{dir_manager::get_reading_sessions_path, opds_server::format_timestamp};
+use crate::models::stats::ReadingSession;
+
+pub fn load_sessions() -> Vec<ReadingSession> {
+    load_sessions_from(&get_reading_sessions_path())
+}
+
+fn load_sessions_from(path: &Path) -> Vec<ReadingSession> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+        .and_then(|json| json.as_array().cloned())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(ReadingSession::from_json)
+        .collect()
+}
+
+fn save_sessions_to(path: &Path, sessions: &[ReadingSession]) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir)?;
+    }
+    let json = sessions
+        .iter()
+        .map(ReadingSession::to_json)
+        .collect::<Vec<_>>();
+    std::fs::write(path, serde_json::to_string_pretty(&json)?)?;
+    Ok(())
+}
+
+/// Saves a session, it replaces the one of the same book started at the same time.
+/// It is saved at every page, so it isn't lost if the application is closed
+pub fn save_session(session: &ReadingSession) -> Result<(), Box<dyn Error>> {
+    if session.is_empty() {
+        return Ok(());
+    }
+    let path = get_reading_sessions_path();
+    let mut sessions = load_sessions_from(&path);
+    match sessions
+        .iter_mut()
+        .find(|saved| saved.book == session.book && saved.start == session.start)
+    {
+        Some(saved) => *saved = session.clone(),
+        None => sessions.push(session.clone()),
+    }
+    save_sessions_to(&path, &sessions)
+}
+
+/// Keeps the sessions of a book whose file has been moved or renamed
+pub fn rename_book(from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+    let path = get_reading_sessions_path();
+    let mut sessions = load_sessions_from(&path);
+    let mut renamed = false;
+    for session in sessions.iter_mut().filter(|session| session.book == from) {
+        session.book = to.to_string();
+        renamed = true;
+    }
+    if renamed {
+        save_sessions_to(&path, &sessions)?;
+    }
+    Ok(())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The sessions as CSV, one per line, with the title of the book given by title_of
+pub fn to_csv(sessions: &[ReadingSession], title_of: impl Fn(&str) -> String) -> String {
+    let mut csv =
+        String::from("libro,titolo,inizio,fine,secondi,pagine_avanti,pagine_indietro,caratteri\n");
+    for session in sessions {
+        let fields = [
+            csv_field(&session.book),
+            csv_field(&title_of(&session.book)),
+            format_timestamp(session.start),
+            format_timestamp(session.end),
+            session.seconds.to_string(),
+            session.pages_forward.to_string(),
+            session.pages_back.to_string(),
+            session.chars.to_string(),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Adds the csv extension to the file chosen by the user, if it is missing
+pub fn with_csv_extension(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("csv") => path.to_path_buf(),
+        _ => path.with_extension("csv"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_csv() {
+        let mut session = ReadingSession::new("/libri/dune.epub", 1_600_000_000, 0);
+        session.page_turned(3, 1_600_000_120);
+        let csv = to_csv(&[session], |_| "Dune, \"il\" deserto".to_string());
+        assert_eq!(
+            csv,
+            "libro,titolo,inizio,fine,secondi,pagine_avanti,pagine_indietro,caratteri\n\
+            /libri/dune.epub,\"Dune, \"\"il\"\" deserto\",2020-09-13T12:26:40Z,2020-09-13T12:28:40Z,120,3,0,0\n"
+        );
+        assert_eq!(
+            with_csv_extension(Path::new("/tmp/stats")),
+            Path::new("/tmp/stats.csv")
+        );
+    }
+}
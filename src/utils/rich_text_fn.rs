@@ -12,7 +12,8 @@ use druid::{
 
 const BLOCKQUOTE_COLOR: Color = Color::grey8(0x88);
 const LINK_COLOR: Color = Color::rgb8(0, 0, 0xEE);
-const OPEN_LINK: Selector<String> = Selector::new("druid-example.open-link");
+/// Sent when a link of the text is clicked, with its target
+pub const OPEN_LINK: Selector<String> = Selector::new("druid-example.open-link");
 
 
 /// Parse a markdown string and generate a `RichText` object with
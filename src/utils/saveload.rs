@@ -1,10 +1,11 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::{create_dir_all, File, OpenOptions},
     io::BufReader,
     path::Path,
     str::FromStr,
     sync::mpsc::channel,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use druid::im::Vector;
@@ -12,18 +13,25 @@ use rust_fuzzy_search::fuzzy_compare;
 use serde_json::{json, Value};
 
 use crate::{
-    models::{note::Note, book::{PAGE_WIDTH, PAGE_HEIGHT}},
+    models::{note::Note, book::{PAGE_WIDTH, PAGE_HEIGHT}, metadata::BookMetadata, reading::ReadingInfo, series::Series},
     utils::{
         dir_manager::{
-            get_books_notes_path, get_edited_books_dir, get_epub_dir, get_saved_books_dir,
-            get_savedata_path,
+            get_books_notes_path, get_edited_books_dir, get_epub_dir, get_excluded_books_path,
+            get_saved_books_dir, get_savedata_path,
         },
         epub_utils::{get_metadata_of_book, split_chapter_in_vec},
     },
     MYENV,
 };
 
-use super::{dir_manager::get_metadata_path, envmanager::FontSize};
+use super::{
+    cbz_utils::{get_pages_of_comic, is_cbz},
+    collections::Collections,
+    dir_manager::{get_custom_cover_path, get_metadata_path},
+    envmanager::FontSize,
+    kosync_client, opf_utils, reading_stats,
+    sync_log::{self, Change},
+};
 
 pub enum FileExtension {
     TXT,
@@ -40,7 +48,49 @@ pub fn save_data<T: Into<String> + Clone>(
     font_size: FontSize,
     edited: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    // the time is compared with the one of the position saved on other devices
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let book_path: String = book_path.into();
+    let content: String = content.into();
+    let font_size_name = font_size.to_string();
+
+    write_savedata(
+        book_path.as_str(),
+        chapter,
+        page,
+        content.as_str(),
+        font_size,
+        edited,
+        timestamp,
+    )?;
+
+    sync_log::record(
+        &book_path,
+        Change::Progress {
+            chapter,
+            page,
+            content,
+            font_size: font_size_name,
+        },
+    );
+    kosync_client::push_position(book_path, chapter, page);
 
+    Ok(())
+}
+
+/// function to write the position of a book in the savedata,
+/// without sending it to the other devices
+pub fn write_savedata<T: Into<String> + Clone>(
+    book_path: T,
+    chapter: usize,
+    page: usize,
+    content: T,
+    font_size: FontSize,
+    edited: bool,
+    timestamp: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
     // check if exists a savedata file
     let savedata_path = get_savedata_path();
     let mut json = json!({});
@@ -71,7 +121,8 @@ pub fn save_data<T: Into<String> + Clone>(
             "page":page,
             "font_size":font_size.to_string(),
             "edited_chapters": set,
-            "content":content.into()
+            "content":content.into(),
+            "timestamp": timestamp
         }
     );
 
@@ -89,6 +140,42 @@ pub fn save_data<T: Into<String> + Clone>(
     Ok(())
 }
 
+/// function to load the time the position of a book was saved at
+pub fn load_timestamp<T: Into<String> + Clone>(book_path: T) -> Option<u64> {
+    let file = File::open(get_savedata_path()).ok()?;
+    let json: Value = serde_json::from_reader(BufReader::new(file)).ok()?;
+    json.get(book_path.into())?.get("timestamp")?.as_u64()
+}
+
+/// function to save the offset in the text of the chapter read in the continuous view,
+/// it is more precise than the page and it is removed when the position is saved again
+pub fn save_offset(book_path: &str, offset: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let file = File::open(get_savedata_path())?;
+    let mut json: Value = serde_json::from_reader(BufReader::new(file))?;
+    let Some(value) = json.get_mut(book_path).and_then(|value| value.as_object_mut()) else {
+        return Ok(());
+    };
+    value.insert("offset".to_string(), json!(offset));
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(get_savedata_path())?;
+    serde_json::to_writer_pretty(file, &json)?;
+    Ok(())
+}
+
+/// function to load the offset in the text of the chapter read in the continuous view
+pub fn load_offset(book_path: &str) -> Option<usize> {
+    let file = File::open(get_savedata_path()).ok()?;
+    let json: Value = serde_json::from_reader(BufReader::new(file)).ok()?;
+    json.get(book_path)?
+        .get("offset")?
+        .as_u64()
+        .map(|offset| offset as usize)
+}
+
 pub fn remove_savedata_of_book<T: Into<String> + Clone>(
     book_path: T,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -171,7 +258,70 @@ pub fn remove_edited_chapter<T: Into<String> + Clone>(book_path: T, chapter_numb
     let path = get_edited_books_dir()
         .join(folder_name)
         .join(format!("page_{}.txt", chapter_number));
-    let _ = std::fs::remove_file(path);
+    if path.exists() {
+        // the other devices go back to the original text too
+        let base = sync_log::get_chapter_hash(&book, chapter_number);
+        let _ = std::fs::remove_file(path);
+        sync_log::record(
+            &book,
+            Change::Edit {
+                chapter: chapter_number,
+                base,
+                text: None,
+            },
+        );
+    }
+}
+
+/// function to write or remove (when the text is None) the edited text of a chapter,
+/// without sending it to the other devices
+pub fn write_edited_chapter(
+    book_path: &str,
+    chapter_number: usize,
+    text: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut json = json!({});
+    if let Ok(opened_file) = File::open(get_savedata_path()) {
+        if let Ok(content) = serde_json::from_reader(BufReader::new(opened_file)) {
+            json = content
+        };
+    }
+
+    let mut set = json[book_path]["edited_chapters"]
+        .as_array()
+        .unwrap_or(&vec![])
+        .iter()
+        .filter_map(|x| x.as_u64().map(|x| x as usize))
+        .filter(|chapter| *chapter != chapter_number)
+        .collect::<Vec<usize>>();
+    if text.is_some() {
+        set.push(chapter_number);
+        set.sort();
+    }
+    // the position of a book never opened on this device isn't created
+    if json[book_path].is_object() {
+        json[book_path]["edited_chapters"] = json!(set);
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(get_savedata_path())?;
+        serde_json::to_writer_pretty(file, &json)?;
+    }
+
+    let folder_name = Path::new(book_path).file_stem().unwrap().to_str().unwrap();
+    let folder = get_edited_books_dir().join(folder_name);
+    let path = folder.join(format!("page_{}.txt", chapter_number));
+    match text {
+        Some(text) => {
+            create_dir_all(&folder)?;
+            std::fs::write(path, text)?;
+        }
+        None => {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+    Ok(())
 }
 
 fn evaluate_numeric_options(chapter: Option<u64>, page: Option<u64>) -> Option<(usize, usize)> {
@@ -190,6 +340,16 @@ fn evaluate_str_options<'a>(
 
 /// function to get the most similar page of chapter to the last read one
 fn search_page<T: Into<String> + Clone>(book_path: T, chapter_number: usize, text: &str) -> usize {
+    // pages of a comic are identified by the name of their image
+    let path: String = book_path.clone().into();
+    if is_cbz(&path) {
+        return get_pages_of_comic(&path)
+            .unwrap_or_default()
+            .iter()
+            .position(|page| page == text)
+            .unwrap_or_default();
+    }
+
     let pages = split_chapter_in_vec(
         book_path.clone().into().as_str(),
         Option::None,
@@ -214,10 +374,23 @@ pub fn save_favorite<T: Into<String> + Clone>(
     book_path: T,
     favorite: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    if write_favorite(book_path.clone(), favorite)? {
+        sync_log::record(&book_path.into(), Change::Favorite { favorite });
+    }
+    Ok(())
+}
+
+/// function to write the favorite flag in the metadata of a book,
+/// without sending it to the other devices.
+/// Returns false if the flag was already set
+pub fn write_favorite<T: Into<String> + Clone>(
+    book_path: T,
+    favorite: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
     let mut metadata = get_metadata_of_book(book_path.clone().into().as_str());
-    let old_string = &metadata["favorite"];
+    let old_string = metadata.get("favorite").map_or("false", |s| s.as_str());
     if (old_string == "true" && favorite) || (old_string == "false" && !favorite) {
-        return Ok(());
+        return Ok(false);
     }
 
     metadata.insert(
@@ -236,7 +409,68 @@ pub fn save_favorite<T: Into<String> + Clone>(
         .unwrap();
 
     serde_json::to_writer_pretty(metadata_file, &json)?;
-    return Ok(());
+    return Ok(true);
+}
+
+/// function to save the reading status, the dates and the rating in the metadata of a book
+pub fn save_reading_info(
+    book_path: &str,
+    info: &ReadingInfo,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut metadata = get_metadata_of_book(book_path);
+    info.write_to(&mut metadata);
+
+    let metadata_file = File::create(get_metadata_path(&book_path.to_string()))?;
+    serde_json::to_writer_pretty(metadata_file, &json!(metadata))?;
+    Ok(())
+}
+
+/// function to save if a book is archived, i.e. hidden from the library with all its data
+pub fn save_archived(book_path: &str, archived: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut metadata = get_metadata_of_book(book_path);
+    metadata.insert("archived".to_string(), archived.to_string());
+
+    let metadata_file = File::create(get_metadata_path(&book_path.to_string()))?;
+    serde_json::to_writer_pretty(metadata_file, &json!(metadata))?;
+    Ok(())
+}
+
+/// function to save the series of a book in its metadata, None removes it
+pub fn save_series(book_path: &str, series: Option<&Series>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut metadata = get_metadata_of_book(book_path);
+    Series::write_to(series, &mut metadata);
+
+    let metadata_file = File::create(get_metadata_path(&book_path.to_string()))?;
+    serde_json::to_writer_pretty(metadata_file, &json!(metadata))?;
+    Ok(())
+}
+
+/// Saves the metadata edited by the user and the cover they chose, if any.
+/// They are written also in the EPUB if requested, otherwise only the library sees them
+pub fn save_edited_metadata(
+    book_path: &str,
+    edited: &BookMetadata,
+    cover_path: Option<&str>,
+    write_to_epub: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cover = cover_path.map(std::fs::read).transpose()?;
+    if write_to_epub {
+        opf_utils::write_to_epub(book_path, edited, cover.as_deref())?;
+    }
+
+    if let Some(cover) = cover {
+        let cover_path = get_custom_cover_path(book_path);
+        if let Some(dir) = cover_path.parent() {
+            create_dir_all(dir)?;
+        }
+        image::load_from_memory(&cover)?.save(cover_path)?;
+    }
+
+    let mut metadata = get_metadata_of_book(book_path);
+    edited.write_to(&mut metadata);
+    let metadata_file = File::create(get_metadata_path(&book_path.to_string()))?;
+    serde_json::to_writer_pretty(metadata_file, &json!(metadata))?;
+    Ok(())
 }
 
 /// function to load the last read page of a chapter given the path of the book
@@ -314,6 +548,37 @@ pub fn save_note<T: Into<String> + Clone>(
     page_text: T,
     note: T,
 ) -> Result<String, Box<dyn std::error::Error>> {
+    let text = page_text.into();
+    let to_take = if text.len() > 200 {
+        text.len() / 3
+    } else {
+        text.len()
+    };
+    let book_path: String = book_path.into();
+    let start = text[..to_take].to_string();
+    let note: String = note.into();
+
+    add_note(&book_path, chapter, &start, &note)?;
+    sync_log::record(
+        &book_path,
+        Change::NoteAdd {
+            chapter,
+            start: start.clone(),
+            note,
+        },
+    );
+
+    Ok(start)
+}
+
+/// function to add a note to a chapter of a book, given the start of its page,
+/// without sending it to the other devices
+pub fn add_note(
+    book_path: &str,
+    chapter: usize,
+    start: &str,
+    note: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
     // check if exists a file
     let notes_path = get_books_notes_path();
     let mut json = json!({});
@@ -328,22 +593,16 @@ pub fn save_note<T: Into<String> + Clone>(
         create_dir_all(notes_path.parent().unwrap()).unwrap();
     }
 
-    let text = page_text.into();
-    let to_take = if text.len() > 200 {
-        text.len() / 3
-    } else {
-        text.len()
-    };
     // json value for the note to save
     let value = json!(
         {
-            "start": &text[..to_take],
-            "note":note.into()
+            "start": start,
+            "note": note
         }
     );
 
     // array of the book
-    if let Some(array) = json[book_path.clone().into()].as_array_mut() {
+    if let Some(array) = json[book_path].as_array_mut() {
         // other stuff
         if let Some(obj) = array.iter_mut().find(|obj| obj["chapter"] == chapter) {
             // chapter already exists
@@ -359,7 +618,7 @@ pub fn save_note<T: Into<String> + Clone>(
             array.push(json!({"chapter":chapter, "notes":[value]}));
         }
     } else {
-        json[book_path.into()] = json!([
+        json[book_path] = json!([
             {"chapter":chapter,
             "notes":[value]
         }]);
@@ -373,7 +632,26 @@ pub fn save_note<T: Into<String> + Clone>(
 
     serde_json::to_writer_pretty(file, &json)?;
 
-    Ok(text[..to_take].to_string())
+    Ok(())
+}
+
+/// function to check if a chapter of a book already has a note
+pub fn has_note(book_path: &str, chapter: usize, start: &str, note: &str) -> bool {
+    let Ok(file) = File::open(get_books_notes_path()) else {
+        return false;
+    };
+    let Ok(json) = serde_json::from_reader::<_, Value>(BufReader::new(file)) else {
+        return false;
+    };
+
+    json[book_path]
+        .as_array()
+        .unwrap_or(&vec![])
+        .iter()
+        .filter(|item| item["chapter"] == chapter)
+        .filter_map(|item| item["notes"].as_array())
+        .flatten()
+        .any(|item| item["start"] == start && item["note"] == note)
 }
 
 /// function to load notes of a book
@@ -414,6 +692,21 @@ pub fn delete_note<T: Into<String> + Clone>(
     book_path: T,
     chapter: usize,
     start_page: T,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let book_path: String = book_path.into();
+    let start: String = start_page.into();
+
+    remove_note(&book_path, chapter, &start)?;
+    sync_log::record(&book_path, Change::NoteDelete { chapter, start });
+
+    Ok(())
+}
+
+/// function to delete a note of a book, without sending it to the other devices
+pub fn remove_note(
+    book_path: &str,
+    chapter: usize,
+    start_page: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // open file to read
     let Ok(file) = File::open(get_books_notes_path()) else {
@@ -424,7 +717,7 @@ pub fn delete_note<T: Into<String> + Clone>(
     let mut json: Value = serde_json::from_reader(reader)?;
 
     // check if there is a book with that name and an array
-    let Some(book_array) = json[book_path.clone().into()].as_array_mut() else {
+    let Some(book_array) = json[book_path].as_array_mut() else {
         return Ok(());
     };
 
@@ -440,7 +733,7 @@ pub fn delete_note<T: Into<String> + Clone>(
 
             for (i, note) in notes_array.into_iter().enumerate() {
                 let saved_start_page = note["start"].as_str().unwrap();
-                if saved_start_page == start_page.clone().into() {
+                if saved_start_page == start_page {
                     // note found
                     index_to_delete = Some(i);
                 }
@@ -509,6 +802,11 @@ pub fn delete_notes<T: Into<String> + Clone>(
 
     serde_json::to_writer_pretty(file, &json)?;
 
+    let book_path: String = book_path.into();
+    for start in start_pages {
+        sync_log::record(&book_path, Change::NoteDelete { chapter, start });
+    }
+
     Ok(())
 }
 
@@ -529,6 +827,20 @@ pub fn delete_all_notes<T: Into<String> + Clone>(
         return Ok(());
     };
 
+    // the deleted notes are sent to the other devices one by one
+    let deleted = book_array
+        .iter()
+        .flat_map(|item| {
+            let chapter = item["chapter"].as_u64().unwrap_or_default() as usize;
+            item["notes"]
+                .as_array()
+                .unwrap_or(&vec![])
+                .iter()
+                .filter_map(|note| note["start"].as_str())
+                .map(|start| (chapter, start.to_string()))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
     book_array.clear();
 
     // open file to write
@@ -540,6 +852,11 @@ pub fn delete_all_notes<T: Into<String> + Clone>(
 
     serde_json::to_writer_pretty(file, &json)?;
 
+    let book_path: String = book_path.into();
+    for (chapter, start) in deleted {
+        sync_log::record(&book_path, Change::NoteDelete { chapter, start });
+    }
+
     Ok(())
 }
 
@@ -552,27 +869,122 @@ pub fn delete_book(book_path: &String) -> Result<(), Box<dyn std::error::Error>>
         let saved_book = get_saved_books_dir().join(epub.file_stem().unwrap());
         std::fs::remove_dir_all(saved_book)?;
 
-        // remove from epubs dir
-        std::fs::remove_file(book_path)?;
+        // remove from epubs dir, books outside of it (e.g. in a Calibre library)
+        // are only read and never deleted
+        if epub.starts_with(get_epub_dir()) {
+            std::fs::remove_file(book_path)?;
+        }
     }
 
+    // the book is forgotten by its collections and tags
+    let mut shelves = Collections::load();
+    shelves.remove_book(book_path);
+    shelves.save()?;
+
     Ok(())
 }
 
-pub fn copy_book_in_folder(from: &String) -> Result<(), Box<dyn std::error::Error>> {
-    let path = Path::new(from);
-    if !path.exists() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "file not found",
-        )));
+/// Replaces the path of a book in a json file whose keys are the paths of the books
+fn rename_json_key(path: &Path, from: &str, to: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return Ok(());
+    };
+    let mut json: Value = serde_json::from_str(&text)?;
+    let Some(object) = json.as_object_mut() else {
+        return Ok(());
+    };
+    if let Some(value) = object.remove(from) {
+        object.insert(to.to_string(), value);
+        std::fs::write(path, serde_json::to_string_pretty(&json)?)?;
     }
+    Ok(())
+}
+
+/// Returns the value of a book in a json file whose keys are the paths of the books
+pub fn read_book_entry(path: &Path, book_path: &str) -> Option<Value> {
+    let text = std::fs::read_to_string(path).ok()?;
+    let json: Value = serde_json::from_str(&text).ok()?;
+    json.get(book_path).cloned()
+}
 
-    let to = get_epub_dir().join(path.file_name().unwrap());
-    std::fs::copy(from, to)?;
+/// Sets the value of a book in a json file whose keys are the paths of the books,
+/// None removes it
+pub fn write_book_entry(
+    path: &Path,
+    book_path: &str,
+    value: Option<Value>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut json = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+        .filter(|json| json.is_object())
+        .unwrap_or(json!({}));
+    let object = json.as_object_mut().unwrap();
+    let changed = match value {
+        Some(value) => object.insert(book_path.to_string(), value.clone()) != Some(value),
+        None => object.remove(book_path).is_some(),
+    };
+    if !changed {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&json)?)?;
+    Ok(())
+}
+
+/// Books removed from the library whose file has been kept,
+/// they aren't loaded again until they are imported by the user
+pub fn load_excluded_books() -> HashSet<String> {
+    std::fs::read_to_string(get_excluded_books_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn set_book_excluded(book_path: &str, excluded: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut books = load_excluded_books();
+    let changed = if excluded {
+        books.insert(book_path.to_string())
+    } else {
+        books.remove(book_path)
+    };
+    if changed {
+        let mut books = books.into_iter().collect::<Vec<_>>();
+        books.sort();
+        std::fs::write(get_excluded_books_path(), serde_json::to_string_pretty(&books)?)?;
+    }
     Ok(())
 }
 
+/// Keeps the data of a book whose file has been moved or renamed:
+/// the position, the notes, the collections and the extracted chapters
+pub fn move_book_data(from: &str, to: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let old_stem = Path::new(from).file_stem().unwrap_or_default();
+    let new_stem = Path::new(to).file_stem().unwrap_or_default();
+    if old_stem != new_stem {
+        for dir in [get_saved_books_dir(), get_edited_books_dir()] {
+            let (old, new) = (dir.join(old_stem), dir.join(new_stem));
+            if old.exists() && !new.exists() {
+                std::fs::rename(old, new)?;
+            }
+        }
+    }
+
+    rename_json_key(&get_savedata_path(), from, to)?;
+    rename_json_key(&get_books_notes_path(), from, to)?;
+    let shelves = Collections::load().rename_books(|path| {
+        if path == from {
+            to.to_string()
+        } else {
+            path.to_string()
+        }
+    });
+    shelves.save()?;
+    reading_stats::rename_book(from, to)?;
+    Ok(())
+}
 
 // Tests are provided only for the functions that are really used
 #[cfg(test)]
@@ -696,7 +1108,8 @@ mod tests {
         assert_eq!(json, json!({
             book_path:{
                 "chapter":chapter, "page":page, "content":content,
-                "font_size": "medium", "edited_chapters": []
+                "font_size": "medium", "edited_chapters": [],
+                "timestamp": load_timestamp(book_path).unwrap()
             }
         }));
 
@@ -732,7 +1145,8 @@ mod tests {
         assert_eq!(json, json!({
             book_path:{
                 "chapter":chapter, "page":page+1, "content":new_content,
-                "font_size": "medium", "edited_chapters": []
+                "font_size": "medium", "edited_chapters": [],
+                "timestamp": load_timestamp(book_path).unwrap()
             }
         }));
 
@@ -770,11 +1184,13 @@ mod tests {
         assert_eq!(json, json!({
             book_path:{
                 "chapter":chapter, "page":page, "content":new_content,
-                "font_size": "medium", "edited_chapters": []
+                "font_size": "medium", "edited_chapters": [],
+                "timestamp": load_timestamp(book_path).unwrap()
             },
             new_book_path:{
                 "chapter":new_chapter, "page":new_page, "content":new_content,
-                "font_size": "medium", "edited_chapters": []
+                "font_size": "medium", "edited_chapters": [],
+                "timestamp": load_timestamp(new_book_path).unwrap()
             }
         }));
 
@@ -807,7 +1223,8 @@ mod tests {
         assert_eq!(json, json!({
             book_path:{
                 "chapter":chapter, "page":page, "content":content,
-                "font_size": "medium", "edited_chapters": [chapter]
+                "font_size": "medium", "edited_chapters": [chapter],
+                "timestamp": load_timestamp(book_path).unwrap()
             }
         }));
 
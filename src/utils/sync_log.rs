@@ -0,0 +1,743 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{create_dir_all, File, OpenOptions},
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use druid::Data;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+
+use super::{
+    dir_manager::{
+        get_books_notes_path, get_edited_books_dir, get_epub_dir, get_savedata_path,
+        get_sync_conflicts_path, get_sync_state_path,
+    },
+    envmanager::FontSize,
+    saveload,
+};
+
+/// Folder created in the shared directory, it contains a log for every device
+const LOGS_DIR: &str = "crab-reader-sync";
+/// Hash of a chapter that has never been edited
+const ORIGINAL: &str = "original";
+
+/// Shared directory, kept apart from the env because the changes
+/// are recorded while the lock of the env may be held
+static SYNC_DIR: Lazy<Mutex<Option<PathBuf>>> = Lazy::new(|| Mutex::new(None));
+/// Name of this device, looked up once since every change recorded needs it
+static DEVICE_NAME: Lazy<String> = Lazy::new(find_device_name);
+
+/// Change made on a device to one of its books
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Progress {
+        chapter: usize,
+        page: usize,
+        content: String,
+        font_size: String,
+    },
+    Favorite {
+        favorite: bool,
+    },
+    NoteAdd {
+        chapter: usize,
+        start: String,
+        note: String,
+    },
+    NoteDelete {
+        chapter: usize,
+        start: String,
+    },
+    /// text of a chapter, None when it goes back to the original one.
+    /// base is the hash of the text that has been edited
+    Edit {
+        chapter: usize,
+        base: String,
+        text: Option<String>,
+    },
+}
+
+/// Line of the log of a device
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    pub device: String,
+    /// milliseconds since the epoch
+    pub time: u64,
+    /// file name of the book, the folders differ between the devices
+    pub book: String,
+    pub change: Change,
+}
+
+impl LogEntry {
+    pub fn to_json(&self) -> Value {
+        let mut value = match &self.change {
+            Change::Progress {
+                chapter,
+                page,
+                content,
+                font_size,
+            } => json!({
+                "type": "progress",
+                "chapter": chapter,
+                "page": page,
+                "content": content,
+                "font_size": font_size,
+            }),
+            Change::Favorite { favorite } => json!({ "type": "favorite", "favorite": favorite }),
+            Change::NoteAdd {
+                chapter,
+                start,
+                note,
+            } => json!({
+                "type": "note_add",
+                "chapter": chapter,
+                "start": start,
+                "note": note,
+            }),
+            Change::NoteDelete { chapter, start } => json!({
+                "type": "note_delete",
+                "chapter": chapter,
+                "start": start,
+            }),
+            Change::Edit {
+                chapter,
+                base,
+                text,
+            } => json!({
+                "type": "edit",
+                "chapter": chapter,
+                "base": base,
+                "text": text,
+            }),
+        };
+        value["device"] = json!(self.device);
+        value["time"] = json!(self.time);
+        value["book"] = json!(self.book);
+        value
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        let chapter = value["chapter"].as_u64().unwrap_or_default() as usize;
+        let get_str = |key: &str| value.get(key)?.as_str().map(|s| s.to_string());
+
+        let change = match value.get("type")?.as_str()? {
+            "progress" => Change::Progress {
+                chapter,
+                page: value.get("page")?.as_u64()? as usize,
+                content: get_str("content").unwrap_or_default(),
+                font_size: get_str("font_size").unwrap_or_default(),
+            },
+            "favorite" => Change::Favorite {
+                favorite: value.get("favorite")?.as_bool()?,
+            },
+            "note_add" => Change::NoteAdd {
+                chapter,
+                start: get_str("start")?,
+                note: get_str("note")?,
+            },
+            "note_delete" => Change::NoteDelete {
+                chapter,
+                start: get_str("start")?,
+            },
+            "edit" => Change::Edit {
+                chapter,
+                base: get_str("base")?,
+                text: get_str("text"),
+            },
+            _ => return None,
+        };
+
+        Some(Self {
+            device: get_str("device")?,
+            time: value.get("time")?.as_u64()?,
+            book: get_str("book")?,
+            change,
+        })
+    }
+
+    /// Key of the value that is replaced by the most recent change,
+    /// None for the changes that are merged
+    fn get_lww_key(&self) -> Option<String> {
+        match self.change {
+            Change::Progress { .. } => Some(format!("{}/progress", self.book)),
+            Change::Favorite { .. } => Some(format!("{}/favorite", self.book)),
+            _ => None,
+        }
+    }
+}
+
+/// Edit of a chapter made on another device on a text that has been edited here too
+#[derive(Clone, Debug, PartialEq, Data)]
+pub struct Conflict {
+    pub book_path: String,
+    pub chapter: usize,
+    pub device: String,
+    pub time: u64,
+    /// None when the chapter is the original one
+    pub local_text: Option<String>,
+    pub remote_text: Option<String>,
+}
+
+impl Conflict {
+    fn to_json(&self) -> Value {
+        json!({
+            "book_path": self.book_path,
+            "chapter": self.chapter,
+            "device": self.device,
+            "time": self.time,
+            "local_text": self.local_text,
+            "remote_text": self.remote_text,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            book_path: value.get("book_path")?.as_str()?.to_string(),
+            chapter: value.get("chapter")?.as_u64()? as usize,
+            device: value.get("device")?.as_str()?.to_string(),
+            time: value.get("time")?.as_u64()?,
+            local_text: value["local_text"].as_str().map(|s| s.to_string()),
+            remote_text: value["remote_text"].as_str().map(|s| s.to_string()),
+        })
+    }
+}
+
+/// What happens to an edit made on another device
+#[derive(Debug, PartialEq)]
+pub enum EditAction {
+    Apply,
+    Skip,
+    Conflict,
+}
+
+/// The edit is applied only on the text it has been made on,
+/// if the chapter has already the same text there is nothing to do
+pub fn classify_edit(local_hash: &str, base: &str, new_hash: &str) -> EditAction {
+    if local_hash == new_hash {
+        EditAction::Skip
+    } else if local_hash == base {
+        EditAction::Apply
+    } else {
+        EditAction::Conflict
+    }
+}
+
+/// Lines of the logs already merged and time of the last change of every value
+#[derive(Debug, Default, PartialEq)]
+pub struct SyncState {
+    pub cursors: HashMap<String, usize>,
+    pub times: HashMap<String, u64>,
+}
+
+impl SyncState {
+    pub fn load() -> Self {
+        let Some(json) = std::fs::read_to_string(get_sync_state_path())
+            .ok()
+            .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        else {
+            return Self::default();
+        };
+        let to_map = |value: &Value| {
+            value
+                .as_object()
+                .map(|object| {
+                    object
+                        .iter()
+                        .filter_map(|(key, value)| Some((key.clone(), value.as_u64()?)))
+                        .collect::<HashMap<_, _>>()
+                })
+                .unwrap_or_default()
+        };
+        Self {
+            cursors: to_map(&json["cursors"])
+                .into_iter()
+                .map(|(device, cursor)| (device, cursor as usize))
+                .collect(),
+            times: to_map(&json["times"]),
+        }
+    }
+
+    pub fn save(&self) {
+        let json = json!({ "cursors": self.cursors, "times": self.times });
+        if let Err(e) = std::fs::write(get_sync_state_path(), json.to_string()) {
+            println!("ERROR: failed to save the sync state: {}", e);
+        }
+    }
+
+    /// Returns true if the change is more recent than the value it replaces,
+    /// the time of the value is updated
+    fn update_time(&mut self, key: String, time: u64) -> bool {
+        match self.times.get(&key) {
+            Some(last) if *last >= time => false,
+            _ => {
+                self.times.insert(key, time);
+                true
+            }
+        }
+    }
+}
+
+/// Chapter edits and books changed by a merge
+#[derive(Debug, Default)]
+pub struct MergeResult {
+    pub changed_books: HashSet<String>,
+    pub new_conflicts: usize,
+}
+
+/// Sets the directory shared with the other devices, None disables the sync
+pub fn set_sync_dir(dir: Option<PathBuf>) {
+    *SYNC_DIR.lock().unwrap() = dir;
+}
+
+pub fn get_sync_dir() -> Option<PathBuf> {
+    SYNC_DIR.lock().unwrap().clone()
+}
+
+/// Returns the name of this device, it is the name of its log
+pub fn get_device_name() -> String {
+    DEVICE_NAME.clone()
+}
+
+fn find_device_name() -> String {
+    let name = std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| {
+            std::process::Command::new("hostname")
+                .output()
+                .ok()
+                .and_then(|output| String::from_utf8(output.stdout).ok())
+        })
+        .unwrap_or_default();
+
+    let name = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if name.is_empty() {
+        "crab-reader".to_string()
+    } else {
+        name
+    }
+}
+
+fn get_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+fn get_file_name(book_path: &str) -> String {
+    Path::new(book_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(book_path)
+        .to_string()
+}
+
+/// Appends a change to the log of its device
+pub fn append_entry(dir: &Path, entry: &LogEntry) -> Result<(), Box<dyn std::error::Error>> {
+    let logs_dir = dir.join(LOGS_DIR);
+    create_dir_all(&logs_dir)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(logs_dir.join(format!("{}.jsonl", entry.device)))?;
+    writeln!(file, "{}", entry.to_json())?;
+    Ok(())
+}
+
+/// Reads the changes of the other devices that haven't been merged yet,
+/// the cursors are moved after the lines that have been read.
+/// A line that is still being written (without the newline) is read next time
+pub fn read_new_entries(
+    dir: &Path,
+    device: &str,
+    cursors: &mut HashMap<String, usize>,
+) -> Vec<LogEntry> {
+    let mut entries = vec![];
+    let Ok(logs) = std::fs::read_dir(dir.join(LOGS_DIR)) else {
+        return entries;
+    };
+
+    for log in logs.flatten() {
+        let path = log.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("jsonl") {
+            continue;
+        }
+        let Some(log_device) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if log_device == device {
+            continue;
+        }
+        let cursor = cursors.entry(log_device.to_string()).or_default();
+        entries.extend(read_log(&path, cursor));
+    }
+    entries
+}
+
+/// Reads the changes of this device recorded since the last merge
+pub fn read_own_entries(
+    dir: &Path,
+    device: &str,
+    cursors: &mut HashMap<String, usize>,
+) -> Vec<LogEntry> {
+    let path = dir.join(LOGS_DIR).join(format!("{}.jsonl", device));
+    read_log(&path, cursors.entry(device.to_string()).or_default())
+}
+
+/// Reads the complete lines of a log after the cursor, which is moved after them
+fn read_log(path: &Path, cursor: &mut usize) -> Vec<LogEntry> {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return vec![];
+    };
+    let complete_lines = content.matches('\n').count();
+    let entries = content
+        .lines()
+        .take(complete_lines)
+        .skip(*cursor)
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .filter_map(|value| LogEntry::from_json(&value))
+        .collect();
+    *cursor = complete_lines.max(*cursor);
+    entries
+}
+
+/// Returns the hash of the edited text of a chapter
+pub fn get_chapter_hash(book_path: &str, chapter: usize) -> String {
+    let folder_name = Path::new(book_path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    let path = get_edited_books_dir()
+        .join(folder_name)
+        .join(format!("page_{}.txt", chapter));
+    match std::fs::read(path) {
+        Ok(text) => format!("{:x}", md5::compute(text)),
+        Err(_) => ORIGINAL.to_string(),
+    }
+}
+
+fn get_text_hash(text: &Option<String>) -> String {
+    match text {
+        Some(text) => format!("{:x}", md5::compute(text.as_bytes())),
+        None => ORIGINAL.to_string(),
+    }
+}
+
+fn get_edited_text(book_path: &str, chapter: usize) -> Option<String> {
+    let folder_name = Path::new(book_path).file_stem()?.to_str()?;
+    let path = get_edited_books_dir()
+        .join(folder_name)
+        .join(format!("page_{}.txt", chapter));
+    std::fs::read_to_string(path).ok()
+}
+
+/// Records a change made on this device, nothing is done if the sync isn't enabled
+pub fn record(book_path: &str, change: Change) {
+    let Some(dir) = get_sync_dir() else {
+        return;
+    };
+
+    // the time of the change is taken from the log when merging,
+    // so the state isn't rewritten at every page turned
+    let entry = LogEntry {
+        device: get_device_name(),
+        time: get_time(),
+        book: get_file_name(book_path),
+        change,
+    };
+    if let Err(e) = append_entry(&dir, &entry) {
+        println!("ERROR: failed to record the change of {}: {}", book_path, e);
+    }
+}
+
+/// Finds the path of a book on this device given its file name
+fn find_local_book(name: &str) -> Option<String> {
+    let path = get_epub_dir().join(name);
+    if path.exists() {
+        return path.to_str().map(|path| path.to_string());
+    }
+
+    // books outside of the library folder are found in the saved positions and notes
+    [get_savedata_path(), get_books_notes_path()]
+        .iter()
+        .filter_map(|path| File::open(path).ok())
+        .filter_map(|file| serde_json::from_reader::<_, Value>(BufReader::new(file)).ok())
+        .filter_map(|json| json.as_object().cloned())
+        .flat_map(|object| object.into_iter().map(|(key, _)| key))
+        .find(|key| get_file_name(key) == name && Path::new(key).exists())
+}
+
+/// Merges the changes of the other devices in the books of this device
+pub fn merge() -> MergeResult {
+    let mut result = MergeResult::default();
+    let Some(dir) = get_sync_dir() else {
+        return result;
+    };
+
+    let mut state = SyncState::load();
+    let device = get_device_name();
+    // an older change of another device must not replace one made here
+    for entry in read_own_entries(&dir, &device, &mut state.cursors) {
+        if let Some(key) = entry.get_lww_key() {
+            state.update_time(key, entry.time);
+        }
+    }
+    let mut entries = read_new_entries(&dir, &device, &mut state.cursors);
+    // the changes are applied in the order they have been made
+    entries.sort_by(|a, b| (a.time, &a.device).cmp(&(b.time, &b.device)));
+
+    let mut conflicts = load_conflicts();
+    for entry in entries {
+        // the book hasn't been added on this device
+        let Some(book_path) = find_local_book(&entry.book) else {
+            continue;
+        };
+        if let Some(key) = entry.get_lww_key() {
+            if !state.update_time(key, entry.time) {
+                continue;
+            }
+        }
+
+        let applied = match entry.change {
+            Change::Progress {
+                chapter,
+                page,
+                content,
+                font_size,
+            } => saveload::write_savedata(
+                book_path.as_str(),
+                chapter,
+                page,
+                content.as_str(),
+                FontSize::from(font_size.as_str()),
+                false,
+                entry.time / 1000,
+            )
+            .is_ok(),
+            Change::Favorite { favorite } => {
+                matches!(
+                    saveload::write_favorite(book_path.as_str(), favorite),
+                    Ok(true)
+                )
+            }
+            Change::NoteAdd {
+                chapter,
+                start,
+                note,
+            } => {
+                !saveload::has_note(&book_path, chapter, &start, &note)
+                    && saveload::add_note(&book_path, chapter, &start, &note).is_ok()
+            }
+            Change::NoteDelete { chapter, start } => {
+                saveload::remove_note(&book_path, chapter, &start).is_ok()
+            }
+            Change::Edit {
+                chapter,
+                base,
+                text,
+            } => {
+                let local_hash = get_chapter_hash(&book_path, chapter);
+                match classify_edit(&local_hash, &base, &get_text_hash(&text)) {
+                    EditAction::Apply => {
+                        saveload::write_edited_chapter(&book_path, chapter, text.as_deref()).is_ok()
+                    }
+                    EditAction::Skip => false,
+                    EditAction::Conflict => {
+                        conflicts.push(Conflict {
+                            book_path: book_path.clone(),
+                            chapter,
+                            device: entry.device.clone(),
+                            time: entry.time,
+                            local_text: get_edited_text(&book_path, chapter),
+                            remote_text: text,
+                        });
+                        result.new_conflicts += 1;
+                        false
+                    }
+                }
+            }
+        };
+        if applied {
+            result.changed_books.insert(book_path);
+        }
+    }
+
+    state.save();
+    if result.new_conflicts > 0 {
+        save_conflicts(&conflicts);
+    }
+    result
+}
+
+/// Returns the chapter edits that are waiting to be resolved
+pub fn load_conflicts() -> Vec<Conflict> {
+    std::fs::read_to_string(get_sync_conflicts_path())
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .and_then(|json| {
+            json.as_array()
+                .map(|array| array.iter().filter_map(Conflict::from_json).collect())
+        })
+        .unwrap_or_default()
+}
+
+/// Adds conflicts found outside of the sync, e.g. importing an archive
+pub fn add_conflicts(new_conflicts: Vec<Conflict>) {
+    let mut conflicts = load_conflicts();
+    conflicts.extend(new_conflicts);
+    save_conflicts(&conflicts);
+}
+
+fn save_conflicts(conflicts: &[Conflict]) {
+    let json = Value::Array(conflicts.iter().map(Conflict::to_json).collect());
+    if let Err(e) = std::fs::write(get_sync_conflicts_path(), json.to_string()) {
+        println!("ERROR: failed to save the sync conflicts: {}", e);
+    }
+}
+
+/// Resolves a conflict keeping the text of the other device or the one of this device,
+/// the choice is sent to the other devices as a new edit
+pub fn resolve_conflict(
+    conflict: &Conflict,
+    keep_remote: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let local_hash = get_chapter_hash(&conflict.book_path, conflict.chapter);
+    let change = if keep_remote {
+        saveload::write_edited_chapter(
+            &conflict.book_path,
+            conflict.chapter,
+            conflict.remote_text.as_deref(),
+        )?;
+        Change::Edit {
+            chapter: conflict.chapter,
+            base: local_hash,
+            text: conflict.remote_text.clone(),
+        }
+    } else {
+        // the other device has the remote text, it is replaced with the local one
+        Change::Edit {
+            chapter: conflict.chapter,
+            base: get_text_hash(&conflict.remote_text),
+            text: get_edited_text(&conflict.book_path, conflict.chapter),
+        }
+    };
+    record(&conflict.book_path, change);
+
+    let mut conflicts = load_conflicts();
+    conflicts.retain(|other| other != conflict);
+    save_conflicts(&conflicts);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::temp_dir;
+
+    fn entry(device: &str, time: u64, change: Change) -> LogEntry {
+        LogEntry {
+            device: device.to_string(),
+            time,
+            book: "book.epub".to_string(),
+            change,
+        }
+    }
+
+    #[test]
+    fn entries_are_read_once() {
+        let dir = temp_dir("sync-log");
+        let first = entry("laptop", 1, Change::Favorite { favorite: true });
+        let second = entry(
+            "laptop",
+            2,
+            Change::NoteAdd {
+                chapter: 3,
+                start: "C'era una volta".to_string(),
+                note: "inizio".to_string(),
+            },
+        );
+        let own = entry("desktop", 3, Change::Favorite { favorite: false });
+        append_entry(&dir, &first).unwrap();
+        append_entry(&dir, &own).unwrap();
+
+        let mut cursors = HashMap::new();
+        assert_eq!(read_new_entries(&dir, "desktop", &mut cursors), vec![first]);
+        assert_eq!(read_new_entries(&dir, "desktop", &mut cursors), vec![]);
+
+        append_entry(&dir, &second).unwrap();
+        // a line that is still being written is skipped
+        let log = dir.join(LOGS_DIR).join("laptop.jsonl");
+        let mut file = OpenOptions::new().append(true).open(&log).unwrap();
+        write!(file, "{{\"type\": \"favo").unwrap();
+
+        assert_eq!(
+            read_new_entries(&dir, "desktop", &mut cursors),
+            vec![second]
+        );
+        assert_eq!(cursors["laptop"], 2);
+
+        // the own changes are read apart, to know when the values have been changed here
+        assert_eq!(read_own_entries(&dir, "desktop", &mut cursors), vec![own]);
+        assert_eq!(read_own_entries(&dir, "desktop", &mut cursors), vec![]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn entries_survive_json() {
+        let edit = entry(
+            "laptop",
+            10,
+            Change::Edit {
+                chapter: 2,
+                base: ORIGINAL.to_string(),
+                text: None,
+            },
+        );
+        assert_eq!(LogEntry::from_json(&edit.to_json()), Some(edit));
+
+        let progress = entry(
+            "laptop",
+            11,
+            Change::Progress {
+                chapter: 4,
+                page: 7,
+                content: "testo della pagina".to_string(),
+                font_size: "medium".to_string(),
+            },
+        );
+        assert_eq!(LogEntry::from_json(&progress.to_json()), Some(progress));
+    }
+
+    #[test]
+    fn last_writer_wins() {
+        let mut state = SyncState::default();
+        assert!(state.update_time("book.epub/progress".to_string(), 20));
+        assert!(!state.update_time("book.epub/progress".to_string(), 10));
+        assert!(state.update_time("book.epub/progress".to_string(), 30));
+        assert_eq!(state.times["book.epub/progress"], 30);
+    }
+
+    #[test]
+    fn edits_are_classified() {
+        assert_eq!(classify_edit(ORIGINAL, ORIGINAL, "abc"), EditAction::Apply);
+        assert_eq!(classify_edit("abc", "abc", ORIGINAL), EditAction::Apply);
+        assert_eq!(classify_edit("abc", ORIGINAL, "abc"), EditAction::Skip);
+        assert_eq!(classify_edit("def", ORIGINAL, "abc"), EditAction::Conflict);
+    }
+}
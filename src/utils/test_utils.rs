@@ -0,0 +1,10 @@
+use std::path::PathBuf;
+
+/// Returns an empty folder in the temporary directory, named after the test
+/// and the running process so that parallel test runs don't collide
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("crab-reader-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
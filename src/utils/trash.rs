@@ -0,0 +1,296 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Value};
+
+use super::{
+    collections::Collections,
+    dir_manager::{
+        get_books_notes_path, get_edited_books_dir, get_saved_books_dir, get_savedata_path,
+        get_trash_dir,
+    },
+    dir_watcher::{get_watched_dirs, is_watched},
+    envmanager::FontSize,
+    saveload::{read_book_entry, save_archived, save_data, set_book_excluded, write_book_entry},
+    sync_log::{self, Change},
+};
+use crate::{models::book_actions::BookAction, MYENV};
+
+/// Data of a book changed by an action, kept until the action can be undone.
+/// The files and the folders of the book are moved in the trash meanwhile
+pub struct Snapshot {
+    pub id: u64,
+    pub action: BookAction,
+    pub book_path: String,
+    /// position and edited chapters
+    savedata: Option<Value>,
+    notes: Option<Value>,
+    collections: Vec<String>,
+    tags: Vec<String>,
+    /// original paths of the files and the folders moved in the trash
+    trashed: Vec<PathBuf>,
+    /// chapters whose edits have been discarded, with the hash of the edited text
+    edits: Vec<(usize, String)>,
+}
+
+/// Returns an id for a new action, every action has its folder in the trash
+pub fn new_action_id() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+/// Returns true for the books in the epubs folder or in the watched ones,
+/// the other files (e.g. of a Calibre library) aren't deleted by the library
+pub fn can_delete_file(book_path: &str) -> bool {
+    is_watched(Path::new(book_path), &get_watched_dirs())
+}
+
+/// Removes what has been left in the trash, e.g. when the application
+/// has been closed before the last action could no longer be undone
+pub fn empty_trash() {
+    let _ = std::fs::remove_dir_all(get_trash_dir());
+}
+
+/// Moves a file or a folder, the files can be copied to another disk, e.g. from a watched folder
+fn move_path(from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    if std::fs::rename(from, to).is_err() && from.is_file() {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    } else if from.exists() {
+        return Err(format!("impossibile spostare {}", from.display()).into());
+    }
+    Ok(())
+}
+
+fn book_folder(dir: PathBuf, book_path: &str) -> PathBuf {
+    dir.join(Path::new(book_path).file_stem().unwrap_or_default())
+}
+
+impl Snapshot {
+    /// Does an action on a book, saving what is needed to undo it
+    pub fn apply(action: BookAction, book_path: &str, id: u64) -> Result<Self, Box<dyn Error>> {
+        Self::apply_to(action, book_path, id, true)
+    }
+
+    /// Removes a duplicate merged in the kept book, its file is deleted when the library can.
+    /// If the two files have the same name their chapters and edits are in the same folders,
+    /// which are left to the kept book
+    pub fn remove_duplicate(book_path: &str, keep: &str, id: u64) -> Result<Self, Box<dyn Error>> {
+        let action = if can_delete_file(book_path) {
+            BookAction::DeleteFile
+        } else {
+            BookAction::Remove
+        };
+        let own_folders = Path::new(book_path).file_stem() != Path::new(keep).file_stem();
+        Self::apply_to(action, book_path, id, own_folders)
+    }
+
+    fn apply_to(
+        action: BookAction,
+        book_path: &str,
+        id: u64,
+        own_folders: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let shelves = Collections::load();
+        let mut snapshot = Self {
+            id,
+            action,
+            book_path: book_path.to_string(),
+            savedata: read_book_entry(&get_savedata_path(), book_path),
+            notes: read_book_entry(&get_books_notes_path(), book_path),
+            collections: shelves.get_collections_of(book_path),
+            tags: shelves.get_tags_of(book_path),
+            trashed: vec![],
+            edits: vec![],
+        };
+
+        match action {
+            BookAction::Remove | BookAction::DeleteFile => {
+                if own_folders {
+                    snapshot.trash(&book_folder(get_saved_books_dir(), book_path))?;
+                    snapshot.trash(&book_folder(get_edited_books_dir(), book_path))?;
+                }
+                if action == BookAction::DeleteFile {
+                    snapshot.trash(Path::new(book_path))?;
+                } else {
+                    // the file is still in a folder read by the library
+                    set_book_excluded(book_path, true)?;
+                }
+                write_book_entry(&get_savedata_path(), book_path, None)?;
+                write_book_entry(&get_books_notes_path(), book_path, None)?;
+                let mut shelves = shelves;
+                shelves.remove_book(book_path);
+                shelves.save()?;
+            }
+            BookAction::Archive => save_archived(book_path, true)?,
+            BookAction::Unarchive => save_archived(book_path, false)?,
+            BookAction::ResetProgress => {
+                if let Some(mut savedata) = snapshot.savedata.clone() {
+                    savedata["chapter"] = json!(1);
+                    savedata["page"] = json!(0);
+                    savedata["content"] = json!("");
+                    write_book_entry(&get_savedata_path(), book_path, Some(savedata))?;
+                }
+            }
+            BookAction::ClearNotes => {
+                write_book_entry(&get_books_notes_path(), book_path, None)?;
+            }
+            BookAction::DiscardEdits => {
+                let Some(mut savedata) = snapshot.savedata.clone() else {
+                    return Ok(snapshot);
+                };
+                snapshot.edits = savedata["edited_chapters"]
+                    .as_array()
+                    .unwrap_or(&vec![])
+                    .iter()
+                    .filter_map(|chapter| chapter.as_u64())
+                    .map(|chapter| {
+                        let chapter = chapter as usize;
+                        (chapter, sync_log::get_chapter_hash(book_path, chapter))
+                    })
+                    .collect();
+                snapshot.trash(&book_folder(get_edited_books_dir(), book_path))?;
+                savedata["edited_chapters"] = json!([]);
+                write_book_entry(&get_savedata_path(), book_path, Some(savedata))?;
+            }
+        }
+        Ok(snapshot)
+    }
+
+    fn trash_dir(&self) -> PathBuf {
+        get_trash_dir().join(self.id.to_string())
+    }
+
+    /// Moves a file or a folder in the trash, if it exists
+    fn trash(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let dir = self.trash_dir();
+        std::fs::create_dir_all(&dir)?;
+        move_path(path, &dir.join(self.trashed.len().to_string()))?;
+        self.trashed.push(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Puts back the book as it was before the action
+    pub fn restore(&self) -> Result<(), Box<dyn Error>> {
+        let book_path = self.book_path.as_str();
+        for (i, path) in self.trashed.iter().enumerate() {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            move_path(&self.trash_dir().join(i.to_string()), path)?;
+        }
+
+        match self.action {
+            BookAction::Remove | BookAction::DeleteFile => {
+                write_book_entry(&get_savedata_path(), book_path, self.savedata.clone())?;
+                write_book_entry(&get_books_notes_path(), book_path, self.notes.clone())?;
+                let mut shelves = Collections::load();
+                let books = [self.book_path.clone()];
+                for name in &self.collections {
+                    shelves.add_to_collection(name, &books)?;
+                }
+                for tag in &self.tags {
+                    shelves.add_tag(tag, &books)?;
+                }
+                shelves.save()?;
+                set_book_excluded(book_path, false)?;
+            }
+            BookAction::Archive => save_archived(book_path, false)?,
+            BookAction::Unarchive => save_archived(book_path, true)?,
+            BookAction::ResetProgress | BookAction::DiscardEdits => {
+                write_book_entry(&get_savedata_path(), book_path, self.savedata.clone())?;
+            }
+            BookAction::ClearNotes => {
+                write_book_entry(&get_books_notes_path(), book_path, self.notes.clone())?;
+            }
+        }
+        let _ = std::fs::remove_dir_all(self.trash_dir());
+        Ok(())
+    }
+
+    /// The action can no longer be undone: the trash is emptied
+    /// and the changes are sent to the other devices
+    pub fn commit(&self) -> Result<(), Box<dyn Error>> {
+        let book_path = self.book_path.as_str();
+        match self.action {
+            BookAction::ResetProgress if self.savedata.is_some() => {
+                let font_size = FontSize::from(MYENV.lock().unwrap().font.size);
+                save_data(book_path, 1, 0, "", font_size, false)?;
+            }
+            BookAction::ClearNotes => {
+                for (chapter, start) in note_starts(self.notes.as_ref()) {
+                    sync_log::record(book_path, Change::NoteDelete { chapter, start });
+                }
+            }
+            BookAction::DiscardEdits => {
+                for (chapter, base) in &self.edits {
+                    let change = Change::Edit {
+                        chapter: *chapter,
+                        base: base.clone(),
+                        text: None,
+                    };
+                    sync_log::record(book_path, change);
+                }
+            }
+            _ => {}
+        }
+        if self.trash_dir().exists() {
+            std::fs::remove_dir_all(self.trash_dir())?;
+        }
+        Ok(())
+    }
+}
+
+/// Chapter and start of the notes of a book, as they are saved in books_notes.json
+fn note_starts(notes: Option<&Value>) -> Vec<(usize, String)> {
+    notes
+        .and_then(|notes| notes.as_array())
+        .map(|chapters| {
+            chapters
+                .iter()
+                .flat_map(|item| {
+                    let chapter = item["chapter"].as_u64().unwrap_or_default() as usize;
+                    item["notes"]
+                        .as_array()
+                        .unwrap_or(&vec![])
+                        .iter()
+                        .filter_map(|note| note["start"].as_str())
+                        .map(|start| (chapter, start.to_string()))
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_starts() {
+        let notes = json!([
+            {"chapter": 2, "notes": [{"start": "Nel mezzo", "note": "inizio"}]},
+            {"chapter": 5, "notes": [
+                {"start": "Amor", "note": "Francesca"},
+                {"start": "Fatti non foste", "note": "Ulisse"}
+            ]}
+        ]);
+        assert_eq!(
+            note_starts(Some(&notes)),
+            vec![
+                (2, "Nel mezzo".to_string()),
+                (5, "Amor".to_string()),
+                (5, "Fatti non foste".to_string())
+            ]
+        );
+        assert!(note_starts(None).is_empty());
+    }
+}
@@ -0,0 +1,836 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::Read,
+    path::{Component, Path},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use druid::{ExtEventSink, Target};
+use serde_json::{json, Value};
+
+use super::{
+    archive::EXPORTED_SETTINGS,
+    dir_manager::{get_app_dir, get_backup_state_path},
+    sync_log::get_device_name,
+};
+use crate::{models::backup::BACKUP_DONE, MYENV};
+
+/// Folder created on the server, it contains the files and the snapshots
+const REMOTE_DIR: &str = "crab-reader-backup";
+/// Files saved by content, a file is uploaded only if its checksum is new
+const OBJECTS_DIR: &str = "objects";
+/// Lists of the files of every backup
+const SNAPSHOTS_DIR: &str = "snapshots";
+/// Settings of the application, they hold the passwords of the accounts
+const ENV: &str = "conf/env.json";
+/// Files that only make sense on this device. Of the env only the settings
+/// that can be moved to another device are backed up, without the accounts
+const LOCAL_ONLY: [&str; 3] = ["conf/sync_state.json", "conf/backup_state.json", ENV];
+/// Folders that are backed up, the files of a snapshot are only restored in them
+const BACKED_UP_DIRS: [&str; 3] = ["conf/", "edited_books/", "saved_books/"];
+/// Time between two checks of the scheduled backup
+const SCHEDULER_CHECK: Duration = Duration::from_secs(10 * 60);
+
+/// WebDAV endpoint where the library is backed up
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebDavSettings {
+    pub url: String,
+    pub username: String,
+    pub password: String,
+    /// hours between two automatic backups, 0 disables them
+    pub interval_hours: u64,
+}
+
+impl WebDavSettings {
+    pub fn new(url: &str, username: &str, password: &str, interval_hours: u64) -> Self {
+        Self {
+            url: url.trim().trim_end_matches('/').to_string(),
+            username: username.trim().to_string(),
+            password: password.to_string(),
+            interval_hours,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "url": self.url,
+            "username": self.username,
+            "password": self.password,
+            "interval_hours": self.interval_hours,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            url: value.get("url")?.as_str()?.to_string(),
+            username: value.get("username")?.as_str()?.to_string(),
+            password: value.get("password")?.as_str()?.to_string(),
+            interval_hours: value["interval_hours"].as_u64().unwrap_or_default(),
+        })
+    }
+}
+
+/// Backup of the library at a given time
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    /// milliseconds since the epoch
+    pub time: u64,
+    pub device: String,
+}
+
+impl Snapshot {
+    /// The name is <time>-<device>.json, the snapshots are sorted by it
+    fn from_name(name: &str) -> Option<Self> {
+        let (time, device) = name.strip_suffix(".json")?.split_once('-')?;
+        Some(Self {
+            name: name.to_string(),
+            time: time.parse().ok()?,
+            device: device.to_string(),
+        })
+    }
+}
+
+/// Result of a backup
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupReport {
+    pub snapshot: Snapshot,
+    pub files: usize,
+    pub uploaded: usize,
+}
+
+/// What is taken back from a snapshot
+#[derive(Clone, Debug, PartialEq)]
+pub enum Restore {
+    All,
+    /// metadata, edited chapters, position and notes of a book
+    Book(String),
+    /// only the notes of a book
+    Notes(String),
+}
+
+/// Returns the files that are backed up, relative to the folder of the application.
+/// The books and their extracted chapters aren't saved, they can be added again
+pub fn collect_files(root: &Path) -> Vec<String> {
+    fn walk(root: &Path, dir: &Path, files: &mut Vec<String>) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                walk(root, &path, files);
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                let relative = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().to_string())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.push(relative);
+            }
+        }
+    }
+
+    let mut files = vec![];
+    walk(root, &root.join("conf"), &mut files);
+    walk(root, &root.join("edited_books"), &mut files);
+    if let Ok(books) = std::fs::read_dir(root.join("saved_books")) {
+        for book in books.flatten() {
+            if book.path().join("metadata.json").is_file() {
+                files.push(format!(
+                    "saved_books/{}/metadata.json",
+                    book.file_name().to_string_lossy()
+                ));
+            }
+        }
+    }
+    files.retain(|file| !LOCAL_ONLY.contains(&file.as_str()));
+    files.sort();
+    files
+}
+
+/// Settings of the env that are backed up
+fn exported_settings(root: &Path) -> Value {
+    let env = std::fs::read_to_string(root.join(ENV))
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .unwrap_or(json!({}));
+    let mut settings = json!({});
+    for key in EXPORTED_SETTINGS {
+        if let Some(value) = env.get(key) {
+            settings[key] = value.clone();
+        }
+    }
+    settings
+}
+
+/// The files of a manifest come from the server, only the relative paths
+/// in the backed up folders are written, never outside of the folder of the application
+fn is_restorable(file: &str) -> bool {
+    Path::new(file)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        && BACKED_UP_DIRS.iter().any(|dir| file.starts_with(dir))
+        && (file == ENV || !LOCAL_ONLY.contains(&file))
+}
+
+fn checksum(bytes: &[u8]) -> String {
+    format!("{:x}", md5::compute(bytes))
+}
+
+/// Minimal WebDAV client, only the methods needed by the backup
+struct Client {
+    base: String,
+    auth: String,
+    agent: ureq::Agent,
+}
+
+impl Client {
+    fn new(settings: &WebDavSettings) -> Self {
+        let credentials = format!("{}:{}", settings.username, settings.password);
+        Self {
+            base: format!("{}/{}", settings.url, REMOTE_DIR),
+            auth: format!("Basic {}", STANDARD.encode(credentials)),
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(60))
+                .build(),
+        }
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let url = if path.is_empty() {
+            format!("{}/", self.base)
+        } else {
+            format!("{}/{}", self.base, path)
+        };
+        self.agent
+            .request(method, &url)
+            .set("Authorization", &self.auth)
+    }
+
+    /// Converts the errors of the server in readable messages
+    fn check(result: Result<ureq::Response, ureq::Error>) -> Result<ureq::Response, String> {
+        match result {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(401, _)) => Err("utente o password errati".into()),
+            Err(ureq::Error::Status(code, _)) => Err(format!("errore {}", code)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Creates a collection, it isn't an error if it already exists
+    fn mkcol(&self, path: &str) -> Result<(), String> {
+        match self.request("MKCOL", path).call() {
+            Ok(_) | Err(ureq::Error::Status(405, _)) => Ok(()),
+            result => Self::check(result).map(|_| ()),
+        }
+    }
+
+    /// Returns the names of the files of a collection, none if it doesn't exist
+    fn list(&self, path: &str) -> Result<Vec<String>, String> {
+        let body = r#"<?xml version="1.0" encoding="utf-8"?><d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/></d:prop></d:propfind>"#;
+        let result = self
+            .request("PROPFIND", &format!("{}/", path))
+            .set("Depth", "1")
+            .set("Content-Type", "application/xml")
+            .send_string(body);
+        let response = match result {
+            Err(ureq::Error::Status(404, _)) => return Ok(vec![]),
+            result => Self::check(result)?,
+        };
+        let text = response.into_string().map_err(|e| e.to_string())?;
+        let document = roxmltree::Document::parse(&text).map_err(|e| e.to_string())?;
+
+        Ok(document
+            .descendants()
+            .filter(|node| node.tag_name().name() == "href")
+            .filter_map(|node| node.text())
+            .map(|href| href.trim_end_matches('/'))
+            // the collection itself is in the list too
+            .filter(|href| !href.ends_with(&format!("{}/{}", REMOTE_DIR, path)))
+            .filter_map(|href| href.rsplit('/').next())
+            .map(|name| name.to_string())
+            .collect())
+    }
+
+    fn put(&self, path: &str, bytes: &[u8]) -> Result<(), String> {
+        Self::check(self.request("PUT", path).send_bytes(bytes)).map(|_| ())
+    }
+
+    fn get(&self, path: &str) -> Result<Vec<u8>, String> {
+        let response = Self::check(self.request("GET", path).call())?;
+        let mut bytes = vec![];
+        response
+            .into_reader()
+            .read_to_end(&mut bytes)
+            .map_err(|e| e.to_string())?;
+        Ok(bytes)
+    }
+
+    fn get_manifest(&self, snapshot: &str) -> Result<BTreeMap<String, String>, String> {
+        let bytes = self.get(&format!("{}/{}", SNAPSHOTS_DIR, snapshot))?;
+        let json: Value = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+        Ok(json["files"]
+            .as_object()
+            .map(|files| {
+                files
+                    .iter()
+                    .filter_map(|(file, hash)| Some((file.clone(), hash.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+/// Uploads the files that changed since the last backups and saves a new snapshot
+pub fn backup(
+    settings: &WebDavSettings,
+    root: &Path,
+    device: &str,
+) -> Result<BackupReport, String> {
+    let client = Client::new(settings);
+    client.mkcol("")?;
+    client.mkcol(OBJECTS_DIR)?;
+    client.mkcol(SNAPSHOTS_DIR)?;
+
+    let mut uploaded_objects = client
+        .list(OBJECTS_DIR)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let mut manifest = BTreeMap::new();
+    let mut uploaded = 0;
+    let files = collect_files(root)
+        .into_iter()
+        .filter_map(|file| Some((std::fs::read(root.join(&file)).ok()?, file)));
+    let settings = (
+        exported_settings(root).to_string().into_bytes(),
+        ENV.to_string(),
+    );
+    for (bytes, file) in files.chain([settings]) {
+        let hash = checksum(&bytes);
+        if !uploaded_objects.contains(&hash) {
+            client.put(&format!("{}/{}", OBJECTS_DIR, hash), &bytes)?;
+            uploaded_objects.insert(hash.clone());
+            uploaded += 1;
+        }
+        manifest.insert(file, hash);
+    }
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64);
+    let snapshot = Snapshot {
+        name: format!("{}-{}.json", time, device),
+        time,
+        device: device.to_string(),
+    };
+    let json = json!({ "time": time, "device": device, "files": manifest });
+    client.put(
+        &format!("{}/{}", SNAPSHOTS_DIR, snapshot.name),
+        json.to_string().as_bytes(),
+    )?;
+
+    Ok(BackupReport {
+        snapshot,
+        files: manifest.len(),
+        uploaded,
+    })
+}
+
+/// Returns the snapshots on the server, the most recent first
+pub fn list_snapshots(settings: &WebDavSettings) -> Result<Vec<Snapshot>, String> {
+    let mut snapshots = Client::new(settings)
+        .list(SNAPSHOTS_DIR)?
+        .iter()
+        .filter_map(|name| Snapshot::from_name(name))
+        .collect::<Vec<_>>();
+    snapshots.sort_by(|a, b| b.time.cmp(&a.time).then(b.name.cmp(&a.name)));
+    Ok(snapshots)
+}
+
+/// Replaces the value of a book in one of the JSON files keyed by book path,
+/// the other books are left as they are
+fn restore_key(
+    client: &Client,
+    root: &Path,
+    manifest: &BTreeMap<String, String>,
+    file: &str,
+    key: &str,
+) -> Result<bool, String> {
+    let Some(hash) = manifest.get(file) else {
+        return Ok(false);
+    };
+    let remote: Value = serde_json::from_slice(&client.get(&format!("{}/{}", OBJECTS_DIR, hash))?)
+        .map_err(|e| e.to_string())?;
+    let path = root.join(file);
+    let mut local = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .filter(|json| json.is_object())
+        .unwrap_or(json!({}));
+
+    match remote.get(key) {
+        Some(value) => local[key] = value.clone(),
+        None => {
+            local.as_object_mut().unwrap().remove(key);
+        }
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(
+        path,
+        serde_json::to_string_pretty(&local).unwrap_or_default(),
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// Takes back the settings of a snapshot, the accounts of this device are kept.
+/// Returns true if a setting has changed
+fn restore_settings(client: &Client, root: &Path, hash: &str) -> Result<bool, String> {
+    let remote: Value = serde_json::from_slice(&client.get(&format!("{}/{}", OBJECTS_DIR, hash))?)
+        .map_err(|e| e.to_string())?;
+    let path = root.join(ENV);
+    let mut env = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .filter(|json| json.is_object())
+        .unwrap_or(json!({}));
+    let mut changed = false;
+    for key in EXPORTED_SETTINGS {
+        if let Some(value) = remote.get(key).filter(|value| env.get(key) != Some(*value)) {
+            env[key] = value.clone();
+            changed = true;
+        }
+    }
+    if changed {
+        std::fs::write(path, serde_json::to_string_pretty(&env).unwrap_or_default())
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(changed)
+}
+
+/// Takes back the files of a snapshot, returns how many files have been restored
+pub fn restore(
+    settings: &WebDavSettings,
+    root: &Path,
+    snapshot: &str,
+    what: &Restore,
+) -> Result<usize, String> {
+    let client = Client::new(settings);
+    let manifest = client.get_manifest(snapshot)?;
+
+    let (files, book_path) = match what {
+        Restore::All => (manifest.keys().cloned().collect::<Vec<_>>(), None),
+        Restore::Book(path) => {
+            let stem = Path::new(path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let prefixes = [
+                format!("saved_books/{}/", stem),
+                format!("edited_books/{}/", stem),
+            ];
+            let files = manifest
+                .keys()
+                .filter(|file| prefixes.iter().any(|prefix| file.starts_with(prefix)))
+                .cloned()
+                .collect();
+            (files, Some(path))
+        }
+        Restore::Notes(path) => (vec![], Some(path)),
+    };
+
+    let mut restored = 0;
+    for file in files {
+        if !is_restorable(&file) {
+            println!("ERROR: the backup file {} can't be restored", file);
+            continue;
+        }
+        let hash = &manifest[&file];
+        if file == ENV {
+            if restore_settings(&client, root, hash)? {
+                restored += 1;
+            }
+            continue;
+        }
+        let path = root.join(&file);
+        // files that didn't change aren't downloaded
+        if std::fs::read(&path).is_ok_and(|bytes| &checksum(&bytes) == hash) {
+            continue;
+        }
+        let bytes = client.get(&format!("{}/{}", OBJECTS_DIR, hash))?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(path, bytes).map_err(|e| e.to_string())?;
+        restored += 1;
+    }
+
+    if let Some(book_path) = book_path {
+        if restore_key(&client, root, &manifest, "conf/books_notes.json", book_path)? {
+            restored += 1;
+        }
+        if matches!(what, Restore::Book(_))
+            && restore_key(&client, root, &manifest, "conf/books_saved.json", book_path)?
+        {
+            restored += 1;
+        }
+    }
+    Ok(restored)
+}
+
+/// Returns the time of the last backup made by this device
+pub fn get_last_backup() -> Option<u64> {
+    let content = std::fs::read_to_string(get_backup_state_path()).ok()?;
+    serde_json::from_str::<Value>(&content).ok()?["last_backup"].as_u64()
+}
+
+fn save_last_backup(time: u64) {
+    let json = json!({ "last_backup": time });
+    if let Err(e) = std::fs::write(get_backup_state_path(), json.to_string()) {
+        println!("ERROR: failed to save the time of the backup: {}", e);
+    }
+}
+
+/// Tells if the scheduled backup has to be made
+pub fn is_backup_due(settings: &WebDavSettings, last_backup: Option<u64>, now: u64) -> bool {
+    match last_backup {
+        _ if settings.interval_hours == 0 => false,
+        Some(last_backup) => now >= last_backup + settings.interval_hours * 3600,
+        None => true,
+    }
+}
+
+/// Backs up the folder of the application
+pub fn run_backup(settings: &WebDavSettings) -> Result<BackupReport, String> {
+    let report = backup(settings, &get_app_dir(), &get_device_name())?;
+    save_last_backup(report.snapshot.time / 1000);
+    Ok(report)
+}
+
+/// Starts the thread of the scheduled backups, the result is sent with BACKUP_DONE
+pub fn start_scheduler(sink: ExtEventSink) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(SCHEDULER_CHECK);
+        let Some(settings) = MYENV.lock().unwrap().webdav.clone() else {
+            continue;
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        if !is_backup_due(&settings, get_last_backup(), now) {
+            continue;
+        }
+        let result = run_backup(&settings);
+        if let Err(e) = &result {
+            println!("ERROR: scheduled backup failed: {}", e);
+        }
+        if sink
+            .submit_command(BACKUP_DONE, result, Target::Auto)
+            .is_err()
+        {
+            break;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::temp_dir;
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    /// WebDAV server kept in memory, with the methods used by the client
+    #[derive(Default)]
+    struct DavStorage {
+        collections: HashSet<String>,
+        files: HashMap<String, Vec<u8>>,
+    }
+
+    fn parent(path: &str) -> &str {
+        path.rsplit_once('/').map_or("", |(parent, _)| parent)
+    }
+
+    fn handle(stream: &mut TcpStream, storage: &Mutex<DavStorage>, puts: &AtomicUsize) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches('/')
+            .to_string();
+
+        let mut length = 0;
+        let mut authorized = false;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).unwrap() <= 2 {
+                break;
+            }
+            let (name, value) = line.split_once(':').unwrap();
+            match name.to_lowercase().as_str() {
+                "content-length" => length = value.trim().parse().unwrap(),
+                // dante:inferno
+                "authorization" => authorized = value.trim() == "Basic ZGFudGU6aW5mZXJubw==",
+                _ => {}
+            }
+        }
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body).unwrap();
+
+        let mut storage = storage.lock().unwrap();
+        let (status, body) = match method.as_str() {
+            _ if !authorized => ("401 Unauthorized", vec![]),
+            "MKCOL" if storage.collections.contains(&path) => ("405 Method Not Allowed", vec![]),
+            "MKCOL" if !storage.collections.contains(parent(&path)) => ("409 Conflict", vec![]),
+            "MKCOL" => {
+                storage.collections.insert(path);
+                ("201 Created", vec![])
+            }
+            "PUT" if !storage.collections.contains(parent(&path)) => ("409 Conflict", vec![]),
+            "PUT" => {
+                puts.fetch_add(1, Ordering::Relaxed);
+                storage.files.insert(path, body);
+                ("201 Created", vec![])
+            }
+            "GET" => match storage.files.get(&path) {
+                Some(bytes) => ("200 OK", bytes.clone()),
+                None => ("404 Not Found", vec![]),
+            },
+            "PROPFIND" if storage.collections.contains(&path) => {
+                let children = storage
+                    .files
+                    .keys()
+                    .chain(storage.collections.iter())
+                    .filter(|child| parent(child) == path)
+                    .map(|child| format!("<d:response><d:href>{}</d:href></d:response>", child))
+                    .collect::<String>();
+                let xml = format!(
+                    r#"<?xml version="1.0"?><d:multistatus xmlns:d="DAV:"><d:response><d:href>{}/</d:href></d:response>{}</d:multistatus>"#,
+                    path, children
+                );
+                ("207 Multi-Status", xml.into_bytes())
+            }
+            _ => ("404 Not Found", vec![]),
+        };
+
+        let header = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            body.len()
+        );
+        stream.write_all(header.as_bytes()).unwrap();
+        stream.write_all(&body).unwrap();
+    }
+
+    /// Starts the WebDAV stand-in, returns its settings and the counter of the uploads
+    fn start_server() -> (WebDavSettings, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let storage = Arc::new(Mutex::new(DavStorage::default()));
+        storage.lock().unwrap().collections.insert("/dav".into());
+        let puts = Arc::new(AtomicUsize::new(0));
+
+        let counter = puts.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut stream = stream;
+                handle(&mut stream, &storage, &counter);
+            }
+        });
+
+        let settings = WebDavSettings::new(
+            &format!("http://127.0.0.1:{}/dav/", port),
+            "dante",
+            "inferno",
+            24,
+        );
+        (settings, puts)
+    }
+
+    fn create_root(name: &str) -> PathBuf {
+        let root = temp_dir(&format!("backup-{}", name));
+        for dir in ["conf", "edited_books/divina", "saved_books/divina/text"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::write(
+            root.join("conf/books_notes.json"),
+            json!({
+                "/libri/divina.epub": [{"chapter": 1, "notes": [{"start": "Nel mezzo", "note": "inizio"}]}],
+                "/libri/decameron.epub": [{"chapter": 2, "notes": [{"start": "Umana cosa", "note": "proemio"}]}],
+            })
+            .to_string(),
+        )
+        .unwrap();
+        std::fs::write(root.join("conf/sync_state.json"), "{}").unwrap();
+        std::fs::write(
+            root.join("edited_books/divina/page_1.txt"),
+            "testo modificato",
+        )
+        .unwrap();
+        std::fs::write(root.join("saved_books/divina/metadata.json"), "{}").unwrap();
+        std::fs::write(root.join("saved_books/divina/text/page_1.html"), "capitolo").unwrap();
+        root
+    }
+
+    #[test]
+    fn test_collect_files() {
+        let root = create_root("collect");
+        assert_eq!(
+            collect_files(&root),
+            vec![
+                "conf/books_notes.json",
+                "edited_books/divina/page_1.txt",
+                "saved_books/divina/metadata.json",
+            ]
+        );
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_incremental_backup() {
+        let (settings, puts) = start_server();
+        let root = create_root("incremental");
+
+        let first = backup(&settings, &root, "laptop").unwrap();
+        // the files and the settings, which are empty like the metadata and share its object
+        assert_eq!((first.files, first.uploaded), (4, 3));
+        // 3 objects and the snapshot
+        assert_eq!(puts.load(Ordering::Relaxed), 4);
+
+        std::fs::write(root.join("edited_books/divina/page_1.txt"), "altro testo").unwrap();
+        let second = backup(&settings, &root, "laptop").unwrap();
+        assert_eq!((second.files, second.uploaded), (4, 1));
+        assert_eq!(puts.load(Ordering::Relaxed), 6);
+
+        assert_eq!(list_snapshots(&settings).unwrap().len(), 2);
+
+        let wrong = WebDavSettings::new(&settings.url, "dante", "purgatorio", 0);
+        assert_eq!(
+            backup(&wrong, &root, "laptop").unwrap_err(),
+            "utente o password errati"
+        );
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_restore() {
+        let (settings, _) = start_server();
+        let root = create_root("restore");
+        let snapshot = backup(&settings, &root, "laptop").unwrap().snapshot;
+
+        // only the notes of one book are taken back
+        std::fs::write(
+            root.join("conf/books_notes.json"),
+            json!({ "/libri/decameron.epub": [] }).to_string(),
+        )
+        .unwrap();
+        std::fs::write(root.join("edited_books/divina/page_1.txt"), "perso").unwrap();
+        let restored = restore(
+            &settings,
+            &root,
+            &snapshot.name,
+            &Restore::Notes("/libri/divina.epub".into()),
+        )
+        .unwrap();
+        assert_eq!(restored, 1);
+        let notes: Value = serde_json::from_str(
+            &std::fs::read_to_string(root.join("conf/books_notes.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(notes["/libri/divina.epub"][0]["notes"][0]["note"], "inizio");
+        assert_eq!(notes["/libri/decameron.epub"], json!([]));
+        assert_eq!(
+            std::fs::read_to_string(root.join("edited_books/divina/page_1.txt")).unwrap(),
+            "perso"
+        );
+
+        // everything else is restored, only the changed files are downloaded
+        let restored = restore(&settings, &root, &snapshot.name, &Restore::All).unwrap();
+        assert_eq!(restored, 2);
+        assert_eq!(
+            std::fs::read_to_string(root.join("edited_books/divina/page_1.txt")).unwrap(),
+            "testo modificato"
+        );
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_restore_only_safe_files_and_settings() {
+        let (settings, _) = start_server();
+        let root = create_root("safe");
+        let env = json!({ "theme": "dark", "webdav": { "password": "inferno" } });
+        std::fs::write(root.join(ENV), env.to_string()).unwrap();
+        let snapshot = backup(&settings, &root, "laptop").unwrap().snapshot;
+
+        // the accounts aren't sent to the server
+        let client = Client::new(&settings);
+        let manifest = client.get_manifest(&snapshot.name).unwrap();
+        let backed_up = client
+            .get(&format!("{}/{}", OBJECTS_DIR, manifest[ENV]))
+            .unwrap();
+        assert!(!String::from_utf8(backed_up).unwrap().contains("inferno"));
+
+        // a manifest of the server with files outside of the folder of the application
+        let hash = &manifest["edited_books/divina/page_1.txt"];
+        let outside = root.parent().unwrap().join("crab-reader-outside.txt");
+        let evil = json!({ "files": {
+            "../crab-reader-outside.txt": hash,
+            outside.to_string_lossy(): hash,
+            "edited_books/../../crab-reader-outside.txt": hash,
+            "epubs/divina.epub": hash,
+            "conf/sync_state.json": hash,
+        }});
+        client
+            .put(
+                &format!("{}/evil.json", SNAPSHOTS_DIR),
+                evil.to_string().as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(restore(&settings, &root, "evil.json", &Restore::All), Ok(0));
+        assert!(!outside.exists());
+        assert!(!root.join("epubs").exists());
+        assert_eq!(
+            std::fs::read_to_string(root.join("conf/sync_state.json")).unwrap(),
+            "{}"
+        );
+
+        // the settings are taken back, the accounts of this device are kept
+        let env = json!({ "theme": "light", "webdav": { "password": "paradiso" } });
+        std::fs::write(root.join(ENV), env.to_string()).unwrap();
+        assert_eq!(
+            restore(&settings, &root, &snapshot.name, &Restore::All),
+            Ok(1)
+        );
+        let env: Value =
+            serde_json::from_str(&std::fs::read_to_string(root.join(ENV)).unwrap()).unwrap();
+        assert_eq!(env["theme"], "dark");
+        assert_eq!(env["webdav"]["password"], "paradiso");
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_backup_schedule() {
+        let settings = WebDavSettings::new("http://localhost", "dante", "inferno", 24);
+        assert!(is_backup_due(&settings, None, 1000));
+        assert!(!is_backup_due(&settings, Some(1000), 1000 + 3600));
+        assert!(is_backup_due(&settings, Some(1000), 1000 + 24 * 3600));
+        let manual = WebDavSettings::new("http://localhost", "dante", "inferno", 0);
+        assert!(!is_backup_due(&manual, None, 1000));
+    }
+}